@@ -26,6 +26,16 @@ export function activate(context: ExtensionContext) {
 	const enableDebug: Boolean = serverConfigutration.get("debugLog")
 	const traceFile: PathLike = serverConfigutration.get("trace")
 	const serverModule: string = serverConfigutration.get("path")
+	const enableEvaluation: Boolean = serverConfigutration.get("evaluation")
+	const enableValueHints: Boolean = serverConfigutration.get("valueHints")
+
+	const serverArgs: string[] = traceFile ? ["--trace", traceFile.toString()] : []
+	if (!enableEvaluation) {
+		serverArgs.push("--no-eval")
+	}
+	if (enableValueHints) {
+		serverArgs.push("--value-hints")
+	}
 
 
 	console.error(debugServerModule)
@@ -39,11 +49,11 @@ export function activate(context: ExtensionContext) {
 	const serverOptions: ServerOptions = {
 		run: {
 			command: serverModule, transport: TransportKind.stdio, options: (enableDebug ? debugOptions : {}),
-			args: traceFile ? ["--trace", traceFile.toString()] : [],
+			args: serverArgs,
 		},
 		debug: {
 			command: debugServerModule,
-			args: traceFile ? ["--trace", traceFile.toString()] : [],
+			args: serverArgs,
 			transport: TransportKind.stdio,
 			options: debugOptions
 		}
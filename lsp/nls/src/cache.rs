@@ -1,9 +1,17 @@
-use std::{collections::HashMap, ffi::OsString, io};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    ffi::{OsStr, OsString},
+    fs, io,
+};
 
 use codespan::FileId;
+use log::debug;
+use lsp_types::Url;
 use nickel_lang::{
-    cache::{Cache, CacheError, CacheOp, CachedTerm, EntryState},
+    cache::{Cache, CacheError, CacheOp, CachedTerm, EntryState, ImportResolver, InputFormat},
     error::TypecheckError,
+    term::{RichTerm, Term, TraverseOrder},
     typecheck,
 };
 
@@ -17,6 +25,16 @@ pub trait CacheExt {
         initial_env: &typecheck::Environment,
         lin_cache: &mut HashMap<FileId, Completed>,
     ) -> Result<CacheOp<()>, CacheError<TypecheckError>>;
+    fn import_uri(&self, importer: FileId, path: &OsStr) -> Option<Url>;
+    fn import_paths(&self, file_id: FileId) -> Vec<OsString>;
+    fn importers(&self, file_id: FileId) -> Vec<FileId>;
+    fn resolve_typed_imports(&self, file_id: FileId) -> (RichTerm, HashMap<FileId, OsString>);
+    fn linearize_imports(
+        &mut self,
+        file_id: FileId,
+        initial_env: &typecheck::Environment,
+        lin_cache: &mut HashMap<FileId, Completed>,
+    );
 }
 
 impl CacheExt for Cache {
@@ -43,19 +61,166 @@ impl CacheExt for Cache {
         }
 
         // After self.parse(), the cache must be populated
-        let CachedTerm { term, state, .. } = self.terms().get(&file_id).unwrap();
+        let CachedTerm { state, .. } = self.terms().get(&file_id).unwrap();
 
         if *state > EntryState::Typechecked && lin_cache.contains_key(&file_id) {
             Ok(CacheOp::Cached(()))
         } else if *state >= EntryState::Parsed {
-            let host = AnalysisHost::new();
-            let (_, linearized) =
-                typecheck::type_check_linearize(term, initial_env.clone(), self, host)?;
-            self.update_state(file_id, EntryState::Typechecked);
+            let (term, imports) = self.resolve_typed_imports(file_id);
+            let host = AnalysisHost::with_imports(imports);
+            let (wildcards, checked_annots, linearized) =
+                typecheck::type_check_linearize(&term, initial_env.clone(), self, host)
+                    // The entry may have been invalidated by a change of its imports
+                    .inspect_err(|_| {
+                        self.update_state(file_id, EntryState::Parsed);
+                    })?;
+            let interface = self.get_interface(file_id);
+            self.set_typechecked(file_id, &term, wildcards, checked_annots);
             lin_cache.insert(file_id, linearized);
+
+            // The importers are typechecked again against the new interface, including the ones
+            // which failed to typecheck or which were analyzed before `file_id` was known.
+            if self.get_interface(file_id) != interface {
+                for importer in self.importers(file_id) {
+                    if matches!(
+                        self.entry_state(importer),
+                        Some(EntryState::Parsed | EntryState::Typechecked)
+                    ) {
+                        self.update_state(importer, EntryState::ImportsResolved);
+                    }
+                }
+            }
             Ok(CacheOp::Done(()))
         } else {
             panic!()
         }
     }
+
+    /// Return the URI of the file imported by `path` from `importer`. Files are named by their
+    /// URI in the cache of the server, so imports are resolved relatively to this URI.
+    fn import_uri(&self, importer: FileId, path: &OsStr) -> Option<Url> {
+        let base = Url::parse(&self.name(importer).to_string_lossy()).ok()?;
+        base.join(&path.to_string_lossy()).ok()
+    }
+
+    /// Return the paths of the imports of the parsed file `file_id`.
+    fn import_paths(&self, file_id: FileId) -> Vec<OsString> {
+        let mut paths = Vec::new();
+
+        if let Some(term) = self.get_owned(file_id) {
+            let _: Result<_, Infallible> = term.traverse(
+                &mut |rt: RichTerm, paths: &mut Vec<OsString>| {
+                    if let Term::Import(path) = rt.as_ref() {
+                        paths.push(path.clone());
+                    }
+                    Ok(rt)
+                },
+                &mut paths,
+                TraverseOrder::TopDown,
+            );
+        }
+
+        paths
+    }
+
+    /// Return the parsed files which import `file_id`. Imports are not resolved by the server, so
+    /// the importers are found from their import paths, which also finds the files importing
+    /// `file_id` before it was known to the cache.
+    fn importers(&self, file_id: FileId) -> Vec<FileId> {
+        let uri = match Url::parse(&self.name(file_id).to_string_lossy()) {
+            Ok(uri) => uri,
+            Err(_) => return Vec::new(),
+        };
+
+        self.terms()
+            .keys()
+            .copied()
+            .filter(|importer| {
+                self.import_paths(*importer)
+                    .iter()
+                    .any(|path| self.import_uri(*importer, path).as_ref() == Some(&uri))
+            })
+            .collect()
+    }
+
+    /// Resolve the imports of the parsed file `file_id` whose interface type is known, that is
+    /// which have been typechecked, such that they are typechecked against this interface. The
+    /// other imports are typechecked as `Dyn`. The cached term isn't modified: a resolved copy is
+    /// returned, together with the resolved imports and their path.
+    fn resolve_typed_imports(&self, file_id: FileId) -> (RichTerm, HashMap<FileId, OsString>) {
+        let mut imports = HashMap::new();
+        let term = self.get_owned(file_id).unwrap();
+
+        let result: Result<_, Infallible> = term.traverse(
+            &mut |rt: RichTerm, imports: &mut HashMap<FileId, OsString>| {
+                let imported = match rt.as_ref() {
+                    Term::Import(path) => self
+                        .import_uri(file_id, path)
+                        .and_then(|uri| self.id_of(uri.as_str()))
+                        .filter(|id| self.get_interface(*id).is_some())
+                        .map(|id| (id, path.clone())),
+                    _ => None,
+                };
+
+                match imported {
+                    Some((id, path)) => {
+                        imports.insert(id, path);
+                        Ok(RichTerm::new(Term::ResolvedImport(id), rt.pos))
+                    }
+                    None => Ok(rt),
+                }
+            },
+            &mut imports,
+            TraverseOrder::BottomUp,
+        );
+
+        (result.unwrap(), imports)
+    }
+
+    /// Load, parse and linearize the files imported by `file_id`, and recursively their own
+    /// imports, such that their interface types are known when typechecking `file_id`. Files
+    /// which have already been parsed or linearized, such as the documents opened by the client,
+    /// are not loaded again. Files which can't be read or parsed are ignored.
+    fn linearize_imports(
+        &mut self,
+        file_id: FileId,
+        initial_env: &typecheck::Environment,
+        lin_cache: &mut HashMap<FileId, Completed>,
+    ) {
+        for path in self.import_paths(file_id) {
+            let uri = match self.import_uri(file_id, &path) {
+                Some(uri) => uri,
+                None => continue,
+            };
+
+            if let Some(id) = self.id_of(uri.as_str()) {
+                if lin_cache.contains_key(&id) || self.terms().contains_key(&id) {
+                    continue;
+                }
+            }
+
+            let file_path = match uri.to_file_path() {
+                Ok(file_path) => file_path,
+                Err(_) => continue,
+            };
+            let source = match fs::read_to_string(&file_path) {
+                Ok(source) => source,
+                Err(err) => {
+                    debug!("Could not read import {}: {}", uri, err);
+                    continue;
+                }
+            };
+
+            let imported = match self.update_content(uri.as_str(), source) {
+                Ok(imported) => imported,
+                Err(_) => continue,
+            };
+            let format = InputFormat::from_path_buf(&file_path).unwrap_or(InputFormat::Nickel);
+
+            if self.parse_multi(imported, format).is_ok() {
+                self.linearize_imports(imported, initial_env, lin_cache);
+                let _ = self.typecheck_with_analysis(imported, initial_env, lin_cache);
+            }
+        }
+    }
 }
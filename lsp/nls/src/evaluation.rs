@@ -0,0 +1,237 @@
+//! Background evaluation of the open documents.
+//!
+//! Parsing and typechecking only catch part of the errors of a configuration: contract
+//! violations, missing fields or incompatible merges are found by evaluating it. Evaluation may be
+//! slow or may not terminate, so it is performed by a worker thread under an evaluation
+//! [budget](nickel_lang::eval::budget), and the errors it finds are published as diagnostics.
+//!
+//! Documents are submitted again each time they change. A pending or running evaluation of a
+//! previous version of the same document is then cancelled.
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use codespan::FileId;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use log::{debug, trace};
+use lsp_server::Notification;
+use lsp_types::{PublishDiagnosticsParams, Url};
+use nickel_lang::{
+    cache::{Cache, Envs, ErrorTolerance},
+    error::{EvalError, ToDiagnostic},
+    eval::{
+        self,
+        budget::{self, Budget},
+        gc,
+    },
+};
+
+use crate::{
+    diagnostic::DiagnosticCompat,
+    fixes::{self, Fix},
+};
+
+/// The resources allotted to the evaluation of a document.
+#[derive(Debug, Clone)]
+pub struct EvalConfig {
+    /// The maximum duration of an evaluation.
+    pub timeout: Duration,
+    /// The maximum number of steps of an evaluation.
+    pub steps: usize,
+}
+
+/// A document to evaluate.
+struct Job {
+    uri: Url,
+    text: String,
+    /// Set when a newer version of the document has been submitted.
+    cancelled: Arc<AtomicBool>,
+}
+
+/// The stack size of the evaluation thread. Some parts of the evaluator, such as the merging of
+/// nested records or the forcing of deep values, recurse on the native stack. The evaluation of
+/// a document shouldn't be able to take the whole server down.
+const EVAL_STACK_SIZE: usize = 64 * 1024 * 1024;
+
+/// The handle to the evaluation thread, owned by the server.
+pub struct EvalWorker {
+    jobs: Sender<Job>,
+    /// The cancellation flag of the last submitted job of each document.
+    pending: HashMap<Url, Arc<AtomicBool>>,
+}
+
+impl EvalWorker {
+    /// Start the evaluation thread. `publish` is called from this thread to send the diagnostics
+    /// of an evaluation to the client.
+    pub fn spawn<F>(config: EvalConfig, publish: F) -> Self
+    where
+        F: Fn(Notification) + Send + 'static,
+    {
+        let (jobs, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name(String::from("evaluation"))
+            .stack_size(EVAL_STACK_SIZE)
+            .spawn(move || {
+                let evaluator = Evaluator::new(config);
+                // Documents are evaluated again and again by this thread, so the reference
+                // cycles they create must be collected. The thunks of the stdlib are created
+                // before, as they live as long as the server and needn't be registered.
+                gc::enable();
+                evaluator.run(receiver, publish)
+            })
+            .expect("failed to spawn the evaluation thread");
+
+        EvalWorker {
+            jobs,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Cancel the evaluation of the previous version of a document, and evaluate its new content
+    /// if `evaluate` is true. Documents with static errors are not evaluated, but their previous
+    /// evaluation still needs to be cancelled.
+    pub fn submit(&mut self, uri: Url, text: String, evaluate: bool) {
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        if let Some(previous) = self.pending.insert(uri.clone(), cancelled.clone()) {
+            previous.store(true, Ordering::Relaxed);
+        }
+
+        if evaluate {
+            // The worker only stops with the server, so there is nothing to do on failure.
+            let _ = self.jobs.send(Job {
+                uri,
+                text,
+                cancelled,
+            });
+        }
+    }
+}
+
+/// The state of the evaluation thread.
+struct Evaluator {
+    config: EvalConfig,
+    /// A cache holding the prepared standard library. Each document is evaluated in a fresh copy
+    /// of this cache, such that changes to imported files are taken into account.
+    stdlib: Cache,
+    envs: Envs,
+}
+
+impl Evaluator {
+    fn new(config: EvalConfig) -> Self {
+        let mut stdlib = Cache::new(ErrorTolerance::Strict);
+        let envs = stdlib.prepare_stdlib().unwrap();
+
+        Evaluator {
+            config,
+            stdlib,
+            envs,
+        }
+    }
+
+    fn run<F>(self, jobs: Receiver<Job>, publish: F)
+    where
+        F: Fn(Notification),
+    {
+        for job in jobs {
+            if job.cancelled.load(Ordering::Relaxed) {
+                trace!("Skipping stale evaluation of {}", job.uri);
+                continue;
+            }
+
+            let diagnostics = self.evaluate(&job);
+            // Recursive records and let-bindings of the document create reference cycles, which
+            // are not freed together with the cache of the evaluation.
+            gc::collect_cycles();
+
+            // The document may have changed while it was evaluated. Its new version has already
+            // been checked, and publishing would overwrite these diagnostics.
+            if job.cancelled.load(Ordering::Relaxed) || diagnostics.is_empty() {
+                continue;
+            }
+
+            publish(Notification::new(
+                "textDocument/publishDiagnostics".into(),
+                PublishDiagnosticsParams {
+                    uri: job.uri,
+                    diagnostics,
+                    version: None,
+                },
+            ));
+        }
+    }
+
+    /// Deep evaluate a document, and return the diagnostics of the evaluation error, if any.
+    fn evaluate(&self, job: &Job) -> Vec<lsp_types::Diagnostic> {
+        debug!("Evaluating {}", job.uri);
+        let mut cache = self.stdlib.clone();
+
+        // Imports are resolved relatively to the name of the document, so we use its path when
+        // possible.
+        let name = job
+            .uri
+            .to_file_path()
+            .map(PathBuf::into_os_string)
+            .unwrap_or_else(|_| job.uri.as_str().into());
+        let file_id = cache.add_string(name, job.text.clone());
+
+        // Static errors are reported by the server itself.
+        if cache.prepare(file_id, &self.envs.type_env).is_err() {
+            return Vec::new();
+        }
+        let term = cache.get_owned(file_id).unwrap();
+
+        let budget = Budget::new()
+            .with_steps(self.config.steps)
+            .with_timeout(self.config.timeout)
+            .with_cancellation(job.cancelled.clone());
+        let result = budget::with_budget(budget, || {
+            eval::eval_deep(term, &self.envs.eval_env, &mut cache)
+        });
+
+        match result {
+            Ok(_) => Vec::new(),
+            Err(EvalError::Interrupted(reason, _)) => {
+                debug!("Evaluation of {} interrupted: {}", job.uri, reason);
+                Vec::new()
+            }
+            Err(error) => {
+                let fixes = Fix::from_eval_error(&error, file_id, cache.files());
+                let contract_id = cache.id_of("<stdlib/contract.ncl>");
+                let mut diagnostics: Vec<_> = error
+                    .to_diagnostic(cache.files_mut(), contract_id)
+                    .into_iter()
+                    .flat_map(|diagnostic| {
+                        lsp_types::Diagnostic::from_codespan(
+                            in_document(diagnostic, file_id),
+                            cache.files_mut(),
+                        )
+                    })
+                    .collect();
+                fixes::attach(&mut diagnostics, &fixes);
+                diagnostics
+            }
+        }
+    }
+}
+
+/// Only keep the labels of a diagnostic pointing to the evaluated document, as the diagnostics are
+/// published for this document only. If none is left, the diagnostic is attached to the beginning
+/// of the document, so that the error is still shown.
+fn in_document(mut diagnostic: Diagnostic<FileId>, file_id: FileId) -> Diagnostic<FileId> {
+    diagnostic.labels.retain(|label| label.file_id == file_id);
+
+    if diagnostic.labels.is_empty() {
+        diagnostic.labels.push(Label::primary(file_id, 0..0));
+    }
+
+    diagnostic
+}
@@ -1,23 +1,43 @@
-use anyhow::Result;
+use std::{collections::HashSet, time::Instant};
+
+use anyhow::{anyhow, bail, Result};
 use codespan::FileId;
-use codespan_reporting::diagnostic::Diagnostic;
+use codespan_lsp::position_to_byte_index;
 use log::trace;
 use lsp_server::RequestId;
 use lsp_types::{
-    notification::{DidOpenTextDocument, Notification},
-    DidChangeTextDocumentParams, DidOpenTextDocumentParams, PublishDiagnosticsParams, Url,
+    notification::{DidChangeTextDocument, DidOpenTextDocument, Notification},
+    DidChangeTextDocumentParams, DidOpenTextDocumentParams, PublishDiagnosticsParams,
+    TextDocumentContentChangeEvent, Url,
 };
 use nickel_lang::{
-    cache::{CacheError, CacheOp},
-    error::ToDiagnostic,
+    cache::{CacheError, CacheOp, EntryState},
+    error::{ToDiagnostic, TypecheckError},
 };
 
-use crate::trace::{param::FileUpdate, Enrich, Trace};
+use crate::{
+    fixes::{self, Fix},
+    trace::{
+        param::{Changes, FileUpdate},
+        Enrich, Trace,
+    },
+};
 
 use super::cache::CacheExt;
 use super::diagnostic::DiagnosticCompat;
 use super::server::Server;
 
+/// The method under which analyses of documents are traced.
+const ANALYSIS: &str = "nls/analysis";
+
+/// A document changed since its last analysis.
+pub struct Pending {
+    file_id: FileId,
+    /// The number of changes since the last analysis.
+    changes: usize,
+    pub last_change: Instant,
+}
+
 pub fn handle_open(server: &mut Server, params: DidOpenTextDocumentParams) -> Result<()> {
     let id: RequestId = format!(
         "{}#{}",
@@ -32,75 +52,211 @@ pub fn handle_open(server: &mut Server, params: DidOpenTextDocumentParams) -> Re
             content: &params.text_document.text,
         },
     );
+    // The document may already be known to the cache as the import of another document.
     let file_id = server
         .cache
-        .add_string(params.text_document.uri.as_str(), params.text_document.text);
+        .update_content(params.text_document.uri.as_str(), params.text_document.text)?;
 
+    // A pending analysis of a previous version is superseded.
+    server.pending.remove(&params.text_document.uri);
     parse_and_typecheck(server, params.text_document.uri, file_id)?;
+    analyze_importers(server)?;
     Trace::reply(id);
     Ok(())
 }
 
-pub fn handle_save(server: &mut Server, params: DidChangeTextDocumentParams) -> Result<()> {
-    let id: RequestId = format!(
-        "{}#{}",
-        params.text_document.uri, params.text_document.version
-    )
-    .into();
+pub fn handle_change(server: &mut Server, params: DidChangeTextDocumentParams) -> Result<()> {
+    let uri = params.text_document.uri;
+    let id: RequestId = format!("{}#{}", uri, params.text_document.version).into();
 
-    Trace::receive(id.clone(), DidOpenTextDocument::METHOD);
+    Trace::receive(id.clone(), DidChangeTextDocument::METHOD);
+
+    let file_id = apply_changes(server, &uri, params.content_changes)?;
     Trace::enrich(
         &id,
         FileUpdate {
-            content: &params.content_changes[0].text,
+            content: server.cache.files().source(file_id),
         },
     );
 
-    let file_id = server.cache.update_content(
-        params.text_document.uri.as_str(),
-        params.content_changes[0].text.to_owned(),
-    )?;
+    // The analysis is delayed until the document stops changing, see `analyze_pending`.
+    let pending = server.pending.entry(uri).or_insert(Pending {
+        file_id,
+        changes: 0,
+        last_change: Instant::now(),
+    });
+    pending.changes += 1;
+    pending.last_change = Instant::now();
 
-    // TODO: make this part more abstracted
-    //       implement typecheck (at least) as part of a persistent AST representation
-    //       for now execute the same as above for handling `open` notifications
-    parse_and_typecheck(server, params.text_document.uri, file_id)?;
     Trace::reply(id);
     Ok(())
 }
 
-fn typecheck(server: &mut Server, file_id: FileId) -> Result<CacheOp<()>, Vec<Diagnostic<FileId>>> {
+/// Apply the changes of a document in order, the range of each change being relative to the
+/// content resulting from the previous ones.
+fn apply_changes(
+    server: &mut Server,
+    uri: &Url,
+    changes: Vec<TextDocumentContentChangeEvent>,
+) -> Result<FileId> {
+    let mut file_id = server.cache.id_of(uri.as_str());
+
+    for change in changes {
+        let content = match (change.range, file_id) {
+            (Some(range), Some(file_id)) => {
+                let files = server.cache.files();
+                // Lines past the end of the document make `position_to_byte_index` panic
+                let lines = files.source(file_id).matches('\n').count() as u32 + 1;
+                if range.start.line >= lines || range.end.line >= lines {
+                    bail!("Invalid change of {}: {:?} is out of bounds", uri, range);
+                }
+                let start = position_to_byte_index(files, file_id, &range.start)
+                    .map_err(|err| anyhow!("Invalid change of {}: {:?}", uri, err))?;
+                let end = position_to_byte_index(files, file_id, &range.end)
+                    .map_err(|err| anyhow!("Invalid change of {}: {:?}", uri, err))?;
+                let mut content = files.source(file_id).clone();
+                content.replace_range(start..end, &change.text);
+                content
+            }
+            (Some(_), None) => bail!("Incremental change of unknown document {}", uri),
+            (None, _) => change.text,
+        };
+
+        file_id = Some(server.cache.update_content(uri.as_str(), content)?);
+    }
+
+    file_id.ok_or_else(|| anyhow!("No content for unknown document {}", uri))
+}
+
+/// Analyze the documents changed since their last analysis. Unless `all` is set, only the
+/// documents which haven't changed for the debounce delay of the server are analyzed, such that
+/// a burst of changes is analyzed once.
+///
+/// Each analysis parses, typechecks and linearizes the whole document again: the linearization
+/// and the types of the top-level fields which didn't change are not reused. Reusing them would
+/// require shifting the positions of every following item, and typechecking again the fields
+/// which depend on a changed one. Debouncing already makes a burst of changes cost a single
+/// analysis, whose duration is traced under the `nls/analysis` method.
+pub fn analyze_pending(server: &mut Server, all: bool) -> Result<()> {
+    let now = Instant::now();
+    let debounce = server.debounce;
+    let ready: Vec<Url> = server
+        .pending
+        .iter()
+        .filter(|(_, pending)| all || pending.last_change + debounce <= now)
+        .map(|(uri, _)| uri.clone())
+        .collect();
+
+    for uri in ready {
+        analyze_document(server, &uri)?;
+    }
+
+    Ok(())
+}
+
+/// Analyze a document if it changed since its last analysis, regardless of the debounce delay.
+pub fn analyze_document(server: &mut Server, uri: &Url) -> Result<()> {
+    let Pending {
+        file_id, changes, ..
+    } = match server.pending.remove(uri) {
+        Some(pending) => pending,
+        None => return Ok(()),
+    };
+
+    let id: RequestId = format!("{}#analysis", uri).into();
+    Trace::receive(id.clone(), ANALYSIS);
+    Trace::enrich(
+        &id,
+        FileUpdate {
+            content: server.cache.files().source(file_id),
+        },
+    );
+    Trace::enrich(&id, Changes(changes));
+
+    parse_and_typecheck(server, uri.clone(), file_id)?;
+
+    if let Some(linearization) = server.lin_cache.get(&file_id) {
+        Trace::enrich(&id, linearization);
+    }
+    analyze_importers(server)?;
+    Trace::reply(id);
+
+    Ok(())
+}
+
+fn typecheck(
+    server: &mut Server,
+    file_id: FileId,
+) -> Result<CacheOp<()>, Vec<lsp_types::Diagnostic>> {
     server
         .cache
         .typecheck_with_analysis(file_id, &server.initial_env, &mut server.lin_cache)
         .map_err(|error| match error {
-            CacheError::Error(tc_error) => tc_error.to_diagnostic(server.cache.files_mut(), None),
+            CacheError::Error(tc_error) => typecheck_diagnostics(server, &tc_error, file_id),
             CacheError::NotParsed => unreachable!(),
         })
 }
 
+/// Convert a typechecking error to diagnostics carrying its fixes. Each error reported at once is
+/// converted separately, such that its fixes are only attached to its own diagnostics.
+fn typecheck_diagnostics(
+    server: &mut Server,
+    error: &TypecheckError,
+    file_id: FileId,
+) -> Vec<lsp_types::Diagnostic> {
+    if let TypecheckError::Multiple(errors) = error {
+        return errors
+            .iter()
+            .flat_map(|error| typecheck_diagnostics(server, error, file_id))
+            .collect();
+    }
+
+    let fixes = Fix::from_typecheck_error(error, file_id, server.cache.files());
+    let mut diagnostics: Vec<_> = error
+        .to_diagnostic(server.cache.files_mut(), None)
+        .into_iter()
+        .flat_map(|d| lsp_types::Diagnostic::from_codespan(d, server.cache.files_mut()))
+        .collect();
+    fixes::attach(&mut diagnostics, &fixes);
+    diagnostics
+}
+
 fn parse_and_typecheck(server: &mut Server, uri: Url, file_id: FileId) -> Result<()> {
+    // Typechecking errors are converted separately, as they carry quick fixes
+    let mut typecheck_diagnostics = Vec::new();
     let diagnostics = server
         .cache
         .parse(file_id)
         .map_err(|parse_err| parse_err.to_diagnostic(server.cache.files_mut(), None))
         .map(|parse_errs| {
             // Parse errors are not fatal
-            let mut d = parse_errs
+            let d = parse_errs
                 .inner()
                 .to_diagnostic(server.cache.files_mut(), None);
+            // The imports are typechecked first, such that their interface types are known
+            server
+                .cache
+                .linearize_imports(file_id, &server.initial_env, &mut server.lin_cache);
             trace!("Parsed, checking types");
-            let _ = typecheck(server, file_id).map_err(|mut ty_d| d.append(&mut ty_d));
+            let _ = typecheck(server, file_id).map_err(|ty_d| typecheck_diagnostics = ty_d);
             d
         })
         .unwrap_or_else(|d| d);
 
-    let diagnostics = diagnostics
+    let diagnostics: Vec<_> = diagnostics
         .into_iter()
         .map(|d| lsp_types::Diagnostic::from_codespan(d, server.cache.files_mut()))
         .flatten()
+        .chain(typecheck_diagnostics)
         .collect();
 
+    // Only documents without static errors are evaluated, but the pending evaluation of a
+    // previous version must be cancelled in any case.
+    if let Some(eval) = server.eval.as_mut() {
+        let text = server.cache.files().source(file_id).clone();
+        eval.submit(uri.clone(), text, diagnostics.is_empty());
+    }
+
     server.notify(lsp_server::Notification::new(
         "textDocument/publishDiagnostics".into(),
         PublishDiagnosticsParams {
@@ -112,3 +268,210 @@ fn parse_and_typecheck(server: &mut Server, uri: Url, file_id: FileId) -> Result
 
     Ok(())
 }
+
+/// Analyze again the files importing a file whose interface type has changed, which the cache
+/// resets to the [EntryState::ImportsResolved] state, such that they are typechecked against
+/// the new interface. A change of the interface of an importer is propagated in turn, but each
+/// file is analyzed at most once, which avoids looping on import cycles.
+fn analyze_importers(server: &mut Server) -> Result<()> {
+    let mut analyzed = HashSet::new();
+
+    loop {
+        let invalidated: Vec<FileId> = server
+            .cache
+            .terms()
+            .keys()
+            .filter(|file_id| {
+                !analyzed.contains(*file_id)
+                    && server.cache.entry_state(**file_id) == Some(EntryState::ImportsResolved)
+            })
+            .copied()
+            .collect();
+
+        if invalidated.is_empty() {
+            return Ok(());
+        }
+
+        for file_id in invalidated {
+            analyzed.insert(file_id);
+            let uri = Url::parse(&server.cache.name(file_id).to_string_lossy())?;
+            parse_and_typecheck(server, uri, file_id)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use lsp_types::{
+        request::{DocumentLinkRequest, HoverRequest, Rename},
+        DocumentLinkParams, HoverParams, PartialResultParams, Position, Range, RenameParams,
+        TextDocumentIdentifier, VersionedTextDocumentIdentifier, WorkDoneProgressParams,
+    };
+
+    use super::*;
+    use crate::{
+        linearization::interface::TermKind,
+        testing::{position_params, uri, TestServer},
+    };
+
+    fn edit(line: u32, start: u32, end: u32, text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(Range::new(
+                Position::new(line, start),
+                Position::new(line, end),
+            )),
+            range_length: None,
+            text: text.to_owned(),
+        }
+    }
+
+    fn change(
+        server: &mut TestServer,
+        uri: &Url,
+        changes: Vec<TextDocumentContentChangeEvent>,
+    ) -> Result<()> {
+        handle_change(
+            &mut server.server,
+            DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier {
+                    uri: uri.clone(),
+                    version: 1,
+                },
+                content_changes: changes,
+            },
+        )
+    }
+
+    fn source(server: &TestServer, uri: &Url) -> String {
+        let file_id = server.server.cache.id_of(uri.as_str()).unwrap();
+        server.server.cache.files().source(file_id).clone()
+    }
+
+    /// The names declared in the last linearization of a document.
+    fn declarations(server: &TestServer, uri: &Url) -> Vec<String> {
+        let file_id = server.server.cache.id_of(uri.as_str()).unwrap();
+        server.server.lin_cache[&file_id]
+            .linearization
+            .iter()
+            .filter_map(|item| match &item.kind {
+                TermKind::Declaration(ident, ..) => Some(ident.label.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn hover(server: &mut TestServer, uri: &Url) {
+        server.request::<HoverRequest>(HoverParams {
+            text_document_position_params: position_params(uri, 0, 0),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        });
+    }
+
+    #[test]
+    fn incremental_changes() {
+        let mut server = TestServer::new();
+        let main = uri("main.ncl");
+        server.open(&main, "let x = 1 in\nx");
+
+        change(
+            &mut server,
+            &main,
+            vec![
+                edit(0, 8, 9, "2"),
+                edit(1, 1, 1, " + 1"),
+                edit(0, 4, 5, "y"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(source(&server, &main), "let y = 2 in\nx + 1");
+
+        // Some clients send the whole content of the document.
+        server.change(&main, "let z = 3 in\nz");
+        assert_eq!(source(&server, &main), "let z = 3 in\nz");
+    }
+
+    #[test]
+    fn invalid_change() {
+        let mut server = TestServer::new();
+        let main = uri("main.ncl");
+        server.open(&main, "let x = 1 in\nx");
+
+        assert!(change(&mut server, &main, vec![edit(2, 0, 0, "x")]).is_err());
+        assert!(change(&mut server, &main, vec![edit(0, 20, 20, "x")]).is_err());
+        assert!(change(&mut server, &uri("unknown.ncl"), vec![edit(0, 0, 0, "x")]).is_err());
+        assert_eq!(source(&server, &main), "let x = 1 in\nx");
+    }
+
+    #[test]
+    fn debounced_analysis() {
+        let mut server = TestServer::new();
+        server.server.debounce = Duration::from_secs(3600);
+        let main = uri("main.ncl");
+        server.open(&main, "let x = 1 in x");
+
+        server.change(&main, "let y = 1 in y");
+        server.change(&main, "let z = 1 in z");
+        analyze_pending(&mut server.server, false).unwrap();
+        assert_eq!(server.server.pending[&main].changes, 2);
+        assert_eq!(declarations(&server, &main), vec!["x"]);
+
+        analyze_pending(&mut server.server, true).unwrap();
+        assert!(server.server.pending.is_empty());
+        assert_eq!(declarations(&server, &main), vec!["z"]);
+    }
+
+    #[test]
+    fn requests_analyze_their_document() {
+        let mut server = TestServer::new();
+        let (main, other) = (uri("main.ncl"), uri("other.ncl"));
+        server.open(&main, "let x = 1 in x");
+        server.open(&other, "let x = 1 in x");
+        server.change(&main, "let y = 1 in y");
+        server.change(&other, "let y = 1 in y");
+
+        // Document links only lex the current content.
+        server.request::<DocumentLinkRequest>(DocumentLinkParams {
+            text_document: TextDocumentIdentifier { uri: main.clone() },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        });
+        assert_eq!(server.server.pending.len(), 2);
+
+        hover(&mut server, &main);
+        assert!(!server.server.pending.contains_key(&main));
+        assert!(server.server.pending.contains_key(&other));
+        assert_eq!(declarations(&server, &main), vec!["y"]);
+        assert_eq!(declarations(&server, &other), vec!["x"]);
+
+        // Renaming may edit any document.
+        server.request::<Rename>(RenameParams {
+            text_document_position: position_params(&main, 0, 4),
+            new_name: String::from("z"),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        });
+        assert!(server.server.pending.is_empty());
+        assert_eq!(declarations(&server, &other), vec!["y"]);
+    }
+
+    #[test]
+    fn importers_follow_interface_changes() {
+        let mut server = TestServer::new();
+        let (main, lib) = (uri("main.ncl"), uri("lib.ncl"));
+        // Without the interface of `lib`, its fields can't be accessed in typed code.
+        server.open(&main, "let lib = import \"lib.ncl\" in (lib.x : Num)");
+        assert_ne!(server.diagnostics(&main), Vec::new());
+
+        server.open(&lib, "{x = 1} : {x : Num}");
+        assert_eq!(server.diagnostics(&main), Vec::new());
+
+        server.change(&lib, "{x = \"a\"} : {x : Str}");
+        analyze_pending(&mut server.server, true).unwrap();
+        assert_ne!(server.diagnostics(&main), Vec::new());
+
+        server.change(&lib, "{x = 2} : {x : Num}");
+        analyze_pending(&mut server.server, true).unwrap();
+        assert_eq!(server.diagnostics(&main), Vec::new());
+    }
+}
@@ -0,0 +1,262 @@
+//! Quick fixes for the errors reported as diagnostics.
+//!
+//! The fixes of an error are computed when the error is converted to diagnostics, as this is when
+//! its spans are available, and attached to the `data` field of the diagnostics. Clients send the
+//! diagnostics back when requesting code actions for a range, which are then built from these
+//! fixes (see [crate::requests::code_actions]). This works the same for the errors found by the
+//! server and for the errors found by the evaluation thread, which uses its own cache.
+use codespan::{FileId, Files};
+use codespan_lsp::byte_index_to_position;
+use lsp_types::{Position, Range, TextEdit};
+use nickel_lang::{
+    error::{EvalError, TypecheckError},
+    identifier::Ident,
+    position::{RawSpan, TermPos},
+    term::{RichTerm, Term},
+    types::{AbsType, Types},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::requests::completion::row_fields;
+
+/// The prefix of the blame message of record contracts for a missing field, see
+/// `$record_extend` in the contracts of the standard library.
+const MISSING_FIELD_TAG: &str = "missing field `";
+
+/// The maximum length of the source of a value shown in the title of a fix.
+const MAX_EXCERPT_WIDTH: usize = 24;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Fix {
+    /// A fix whose edits are known when the error is reported.
+    Edit { title: String, edits: Vec<TextEdit> },
+    /// Replace an unbound identifier by a similar identifier in scope. The candidates depend on
+    /// the linearization of the document, and are found when code actions are requested.
+    Unbound { name: String, range: Range },
+}
+
+impl Fix {
+    /// The fixes of an error found while typechecking the document `file_id`.
+    pub fn from_typecheck_error(
+        error: &TypecheckError,
+        file_id: FileId,
+        files: &Files<String>,
+    ) -> Vec<Fix> {
+        match error {
+            TypecheckError::Multiple(errors) => errors
+                .iter()
+                .flat_map(|error| Fix::from_typecheck_error(error, file_id, files))
+                .collect(),
+            TypecheckError::UnboundIdentifier(ident, pos) => {
+                unbound(ident, *pos, file_id, files).into_iter().collect()
+            }
+            TypecheckError::MissingRow(ident, expected, _, pos) => {
+                let ty = row_fields(expected)
+                    .into_iter()
+                    .find(|(id, ..)| id == ident)
+                    .map(|(_, ty, _)| ty);
+                add_field(ident, ty.as_ref(), *pos, file_id, files)
+                    .into_iter()
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// The fixes of an error found while evaluating the document `file_id`.
+    pub fn from_eval_error(error: &EvalError, file_id: FileId, files: &Files<String>) -> Vec<Fix> {
+        match error {
+            EvalError::UnboundIdentifier(ident, pos) => {
+                unbound(ident, *pos, file_id, files).into_iter().collect()
+            }
+            // A record contract reporting a missing field, such as `{} | {foo : Num}`
+            EvalError::BlameError(label, _) if label.tag.starts_with(MISSING_FIELD_TAG) => {
+                let field = label.tag[MISSING_FIELD_TAG.len()..].trim_end_matches('`');
+                let ident = Ident::from(field);
+                let ty = row_fields(&label.types)
+                    .into_iter()
+                    .find(|(id, ..)| *id == ident)
+                    .map(|(_, ty, _)| ty);
+                add_field(&ident, ty.as_ref(), label.arg_pos, file_id, files)
+                    .into_iter()
+                    .collect()
+            }
+            EvalError::MergeIncompatibleArgs(left, right, _) => [left, right]
+                .iter()
+                .filter_map(|value| make_default(value, file_id, files))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Attach fixes to the diagnostics of an error.
+pub fn attach(diagnostics: &mut [lsp_types::Diagnostic], fixes: &[Fix]) {
+    if fixes.is_empty() {
+        return;
+    }
+
+    for diagnostic in diagnostics {
+        diagnostic.data = serde_json::to_value(fixes).ok();
+    }
+}
+
+/// Read the fixes attached to a diagnostic, if any.
+pub fn attached(diagnostic: &lsp_types::Diagnostic) -> Vec<Fix> {
+    diagnostic
+        .data
+        .clone()
+        .and_then(|data| serde_json::from_value(data).ok())
+        .unwrap_or_default()
+}
+
+/// The span of a position, if it is a position of the document as written by the user.
+fn original_span(pos: TermPos, file_id: FileId) -> Option<RawSpan> {
+    match pos {
+        TermPos::Original(span) if span.src_id == file_id => Some(span),
+        _ => None,
+    }
+}
+
+fn position(files: &Files<String>, file_id: FileId, index: usize) -> Option<Position> {
+    byte_index_to_position(files, file_id, index).ok()
+}
+
+fn unbound(ident: &Ident, pos: TermPos, file_id: FileId, files: &Files<String>) -> Option<Fix> {
+    let span = original_span(pos, file_id)?;
+
+    Some(Fix::Unbound {
+        name: ident.label.clone(),
+        range: Range {
+            start: position(files, file_id, span.start.to_usize())?,
+            end: position(files, file_id, span.end.to_usize())?,
+        },
+    })
+}
+
+/// Add the definition of a field to the record literal at `pos`. The field is defined with a
+/// placeholder value of type `ty`, if it is known.
+fn add_field(
+    ident: &Ident,
+    ty: Option<&Types>,
+    pos: TermPos,
+    file_id: FileId,
+    files: &Files<String>,
+) -> Option<Fix> {
+    let span = original_span(pos, file_id)?;
+    let record = files
+        .source(file_id)
+        .get(span.start.to_usize()..span.end.to_usize())?;
+
+    if !(record.starts_with('{') && record.ends_with('}')) {
+        return None;
+    }
+
+    let fields = record[1..record.len() - 1].trim_end();
+    let definition = format!("{} = {}", field_name(ident), placeholder(ty));
+    let fields_start = span.start.to_usize() + 1;
+    let fields_end = fields_start + fields.len();
+
+    let (start, end, new_text) = if fields.trim_start().is_empty() {
+        // Replace the content of an empty record
+        (
+            fields_start,
+            span.end.to_usize() - 1,
+            format!(" {} ", definition),
+        )
+    } else if fields.ends_with(',') {
+        (fields_end, fields_end, format!(" {},", definition))
+    } else {
+        (fields_end, fields_end, format!(", {}", definition))
+    };
+
+    Some(Fix::Edit {
+        title: format!("Add the missing field `{}`", ident),
+        edits: vec![TextEdit {
+            range: Range {
+                start: position(files, file_id, start)?,
+                end: position(files, file_id, end)?,
+            },
+            new_text,
+        }],
+    })
+}
+
+/// Quote a field name if it isn't a valid identifier.
+fn field_name(ident: &Ident) -> String {
+    let name = &ident.label;
+    let valid = name
+        .chars()
+        .next()
+        .map(|c| c.is_ascii_alphabetic() || c == '_')
+        .unwrap_or(false)
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '\''));
+
+    if valid {
+        name.clone()
+    } else {
+        format!("{:?}", name)
+    }
+}
+
+/// A value of the given type, to be replaced by the user.
+fn placeholder(ty: Option<&Types>) -> &'static str {
+    match ty {
+        Some(Types(AbsType::Num())) => "0",
+        Some(Types(AbsType::Str())) => "\"\"",
+        Some(Types(AbsType::Bool())) => "false",
+        Some(Types(AbsType::Array(_))) => "[]",
+        Some(Types(AbsType::StaticRecord(_) | AbsType::DynRecord(_))) => "{}",
+        _ => "null",
+    }
+}
+
+/// The beginning of the source of a value, to be shown in the title of a fix.
+fn excerpt(source: &str) -> String {
+    let line = source.lines().next().unwrap_or_default();
+
+    if line.len() < source.len() || line.chars().count() > MAX_EXCERPT_WIDTH {
+        let cut: String = line.chars().take(MAX_EXCERPT_WIDTH).collect();
+        format!("{}…", cut.trim_end())
+    } else {
+        line.to_owned()
+    }
+}
+
+/// Give the default priority to one of the values of an incompatible merge, by annotating it with
+/// `| default`.
+fn make_default(value: &RichTerm, file_id: FileId, files: &Files<String>) -> Option<Fix> {
+    let span = original_span(value.pos, file_id)?;
+    let source = files
+        .source(file_id)
+        .get(span.start.to_usize()..span.end.to_usize())?;
+
+    // The body of a function would extend over the annotation.
+    let (before, after) = match value.as_ref() {
+        Term::Fun(..) | Term::FunPattern(..) => ("(", ") | default"),
+        _ => ("", " | default"),
+    };
+
+    let start = position(files, file_id, span.start.to_usize())?;
+    let end = position(files, file_id, span.end.to_usize())?;
+
+    Some(Fix::Edit {
+        title: format!("Make `{}` a default value", excerpt(source)),
+        edits: vec![
+            TextEdit {
+                range: Range { start, end: start },
+                new_text: before.to_owned(),
+            },
+            TextEdit {
+                range: Range { start: end, end },
+                new_text: after.to_owned(),
+            },
+        ]
+        .into_iter()
+        .filter(|edit| !edit.new_text.is_empty())
+        .collect(),
+    })
+}
@@ -3,7 +3,7 @@ use std::{collections::HashMap, mem};
 use log::debug;
 use nickel_lang::{
     identifier::Ident,
-    term::{MetaValue, RichTerm, Term},
+    term::{MergePriority, MetaValue, RichTerm, Term},
     typecheck::{
         linearization::{LinearizationState, Scope},
         TypeWrapper,
@@ -55,6 +55,7 @@ impl Building {
             TermKind::Structure => unreachable!(),
             TermKind::Usage(_) => unreachable!(),
             TermKind::Record(_) => unreachable!(),
+            TermKind::Import(_) => unreachable!(),
             TermKind::Declaration(_, ref mut usages, _)
             | TermKind::RecordField { ref mut usages, .. } => usages.push(usage),
         };
@@ -93,7 +94,11 @@ impl Building {
                 scope: scope.clone(),
                 meta: match value.term.as_ref() {
                     Term::MetaValue(meta @ MetaValue { .. }) => Some(MetaValue {
-                        value: None,
+                        // Default values are kept to be suggested by completion
+                        value: match meta.priority {
+                            MergePriority::Default => meta.value.clone(),
+                            MergePriority::Normal => None,
+                        },
                         ..meta.clone()
                     }),
                     _ => None,
@@ -191,6 +196,34 @@ impl Building {
                 continue;
             }
 
+            // fields of an imported file are recorded together with the import, to be resolved
+            // against the imported file later
+            let imported = match parent_referenced.map(|parent| &parent.kind) {
+                Some(TermKind::Usage(UsageState::Imported { import, fields })) => {
+                    Some((*import, fields.clone()))
+                }
+                _ => match parent_declaration {
+                    Some(LinearizationItem {
+                        id,
+                        kind: TermKind::Import(_),
+                        ..
+                    }) => Some((*id, Vec::new())),
+                    _ => None,
+                },
+            };
+
+            if let Some((import, mut fields)) = imported {
+                fields.push(child_ident.clone());
+                debug!("Associating child {} to import {}", child_ident, import);
+                self.linearization.get_mut(*child_item).unwrap().kind =
+                    TermKind::Usage(UsageState::Imported { import, fields });
+
+                if defers.is_empty() && !unresolved.is_empty() {
+                    defers = mem::take(&mut unresolved);
+                }
+                continue;
+            }
+
             let referenced_declaration = parent_declaration
                 // resolve indirection by following the usage
                 .and_then(|parent_declaration| self.resolve_reference(parent_declaration))
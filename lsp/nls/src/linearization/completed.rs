@@ -4,6 +4,7 @@ use codespan::ByteIndex;
 use nickel_lang::{
     term::MetaValue,
     typecheck::linearization::{LinearizationState, Scope},
+    types::{AbsType, Types},
 };
 
 use super::{
@@ -114,6 +115,13 @@ impl Completed {
     ) -> (Resolved, Vec<String>) {
         let mut extra = Vec::new();
 
+        // Usages have a type only if the typechecker has refined the type of the variable, such
+        // as in a branch testing its type. Otherwise, we use the type of the declaration.
+        let refined_ty = match item.kind {
+            TermKind::Usage(_) if item.ty != Types(AbsType::Dyn()) => Some(item.ty.clone()),
+            _ => None,
+        };
+
         let item = match item.kind {
             TermKind::Usage(UsageState::Resolved(declaration)) => self
                 .get_item(declaration)
@@ -159,7 +167,7 @@ impl Completed {
             extra.push(format!("Merge Priority: {:?}", priority));
         }
 
-        (item.ty.to_owned(), extra)
+        (refined_ty.unwrap_or_else(|| item.ty.to_owned()), extra)
     }
 }
 
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, ffi::OsString};
 
 use nickel_lang::{identifier::Ident, typecheck::TypeWrapper, types::Types};
 
@@ -20,7 +20,8 @@ impl ResolutionState for Resolved {}
 /// 1. Declarations
 /// 2. Usages
 /// 3. Records, listing their fields
-/// 4. wildcard (Structure) for any other kind of term.
+/// 4. Imports, recording the imported path
+/// 5. wildcard (Structure) for any other kind of term.
 /// Can be extended later to represent Contracts, Records, etc.
 #[derive(Debug, Clone, PartialEq)]
 pub enum TermKind {
@@ -33,6 +34,7 @@ pub enum TermKind {
         usages: Vec<ID>,
         value: ValueState,
     },
+    Import(OsString),
     Structure,
 }
 
@@ -56,7 +58,17 @@ impl ValueState {
 pub enum UsageState {
     Unbound,
     Resolved(ID),
-    Deferred { parent: ID, child: Ident },
+    Deferred {
+        parent: ID,
+        child: Ident,
+    },
+    /// Accesses to the fields of an imported file can't be resolved within the importing file.
+    /// Instead, we record the import and the path of the accessed field inside the imported
+    /// value, e.g. `["server", "port"]` for `lib.server.port` where `lib = import "lib.ncl"`.
+    Imported {
+        import: ID,
+        fields: Vec<Ident>,
+    },
 }
 
 impl From<Option<ID>> for UsageState {
@@ -1,6 +1,6 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, ffi::OsString, rc::Rc};
 
-use codespan::ByteIndex;
+use codespan::{ByteIndex, FileId};
 use log::debug;
 use nickel_lang::{
     identifier::Ident,
@@ -68,11 +68,21 @@ pub struct AnalysisHost {
     /// are recorded first until a variable (`outer`). is found.
     /// Then, access to all nested records are resolved at once.
     access: Option<Vec<Ident>>,
+    /// The variables whose type has been refined by the typechecker in the current scope, such as
+    /// `x` in the `then` branch of `if builtin.is_num x then .. else ..`.
+    refined: HashMap<Ident, TypeWrapper>,
+    /// The path of the imports resolved before typechecking, such that they are linearized as the
+    /// imports written in the document.
+    imports: Rc<HashMap<FileId, OsString>>,
 }
 
 impl AnalysisHost {
-    pub fn new() -> Self {
-        Default::default()
+    /// A host for a term whose imports `imports`, given with their path, have been resolved.
+    pub fn with_imports(imports: HashMap<FileId, OsString>) -> Self {
+        AnalysisHost {
+            imports: Rc::new(imports),
+            ..Default::default()
+        }
     }
 }
 
@@ -86,6 +96,8 @@ impl Default for AnalysisHost {
             record_fields: Default::default(),
             let_binding: Default::default(),
             access: Default::default(),
+            refined: Default::default(),
+            imports: Default::default(),
         }
     }
 }
@@ -113,6 +125,19 @@ impl Linearizer for AnalysisHost {
             term,
             Term::Op1(UnaryOp::StaticAccess(_), _) | Term::MetaValue(_)
         ) {
+            // The value of a record access `outer.middle.inner` is the item of its last accessor,
+            // which is pushed after the variable and the previous accessors
+            let usage_offset = if matches!(term, Term::Var(_)) {
+                debug!(
+                    "associating value {:?} with chain {:?}",
+                    term,
+                    self.access.as_ref()
+                );
+                self.access.as_ref().map(|v| v.len()).unwrap_or(0)
+            } else {
+                0
+            };
+
             if let Some((record, (offset, Ident { pos: field_pos, .. }))) = self
                 .record_fields
                 .take()
@@ -126,16 +151,6 @@ impl Linearizer for AnalysisHost {
 
                 for field in lin.linearization.get_mut(record + offset).into_iter() {
                     debug!("{:?}", field.kind);
-                    let usage_offset = if matches!(term, Term::Var(_)) {
-                        debug!(
-                            "associating nested field {:?} with chain {:?}",
-                            term,
-                            self.access.as_ref()
-                        );
-                        self.access.as_ref().map(|v| v.len()).unwrap_or(0)
-                    } else {
-                        0
-                    };
                     match field.kind {
                         TermKind::RecordField { ref mut value, .. } => {
                             *value = ValueState::Known(id_gen.get() + usage_offset);
@@ -148,7 +163,7 @@ impl Linearizer for AnalysisHost {
             }
 
             if let Some(declaration) = self.let_binding.take() {
-                lin.inform_declaration(declaration, id_gen.get());
+                lin.inform_declaration(declaration, id_gen.get() + usage_offset);
             }
         }
 
@@ -183,6 +198,7 @@ impl Linearizer for AnalysisHost {
                     };
 
                     let id = id_gen.get_and_advance();
+                    self.refined.remove(ident);
                     self.env.insert(ident.to_owned(), id);
                     lin.push(LinearizationItem {
                         id,
@@ -196,6 +212,7 @@ impl Linearizer for AnalysisHost {
                 for matched in destruct.to_owned().inner() {
                     let (ident, term) = matched.as_meta_field();
                     let id = id_gen.get_and_advance();
+                    self.refined.remove(&ident);
                     self.env.insert(ident.to_owned(), id);
                     lin.push(LinearizationItem {
                         id,
@@ -240,6 +257,8 @@ impl Linearizer for AnalysisHost {
                     }
                     _ => unreachable!(),
                 };
+                // A new declaration shadows the refined type of a previous one.
+                self.refined.remove(ident);
                 self.env.insert(ident.to_owned(), id_gen.get());
                 lin.push(LinearizationItem {
                     id: id_gen.get(),
@@ -261,7 +280,11 @@ impl Linearizer for AnalysisHost {
                 lin.push(LinearizationItem {
                     id: root_id,
                     pos: ident.pos,
-                    ty: TypeWrapper::Concrete(AbsType::Dyn()),
+                    ty: self
+                        .refined
+                        .get(ident)
+                        .cloned()
+                        .unwrap_or(TypeWrapper::Concrete(AbsType::Dyn())),
                     scope: self.scope.clone(),
                     kind: TermKind::Usage(UsageState::from(self.env.get(ident))),
                     meta: self.meta.take(),
@@ -300,6 +323,14 @@ impl Linearizer for AnalysisHost {
                     meta: self.meta.take(),
                 });
 
+                // The fields of a recursive record shadow the refined types of the variables of
+                // the same name.
+                if let Term::RecRecord(..) = term {
+                    for ident in fields.keys() {
+                        self.refined.remove(ident);
+                    }
+                }
+
                 lin.register_fields(fields, id, self.scope.clone(), &mut self.env);
                 let mut field_names = fields.keys().cloned().collect::<Vec<_>>();
                 field_names.sort_unstable();
@@ -307,6 +338,24 @@ impl Linearizer for AnalysisHost {
                 self.record_fields =
                     Some((id + 1, field_names.into_iter().enumerate().rev().collect()));
             }
+            Term::Import(path) => lin.push(LinearizationItem {
+                id,
+                pos,
+                ty,
+                kind: TermKind::Import(path.clone()),
+                scope: self.scope.clone(),
+                meta: self.meta.take(),
+            }),
+            Term::ResolvedImport(file_id) if self.imports.contains_key(file_id) => {
+                lin.push(LinearizationItem {
+                    id,
+                    pos,
+                    ty,
+                    kind: TermKind::Import(self.imports[file_id].clone()),
+                    scope: self.scope.clone(),
+                    meta: self.meta.take(),
+                })
+            }
             Term::Op1(UnaryOp::StaticAccess(ident), _) => {
                 let x = self.access.get_or_insert(Vec::with_capacity(1));
                 x.push(ident.to_owned())
@@ -432,6 +481,8 @@ impl Linearizer for AnalysisHost {
             }),
             let_binding: self.let_binding.take(),
             access: self.access.clone(),
+            refined: self.refined.clone(),
+            imports: self.imports.clone(),
         }
     }
 
@@ -455,6 +506,8 @@ impl Linearizer for AnalysisHost {
             record_fields: None,
             let_binding: None,
             access: None,
+            refined: self.refined.clone(),
+            imports: self.imports.clone(),
         }
     }
 
@@ -473,6 +526,16 @@ impl Linearizer for AnalysisHost {
             item.ty = new_type;
         }
     }
+
+    fn refine_ident(
+        &mut self,
+        _lin: &mut Linearization<Building>,
+        ident: &Ident,
+        new_type: TypeWrapper,
+    ) {
+        debug!("refining {:?} to {:?}", ident, new_type);
+        self.refined.insert(ident.to_owned(), new_type);
+    }
 }
 
 struct IdGen(usize);
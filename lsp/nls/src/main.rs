@@ -1,4 +1,4 @@
-use std::{fs, io, path::PathBuf};
+use std::{fs, io, path::PathBuf, time::Duration};
 
 use anyhow::Result;
 
@@ -7,16 +7,20 @@ use lsp_server::Connection;
 
 mod cache;
 mod diagnostic;
+mod evaluation;
 mod files;
+mod fixes;
 mod linearization;
 mod requests;
 mod server;
 use server::Server;
 use structopt::StructOpt;
 
-use crate::trace::Trace;
+use crate::{evaluation::EvalConfig, trace::Trace};
 
 mod term;
+#[cfg(test)]
+mod testing;
 mod trace;
 
 #[derive(StructOpt, Debug)]
@@ -26,6 +30,26 @@ struct Opt {
     #[structopt(short = "t", long)]
     #[structopt(parse(from_os_str))]
     trace: Option<PathBuf>,
+
+    /// Disables the background evaluation of documents, which reports evaluation errors
+    #[structopt(long)]
+    no_eval: bool,
+
+    /// The maximum duration of the background evaluation of a document, in milliseconds
+    #[structopt(long, default_value = "1000")]
+    eval_timeout: u64,
+
+    /// The maximum number of steps of the background evaluation of a document
+    #[structopt(long, default_value = "10000000")]
+    eval_steps: usize,
+
+    /// The delay after the last change of a document before analyzing it again, in milliseconds
+    #[structopt(long, default_value = "200")]
+    debounce: u64,
+
+    /// Shows the effective value of the fields of merged records as inlay hints
+    #[structopt(long)]
+    value_hints: bool,
 }
 
 fn main() -> Result<()> {
@@ -33,7 +57,7 @@ fn main() -> Result<()> {
 
     let options = Opt::from_args();
 
-    if let Some(file) = options.trace {
+    if let Some(ref file) = options.trace {
         debug!("Writing trace to {:?}", file.canonicalize()?);
         Trace::set_writer(csv::Writer::from_writer(io::BufWriter::new(
             fs::OpenOptions::new().append(true).open(file)?,
@@ -44,9 +68,20 @@ fn main() -> Result<()> {
 
     let capabilities = Server::capabilities();
 
-    connection.initialize(serde_json::to_value(&capabilities)?)?;
+    connection.initialize(capabilities)?;
+
+    let eval_config = (!options.no_eval).then(|| EvalConfig {
+        timeout: Duration::from_millis(options.eval_timeout),
+        steps: options.eval_steps,
+    });
 
-    let _server = Server::new(connection).run();
+    let _server = Server::new(
+        connection,
+        eval_config,
+        options.value_hints,
+        Duration::from_millis(options.debounce),
+    )
+    .run();
 
     Ok(())
 }
@@ -0,0 +1,613 @@
+//! Code actions: the quick fixes attached to the diagnostics of a document (see [crate::fixes]),
+//! and refactorings of the code under the cursor or of the selection.
+use std::collections::{HashMap, HashSet};
+
+use codespan::{ByteIndex, FileId};
+use codespan_lsp::{byte_index_to_position, position_to_byte_index};
+use lsp_server::{RequestId, Response, ResponseError};
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams, Diagnostic, Range, TextEdit,
+    Url, WorkspaceEdit,
+};
+use nickel_lang::{
+    cache::EntryState,
+    identifier::Ident,
+    parser::lexer::{Lexer, NormalToken, Token},
+    term::{MetaValue, RichTerm, Term},
+    types::{AbsType, Types},
+};
+
+use crate::{
+    fixes::{self, Fix},
+    linearization::interface::TermKind,
+    server::Server,
+    term::subterms,
+    trace::{Enrich, Trace},
+};
+
+/// The maximum number of identifiers suggested to replace an unbound identifier.
+const MAX_SUGGESTIONS: usize = 3;
+
+pub fn handle_code_actions(
+    params: CodeActionParams,
+    id: RequestId,
+    server: &mut Server,
+) -> Result<(), ResponseError> {
+    let uri = params.text_document.uri;
+    let file_id = server.cache.id_of(uri.as_str()).unwrap();
+
+    if let Some(linearization) = server.lin_cache.get(&file_id) {
+        Trace::enrich(&id, linearization);
+    }
+
+    let mut actions = Vec::new();
+
+    for diagnostic in params.context.diagnostics.iter() {
+        for fix in fixes::attached(diagnostic) {
+            match fix {
+                Fix::Edit { title, edits } => actions.push(action(
+                    &uri,
+                    title,
+                    CodeActionKind::QUICKFIX,
+                    edits,
+                    Some(diagnostic),
+                )),
+                Fix::Unbound { name, range } => actions.extend(
+                    similar_names(server, file_id, &name, range)
+                        .into_iter()
+                        .map(|candidate| {
+                            action(
+                                &uri,
+                                format!("Replace with `{}`", candidate),
+                                CodeActionKind::QUICKFIX,
+                                vec![TextEdit {
+                                    range,
+                                    new_text: candidate,
+                                }],
+                                Some(diagnostic),
+                            )
+                        }),
+                ),
+            }
+        }
+    }
+
+    // The fixes of an error are attached to each of its diagnostics.
+    let mut titles = HashSet::new();
+    actions.retain(|action| titles.insert(action.title.clone()));
+
+    actions.extend(annotate(server, file_id, &uri, params.range));
+    actions.extend(extract(server, file_id, &uri, params.range));
+
+    if let Some(only) = params.context.only {
+        actions.retain(|action| {
+            let kind = action
+                .kind
+                .as_ref()
+                .map(CodeActionKind::as_str)
+                .unwrap_or("");
+            only.iter().any(|requested| {
+                kind == requested.as_str() || kind.starts_with(&format!("{}.", requested.as_str()))
+            })
+        });
+    }
+
+    let actions: Vec<CodeActionOrCommand> = actions
+        .into_iter()
+        .map(CodeActionOrCommand::CodeAction)
+        .collect();
+
+    server.reply(Response::new_ok(id, actions));
+    Ok(())
+}
+
+fn action(
+    uri: &Url,
+    title: String,
+    kind: CodeActionKind,
+    edits: Vec<TextEdit>,
+    diagnostic: Option<&Diagnostic>,
+) -> CodeAction {
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), edits);
+
+    CodeAction {
+        title,
+        kind: Some(kind),
+        diagnostics: diagnostic.map(|diagnostic| vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit::new(changes)),
+        ..Default::default()
+    }
+}
+
+/// The identifiers in scope at `range`, or defined by the standard library, which are close to
+/// `name`, the closest ones first.
+fn similar_names(server: &Server, file_id: FileId, name: &str, range: Range) -> Vec<String> {
+    let mut names: Vec<String> = server
+        .initial_env
+        .iter_elems()
+        .map(|(ident, _)| ident.label.clone())
+        .collect();
+
+    // The linearization may be the one of a previous version of the document, if the current one
+    // doesn't typecheck. The identifiers in scope are unlikely to have changed much.
+    if let Some(linearization) = server.lin_cache.get(&file_id) {
+        let item = position_to_byte_index(server.cache.files(), file_id, &range.start)
+            .ok()
+            .and_then(|start| linearization.item_at(&(file_id, ByteIndex(start as u32))));
+        let in_scope = match item {
+            Some(item) => linearization.get_in_scope(item),
+            None => linearization.linearization.iter().collect(),
+        };
+
+        names.extend(in_scope.into_iter().filter_map(|item| match &item.kind {
+            TermKind::Declaration(ident, ..) | TermKind::RecordField { ident, .. }
+                if !ident.is_generated() =>
+            {
+                Some(ident.label.clone())
+            }
+            _ => None,
+        }));
+    }
+
+    let max_distance = std::cmp::max(1, name.chars().count() / 3);
+    let mut candidates: Vec<(usize, String)> = names
+        .into_iter()
+        .filter(|candidate| candidate != name)
+        .map(|candidate| (edit_distance(name, &candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+
+    candidates.sort();
+    candidates.dedup();
+    candidates
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+/// The edit distance between two identifiers, where swapping two adjacent characters counts as a
+/// single edit (the optimal string alignment distance).
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // The rows of the distances between the prefixes of `a` of length `i - 1` and `i`, and the
+    // prefixes of `b`.
+    let mut previous: Vec<usize> = Vec::new();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        let mut next = vec![i; b.len() + 1];
+
+        for j in 1..=b.len() {
+            next[j] = if a[i - 1] == b[j - 1] {
+                row[j - 1]
+            } else {
+                1 + row[j - 1].min(row[j]).min(next[j - 1])
+            };
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                next[j] = next[j].min(previous[j - 2] + 1);
+            }
+        }
+
+        previous = std::mem::replace(&mut row, next);
+    }
+
+    row[b.len()]
+}
+
+/// Annotate the `let` binding or the record field under the cursor with its inferred type, if it
+/// doesn't have a type annotation or a contract yet.
+fn annotate(server: &Server, file_id: FileId, uri: &Url, range: Range) -> Option<CodeAction> {
+    let files = server.cache.files();
+    let offset = position_to_byte_index(files, file_id, &range.start).ok()?;
+
+    // Types are only known if the current version of the document typechecks.
+    let cached = server
+        .cache
+        .terms()
+        .get(&file_id)
+        .filter(|cached| cached.state >= EntryState::Typechecked)?;
+    let linearization = server.lin_cache.get(&file_id)?;
+
+    let ident = unannotated_binding(&cached.term, offset)?;
+    let item = linearization.linearization.iter().find(|item| {
+        item.pos == ident.pos
+            && matches!(
+                item.kind,
+                TermKind::Declaration(..) | TermKind::RecordField { .. }
+            )
+    })?;
+
+    if item.ty == Types(AbsType::Dyn()) {
+        return None;
+    }
+
+    let end = byte_index_to_position(files, file_id, ident.pos.unwrap().end.to_usize()).ok()?;
+
+    Some(action(
+        uri,
+        format!("Annotate `{}` with its type `{}`", ident, item.ty),
+        CodeActionKind::REFACTOR_REWRITE,
+        vec![TextEdit {
+            range: Range { start: end, end },
+            new_text: format!(" : {}", item.ty),
+        }],
+        None,
+    ))
+}
+
+/// Find the identifier at `offset` of a `let` binding or of a record field which has neither a
+/// type annotation nor a contract.
+fn unannotated_binding(rt: &RichTerm, offset: usize) -> Option<&Ident> {
+    let contains = |ident: &Ident| {
+        ident
+            .pos
+            .into_opt()
+            .map(|span| span.start.to_usize() <= offset && offset <= span.end.to_usize())
+            .unwrap_or(false)
+    };
+    let annotated = |value: &RichTerm| {
+        matches!(
+            value.as_ref(),
+            Term::MetaValue(meta) if meta.types.is_some() || !meta.contracts.is_empty()
+        )
+    };
+
+    match rt.as_ref() {
+        Term::Let(ident, bound, ..) if contains(ident) => (!annotated(bound)).then(|| ident),
+        Term::Record(fields, _) | Term::RecRecord(fields, ..) => {
+            match fields.iter().find(|(ident, _)| contains(ident)) {
+                Some((ident, value)) => (!annotated(value)).then(|| ident),
+                None => subterms(rt)
+                    .into_iter()
+                    .find_map(|t| unannotated_binding(t, offset)),
+            }
+        }
+        _ => subterms(rt)
+            .into_iter()
+            .find_map(|t| unannotated_binding(t, offset)),
+    }
+}
+
+/// Extract the selected expression into a `let` binding. The binding is inserted at the start of
+/// the innermost function body, `let` body or record field enclosing the selection, such that the
+/// variables of the expression are still in scope.
+fn extract(server: &Server, file_id: FileId, uri: &Url, range: Range) -> Option<CodeAction> {
+    let files = server.cache.files();
+    let source = files.source(file_id);
+    let start = position_to_byte_index(files, file_id, &range.start).ok()?;
+    let end = position_to_byte_index(files, file_id, &range.end).ok()?;
+
+    // Ignore the whitespace around the selection
+    let selected = source.get(start..end)?;
+    let start = start + (selected.len() - selected.trim_start().len());
+    let end = end - (selected.len() - selected.trim_end().len());
+    if start >= end {
+        return None;
+    }
+
+    let term = &server.cache.terms().get(&file_id)?.term;
+    let root = term.pos.into_opt()?.start.to_usize();
+    let insert_at = insertion_point(term, &(start..end), root)?;
+
+    // Extracting the whole body of a function or a binding doesn't bring anything.
+    if insert_at == start {
+        return None;
+    }
+
+    let name = fresh_name(source);
+    let position = |index| byte_index_to_position(files, file_id, index).ok();
+    let insert_at = position(insert_at)?;
+
+    Some(action(
+        uri,
+        format!("Extract into a `let` binding `{}`", name),
+        CodeActionKind::REFACTOR_EXTRACT,
+        vec![
+            TextEdit {
+                range: Range {
+                    start: insert_at,
+                    end: insert_at,
+                },
+                new_text: format!("let {} = {} in ", name, &source[start..end]),
+            },
+            TextEdit {
+                range: Range {
+                    start: position(start)?,
+                    end: position(end)?,
+                },
+                new_text: name,
+            },
+        ],
+        None,
+    ))
+}
+
+/// If `selection` is exactly the span of a subterm of `rt`, return the offset where a binding for
+/// this subterm can be inserted. `insert_at` is the insertion point for `rt` itself.
+fn insertion_point(
+    rt: &RichTerm,
+    selection: &std::ops::Range<usize>,
+    insert_at: usize,
+) -> Option<usize> {
+    match rt.pos.into_opt() {
+        Some(span)
+            if span.start.to_usize() == selection.start && span.end.to_usize() == selection.end =>
+        {
+            return Some(insert_at)
+        }
+        Some(span)
+            if span.start.to_usize() > selection.start || span.end.to_usize() < selection.end =>
+        {
+            return None
+        }
+        _ => (),
+    }
+
+    let start_of = |t: &RichTerm| {
+        t.pos
+            .into_opt()
+            .map(|span| span.start.to_usize())
+            .unwrap_or(insert_at)
+    };
+
+    match rt.as_ref() {
+        Term::Let(_, bound, body, _) | Term::LetPattern(_, _, bound, body) => {
+            insertion_point(bound, selection, insert_at)
+                .or_else(|| insertion_point(body, selection, start_of(body)))
+        }
+        Term::Fun(_, body) | Term::FunPattern(_, _, body) => {
+            insertion_point(body, selection, start_of(body))
+        }
+        Term::Record(fields, _) | Term::RecRecord(fields, ..) => fields
+            .values()
+            .find_map(|value| {
+                let value = match value.as_ref() {
+                    Term::MetaValue(MetaValue {
+                        value: Some(value), ..
+                    }) => value,
+                    _ => value,
+                };
+                insertion_point(value, selection, start_of(value))
+            })
+            .or_else(|| {
+                subterms(rt)
+                    .into_iter()
+                    .find_map(|t| insertion_point(t, selection, insert_at))
+            }),
+        _ => subterms(rt)
+            .into_iter()
+            .find_map(|t| insertion_point(t, selection, insert_at)),
+    }
+}
+
+/// A name for an extracted binding which doesn't appear in the document.
+fn fresh_name(source: &str) -> String {
+    let used: HashSet<&str> = Lexer::new(source)
+        .filter_map(Result::ok)
+        .filter_map(|(_, token, _)| match token {
+            Token::Normal(NormalToken::Identifier(name)) => Some(name),
+            _ => None,
+        })
+        .collect();
+
+    std::iter::once(String::from("value"))
+        .chain((1..).map(|i| format!("value{}", i)))
+        .find(|name| !used.contains(name.as_str()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{
+        request::CodeActionRequest, CodeActionContext, PartialResultParams, Position,
+        TextDocumentIdentifier, WorkDoneProgressParams,
+    };
+
+    use super::*;
+    use crate::{
+        files,
+        testing::{uri, TestServer},
+    };
+
+    /// Request the code actions of a single line document for the range `start..end`, with the
+    /// diagnostics it was published with.
+    fn actions(
+        server: &mut TestServer,
+        uri: &Url,
+        start: u32,
+        end: u32,
+        diagnostics: Vec<Diagnostic>,
+    ) -> Vec<CodeAction> {
+        let position = |character| Position { line: 0, character };
+
+        server
+            .request::<CodeActionRequest>(CodeActionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                range: Range {
+                    start: position(start),
+                    end: position(end),
+                },
+                context: CodeActionContext {
+                    diagnostics,
+                    only: None,
+                },
+                work_done_progress_params: WorkDoneProgressParams::default(),
+                partial_result_params: PartialResultParams::default(),
+            })
+            .unwrap_or_default()
+            .into_iter()
+            .map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => action,
+                CodeActionOrCommand::Command(command) => panic!("unexpected command {:?}", command),
+            })
+            .collect()
+    }
+
+    /// Apply the edits of the code action with the given title to a single line document.
+    fn apply(text: &str, actions: &[CodeAction], title: &str) -> String {
+        let action = actions
+            .iter()
+            .find(|action| action.title == title)
+            .unwrap_or_else(|| panic!("no action `{}` in {:#?}", title, actions));
+        let mut edits: Vec<_> = action
+            .edit
+            .clone()
+            .unwrap()
+            .changes
+            .unwrap()
+            .into_values()
+            .flatten()
+            .collect();
+        edits.sort_by_key(|edit| std::cmp::Reverse(edit.range.start.character));
+
+        let mut text = text.to_owned();
+        for edit in edits {
+            assert_eq!(edit.range.start.line, 0);
+            assert_eq!(edit.range.end.line, 0);
+            text.replace_range(
+                edit.range.start.character as usize..edit.range.end.character as usize,
+                &edit.new_text,
+            );
+        }
+        text
+    }
+
+    fn titles(actions: &[CodeAction]) -> Vec<&str> {
+        actions.iter().map(|action| action.title.as_str()).collect()
+    }
+
+    #[test]
+    fn adds_missing_field() {
+        let mut server = TestServer::new();
+        let file = uri("main.ncl");
+        let text = "let f : {a: Num, b: Str} -> Num = fun r => r.a in (f {a = 1} : Num)";
+        server.open(&file, text);
+
+        let diagnostics = server.diagnostics(&file);
+        let actions = actions(&mut server, &file, 0, 0, diagnostics);
+        assert_eq!(
+            apply(text, &actions, "Add the missing field `b`"),
+            "let f : {a: Num, b: Str} -> Num = fun r => r.a in (f {a = 1, b = \"\"} : Num)"
+        );
+    }
+
+    #[test]
+    fn suggests_identifiers_in_scope() {
+        let mut server = TestServer::new();
+        let file = uri("main.ncl");
+        server.open(&file, "let value = 1 in value + 1");
+        // The identifiers in scope come from the last version of the document which typechecks.
+        let text = "let value = 1 in valeu + 1";
+        server.change(&file, text);
+        files::analyze_pending(&mut server.server, true).unwrap();
+
+        let diagnostics = server.diagnostics(&file);
+        let actions = actions(&mut server, &file, 17, 17, diagnostics);
+        assert_eq!(
+            apply(text, &actions, "Replace with `value`"),
+            "let value = 1 in value + 1"
+        );
+    }
+
+    #[test]
+    fn attaches_fixes_to_their_own_error() {
+        let mut server = TestServer::new();
+        let file = uri("main.ncl");
+        server.open(&file, "{value = 1, a : Num = 1, b = value}");
+        server.change(&file, "{value = 1, a : Num = \"a\", b = valeu}");
+        files::analyze_pending(&mut server.server, true).unwrap();
+
+        let diagnostics = server.diagnostics(&file);
+        assert!(diagnostics.len() > 1);
+        for diagnostic in diagnostics.iter() {
+            let fixes = fixes::attached(diagnostic);
+            if diagnostic.message.contains("unbound") {
+                assert_matches::assert_matches!(
+                    fixes.as_slice(),
+                    [Fix::Unbound { name, .. }] if name == "valeu"
+                );
+            } else {
+                assert_eq!(fixes, Vec::new());
+            }
+        }
+
+        let actions = actions(&mut server, &file, 0, 0, diagnostics);
+        assert_eq!(titles(&actions), vec!["Replace with `value`"]);
+    }
+
+    #[test]
+    fn annotates_with_inferred_type() {
+        let mut server = TestServer::new();
+        let file = uri("main.ncl");
+        // Only the types of statically typed code are inferred.
+        let text = "(let x = 1 + 1 in x) : Num";
+        server.open(&file, text);
+
+        let actions = actions(&mut server, &file, 5, 5, Vec::new());
+        assert_eq!(
+            apply(text, &actions, "Annotate `x` with its type `Num`"),
+            "(let x : Num = 1 + 1 in x) : Num"
+        );
+        // Annotated bindings aren't annotated again
+        server.change(&file, "(let x : Num = 1 + 1 in x) : Num");
+        let actions = super::tests::actions(&mut server, &file, 5, 5, Vec::new());
+        assert!(titles(&actions)
+            .iter()
+            .all(|title| !title.starts_with("Annotate")));
+    }
+
+    #[test]
+    fn makes_merged_values_default() {
+        let mut server = TestServer::with_evaluation();
+        let file = uri("main.ncl");
+        let text = "{a = 1} & {a = 2}";
+        server.open(&file, text);
+
+        let diagnostics = server.eval_diagnostics(&file);
+        let actions = actions(&mut server, &file, 0, 0, diagnostics);
+        assert_eq!(
+            apply(text, &actions, "Make `1` a default value"),
+            "{a = 1 | default} & {a = 2}"
+        );
+        assert_eq!(
+            apply(text, &actions, "Make `2` a default value"),
+            "{a = 1} & {a = 2 | default}"
+        );
+    }
+
+    #[test]
+    fn extracts_into_let_binding() {
+        let mut server = TestServer::new();
+        let file = uri("main.ncl");
+        let text = "let f = fun x => x + 2 * 3 in f 1";
+        server.open(&file, text);
+
+        // The binding is inserted in the body of the function, where `x` would be in scope.
+        let actions = actions(&mut server, &file, 21, 26, Vec::new());
+        assert_eq!(
+            apply(text, &actions, "Extract into a `let` binding `value`"),
+            "let f = fun x => let value = 2 * 3 in x + value in f 1"
+        );
+
+        // The selection must be exactly a subterm, up to whitespace.
+        let actions = super::tests::actions(&mut server, &file, 17, 22, Vec::new());
+        assert!(titles(&actions)
+            .iter()
+            .all(|title| !title.starts_with("Extract")));
+        let actions = super::tests::actions(&mut server, &file, 20, 27, Vec::new());
+        assert!(titles(&actions).contains(&"Extract into a `let` binding `value`"));
+
+        // The name of the binding isn't used in the document.
+        let text = "let value = 1 in value + 2 * 3";
+        server.change(&file, text);
+        let actions = super::tests::actions(&mut server, &file, 25, 30, Vec::new());
+        assert_eq!(
+            apply(text, &actions, "Extract into a `let` binding `value1`"),
+            "let value = 1 in let value1 = 2 * 3 in value + value1"
+        );
+    }
+}
@@ -1,16 +1,37 @@
-use codespan::ByteIndex;
+use std::{collections::HashMap, ffi::OsStr};
+
+use codespan::{ByteIndex, Files};
 use codespan_lsp::position_to_byte_index;
 use log::debug;
 use lsp_server::{RequestId, Response, ResponseError};
-use lsp_types::{CompletionItem, CompletionParams};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionParams, Documentation, InsertTextFormat,
+};
+use nickel_lang::{
+    identifier::Ident,
+    term::{MergePriority, MetaValue, RichTerm, Term, UnaryOp},
+    types::{AbsType, Types},
+};
 use serde_json::Value;
 
 use crate::{
-    linearization::interface::TermKind,
+    cache::CacheExt,
+    linearization::{
+        completed::Completed,
+        interface::{Resolved, TermKind, UsageState, ValueState},
+        LinearizationItem,
+    },
+    requests::goto::root_record,
     server::Server,
     trace::{Enrich, Trace},
 };
 
+type Item = LinearizationItem<Resolved>;
+
+/// The maximum number of indirections (variables, field accesses, contracts) followed when
+/// looking for the fields of a value. Prevents looping on recursive definitions.
+const MAX_DEPTH: usize = 32;
+
 pub fn handle_completion(
     params: CompletionParams,
     id: RequestId,
@@ -34,6 +55,43 @@ pub fn handle_completion(
     Trace::enrich(&id, linearization);
 
     let item = linearization.item_at(&locator);
+    let resolver = FieldResolver {
+        server,
+        linearization,
+        scope_item: item,
+        locator,
+    };
+
+    // The position may be right after a record access such as `config.server.`, in which case
+    // we complete with the fields of the accessed record.
+    let source = server.cache.files().source(file_id);
+    if let Some(path) = source.get(..start).and_then(access_path) {
+        debug!("completing fields of {:?}", path);
+
+        let fields = resolver.path_fields(&path);
+
+        server.reply(Response::new_ok(id, fields));
+        return Ok(());
+    }
+
+    // Inside a record literal, we complete with the fields expected by the contracts applied to
+    // the record which are not defined yet.
+    let expected_items = server
+        .cache
+        .terms()
+        .get(&file_id)
+        .and_then(|cached| enclosing_record(&cached.term, locator.1, Vec::new()))
+        .map(|(expected, defined)| {
+            debug!("completing record literal expecting {:?}", expected);
+            let sources = resolver.expected_sources(&expected);
+            resolver.expected_items(&sources, &defined, server.cache.files())
+        })
+        .unwrap_or_default();
+
+    if !expected_items.is_empty() {
+        server.reply(Response::new_ok(id, expected_items));
+        return Ok(());
+    }
 
     if item == None {
         server.reply(Response::new_ok(id, Value::Null));
@@ -60,3 +118,721 @@ pub fn handle_completion(
     debug!("found closest item: {:?}", item);
     Ok(())
 }
+
+/// If `text` ends with a record access `foo.bar.`, possibly followed by the beginning of a field
+/// name, return the path of the accessed record `["foo", "bar"]`.
+fn access_path(text: &str) -> Option<Vec<Ident>> {
+    let is_ident_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '\'');
+
+    let mut text = text.trim_end_matches(is_ident_char).strip_suffix('.')?;
+    let mut path = Vec::new();
+
+    loop {
+        let start = text.trim_end_matches(is_ident_char).len();
+        let segment = &text[start..];
+
+        if !segment.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            return None;
+        }
+
+        path.push(Ident::from(segment));
+        text = &text[..start];
+
+        match text.strip_suffix('.') {
+            Some(rest) => text = rest,
+            None => break,
+        }
+    }
+
+    path.reverse();
+    Some(path)
+}
+
+/// A contract expected to apply to a record literal: a contract applied to an enclosing value,
+/// together with the path of the record inside this value.
+type Expected = (Types, Vec<Ident>);
+
+/// Find the innermost record literal of `rt` whose fields are being written at `offset`, that is
+/// which contains `offset` but not inside the value of one of its fields. Return the contracts
+/// expected for this record, given the contracts `expected` for `rt`, together with the fields
+/// the record already defines.
+fn enclosing_record(
+    rt: &RichTerm,
+    offset: ByteIndex,
+    expected: Vec<Expected>,
+) -> Option<(Vec<Expected>, Vec<Ident>)> {
+    match rt.as_ref() {
+        Term::MetaValue(meta) => {
+            let mut expected = expected;
+            expected.extend(
+                meta.types
+                    .iter()
+                    .chain(meta.contracts.iter())
+                    .map(|contract| (contract.types.clone(), Vec::new())),
+            );
+            meta.value
+                .as_ref()
+                .and_then(|value| enclosing_record(value, offset, expected))
+        }
+        Term::Record(fields, _) | Term::RecRecord(fields, ..) => {
+            let in_field = fields.iter().find_map(|(id, value)| {
+                let expected = expected
+                    .iter()
+                    .map(|(ty, path)| {
+                        let mut path = path.clone();
+                        path.push(id.clone());
+                        (ty.clone(), path)
+                    })
+                    .collect();
+                enclosing_record(value, offset, expected)
+            });
+
+            let contains = rt
+                .pos
+                .as_opt_ref()
+                .map(|span| span.start < offset && offset < span.end)
+                .unwrap_or(false);
+            // The position may be inside a value which isn't a record literal, such as
+            // `{name = fo}`, in which case no field is being written.
+            let in_value = fields.values().any(|value| {
+                value
+                    .pos
+                    .as_opt_ref()
+                    .map(|span| span.start <= offset && offset <= span.end)
+                    .unwrap_or(false)
+            });
+
+            in_field.or_else(|| {
+                (contains && !in_value).then(|| (expected, fields.keys().cloned().collect()))
+            })
+        }
+        Term::Let(_, t1, t2, _)
+        | Term::LetPattern(_, _, t1, t2)
+        | Term::App(t1, t2)
+        | Term::Op2(_, t1, t2) => enclosing_record(t1, offset, Vec::new())
+            .or_else(|| enclosing_record(t2, offset, Vec::new())),
+        Term::Fun(_, t)
+        | Term::FunPattern(_, _, t)
+        | Term::Op1(_, t)
+        | Term::EnumVariant(_, t)
+        | Term::Sealed(_, t, _) => enclosing_record(t, offset, Vec::new()),
+        Term::Array(ts, _) | Term::OpN(_, ts) => ts
+            .iter()
+            .find_map(|t| enclosing_record(t, offset, Vec::new())),
+        Term::Switch(exp, cases, default, _) => std::iter::once(exp)
+            .chain(cases.values())
+            .chain(default.iter())
+            .find_map(|t| enclosing_record(t, offset, Vec::new())),
+        _ => None,
+    }
+}
+
+/// Where the terms of a source of fields are resolved: the linearization of the file the source
+/// comes from, and the item whose scope is used to look up variables, if any. Otherwise, any
+/// declaration of the linearization can be used.
+#[derive(Debug, Clone, Copy)]
+struct Scope<'a> {
+    linearization: &'a Completed,
+    item: Option<&'a Item>,
+}
+
+/// A source of information about the fields of a value: either the linearization item of a
+/// record literal, or a type or a contract which applies to the value.
+#[derive(Debug, Clone)]
+enum FieldSource<'a> {
+    Record(&'a Completed, &'a Item),
+    Type(Scope<'a>, Types),
+}
+
+/// Find the fields of the values of a linearization, looking up variables in the scope of a given
+/// item. Imported files are resolved through the linearizations of the server.
+struct FieldResolver<'a> {
+    server: &'a Server,
+    linearization: &'a Completed,
+    /// The item whose scope is used to look up variables, if any. Otherwise, any declaration of
+    /// the file can be used.
+    scope_item: Option<&'a Item>,
+    /// The position of the completion. Only the declarations before this position are considered.
+    locator: (codespan::FileId, ByteIndex),
+}
+
+impl<'a> FieldResolver<'a> {
+    /// Return the completion items for the fields of the record at `path`, where the first
+    /// element of the path is a variable.
+    fn path_fields(&self, path: &[Ident]) -> Vec<CompletionItem> {
+        let (var, fields) = match path.split_first() {
+            Some(split) => split,
+            None => return Vec::new(),
+        };
+
+        let mut sources = self
+            .declaration(self.completion_scope(), var)
+            .map(|decl| self.item_sources(self.linearization, decl, MAX_DEPTH))
+            .unwrap_or_default();
+
+        for field in fields {
+            sources = self.field_sources(&sources, field, MAX_DEPTH);
+        }
+
+        self.completion_items(&sources)
+    }
+
+    /// The sources of fields of a record literal, given the contracts expected for it.
+    fn expected_sources(&self, expected: &[Expected]) -> Vec<FieldSource<'a>> {
+        expected
+            .iter()
+            .flat_map(|(ty, path)| {
+                path.iter().fold(
+                    self.type_sources(self.completion_scope(), ty.clone(), MAX_DEPTH),
+                    |sources, field| self.field_sources(&sources, field, MAX_DEPTH),
+                )
+            })
+            .collect()
+    }
+
+    /// The scope of the completion position.
+    fn completion_scope(&self) -> Scope<'a> {
+        Scope {
+            linearization: self.linearization,
+            item: self.scope_item,
+        }
+    }
+
+    /// The scope where the terms of an item of `linearization` are resolved. Items of the file
+    /// being completed are resolved at the completion position, as the linearization may not
+    /// know the scope of items from incomplete code, and items of imported files in their own
+    /// scope.
+    fn scope_of(&self, linearization: &'a Completed, item: &'a Item) -> Scope<'a> {
+        if std::ptr::eq(linearization, self.linearization) {
+            self.completion_scope()
+        } else {
+            Scope {
+                linearization,
+                item: Some(item),
+            }
+        }
+    }
+
+    /// Find the closest declaration of the variable `name` in `scope`. In the file being
+    /// completed, only the declarations before the completion position are considered.
+    fn declaration(&self, scope: Scope<'a>, name: &Ident) -> Option<&'a Item> {
+        let candidates = match scope.item {
+            Some(item) => scope.linearization.get_in_scope(item),
+            None => scope.linearization.linearization.iter().collect(),
+        };
+        let completed_file = std::ptr::eq(scope.linearization, self.linearization);
+
+        candidates
+            .into_iter()
+            .filter(|item| match &item.kind {
+                TermKind::Declaration(ident, ..) | TermKind::RecordField { ident, .. } => {
+                    ident == name
+                }
+                _ => false,
+            })
+            .filter_map(|item| {
+                let span = item.pos.into_opt()?;
+                (!completed_file || (span.src_id == self.locator.0 && span.start <= self.locator.1))
+                    .then(|| (span.start, item))
+            })
+            .max_by_key(|(start, _)| *start)
+            .map(|(_, item)| item)
+    }
+
+    /// The sources of fields of the value represented by an item of `linearization`, following
+    /// variables, declarations and imports to the corresponding value.
+    fn item_sources(
+        &self,
+        linearization: &'a Completed,
+        item: &'a Item,
+        depth: usize,
+    ) -> Vec<FieldSource<'a>> {
+        if depth == 0 {
+            return Vec::new();
+        }
+
+        let scope = self.scope_of(linearization, item);
+        let mut sources = vec![FieldSource::Type(scope, item.ty.clone())];
+        sources.extend(self.meta_sources(scope, item.meta.as_ref(), depth - 1));
+
+        match &item.kind {
+            TermKind::Usage(UsageState::Resolved(referenced)) => {
+                if let Some(referenced) = linearization.get_item(*referenced) {
+                    sources.extend(self.item_sources(linearization, referenced, depth - 1));
+                }
+            }
+            TermKind::Usage(UsageState::Imported { import, fields }) => {
+                if let Some(import) = linearization.get_item(*import) {
+                    let imported = self.item_sources(linearization, import, depth - 1);
+                    sources.extend(fields.iter().fold(imported, |sources, field| {
+                        self.field_sources(&sources, field, depth - 1)
+                    }));
+                }
+            }
+            TermKind::Declaration(_, _, ValueState::Known(value))
+            | TermKind::RecordField {
+                value: ValueState::Known(value),
+                ..
+            } if *value != item.id => {
+                if let Some(value) = linearization.get_item(*value) {
+                    sources.extend(self.item_sources(linearization, value, depth - 1));
+                }
+            }
+            TermKind::Record(_) => sources.push(FieldSource::Record(linearization, item)),
+            TermKind::Import(path) => sources.extend(self.imported_sources(item, path, depth - 1)),
+            _ => (),
+        }
+
+        sources
+    }
+
+    /// The sources of fields of the record defined by the file imported by `path` from the file
+    /// of `import`. The imported file is looked up in the linearizations of the server, which
+    /// include the files imported by the open documents.
+    fn imported_sources(&self, import: &Item, path: &OsStr, depth: usize) -> Vec<FieldSource<'a>> {
+        let imported = import.pos.into_opt().and_then(|span| {
+            let uri = self.server.cache.import_uri(span.src_id, path)?;
+            let file_id = self.server.cache.id_of(uri.as_str())?;
+            let linearization = self.server.lin_cache.get(&file_id)?;
+            Some((
+                linearization,
+                root_record(self.server, file_id, linearization)?,
+            ))
+        });
+
+        match imported {
+            Some((linearization, root)) => self.item_sources(linearization, root, depth),
+            None => Vec::new(),
+        }
+    }
+
+    /// The sources of fields corresponding to the type annotation and the contracts of a value.
+    fn meta_sources(
+        &self,
+        scope: Scope<'a>,
+        meta: Option<&MetaValue>,
+        depth: usize,
+    ) -> Vec<FieldSource<'a>> {
+        meta.into_iter()
+            .flat_map(|meta| meta.types.iter().chain(meta.contracts.iter()))
+            .flat_map(|contract| self.type_sources(scope, contract.types.clone(), depth))
+            .collect()
+    }
+
+    /// The sources of fields corresponding to a type. A contract defined in the file, such as
+    /// `Schema` in `| Schema`, is resolved to its definition.
+    fn type_sources(&self, scope: Scope<'a>, ty: Types, depth: usize) -> Vec<FieldSource<'a>> {
+        match ty {
+            Types(AbsType::Flat(rt)) => self.term_sources(scope, &rt, depth),
+            Types(AbsType::Optional(ty)) => self.type_sources(scope, *ty, depth),
+            ty => vec![FieldSource::Type(scope, ty)],
+        }
+    }
+
+    /// The sources of fields of a term appearing in a contract, which is either a variable or
+    /// an access to the field of a variable.
+    fn term_sources(&self, scope: Scope<'a>, rt: &RichTerm, depth: usize) -> Vec<FieldSource<'a>> {
+        if depth == 0 {
+            return Vec::new();
+        }
+
+        match rt.as_ref() {
+            Term::Var(id) => self
+                .declaration(scope, id)
+                .map(|decl| self.item_sources(scope.linearization, decl, depth - 1))
+                .unwrap_or_default(),
+            Term::Op1(UnaryOp::StaticAccess(field), record) => {
+                let sources = self.term_sources(scope, record, depth - 1);
+                self.field_sources(&sources, field, depth - 1)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// The sources of fields of the field `name` of a value, given the sources of the value.
+    fn field_sources(
+        &self,
+        sources: &[FieldSource<'a>],
+        name: &Ident,
+        depth: usize,
+    ) -> Vec<FieldSource<'a>> {
+        if depth == 0 {
+            return Vec::new();
+        }
+
+        sources
+            .iter()
+            .flat_map(|source| match source {
+                FieldSource::Record(linearization, record) => match &record.kind {
+                    TermKind::Record(fields) => fields
+                        .get(name)
+                        .and_then(|field| linearization.get_item(*field))
+                        .map(|field| self.item_sources(linearization, field, depth - 1))
+                        .unwrap_or_default(),
+                    _ => Vec::new(),
+                },
+                FieldSource::Type(scope, ty) => row_fields(ty)
+                    .into_iter()
+                    .find(|(id, ..)| id == name)
+                    .map(|(_, ty, _)| self.type_sources(*scope, ty, depth - 1))
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// All the fields provided by `sources`. When several sources provide the same field, their
+    /// information is combined.
+    fn fields(&self, sources: &[FieldSource<'a>]) -> Vec<FieldInfo<'a>> {
+        let mut fields: Vec<FieldInfo<'a>> = Vec::new();
+        let mut indices: HashMap<Ident, usize> = HashMap::new();
+
+        let found = sources.iter().flat_map(|source| match source {
+            FieldSource::Record(linearization, record) => match &record.kind {
+                TermKind::Record(fields) => fields
+                    .iter()
+                    .map(|(id, field)| {
+                        let item = linearization.get_item(*field);
+                        FieldInfo {
+                            ident: id.clone(),
+                            ty: item.and_then(field_type),
+                            optional: item
+                                .and_then(|item| item.meta.as_ref())
+                                .map(|meta| meta.opt)
+                                .unwrap_or(false),
+                            item,
+                        }
+                    })
+                    .collect::<Vec<_>>(),
+                _ => Vec::new(),
+            },
+            FieldSource::Type(_, ty) => row_fields(ty)
+                .into_iter()
+                .map(|(ident, ty, optional)| FieldInfo {
+                    ident,
+                    ty: Some(ty),
+                    optional,
+                    item: None,
+                })
+                .collect(),
+        });
+
+        for field in found {
+            match indices.get(&field.ident) {
+                Some(index) => {
+                    let known = &mut fields[*index];
+                    known.ty = known.ty.take().or(field.ty);
+                    known.item = known.item.or(field.item);
+                    known.optional |= field.optional;
+                }
+                None => {
+                    indices.insert(field.ident.clone(), fields.len());
+                    fields.push(field);
+                }
+            }
+        }
+
+        debug!("found {} fields", fields.len());
+        fields
+    }
+
+    /// The completion items of the fields provided by `sources` which are not `defined` yet.
+    /// Required fields are listed first, and the inserted snippet is pre-filled with the default
+    /// value of the field, if any.
+    fn expected_items(
+        &self,
+        sources: &[FieldSource<'a>],
+        defined: &[Ident],
+        files: &Files<String>,
+    ) -> Vec<CompletionItem> {
+        self.fields(sources)
+            .into_iter()
+            .filter(|field| !defined.contains(&field.ident))
+            .map(|field| {
+                let default = field.default(files);
+                let status = if field.optional {
+                    "optional"
+                } else if default.is_some() {
+                    "default"
+                } else {
+                    "required"
+                };
+                let detail = match field.detail() {
+                    Some(ty) => format!("{} ({})", ty, status),
+                    None => status.to_owned(),
+                };
+                let label = field.ident.label.clone();
+
+                CompletionItem {
+                    kind: Some(CompletionItemKind::Field),
+                    detail: Some(detail),
+                    documentation: field.doc().map(Documentation::String),
+                    sort_text: Some(format!(
+                        "{}{}",
+                        if status == "required" { 0 } else { 1 },
+                        label
+                    )),
+                    insert_text: Some(format!(
+                        "{} = ${{1:{}}}",
+                        label,
+                        escape_snippet(default.unwrap_or_default())
+                    )),
+                    insert_text_format: Some(InsertTextFormat::Snippet),
+                    label,
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+
+    /// The completion items of all the fields provided by `sources`.
+    fn completion_items(&self, sources: &[FieldSource<'a>]) -> Vec<CompletionItem> {
+        self.fields(sources)
+            .into_iter()
+            .map(|field| CompletionItem {
+                kind: Some(CompletionItemKind::Field),
+                detail: field.detail(),
+                documentation: field.doc().map(Documentation::String),
+                label: field.ident.label,
+                ..Default::default()
+            })
+            .collect()
+    }
+}
+
+/// The information gathered about a field from the sources of fields of a record.
+struct FieldInfo<'a> {
+    ident: Ident,
+    ty: Option<Types>,
+    optional: bool,
+    /// The linearization item of the field, if it is defined in a record literal.
+    item: Option<&'a Item>,
+}
+
+impl<'a> FieldInfo<'a> {
+    /// The type of the field, if it is known.
+    fn detail(&self) -> Option<String> {
+        self.ty
+            .as_ref()
+            .filter(|ty| **ty != Types(AbsType::Dyn()))
+            .map(|ty| ty.to_string())
+    }
+
+    fn doc(&self) -> Option<String> {
+        self.item
+            .and_then(|item| item.meta.as_ref())
+            .and_then(|meta| meta.doc.clone())
+    }
+
+    /// The source of the default value of the field, if any.
+    fn default<'f>(&self, files: &'f Files<String>) -> Option<&'f str> {
+        let meta = self.item.and_then(|item| item.meta.as_ref())?;
+
+        match meta {
+            MetaValue {
+                priority: MergePriority::Default,
+                value: Some(value),
+                ..
+            } => {
+                let span = value.pos.into_opt()?;
+                files
+                    .source(span.src_id)
+                    .get(span.start.to_usize()..span.end.to_usize())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Escape the characters which have a special meaning in snippets.
+fn escape_snippet(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('$', "\\$")
+        .replace('}', "\\}")
+}
+
+/// The fields of a record type together with their types, and whether they are optional.
+pub(crate) fn row_fields(ty: &Types) -> Vec<(Ident, Types, bool)> {
+    let mut fields = Vec::new();
+    let mut row = match ty {
+        Types(AbsType::StaticRecord(row)) => row.as_ref(),
+        _ => return fields,
+    };
+
+    while let Types(AbsType::RowExtend(id, Some(ty), tail)) = row {
+        match ty.as_ref() {
+            Types(AbsType::Optional(ty)) => fields.push((id.clone(), ty.as_ref().clone(), true)),
+            ty => fields.push((id.clone(), ty.clone(), false)),
+        };
+        row = tail.as_ref();
+    }
+
+    fields
+}
+
+/// The type of a record field item, either inferred or given by an annotation.
+fn field_type(field: &Item) -> Option<Types> {
+    let annotated = field.meta.as_ref().and_then(|meta| {
+        meta.types
+            .iter()
+            .chain(meta.contracts.iter())
+            .next()
+            .map(|contract| contract.types.clone())
+    });
+
+    Some(field.ty.clone())
+        .filter(|ty| *ty != Types(AbsType::Dyn()))
+        .or(annotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{
+        request::Completion, CompletionResponse, PartialResultParams, Url, WorkDoneProgressParams,
+    };
+
+    use super::*;
+    use crate::testing::{position_params, uri, TestServer};
+
+    /// The position of the completion in the documents of the tests.
+    const CURSOR: &str = "<cursor>";
+
+    /// Open `text` as `file` and request the completion at the position marked by `CURSOR`.
+    fn complete(server: &mut TestServer, file: &Url, text: &str) -> Vec<CompletionItem> {
+        let offset = text.find(CURSOR).unwrap();
+        let before = &text[..offset];
+        let line = before.matches('\n').count();
+        let character = before[before.rfind('\n').map_or(0, |i| i + 1)..]
+            .encode_utf16()
+            .count();
+        server.open(file, &text.replacen(CURSOR, "", 1));
+
+        let response = server.request::<Completion>(CompletionParams {
+            text_document_position: position_params(file, line as u32, character as u32),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+            context: None,
+        });
+
+        match response {
+            Some(CompletionResponse::Array(items)) => items,
+            Some(CompletionResponse::List(list)) => list.items,
+            None => Vec::new(),
+        }
+    }
+
+    fn labels(items: &[CompletionItem]) -> Vec<&str> {
+        let mut labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        labels.sort_unstable();
+        labels
+    }
+
+    #[test]
+    fn record_fields() {
+        let mut server = TestServer::new();
+        let items = complete(
+            &mut server,
+            &uri("main.ncl"),
+            "let config = {server = {port | doc \"The port\" = 80, host = \"localhost\"}} in\nconfig.server.<cursor>",
+        );
+
+        assert_eq!(labels(&items), vec!["host", "port"]);
+        let port = items.iter().find(|item| item.label == "port").unwrap();
+        assert_eq!(port.detail.as_deref(), Some("Num"));
+        assert_eq!(
+            port.documentation,
+            Some(Documentation::String(String::from("The port")))
+        );
+    }
+
+    #[test]
+    fn contract_fields() {
+        let mut server = TestServer::new();
+        let items = complete(
+            &mut server,
+            &uri("main.ncl"),
+            "let Schema = {name | Str, port | Num} in\nlet config | Schema = {name = \"a\", port = 1} in\nconfig.<cursor>",
+        );
+
+        assert_eq!(labels(&items), vec!["name", "port"]);
+    }
+
+    #[test]
+    fn imported_fields() {
+        let mut server = TestServer::new();
+        server.open(
+            &uri("lib.ncl"),
+            "{config = {server = {port | doc \"The port\" = 80}}}",
+        );
+        let items = complete(
+            &mut server,
+            &uri("main.ncl"),
+            "let lib = import \"lib.ncl\" in\nlet config = lib.config in\nconfig.server.<cursor>",
+        );
+
+        assert_eq!(labels(&items), vec!["port"]);
+        assert_eq!(
+            items[0].documentation,
+            Some(Documentation::String(String::from("The port")))
+        );
+    }
+
+    #[test]
+    fn expected_fields() {
+        let mut server = TestServer::new();
+        let items = complete(
+            &mut server,
+            &uri("main.ncl"),
+            "let Schema = {name | Str, port | Num | default = 80, tags | Array Str | optional} in\n{config | Schema = {name = \"a\", <cursor>}}",
+        );
+
+        assert_eq!(labels(&items), vec!["port", "tags"]);
+        let port = items.iter().find(|item| item.label == "port").unwrap();
+        assert_eq!(port.detail.as_deref(), Some("Num (default)"));
+        assert_eq!(port.insert_text.as_deref(), Some("port = ${1:80}"));
+        let tags = items.iter().find(|item| item.label == "tags").unwrap();
+        assert_eq!(tags.detail.as_deref(), Some("Array Str (optional)"));
+    }
+
+    #[test]
+    fn expected_fields_not_in_values() {
+        let mut server = TestServer::new();
+        let items = complete(
+            &mut server,
+            &uri("main.ncl"),
+            "let Schema = {name | Str, port | Num} in\nlet foo = \"a\" in\n{config | Schema = {name = foo<cursor>}}",
+        );
+
+        let labels = labels(&items);
+        assert!(labels.contains(&"foo"), "{:?}", labels);
+        assert!(!labels.contains(&"port"), "{:?}", labels);
+    }
+
+    #[test]
+    fn imported_schema() {
+        let mut server = TestServer::new();
+        let items = complete(
+            &mut server,
+            &uri("../../benches/mantis/jobs/completion.ncl"),
+            "let types = import \"../schemas/nomad/types.ncl\" in\nlet NomadJob = types.json.Job in\n{job | NomadJob = {Namespace = \"default\", <cursor>}}",
+        );
+
+        let labels = labels(&items);
+        assert!(labels.contains(&"Datacenters"), "{:?}", labels);
+        assert!(!labels.contains(&"Namespace"), "{:?}", labels);
+
+        let datacenters = items
+            .iter()
+            .find(|item| item.label == "Datacenters")
+            .unwrap();
+        assert!(datacenters.detail.as_ref().unwrap().ends_with("(required)"));
+        let task_groups = items
+            .iter()
+            .find(|item| item.label == "TaskGroups")
+            .unwrap();
+        assert_eq!(
+            task_groups.insert_text.as_deref(),
+            Some("TaskGroups = ${1:[]}")
+        );
+        assert!(task_groups.detail.as_ref().unwrap().ends_with("(default)"));
+    }
+}
@@ -1,17 +1,26 @@
-use codespan::ByteIndex;
+use std::{ops::Range as ByteRange, path::Path};
+
+use codespan::{ByteIndex, FileId};
 use codespan_lsp::position_to_byte_index;
 use log::debug;
 use lsp_server::{RequestId, Response, ResponseError};
 use lsp_types::{
     GotoDefinitionParams, GotoDefinitionResponse, Location, Range, ReferenceParams, Url,
 };
-use nickel_lang::position::RawSpan;
+use nickel_lang::{cache::InputFormat, identifier::Ident, position::RawSpan};
 use serde_json::Value;
 
 use crate::{
+    cache::CacheExt,
     diagnostic::LocationCompat,
-    linearization::interface::{TermKind, UsageState},
+    linearization::{
+        building::ID,
+        completed::Completed,
+        interface::{Resolved, TermKind, UsageState, ValueState},
+        LinearizationItem,
+    },
     server::Server,
+    term::{root_record_pos, RawSpanExt},
     trace::{Enrich, Trace},
 };
 
@@ -73,6 +82,9 @@ pub fn handle_to_definition(
             };
             Some(location)
         }
+        TermKind::Usage(UsageState::Imported { import, ref fields }) => {
+            imported_definition(server, file_id, linearization, import, fields)
+        }
         _ => None,
     };
 
@@ -150,3 +162,270 @@ pub fn handle_to_usages(
     }
     Ok(())
 }
+
+/// Return the location of the definition of the field at `fields` in the file imported by the
+/// item `import`. If the field can't be found, the location of the deepest field of the path
+/// found, or the beginning of the imported file, is returned instead.
+fn imported_definition(
+    server: &Server,
+    importer: FileId,
+    linearization: &Completed,
+    import: ID,
+    fields: &[Ident],
+) -> Option<Location> {
+    let path = match &linearization.get_item(import)?.kind {
+        TermKind::Import(path) => path,
+        _ => return None,
+    };
+    let uri = server.cache.import_uri(importer, path)?;
+    let file_id = server.cache.id_of(uri.as_str())?;
+
+    let format = InputFormat::from_path_buf(Path::new(uri.path())).unwrap_or(InputFormat::Nickel);
+    let range = match format {
+        InputFormat::Nickel => server
+            .lin_cache
+            .get(&file_id)
+            .and_then(|imported| field_definition(server, file_id, imported, fields)),
+        InputFormat::Json => json_key(server.cache.files().source(file_id), fields),
+        InputFormat::Yaml => yaml_key(server.cache.files().source(file_id), fields),
+        InputFormat::Toml => None,
+    }
+    .unwrap_or(0..0);
+
+    Some(Location {
+        uri,
+        range: Range::from_codespan(&file_id, &range, server.cache.files()),
+    })
+}
+
+/// Find the definition of the field at `path` in the record defined by a Nickel file.
+fn field_definition(
+    server: &Server,
+    file_id: FileId,
+    linearization: &Completed,
+    path: &[Ident],
+) -> Option<ByteRange<usize>> {
+    let mut record = root_record(server, file_id, linearization)?;
+    let mut found = None;
+
+    for ident in path {
+        let field = match &record.kind {
+            TermKind::Record(fields) => {
+                fields.get(ident).and_then(|id| linearization.get_item(*id))
+            }
+            _ => None,
+        };
+        let field = match field {
+            Some(field) => field,
+            None => break,
+        };
+
+        found = field.pos.into_opt().map(|span| span.to_range().1).or(found);
+
+        match &field.kind {
+            TermKind::RecordField {
+                value: ValueState::Known(value),
+                ..
+            } => match linearization.get_item(*value) {
+                Some(value) => record = value,
+                None => break,
+            },
+            _ => break,
+        }
+    }
+
+    found
+}
+
+/// Return the item of the record defined by a Nickel file, if the file evaluates to a record
+/// literal, possibly after some let bindings.
+pub(crate) fn root_record<'a>(
+    server: &Server,
+    file_id: FileId,
+    linearization: &'a Completed,
+) -> Option<&'a LinearizationItem<Resolved>> {
+    let root_pos = server
+        .cache
+        .terms()
+        .get(&file_id)
+        .map(|cached| root_record_pos(&cached.term))
+        .filter(|pos| pos.is_def())?;
+
+    linearization
+        .linearization
+        .iter()
+        .find(|item| matches!(item.kind, TermKind::Record(_)) && item.pos == root_pos)
+}
+
+/// Find the key of the field at `path` in a JSON document. Data files are not parsed with
+/// positions, so we look for the key in the source directly.
+fn json_key(source: &str, path: &[Ident]) -> Option<ByteRange<usize>> {
+    let mut depth = 0;
+    let mut matched = 0;
+    let mut chars = source.char_indices();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth = usize::saturating_sub(depth, 1);
+                // We left the value of the last matched key without finding the next one.
+                if depth <= matched && matched > 0 {
+                    return None;
+                }
+            }
+            '"' => {
+                let mut escaped = false;
+                let end = chars
+                    .by_ref()
+                    .find(|(_, c)| {
+                        let closing = *c == '"' && !escaped;
+                        escaped = *c == '\\' && !escaped;
+                        closing
+                    })
+                    .map(|(end, _)| end + 1)?;
+                let is_key = source[end..].trim_start().starts_with(':');
+
+                if is_key
+                    && depth == matched + 1
+                    && path.get(matched).map(|ident| ident.label.as_str())
+                        == Some(&source[start + 1..end - 1])
+                {
+                    matched += 1;
+                    if matched == path.len() {
+                        return Some(start..end);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    None
+}
+
+/// Find the key of the field at `path` in a YAML document, by following the indentation of the
+/// block mappings. Flow mappings and keys in sequences are not supported.
+fn yaml_key(source: &str, path: &[Ident]) -> Option<ByteRange<usize>> {
+    let mut matched = 0;
+    // The indentation of the keys matched so far, and of the keys of the current block.
+    let mut indents: Vec<usize> = Vec::new();
+    let mut block_indent = None;
+    let mut offset = 0;
+
+    for line in source.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+
+        let content = line.trim_start_matches(' ');
+        let indent = line.len() - content.len();
+        let content = content.trim_end();
+
+        if content.is_empty() || content.starts_with('#') || content.starts_with("---") {
+            continue;
+        }
+
+        if matches!(indents.last(), Some(parent) if indent <= *parent) {
+            return None;
+        }
+
+        let level = *block_indent.get_or_insert(indent);
+        let key = content
+            .split_once(':')
+            .filter(|(_, rest)| rest.is_empty() || rest.starts_with(' '))
+            .map(|(key, _)| key.trim_end());
+
+        if let Some(key) = key {
+            let unquoted = key.trim_matches(|c| c == '"' || c == '\'');
+
+            if indent == level
+                && path.get(matched).map(|ident| ident.label.as_str()) == Some(unquoted)
+            {
+                matched += 1;
+                if matched == path.len() {
+                    let start = line_start + indent;
+                    return Some(start..start + key.len());
+                }
+                indents.push(indent);
+                block_indent = None;
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{
+        request::GotoDefinition, PartialResultParams, Position, WorkDoneProgressParams,
+    };
+
+    use super::*;
+    use crate::testing::{position_params, uri, TestServer};
+
+    fn definition(server: &mut TestServer, uri: &Url, line: u32, character: u32) -> Location {
+        let response = server.request::<GotoDefinition>(GotoDefinitionParams {
+            text_document_position_params: position_params(uri, line, character),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        });
+
+        match response {
+            Some(GotoDefinitionResponse::Scalar(location)) => location,
+            response => panic!("unexpected definition {:?}", response),
+        }
+    }
+
+    fn location(uri: Url, line: u32, start: u32, end: u32) -> Location {
+        Location {
+            uri,
+            range: Range::new(Position::new(line, start), Position::new(line, end)),
+        }
+    }
+
+    #[test]
+    fn variable() {
+        let mut server = TestServer::new();
+        let main = uri("main.ncl");
+        server.open(&main, "let x = 1 in\nx + 1");
+
+        assert_eq!(
+            definition(&mut server, &main, 1, 0),
+            location(main, 0, 4, 5)
+        );
+    }
+
+    #[test]
+    fn imported_field() {
+        let mut server = TestServer::new();
+        let (lib, main) = (uri("lib.ncl"), uri("main.ncl"));
+        server.open(&lib, "{server = {port = 80}}");
+        server.open(&main, "let lib = import \"lib.ncl\" in\nlib.server.port");
+
+        assert_eq!(
+            definition(&mut server, &main, 1, 12),
+            location(lib.clone(), 0, 11, 15)
+        );
+        assert_eq!(definition(&mut server, &main, 1, 5), location(lib, 0, 1, 7));
+    }
+
+    #[test]
+    fn imported_data() {
+        let mut server = TestServer::new();
+        let main = uri("../../tests/imports/goto.ncl");
+        server.open(
+            &main,
+            "let data = import \"data.json\" in\nlet conf = import \"data.yaml\" in\n[data.port, conf.debug]",
+        );
+
+        assert_eq!(
+            definition(&mut server, &main, 2, 7),
+            location(uri("../../tests/imports/data.json"), 2, 2, 8)
+        );
+        assert_eq!(
+            definition(&mut server, &main, 2, 18),
+            location(uri("../../tests/imports/data.yaml"), 1, 0, 5)
+        );
+    }
+}
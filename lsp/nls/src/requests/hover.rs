@@ -85,3 +85,69 @@ pub fn handle(
     ));
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{request::HoverRequest, Url, WorkDoneProgressParams};
+
+    use super::*;
+    use crate::testing::{position_params, uri, TestServer};
+
+    fn hovered_type(server: &mut TestServer, uri: &Url, line: u32, character: u32) -> String {
+        let response = server.request::<HoverRequest>(HoverParams {
+            text_document_position_params: position_params(uri, line, character),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        });
+
+        match response {
+            Some(Hover {
+                contents: HoverContents::Array(contents),
+                ..
+            }) => match &contents[0] {
+                MarkedString::LanguageString(LanguageString { value, .. }) => value.clone(),
+                content => panic!("unexpected hover content {:?}", content),
+            },
+            response => panic!("unexpected hover {:?}", response),
+        }
+    }
+
+    #[test]
+    fn refined_variable() {
+        let mut server = TestServer::new();
+        let main = uri("main.ncl");
+        server.open(
+            &main,
+            "(fun x => if builtin.is_num x then x + 1 else 0) : Dyn -> Num",
+        );
+
+        assert_eq!(hovered_type(&mut server, &main, 0, 35), "Num");
+    }
+
+    #[test]
+    fn record_fields_shadow_refinements() {
+        let mut server = TestServer::new();
+        let main = uri("main.ncl");
+        server.open(
+            &main,
+            "(fun x => if builtin.is_num x\n\
+             then {x = \"a\", y = x ++ \"b\"}.y\n\
+             else \"c\") : Dyn -> Str",
+        );
+
+        assert_eq!(hovered_type(&mut server, &main, 1, 19), "Str");
+    }
+
+    #[test]
+    fn patterns_shadow_refinements() {
+        let mut server = TestServer::new();
+        let main = uri("main.ncl");
+        server.open(
+            &main,
+            "(fun x => if builtin.is_num x\n\
+             then let {x} = {x = \"b\"} in x\n\
+             else x) : Dyn -> Dyn",
+        );
+
+        assert_eq!(hovered_type(&mut server, &main, 1, 28), "Dyn");
+    }
+}
@@ -0,0 +1,450 @@
+//! Inlay hints, showing the types inferred for the variables of statically typed blocks and,
+//! optionally, the effective value of the fields of merged records.
+//!
+//! `textDocument/inlayHint` is part of version 3.17 of the protocol, which is not supported by
+//! the version of `lsp-types` used here, so the request and its types are defined in this
+//! module.
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+};
+
+use codespan::{ByteIndex, FileId};
+use codespan_lsp::{byte_index_to_position, position_to_byte_index};
+use lsp_server::{RequestId, Response, ResponseError};
+use lsp_types::{request::Request, Position, Range, TextDocumentIdentifier};
+use nickel_lang::{
+    identifier::Ident,
+    position::RawSpan,
+    term::{BinaryOp, MergePriority, MetaValue, RichTerm, Term},
+    types::{AbsType, Types},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    linearization::interface::{TermKind, ValueState},
+    server::Server,
+    term::subterms,
+    trace::{Enrich, Trace},
+};
+
+/// The maximum length of a value shown in a hint.
+const MAX_VALUE_WIDTH: usize = 24;
+
+pub enum InlayHintRequest {}
+
+impl Request for InlayHintRequest {
+    type Params = InlayHintParams;
+    type Result = Option<Vec<InlayHint>>;
+    const METHOD: &'static str = "textDocument/inlayHint";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHintParams {
+    pub text_document: TextDocumentIdentifier,
+    pub range: Range,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHint {
+    pub position: Position,
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<InlayHintKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tooltip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub padding_left: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(into = "u8", try_from = "u8")]
+pub enum InlayHintKind {
+    Type = 1,
+}
+
+impl From<InlayHintKind> for u8 {
+    fn from(kind: InlayHintKind) -> Self {
+        kind as u8
+    }
+}
+
+impl TryFrom<u8> for InlayHintKind {
+    type Error = String;
+
+    fn try_from(kind: u8) -> Result<Self, Self::Error> {
+        match kind {
+            1 => Ok(InlayHintKind::Type),
+            _ => Err(format!("invalid inlay hint kind {}", kind)),
+        }
+    }
+}
+
+pub fn handle_inlay_hints(
+    params: InlayHintParams,
+    id: RequestId,
+    server: &mut Server,
+) -> Result<(), ResponseError> {
+    let file_id = server
+        .cache
+        .id_of(params.text_document.uri.as_str())
+        .unwrap();
+
+    let start = byte_index(server, file_id, &params.range.start).unwrap_or(0);
+    let end = byte_index(server, file_id, &params.range.end).unwrap_or(usize::MAX);
+    let in_range = |index: ByteIndex| start <= index.to_usize() && index.to_usize() <= end;
+
+    let linearization = server.lin_cache_get(&file_id)?;
+    Trace::enrich(&id, linearization);
+
+    let term = match server.cache.terms().get(&file_id) {
+        Some(cached) => &cached.term,
+        None => {
+            server.reply(Response::new_ok(id, Vec::<InlayHint>::new()));
+            return Ok(());
+        }
+    };
+
+    let mut typed_blocks = Vec::new();
+    collect_typed_blocks(term, &mut typed_blocks);
+
+    // Types of the variables declared in typed blocks without annotation
+    let mut hints: Vec<(ByteIndex, InlayHint)> = linearization
+        .linearization
+        .iter()
+        .filter_map(|item| {
+            let span = item.pos.into_opt()?;
+            let annotated = match &item.kind {
+                TermKind::Declaration(_, _, ValueState::Known(value)) => linearization
+                    .get_item(*value)
+                    .and_then(|value| value.meta.as_ref())
+                    .map(|meta| meta.types.is_some() || !meta.contracts.is_empty())
+                    .unwrap_or(false),
+                TermKind::Declaration(_, _, ValueState::Unknown) => false,
+                _ => return None,
+            };
+
+            let typed = typed_blocks
+                .iter()
+                .any(|block| block.start <= span.start && span.end <= block.end);
+
+            if !typed || annotated || item.ty == Types(AbsType::Dyn()) || !in_range(span.end) {
+                return None;
+            }
+
+            Some((
+                span.end,
+                InlayHint {
+                    position: position(server, file_id, span.end)?,
+                    label: format!(": {}", item.ty),
+                    kind: Some(InlayHintKind::Type),
+                    tooltip: None,
+                    padding_left: Some(false),
+                },
+            ))
+        })
+        .collect();
+
+    if server.value_hints {
+        let mut merges = Vec::new();
+        collect_merges(term, &Env::new(), &mut merges);
+
+        hints.extend(
+            merges
+                .into_iter()
+                .filter(|(span, _)| in_range(span.end))
+                .filter_map(|(span, fields)| {
+                    merge_hint(server, file_id, span, fields).map(|hint| (span.end, hint))
+                }),
+        );
+    }
+
+    hints.sort_by_key(|(index, _)| *index);
+    let hints: Vec<InlayHint> = hints.into_iter().map(|(_, hint)| hint).collect();
+
+    server.reply(Response::new_ok(id, hints));
+    Ok(())
+}
+
+/// The byte index of a position of the requested range. The range may have been computed for a
+/// previous version of the document, so positions after its last line map to its end.
+fn byte_index(server: &Server, file_id: FileId, position: &Position) -> Option<usize> {
+    let source = server.cache.files().source(file_id);
+
+    if position.line as usize > source.matches('\n').count() {
+        Some(source.len())
+    } else {
+        position_to_byte_index(server.cache.files(), file_id, position).ok()
+    }
+}
+
+/// The position of a byte index in a file, if the index is within the file.
+fn position(server: &Server, file_id: FileId, index: ByteIndex) -> Option<Position> {
+    byte_index_to_position(server.cache.files(), file_id, index.to_usize()).ok()
+}
+
+/// Collect the spans of the statically typed blocks of a term, that is the terms annotated with
+/// a type.
+fn collect_typed_blocks(rt: &RichTerm, blocks: &mut Vec<RawSpan>) {
+    match rt.as_ref() {
+        Term::MetaValue(MetaValue {
+            types: Some(_),
+            value: Some(value),
+            ..
+        }) => {
+            if let Some(span) = rt.pos.into_opt().or_else(|| value.pos.into_opt()) {
+                blocks.push(span);
+            }
+        }
+        _ => subterms(rt)
+            .into_iter()
+            .for_each(|t| collect_typed_blocks(t, blocks)),
+    }
+}
+
+/// The record bound to the variables in scope, when it is known statically.
+type Env<'a> = HashMap<Ident, &'a RichTerm>;
+
+/// The definition of a field of a record known statically.
+struct Field<'a> {
+    value: &'a RichTerm,
+    default: bool,
+    /// The variable through which the field was merged, if it was not written in the merge
+    /// expression itself.
+    origin: Option<&'a Ident>,
+}
+
+/// Collect the outermost merge expressions of a term, together with the fields of their result
+/// if they can be determined statically.
+fn collect_merges<'a>(
+    rt: &'a RichTerm,
+    env: &Env<'a>,
+    merges: &mut Vec<(RawSpan, BTreeMap<Ident, Field<'a>>)>,
+) {
+    match rt.as_ref() {
+        Term::Op2(BinaryOp::Merge(), ..) => {
+            if let (Some(span), Some(fields)) = (rt.pos.into_opt(), static_record(rt, env, None)) {
+                merges.push((span, fields));
+            }
+
+            // The operands may contain other merges, but the merges they are made of are part of
+            // this one
+            let mut operands = vec![rt];
+            while let Some(operand) = operands.pop() {
+                match operand.as_ref() {
+                    Term::Op2(BinaryOp::Merge(), t1, t2) => {
+                        operands.push(t1);
+                        operands.push(t2);
+                    }
+                    _ => collect_merges(operand, env, merges),
+                }
+            }
+        }
+        Term::Let(id, bound, body, _) => {
+            collect_merges(bound, env, merges);
+            let mut env = env.clone();
+            env.insert(id.clone(), bound);
+            collect_merges(body, &env, merges);
+        }
+        Term::Fun(id, body) => {
+            let mut env = env.clone();
+            env.remove(id);
+            collect_merges(body, &env, merges);
+        }
+        _ => subterms(rt)
+            .into_iter()
+            .for_each(|t| collect_merges(t, env, merges)),
+    }
+}
+
+/// Compute the fields of a record literal, a variable bound to a record, or a merge of such
+/// records. Return `None` if the term is something else, or if the merge can't be determined
+/// without evaluation.
+fn static_record<'a>(
+    rt: &'a RichTerm,
+    env: &Env<'a>,
+    origin: Option<&'a Ident>,
+) -> Option<BTreeMap<Ident, Field<'a>>> {
+    match rt.as_ref() {
+        Term::Record(fields, _) | Term::RecRecord(fields, ..) => Some(
+            fields
+                .iter()
+                .filter_map(|(id, value)| {
+                    let field = match value.as_ref() {
+                        Term::MetaValue(MetaValue {
+                            value: Some(value),
+                            priority,
+                            ..
+                        }) => Field {
+                            value,
+                            default: *priority == MergePriority::Default,
+                            origin,
+                        },
+                        // Fields without definition only carry contracts
+                        Term::MetaValue(_) => return None,
+                        _ => Field {
+                            value,
+                            default: false,
+                            origin,
+                        },
+                    };
+                    Some((id.clone(), field))
+                })
+                .collect(),
+        ),
+        Term::Var(id) => static_record(env.get(id)?, env, origin.or(Some(id))),
+        Term::MetaValue(MetaValue {
+            value: Some(value), ..
+        }) => static_record(value, env, origin),
+        Term::Op2(BinaryOp::Merge(), t1, t2) => {
+            let mut fields = static_record(t1, env, origin)?;
+
+            for (id, right) in static_record(t2, env, origin)? {
+                let merged = match fields.remove(&id) {
+                    None => right,
+                    Some(left) if left.default && !right.default => right,
+                    Some(left) if right.default && !left.default => left,
+                    // Merging two definitions of the same priority requires evaluation
+                    Some(_) => return None,
+                };
+                fields.insert(id, merged);
+            }
+
+            Some(fields)
+        }
+        _ => None,
+    }
+}
+
+/// Build the hint showing the effective value of the fields of a merge coming from a default
+/// value or from another record, if there are any.
+fn merge_hint(
+    server: &Server,
+    file_id: FileId,
+    span: RawSpan,
+    fields: BTreeMap<Ident, Field>,
+) -> Option<InlayHint> {
+    let shown: Vec<_> = fields
+        .iter()
+        .filter(|(_, field)| field.default || field.origin.is_some())
+        .collect();
+
+    if shown.is_empty() {
+        return None;
+    }
+
+    let label = shown
+        .iter()
+        .map(|(id, field)| format!("{} = {}", id, field.value.pretty_print_cap(MAX_VALUE_WIDTH)))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let tooltip = shown
+        .iter()
+        .map(|(id, field)| match (field.default, field.origin) {
+            (true, Some(origin)) => format!("{}: default value from `{}`", id, origin),
+            (true, None) => format!("{}: default value", id),
+            (false, Some(origin)) => format!("{}: from `{}`", id, origin),
+            (false, None) => unreachable!(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Some(InlayHint {
+        position: position(server, file_id, span.end)?,
+        label: format!("\u{21d2} {{ {} }}", label),
+        kind: None,
+        tooltip: Some(tooltip),
+        padding_left: Some(true),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::Url;
+
+    use super::*;
+    use crate::testing::{uri, TestServer};
+
+    /// Open `text` and return the hints of the whole document, as `(line, character, label)`.
+    fn hints(server: &mut TestServer, text: &str) -> Vec<(u32, u32, String)> {
+        let range = Range::new(Position::new(0, 0), Position::new(u32::MAX, 0));
+        hints_in(server, text, range)
+    }
+
+    /// Open `text` and return the hints of `range`, as `(line, character, label)`.
+    fn hints_in(server: &mut TestServer, text: &str, range: Range) -> Vec<(u32, u32, String)> {
+        let main: Url = uri("main.ncl");
+        server.open(&main, text);
+
+        server
+            .request::<InlayHintRequest>(InlayHintParams {
+                text_document: TextDocumentIdentifier { uri: main },
+                range,
+            })
+            .unwrap()
+            .into_iter()
+            .map(|hint| (hint.position.line, hint.position.character, hint.label))
+            .collect()
+    }
+
+    #[test]
+    fn inferred_types() {
+        let mut server = TestServer::new();
+        let hints = hints(
+            &mut server,
+            "let f : Num -> Num = fun x =>\n  let y = x + 1 in\n  let z : Num = y in\n  z\nin f 1",
+        );
+
+        assert_eq!(
+            hints,
+            vec![
+                (0, 26, String::from(": Num")),
+                (1, 7, String::from(": Num")),
+            ]
+        );
+    }
+
+    #[test]
+    fn range() {
+        let mut server = TestServer::new();
+        let hints = hints_in(
+            &mut server,
+            "let f : Num -> Num = fun x =>\n  let y = x + 1 in\n  y\nin f 1",
+            Range::new(Position::new(1, 0), Position::new(2, 0)),
+        );
+
+        assert_eq!(hints, vec![(1, 7, String::from(": Num"))]);
+    }
+
+    #[test]
+    fn untyped_code() {
+        let mut server = TestServer::new();
+        assert_eq!(
+            hints(&mut server, "let f = fun x => let y = x + 1 in y in f 1"),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn merged_values() {
+        let mut server = TestServer::new();
+        let text = "let base = {port | default = 80, host = \"a\"} in\nbase & {name = \"b\"}";
+        assert_eq!(hints(&mut server, text), vec![]);
+
+        server.server.value_hints = true;
+        assert_eq!(
+            hints(&mut server, text),
+            vec![(1, 19, String::from("\u{21d2} { host = \"a\", port = 80 }"))]
+        );
+    }
+
+    #[test]
+    fn conflicting_merge() {
+        let mut server = TestServer::new();
+        server.server.value_hints = true;
+        assert_eq!(hints(&mut server, "{port = 80} & {port = 443}"), vec![]);
+    }
+}
@@ -0,0 +1,137 @@
+use std::{ffi::OsString, ops::Range};
+
+use lsp_server::{RequestId, Response, ResponseError};
+use lsp_types::{DocumentLink, DocumentLinkParams};
+use nickel_lang::parser::lexer::{Lexer, NormalToken, StringToken, Token};
+
+use crate::{cache::CacheExt, server::Server};
+
+pub fn handle_document_links(
+    params: DocumentLinkParams,
+    id: RequestId,
+    server: &mut Server,
+) -> Result<(), ResponseError> {
+    let file_id = server
+        .cache
+        .id_of(params.text_document.uri.as_str())
+        .unwrap();
+
+    let links = import_paths(server.cache.files().source(file_id))
+        .into_iter()
+        .filter_map(|(span, path)| {
+            let target = server.cache.import_uri(file_id, &path)?;
+            // Only link to files that exist, as an import may still be being typed.
+            target
+                .to_file_path()
+                .map(|file_path| file_path.exists())
+                .unwrap_or(false)
+                .then(|| DocumentLink {
+                    range: codespan_lsp::byte_span_to_range(server.cache.files(), file_id, span)
+                        .unwrap(),
+                    target: Some(target),
+                    tooltip: None,
+                    data: None,
+                })
+        })
+        .collect::<Vec<_>>();
+
+    server.reply(Response::new_ok(id, links));
+    Ok(())
+}
+
+/// Return the path of each import of a source, together with the span of the path, excluding
+/// the quotes. Imports are found by lexing the source, so that the links are available even if
+/// the source doesn't parse.
+fn import_paths(source: &str) -> Vec<(Range<usize>, OsString)> {
+    let mut tokens = Lexer::new(source).filter_map(Result::ok);
+    let mut paths = Vec::new();
+
+    while let Some((_, token, _)) = tokens.next() {
+        if token != Token::Normal(NormalToken::Import) {
+            continue;
+        }
+
+        let start = match tokens.next() {
+            Some((_, Token::Normal(NormalToken::DoubleQuote), start)) => start,
+            _ => continue,
+        };
+
+        let mut path = String::new();
+        for (_, token, end) in tokens.by_ref() {
+            match token {
+                Token::Str(StringToken::Literal(s)) => path.push_str(s),
+                Token::Str(StringToken::EscapedChar(c)) => path.push(c),
+                Token::Str(StringToken::EscapedAscii(code)) => {
+                    path.push(u8::from_str_radix(code, 16).unwrap() as char)
+                }
+                Token::Normal(NormalToken::DoubleQuote) => {
+                    paths.push((start..end - 1, OsString::from(path)));
+                    break;
+                }
+                // Interpolated strings are not valid import paths.
+                _ => break,
+            }
+        }
+    }
+
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{
+        request::DocumentLinkRequest, PartialResultParams, Position, Range, TextDocumentIdentifier,
+        WorkDoneProgressParams,
+    };
+
+    use super::*;
+    use crate::testing::{uri, TestServer};
+
+    #[test]
+    fn existing_imports() {
+        let mut server = TestServer::new();
+        let main = uri("../../tests/imports/links.ncl");
+        server.open(
+            &main,
+            "let data = import \"data.json\" in\nlet missing = import \"missing.ncl\" in\n[data, import \"data.yaml\"]",
+        );
+
+        let links = server
+            .request::<DocumentLinkRequest>(DocumentLinkParams {
+                text_document: TextDocumentIdentifier { uri: main },
+                work_done_progress_params: WorkDoneProgressParams::default(),
+                partial_result_params: PartialResultParams::default(),
+            })
+            .unwrap();
+
+        let links: Vec<_> = links
+            .into_iter()
+            .map(|link| (link.range, link.target.unwrap()))
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                (
+                    Range::new(Position::new(0, 19), Position::new(0, 28)),
+                    uri("../../tests/imports/data.json")
+                ),
+                (
+                    Range::new(Position::new(2, 15), Position::new(2, 24)),
+                    uri("../../tests/imports/data.yaml")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unparsable_document() {
+        let paths: Vec<_> = import_paths("let x = import \"a\\x62.ncl\" in\n(import \"c.ncl\"")
+            .into_iter()
+            .map(|(_, path)| path)
+            .collect();
+        assert_eq!(
+            paths,
+            vec![OsString::from("ab.ncl"), OsString::from("c.ncl")]
+        );
+    }
+}
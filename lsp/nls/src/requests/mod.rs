@@ -1,4 +1,10 @@
+pub mod code_actions;
 pub mod completion;
 pub mod goto;
 pub mod hover;
+pub mod inlay_hints;
+pub mod links;
+pub mod rename;
+pub mod semantic_tokens;
+pub mod signature_help;
 pub mod symbols;
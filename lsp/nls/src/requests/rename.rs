@@ -0,0 +1,620 @@
+use std::collections::HashMap;
+
+use codespan::{ByteIndex, FileId};
+use codespan_lsp::position_to_byte_index;
+use log::debug;
+use lsp_server::{ErrorCode, RequestId, Response, ResponseError};
+use lsp_types::{
+    PrepareRenameResponse, Range, RenameParams, TextDocumentPositionParams, TextEdit, Url,
+    WorkspaceEdit,
+};
+use nickel_lang::{
+    cache::EntryState,
+    identifier::Ident,
+    parser::lexer::{Lexer, NormalToken, Token},
+    position::RawSpan,
+};
+use serde_json::Value;
+
+use crate::{
+    cache::CacheExt,
+    diagnostic::LocationCompat,
+    linearization::{
+        completed::Completed,
+        interface::{Resolved, TermKind, UsageState, ValueState},
+        LinearizationItem,
+    },
+    server::Server,
+    term::root_record_pos,
+    trace::{Enrich, Trace},
+};
+
+type Item = LinearizationItem<Resolved>;
+
+pub fn handle_prepare_rename(
+    params: TextDocumentPositionParams,
+    id: RequestId,
+    server: &mut Server,
+) -> Result<(), ResponseError> {
+    let file_id = server
+        .cache
+        .id_of(params.text_document.uri.as_str())
+        .unwrap();
+
+    let start = position_to_byte_index(server.cache.files(), file_id, &params.position).unwrap();
+
+    let locator = (file_id, ByteIndex(start as u32));
+    let linearization = server.lin_cache_get(&file_id)?;
+
+    Trace::enrich(&id, linearization);
+
+    let response = linearization
+        .item_at(&locator)
+        .filter(|item| declaration_of(linearization, item).is_some())
+        .and_then(|item| item.pos.into_opt())
+        .filter(|span| identifier_at(server, span).is_some())
+        .map(|span| PrepareRenameResponse::RangeWithPlaceholder {
+            range: Range::from_codespan(
+                &span.src_id,
+                &(span.start.to_usize()..span.end.to_usize()),
+                server.cache.files(),
+            ),
+            placeholder: identifier_at(server, &span).unwrap().to_owned(),
+        });
+
+    debug!("prepare rename: {:?}", response);
+
+    match response {
+        Some(response) => server.reply(Response::new_ok(id, response)),
+        None => server.reply(Response::new_ok(id, Value::Null)),
+    }
+    Ok(())
+}
+
+pub fn handle_rename(
+    params: RenameParams,
+    id: RequestId,
+    server: &mut Server,
+) -> Result<(), ResponseError> {
+    let file_id = server
+        .cache
+        .id_of(params.text_document_position.text_document.uri.as_str())
+        .unwrap();
+
+    let start = position_to_byte_index(
+        server.cache.files(),
+        file_id,
+        &params.text_document_position.position,
+    )
+    .unwrap();
+
+    let locator = (file_id, ByteIndex(start as u32));
+    let linearization = server.lin_cache_get(&file_id)?;
+
+    Trace::enrich(&id, linearization);
+
+    if !is_identifier(&params.new_name) {
+        return Err(ResponseError {
+            data: None,
+            message: format!("`{}` is not a valid identifier", params.new_name),
+            code: ErrorCode::InvalidParams as i32,
+        });
+    }
+
+    let declaration = linearization
+        .item_at(&locator)
+        .and_then(|item| declaration_of(linearization, item))
+        .ok_or_else(|| ResponseError {
+            data: None,
+            message: "No variable or record field to rename at this position".to_owned(),
+            code: ErrorCode::InvalidParams as i32,
+        })?;
+
+    let changes = rename(
+        server,
+        file_id,
+        linearization,
+        declaration,
+        &Ident::from(params.new_name.as_str()),
+    )
+    .map_err(|message| ResponseError {
+        data: None,
+        message,
+        code: ErrorCode::InvalidRequest as i32,
+    })?;
+
+    debug!("rename edits: {:?}", changes);
+
+    server.reply(Response::new_ok(id, WorkspaceEdit::new(changes)));
+    Ok(())
+}
+
+/// Return the declaration referred to by `item`: a let binding, a function parameter or a record
+/// field, either declared or used at the position of `item`.
+fn declaration_of<'a>(linearization: &'a Completed, item: &'a Item) -> Option<&'a Item> {
+    let declaration = match item.kind {
+        TermKind::Usage(UsageState::Resolved(declaration)) => {
+            linearization.get_item(declaration)?
+        }
+        _ => item,
+    };
+
+    match declaration.kind {
+        TermKind::Declaration(..) | TermKind::RecordField { .. } => Some(declaration),
+        _ => None,
+    }
+}
+
+/// Compute the edits renaming `declaration` and all its usages to `new_name`, in the file of the
+/// declaration and, for record fields, in the files importing it. Return an error message if the
+/// rename would change the meaning of the program, or if a file to edit has errors, as its
+/// linearization doesn't match its content anymore.
+fn rename(
+    server: &Server,
+    file_id: FileId,
+    linearization: &Completed,
+    declaration: &Item,
+    new_name: &Ident,
+) -> Result<HashMap<Url, Vec<TextEdit>>, String> {
+    let (old_name, usages) = match &declaration.kind {
+        TermKind::Declaration(ident, usages, _) => (ident, usages),
+        TermKind::RecordField {
+            ident,
+            record,
+            usages,
+            ..
+        } => {
+            if let Some(TermKind::Record(fields)) =
+                linearization.get_item(*record).map(|item| &item.kind)
+            {
+                if fields.contains_key(new_name) {
+                    return Err(format!("The record already has a field `{}`", new_name));
+                }
+            }
+
+            (ident, usages)
+        }
+        _ => unreachable!(),
+    };
+
+    if old_name == new_name {
+        return Ok(HashMap::new());
+    }
+
+    check_up_to_date(server, file_id, old_name)?;
+
+    let usages: Vec<&Item> = usages
+        .iter()
+        .filter_map(|usage| linearization.get_item(*usage))
+        .collect();
+
+    check_capture(server, linearization, declaration, &usages, new_name)?;
+
+    let mut spans: Vec<RawSpan> = std::iter::once(declaration)
+        .chain(usages.iter().copied())
+        .filter_map(|item| item.pos.into_opt())
+        .collect();
+
+    // Accesses to a field of the record defined by this file can appear in the files importing
+    // it.
+    if let TermKind::RecordField { .. } = declaration.kind {
+        if let Some(path) = field_path(server, file_id, linearization, declaration) {
+            spans.extend(imported_usages(server, file_id, &path, new_name)?);
+        }
+    }
+
+    let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+
+    for span in spans {
+        if identifier_at(server, &span) != Some(old_name.label.as_str()) {
+            return Err(format!(
+                "`{}` can't be renamed, as it is not always written as an identifier",
+                old_name
+            ));
+        }
+
+        let uri = Url::parse(&server.cache.name(span.src_id).to_string_lossy())
+            .map_err(|err| err.to_string())?;
+        let range = Range::from_codespan(
+            &span.src_id,
+            &(span.start.to_usize()..span.end.to_usize()),
+            server.cache.files(),
+        );
+
+        changes
+            .entry(uri)
+            .or_default()
+            .push(TextEdit::new(range, new_name.label.clone()));
+    }
+
+    Ok(changes)
+}
+
+/// Check that renaming `declaration` to `new_name` doesn't change the declaration any variable
+/// refers to. This is the case if another declaration of `new_name` is visible where
+/// `declaration` is used, or if a variable `new_name` is used where `declaration` is visible.
+///
+/// Scopes are approximated by the scopes of the linearization, so the check may refuse some
+/// renames that would actually be safe.
+fn check_capture(
+    server: &Server,
+    linearization: &Completed,
+    declaration: &Item,
+    usages: &[&Item],
+    new_name: &Ident,
+) -> Result<(), String> {
+    let declares_new_name = |item: &Item| match &item.kind {
+        TermKind::Declaration(ident, ..) | TermKind::RecordField { ident, .. } => {
+            ident == new_name && item.id != declaration.id
+        }
+        _ => false,
+    };
+
+    // The usages of `declaration` as a variable would be captured by another declaration of
+    // `new_name`.
+    let shadowed = std::iter::once(declaration)
+        .chain(
+            usages
+                .iter()
+                .copied()
+                .filter(|usage| !is_field_access(server, usage)),
+        )
+        .any(|site| {
+            linearization
+                .get_in_scope(site)
+                .into_iter()
+                .any(declares_new_name)
+        });
+
+    if shadowed {
+        return Err(format!(
+            "Renaming to `{}` would conflict with another declaration of `{}`",
+            new_name, new_name
+        ));
+    }
+
+    // Variables named `new_name` used where `declaration` is visible would now refer to
+    // `declaration`.
+    let captures = linearization.linearization.iter().any(|item| {
+        item.scope.starts_with(&declaration.scope)
+            && !is_field_access(server, item)
+            && match item.kind {
+                TermKind::Usage(UsageState::Resolved(referenced)) => linearization
+                    .get_item(referenced)
+                    .map(declares_new_name)
+                    .unwrap_or(false),
+                TermKind::Usage(UsageState::Unbound) => item
+                    .pos
+                    .as_opt_ref()
+                    .and_then(|span| identifier_at(server, span))
+                    .map(|name| name == new_name.label)
+                    .unwrap_or(false),
+                _ => false,
+            }
+    });
+
+    if captures {
+        return Err(format!(
+            "Renaming to `{}` would capture other uses of `{}`",
+            new_name, new_name
+        ));
+    }
+
+    Ok(())
+}
+
+/// Return the path of a record field from the record defined by the file, such as
+/// `["server", "port"]` for `port` in `{server = {port = 80}}`, if there is one.
+fn field_path(
+    server: &Server,
+    file_id: FileId,
+    linearization: &Completed,
+    field: &Item,
+) -> Option<Vec<Ident>> {
+    let root_pos = server
+        .cache
+        .terms()
+        .get(&file_id)
+        .map(|cached| root_record_pos(&cached.term))?;
+    let mut path = Vec::new();
+    let mut field = field;
+
+    loop {
+        let (ident, record) = match &field.kind {
+            TermKind::RecordField { ident, record, .. } => (ident, *record),
+            _ => return None,
+        };
+        path.push(ident.clone());
+
+        if root_pos.is_def() && linearization.get_item(record)?.pos == root_pos {
+            path.reverse();
+            return Some(path);
+        }
+
+        // Go up to the field whose value is the enclosing record
+        field = linearization
+            .linearization
+            .iter()
+            .find(|item| match item.kind {
+                TermKind::RecordField {
+                    value: ValueState::Known(value),
+                    ..
+                } => value == record,
+                _ => false,
+            })?;
+    }
+}
+
+/// Return the positions of the accesses to the field at `path` of the record defined by
+/// `file_id`, in the files importing it. The importers are found among the files known to the
+/// server, that is the open files and the files they import.
+///
+/// Return an error message if an importer has errors, or if it accesses a field `new_name` next
+/// to the renamed one: such an access would refer to the renamed field afterwards.
+fn imported_usages(
+    server: &Server,
+    file_id: FileId,
+    path: &[Ident],
+    new_name: &Ident,
+) -> Result<Vec<RawSpan>, String> {
+    let (old_name, parent) = path.split_last().unwrap();
+    let mut captured = parent.to_vec();
+    captured.push(new_name.clone());
+    let mut spans = Vec::new();
+
+    for (importer, linearization) in server.lin_cache.iter() {
+        if *importer == file_id || !imports(server, *importer, linearization, file_id) {
+            continue;
+        }
+
+        check_up_to_date(server, *importer, old_name)?;
+
+        for item in linearization.linearization.iter() {
+            let fields = match &item.kind {
+                TermKind::Usage(UsageState::Imported { import, fields })
+                    if is_import_of(server, *importer, linearization, *import, file_id) =>
+                {
+                    fields
+                }
+                _ => continue,
+            };
+
+            if fields.as_slice() == path {
+                spans.extend(item.pos.into_opt());
+            } else if *fields == captured {
+                return Err(format!(
+                    "Renaming to `{}` would capture an access to `{}` in {}",
+                    new_name,
+                    new_name,
+                    server.cache.name(*importer).to_string_lossy()
+                ));
+            }
+        }
+    }
+
+    Ok(spans)
+}
+
+/// Whether the file `importer`, whose linearization is `linearization`, imports `file_id`.
+fn imports(server: &Server, importer: FileId, linearization: &Completed, file_id: FileId) -> bool {
+    linearization
+        .linearization
+        .iter()
+        .any(|item| is_import_of(server, importer, linearization, item.id, file_id))
+}
+
+/// Whether the item `import` of the linearization of `importer` is an import of `file_id`.
+fn is_import_of(
+    server: &Server,
+    importer: FileId,
+    linearization: &Completed,
+    import: usize,
+    file_id: FileId,
+) -> bool {
+    match linearization.get_item(import).map(|item| &item.kind) {
+        Some(TermKind::Import(imported)) => {
+            import_target(server, importer, imported) == Some(file_id)
+        }
+        _ => false,
+    }
+}
+
+/// Check that the linearization of a file is up to date with its content, that is that its last
+/// version has been typechecked successfully. Otherwise, the positions of the linearization may
+/// not match the content of the file anymore.
+fn check_up_to_date(server: &Server, file_id: FileId, old_name: &Ident) -> Result<(), String> {
+    if matches!(server.cache.entry_state(file_id), Some(state) if state >= EntryState::Typechecked)
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "`{}` can't be renamed while {} has errors",
+            old_name,
+            server.cache.name(file_id).to_string_lossy()
+        ))
+    }
+}
+
+/// Return the id of the file imported by `path` from `importer`, if it is known to the cache.
+fn import_target(server: &Server, importer: FileId, path: &std::ffi::OsStr) -> Option<FileId> {
+    let target = server.cache.import_uri(importer, path)?;
+    server.cache.id_of(target.as_str())
+}
+
+/// Whether the item is the field of a record access `r.field` rather than a variable.
+fn is_field_access(server: &Server, item: &Item) -> bool {
+    item.pos
+        .as_opt_ref()
+        .map(|span| {
+            server.cache.files().source(span.src_id)[..span.start.to_usize()]
+                .trim_end()
+                .ends_with('.')
+        })
+        .unwrap_or(false)
+}
+
+/// Return the source text at `span` if it is an identifier.
+fn identifier_at<'a>(server: &'a Server, span: &RawSpan) -> Option<&'a str> {
+    server
+        .cache
+        .files()
+        .source(span.src_id)
+        .get(span.start.to_usize()..span.end.to_usize())
+        .filter(|text| is_identifier(text))
+}
+
+/// Whether `name` is lexed as a single identifier, and not for example as a keyword.
+fn is_identifier(name: &str) -> bool {
+    let mut tokens = Lexer::new(name);
+
+    matches!(
+        (tokens.next(), tokens.next()),
+        (Some(Ok((_, Token::Normal(NormalToken::Identifier(id)), _))), None) if id == name
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{request::Rename, Position, WorkDoneProgressParams};
+
+    use super::*;
+    use crate::testing::{position_params, uri, TestServer};
+
+    fn params(uri: &Url, line: u32, character: u32, new_name: &str) -> RenameParams {
+        RenameParams {
+            text_document_position: position_params(uri, line, character),
+            new_name: new_name.to_owned(),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        }
+    }
+
+    /// An edited range on a single line, as `(line, start, end)`.
+    type LineRange = (u32, u32, u32);
+
+    /// Return the edited ranges of each file, sorted.
+    fn ranges(edit: WorkspaceEdit, new_name: &str) -> Vec<(Url, Vec<LineRange>)> {
+        let mut files: Vec<_> = edit
+            .changes
+            .unwrap()
+            .into_iter()
+            .map(|(uri, edits)| {
+                let mut ranges: Vec<_> = edits
+                    .into_iter()
+                    .map(|edit| {
+                        assert_eq!(edit.new_text, new_name);
+                        let Range { start, end } = edit.range;
+                        assert_eq!(start.line, end.line);
+                        (start.line, start.character, end.character)
+                    })
+                    .collect();
+                ranges.sort();
+                (uri, ranges)
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn renames_variable() {
+        let mut server = TestServer::new();
+        let file = uri("main.ncl");
+        server.open(&file, "let x = 1 in\nlet y = x + 1 in\nx + y");
+
+        let edit = server.request::<Rename>(params(&file, 0, 4, "z")).unwrap();
+        assert_eq!(
+            ranges(edit, "z"),
+            vec![(file, vec![(0, 4, 5), (1, 8, 9), (2, 0, 1)])]
+        );
+
+        let prepared = server
+            .request::<lsp_types::request::PrepareRenameRequest>(position_params(
+                &uri("main.ncl"),
+                2,
+                0,
+            ))
+            .unwrap();
+        assert_eq!(
+            prepared,
+            PrepareRenameResponse::RangeWithPlaceholder {
+                range: Range::new(Position::new(2, 0), Position::new(2, 1)),
+                placeholder: String::from("x"),
+            }
+        );
+    }
+
+    #[test]
+    fn refuses_capture() {
+        let mut server = TestServer::new();
+        let file = uri("main.ncl");
+        server.open(&file, "let x = 1 in let y = 2 in x + y");
+
+        let error = server
+            .try_request::<Rename>(params(&file, 0, 4, "y"))
+            .unwrap_err();
+        assert!(error.contains("`y`"), "{}", error);
+    }
+
+    #[test]
+    fn refuses_field_collision() {
+        let mut server = TestServer::new();
+        let file = uri("main.ncl");
+        server.open(&file, "{foo = 1, bar = 2}");
+
+        assert!(server
+            .try_request::<Rename>(params(&file, 0, 1, "bar"))
+            .is_err());
+        assert!(server
+            .try_request::<Rename>(params(&file, 0, 1, "baz"))
+            .is_ok());
+    }
+
+    #[test]
+    fn renames_imported_fields() {
+        let mut server = TestServer::new();
+        let (lib, main) = (uri("lib.ncl"), uri("main.ncl"));
+        server.open(&lib, "{server = {port = 80}}");
+        server.open(&main, "let lib = import \"lib.ncl\" in lib.server.port");
+
+        let edit = server
+            .request::<Rename>(params(&lib, 0, 12, "listen"))
+            .unwrap();
+        assert_eq!(
+            ranges(edit, "listen"),
+            vec![(lib, vec![(0, 11, 15)]), (main, vec![(0, 41, 45)])]
+        );
+    }
+
+    #[test]
+    fn refuses_capture_in_importer() {
+        let mut server = TestServer::new();
+        let (lib, main) = (uri("lib.ncl"), uri("main.ncl"));
+        server.open(&lib, "{server = {port = 80}}");
+        server.open(
+            &main,
+            "let lib = import \"lib.ncl\" in [lib.server.port, lib.server.host]",
+        );
+
+        let error = server
+            .try_request::<Rename>(params(&lib, 0, 12, "host"))
+            .unwrap_err();
+        assert!(error.contains("main.ncl"), "{}", error);
+    }
+
+    #[test]
+    fn refuses_when_importer_has_errors() {
+        let mut server = TestServer::new();
+        let (lib, main) = (uri("lib.ncl"), uri("main.ncl"));
+        server.open(&lib, "{server = {port = 80}}");
+        server.open(&main, "let lib = import \"lib.ncl\" in lib.server.port");
+        server.change(
+            &main,
+            "let lib = import \"lib.ncl\" in\n  lib.server.port + (\"80\" : Num)",
+        );
+
+        let error = server
+            .try_request::<Rename>(params(&lib, 0, 12, "listen"))
+            .unwrap_err();
+        assert!(error.contains("has errors"), "{}", error);
+    }
+}
@@ -0,0 +1,636 @@
+//! Semantic highlighting of Nickel documents.
+//!
+//! Tokens are produced by the lexer of Nickel, so that highlighting is available even if the
+//! document doesn't parse. Identifiers are then classified using the linearization of the
+//! document: declarations, usages, record fields and members of the standard library each get
+//! their own token type. The linearization is only used if it is up to date with the content of
+//! the document, that is if the last version was successfully typechecked.
+use std::{collections::HashMap, ops::Range};
+
+use codespan::FileId;
+use codespan_lsp::{byte_index_to_position, position_to_byte_index};
+use lsp_server::{RequestId, Response, ResponseError};
+use lsp_types::{
+    SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokens, SemanticTokensLegend,
+    SemanticTokensParams, SemanticTokensRangeParams, SemanticTokensRangeResult,
+    SemanticTokensResult,
+};
+use nickel_lang::{
+    cache::EntryState,
+    identifier::Ident,
+    parser::lexer::{Lexer, MultiStringToken, NormalToken, StringToken, Token},
+    term::{MetaValue, RichTerm, Term},
+};
+
+use crate::{
+    linearization::interface::{TermKind, UsageState},
+    server::Server,
+    term::subterms,
+    trace::{Enrich, Trace},
+};
+
+/// The token types, in the order of the legend.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TokenType {
+    Namespace,
+    Type,
+    EnumMember,
+    Property,
+    Variable,
+    Function,
+    Keyword,
+    String,
+    Number,
+    Operator,
+}
+
+const TOKEN_TYPES: [SemanticTokenType; 10] = [
+    SemanticTokenType::NAMESPACE,
+    SemanticTokenType::TYPE,
+    SemanticTokenType::ENUM_MEMBER,
+    SemanticTokenType::PROPERTY,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::STRING,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::OPERATOR,
+];
+
+/// The token modifiers, as bits of a modifier set, in the order of the legend.
+const DECLARATION: u32 = 1;
+const DEFAULT_LIBRARY: u32 = 1 << 1;
+
+const TOKEN_MODIFIERS: [SemanticTokenModifier; 2] = [
+    SemanticTokenModifier::DECLARATION,
+    SemanticTokenModifier::DEFAULT_LIBRARY,
+];
+
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TOKEN_TYPES.to_vec(),
+        token_modifiers: TOKEN_MODIFIERS.to_vec(),
+    }
+}
+
+/// A classified token of the source.
+struct Classified {
+    span: Range<usize>,
+    ty: TokenType,
+    modifiers: u32,
+}
+
+pub fn handle_semantic_tokens(
+    params: SemanticTokensParams,
+    id: RequestId,
+    server: &mut Server,
+) -> Result<(), ResponseError> {
+    let file_id = server
+        .cache
+        .id_of(params.text_document.uri.as_str())
+        .unwrap();
+
+    let tokens = classify(server, file_id, &id);
+    let data = encode(server, file_id, tokens);
+
+    server.reply(Response::new_ok(
+        id,
+        SemanticTokensResult::Tokens(SemanticTokens {
+            result_id: None,
+            data,
+        }),
+    ));
+    Ok(())
+}
+
+pub fn handle_semantic_tokens_range(
+    params: SemanticTokensRangeParams,
+    id: RequestId,
+    server: &mut Server,
+) -> Result<(), ResponseError> {
+    let file_id = server
+        .cache
+        .id_of(params.text_document.uri.as_str())
+        .unwrap();
+
+    let files = server.cache.files();
+    let start = position_to_byte_index(files, file_id, &params.range.start).unwrap_or(0);
+    let end = position_to_byte_index(files, file_id, &params.range.end).unwrap_or(usize::MAX);
+
+    let tokens = classify(server, file_id, &id)
+        .into_iter()
+        .filter(|token| start < token.span.end && token.span.start < end);
+    let data = encode(server, file_id, tokens);
+
+    server.reply(Response::new_ok(
+        id,
+        SemanticTokensRangeResult::Tokens(SemanticTokens {
+            result_id: None,
+            data,
+        }),
+    ));
+    Ok(())
+}
+
+/// Lex a document and classify its tokens, in order of appearance. Lexing stops at the first
+/// error, leaving the rest of the document unhighlighted.
+fn classify(server: &Server, file_id: FileId, id: &RequestId) -> Vec<Classified> {
+    let source = server.cache.files().source(file_id);
+
+    // The spans of the items of the linearization, and the spans of the types and contracts
+    // annotating terms.
+    let mut items = HashMap::new();
+    let mut annotations = Vec::new();
+
+    let typechecked = server
+        .cache
+        .terms()
+        .get(&file_id)
+        .filter(|cached| cached.state >= EntryState::Typechecked);
+
+    if let (Some(cached), Some(linearization)) = (typechecked, server.lin_cache.get(&file_id)) {
+        Trace::enrich(id, linearization);
+
+        for item in linearization.linearization.iter() {
+            match (item.pos.into_opt(), &item.kind) {
+                // Functions arguments are also recorded as a structure item at the same position.
+                (_, TermKind::Structure) | (None, _) => (),
+                (Some(span), kind) if span.src_id == file_id => {
+                    items.insert((span.start.to_usize(), span.end.to_usize()), kind);
+                }
+                _ => (),
+            }
+        }
+
+        collect_annotations(&cached.term, file_id, &mut annotations);
+    }
+
+    let is_builtin = |name: &str| server.initial_env.get(&Ident::from(name)).is_some();
+    let in_annotation = |span: &Range<usize>| {
+        annotations
+            .iter()
+            .any(|annot| annot.start <= span.start && span.end <= annot.end)
+    };
+
+    let mut tokens = Vec::new();
+    let mut push = |span: Range<usize>, ty: TokenType, modifiers: u32| {
+        tokens.push(Classified {
+            span,
+            ty,
+            modifiers,
+        })
+    };
+
+    // Whether each opened brace is the start of an interpolated expression.
+    let mut braces: Vec<bool> = Vec::new();
+    // Whether the current chain of field accesses starts with a member of the standard library.
+    let mut builtin_chain = false;
+    // The previous token and its start.
+    let mut previous: Option<(usize, Token)> = None;
+
+    for (start, token, end) in Lexer::new(source).map_while(Result::ok) {
+        let span = start..end;
+
+        match (&token, &previous) {
+            // Enum tags, including their backtick
+            (
+                Token::Normal(NormalToken::Identifier(_)),
+                Some((tag_start, Token::Normal(NormalToken::Backtick))),
+            ) => push(*tag_start..end, TokenType::EnumMember, 0),
+            (Token::Normal(NormalToken::Identifier(name)), previous) => {
+                let after_dot = matches!(previous, Some((_, Token::Normal(NormalToken::Dot))));
+                let kind = items.get(&(start, end)).copied();
+
+                match kind {
+                    _ if in_annotation(&span) => push(span, TokenType::Type, 0),
+                    Some(TermKind::Declaration(..)) => push(span, TokenType::Variable, DECLARATION),
+                    Some(TermKind::RecordField { .. }) => {
+                        push(span, TokenType::Property, DECLARATION)
+                    }
+                    _ if after_dot && builtin_chain => {
+                        push(span, TokenType::Function, DEFAULT_LIBRARY)
+                    }
+                    _ if after_dot => push(span, TokenType::Property, 0),
+                    Some(TermKind::Usage(UsageState::Unbound)) if is_builtin(name) => {
+                        builtin_chain = true;
+                        push(span, TokenType::Namespace, DEFAULT_LIBRARY)
+                    }
+                    _ => push(span, TokenType::Variable, 0),
+                }
+            }
+            _ => classify_token(&token, span, source, &mut braces, &mut push),
+        }
+
+        if !matches!(
+            token,
+            Token::Normal(NormalToken::Dot | NormalToken::Identifier(_))
+        ) {
+            builtin_chain = false;
+        }
+        previous = Some((start, token));
+    }
+
+    tokens
+}
+
+/// Classify the tokens which don't depend on the linearization, that is everything but
+/// identifiers. `braces` records whether each opened brace is the start of an interpolated
+/// expression.
+fn classify_token(
+    token: &Token,
+    span: Range<usize>,
+    source: &str,
+    braces: &mut Vec<bool>,
+    push: &mut impl FnMut(Range<usize>, TokenType, u32),
+) {
+    match token {
+        Token::Normal(NormalToken::NumLiteral(_)) => push(span, TokenType::Number, 0),
+        Token::Normal(
+            NormalToken::Dyn
+            | NormalToken::Num
+            | NormalToken::Bool
+            | NormalToken::Str
+            | NormalToken::Array,
+        ) => push(span, TokenType::Type, 0),
+        Token::Normal(
+            NormalToken::If
+            | NormalToken::Then
+            | NormalToken::Else
+            | NormalToken::Forall
+            | NormalToken::In
+            | NormalToken::Let
+            | NormalToken::Rec
+            | NormalToken::Switch
+            | NormalToken::Null
+            | NormalToken::True
+            | NormalToken::False
+            | NormalToken::Fun
+            | NormalToken::Import
+            | NormalToken::Merge
+            | NormalToken::Default
+            | NormalToken::Doc
+            | NormalToken::Optional,
+        ) => push(span, TokenType::Keyword, 0),
+        Token::Normal(NormalToken::DoubleQuote | NormalToken::MultiStringStart(_))
+        | Token::Str(
+            StringToken::Literal(_) | StringToken::EscapedChar(_) | StringToken::EscapedAscii(_),
+        )
+        | Token::MultiStr(MultiStringToken::Literal(_) | MultiStringToken::End) => {
+            push(span, TokenType::String, 0)
+        }
+        Token::Str(StringToken::Interpolation)
+        | Token::MultiStr(MultiStringToken::Interpolation) => {
+            braces.push(true);
+            push(span, TokenType::Operator, 0)
+        }
+        // Primitive operators, such as `%seq%`
+        Token::Normal(_) if is_primop(&source[span.clone()]) => {
+            push(span, TokenType::Function, DEFAULT_LIBRARY)
+        }
+        Token::Normal(NormalToken::LBrace) => braces.push(false),
+        Token::Normal(NormalToken::RBrace) => {
+            if braces.pop() == Some(true) {
+                push(span, TokenType::Operator, 0)
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Whether the source of a token is a primitive operator, such as `%seq%`.
+fn is_primop(token: &str) -> bool {
+    token.len() > 2 && token.starts_with('%') && token.ends_with('%')
+}
+
+/// Collect the spans of the types and contracts annotating the terms of a document.
+fn collect_annotations(rt: &RichTerm, file_id: FileId, spans: &mut Vec<Range<usize>>) {
+    if let Term::MetaValue(MetaValue {
+        types, contracts, ..
+    }) = rt.as_ref()
+    {
+        spans.extend(
+            types
+                .iter()
+                .chain(contracts.iter())
+                .map(|contract| contract.label.span)
+                .filter(|span| span.src_id == file_id)
+                .map(|span| span.start.to_usize()..span.end.to_usize()),
+        );
+    }
+
+    subterms(rt)
+        .into_iter()
+        .for_each(|t| collect_annotations(t, file_id, spans));
+}
+
+/// Encode tokens relatively to each other, as expected by the protocol. Tokens spanning several
+/// lines, such as multiline strings, are split, as clients may not support multiline tokens.
+fn encode(
+    server: &Server,
+    file_id: FileId,
+    tokens: impl IntoIterator<Item = Classified>,
+) -> Vec<SemanticToken> {
+    let files = server.cache.files();
+    let source = files.source(file_id);
+    let mut data = Vec::new();
+    let (mut line, mut character) = (0, 0);
+
+    for token in tokens {
+        let mut start = token.span.start;
+
+        for part in source[token.span.clone()].split_inclusive('\n') {
+            let end = start + part.trim_end_matches(&['\r', '\n'][..]).len();
+            let range_start = byte_index_to_position(files, file_id, start).unwrap();
+            let range_end = byte_index_to_position(files, file_id, end).unwrap();
+            start += part.len();
+
+            if range_end.character == range_start.character {
+                continue;
+            }
+
+            data.push(SemanticToken {
+                delta_line: range_start.line - line,
+                delta_start: if range_start.line == line {
+                    range_start.character - character
+                } else {
+                    range_start.character
+                },
+                length: range_end.character - range_start.character,
+                token_type: token.ty as u32,
+                token_modifiers_bitset: token.modifiers,
+            });
+
+            line = range_start.line;
+            character = range_start.character;
+        }
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{
+        request::{SemanticTokensFullRequest, SemanticTokensRangeRequest},
+        PartialResultParams, Position, Range, TextDocumentIdentifier, WorkDoneProgressParams,
+    };
+
+    use super::*;
+    use crate::testing::{uri, TestServer};
+
+    /// Decode the tokens of a document, returning their text, type and modifiers.
+    fn decode(source: &str, data: &[SemanticToken]) -> Vec<(String, TokenType, u32)> {
+        let lines: Vec<Vec<u16>> = source
+            .lines()
+            .map(|line| line.encode_utf16().collect())
+            .collect();
+        let (mut line, mut character) = (0, 0);
+
+        data.iter()
+            .map(|token| {
+                if token.delta_line > 0 {
+                    character = 0;
+                }
+                line += token.delta_line;
+                character += token.delta_start;
+
+                let start = character as usize;
+                let end = start + token.length as usize;
+                let text = String::from_utf16(&lines[line as usize][start..end]).unwrap();
+                // The token types are in the order of the legend.
+                let ty = [
+                    TokenType::Namespace,
+                    TokenType::Type,
+                    TokenType::EnumMember,
+                    TokenType::Property,
+                    TokenType::Variable,
+                    TokenType::Function,
+                    TokenType::Keyword,
+                    TokenType::String,
+                    TokenType::Number,
+                    TokenType::Operator,
+                ][token.token_type as usize];
+                (text, ty, token.token_modifiers_bitset)
+            })
+            .collect()
+    }
+
+    fn tokens(source: &str) -> Vec<(String, TokenType, u32)> {
+        let mut server = TestServer::new();
+        let uri = uri("semantic_tokens.ncl");
+        server.open(&uri, source);
+
+        let result = server.request::<SemanticTokensFullRequest>(SemanticTokensParams {
+            text_document: TextDocumentIdentifier { uri },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        });
+
+        match result {
+            Some(SemanticTokensResult::Tokens(tokens)) => decode(source, &tokens.data),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    fn token(text: &str, ty: TokenType, modifiers: u32) -> (String, TokenType, u32) {
+        (text.to_owned(), ty, modifiers)
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(
+            tokens("let x = 1 in x"),
+            vec![
+                token("let", TokenType::Keyword, 0),
+                token("x", TokenType::Variable, DECLARATION),
+                token("1", TokenType::Number, 0),
+                token("in", TokenType::Keyword, 0),
+                token("x", TokenType::Variable, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn record_fields() {
+        assert_eq!(
+            tokens("let r = {foo = 1} in r.foo"),
+            vec![
+                token("let", TokenType::Keyword, 0),
+                token("r", TokenType::Variable, DECLARATION),
+                token("foo", TokenType::Property, DECLARATION),
+                token("1", TokenType::Number, 0),
+                token("in", TokenType::Keyword, 0),
+                token("r", TokenType::Variable, 0),
+                token("foo", TokenType::Property, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn standard_library() {
+        assert_eq!(
+            tokens("array.length (%seq% 1 [])"),
+            vec![
+                token("array", TokenType::Namespace, DEFAULT_LIBRARY),
+                token("length", TokenType::Function, DEFAULT_LIBRARY),
+                token("%seq%", TokenType::Function, DEFAULT_LIBRARY),
+                token("1", TokenType::Number, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn shadowed_standard_library() {
+        assert_eq!(
+            tokens("let array = {length = 1} in array.length"),
+            vec![
+                token("let", TokenType::Keyword, 0),
+                token("array", TokenType::Variable, DECLARATION),
+                token("length", TokenType::Property, DECLARATION),
+                token("1", TokenType::Number, 0),
+                token("in", TokenType::Keyword, 0),
+                token("array", TokenType::Variable, 0),
+                token("length", TokenType::Property, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn annotations() {
+        assert_eq!(
+            tokens("let Port = fun l x => x in {port | Port = 80, id : Num -> Num = fun x => x}"),
+            vec![
+                token("let", TokenType::Keyword, 0),
+                token("Port", TokenType::Variable, DECLARATION),
+                token("fun", TokenType::Keyword, 0),
+                token("l", TokenType::Variable, DECLARATION),
+                token("x", TokenType::Variable, DECLARATION),
+                token("x", TokenType::Variable, 0),
+                token("in", TokenType::Keyword, 0),
+                token("port", TokenType::Property, DECLARATION),
+                token("Port", TokenType::Type, 0),
+                token("80", TokenType::Number, 0),
+                token("id", TokenType::Property, DECLARATION),
+                token("Num", TokenType::Type, 0),
+                token("Num", TokenType::Type, 0),
+                token("fun", TokenType::Keyword, 0),
+                token("x", TokenType::Variable, DECLARATION),
+                token("x", TokenType::Variable, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn enum_tags() {
+        assert_eq!(
+            tokens("switch { `foo => 1, _ => 2 } `bar"),
+            vec![
+                token("switch", TokenType::Keyword, 0),
+                token("`foo", TokenType::EnumMember, 0),
+                token("1", TokenType::Number, 0),
+                token("2", TokenType::Number, 0),
+                token("`bar", TokenType::EnumMember, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(
+            tokens("let x = 1 in {a = \"a%{x}b\", b = m%\"\n  c\n  d\n\"%m}"),
+            vec![
+                token("let", TokenType::Keyword, 0),
+                token("x", TokenType::Variable, DECLARATION),
+                token("1", TokenType::Number, 0),
+                token("in", TokenType::Keyword, 0),
+                token("a", TokenType::Property, DECLARATION),
+                token("\"", TokenType::String, 0),
+                token("a", TokenType::String, 0),
+                token("%{", TokenType::Operator, 0),
+                token("x", TokenType::Variable, 0),
+                token("}", TokenType::Operator, 0),
+                token("b", TokenType::String, 0),
+                token("\"", TokenType::String, 0),
+                token("b", TokenType::Property, DECLARATION),
+                token("m%\"", TokenType::String, 0),
+                // Multiline tokens are split at line ends.
+                token("  c", TokenType::String, 0),
+                token("  d", TokenType::String, 0),
+                token("\"%m", TokenType::String, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn utf16_positions() {
+        assert_eq!(
+            tokens("let s = \"été 🎉\" in s"),
+            vec![
+                token("let", TokenType::Keyword, 0),
+                token("s", TokenType::Variable, DECLARATION),
+                token("\"", TokenType::String, 0),
+                token("été 🎉", TokenType::String, 0),
+                token("\"", TokenType::String, 0),
+                token("in", TokenType::Keyword, 0),
+                token("s", TokenType::Variable, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn unparsable_document() {
+        // Tokens are classified up to the first lexing error. The document can't be parsed, so the
+        // declaration of `x` isn't known.
+        assert_eq!(
+            tokens("let x = 1 in x + \"\\q\" + 2"),
+            vec![
+                token("let", TokenType::Keyword, 0),
+                token("x", TokenType::Variable, 0),
+                token("1", TokenType::Number, 0),
+                token("in", TokenType::Keyword, 0),
+                token("x", TokenType::Variable, 0),
+                token("\"", TokenType::String, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn range() {
+        let mut server = TestServer::new();
+        let uri = uri("semantic_tokens.ncl");
+        let source = "let x = 1 in\nlet y = x in\ny";
+        server.open(&uri, source);
+
+        let result = server.request::<SemanticTokensRangeRequest>(SemanticTokensRangeParams {
+            text_document: TextDocumentIdentifier { uri },
+            range: Range {
+                start: Position {
+                    line: 1,
+                    character: 0,
+                },
+                end: Position {
+                    line: 1,
+                    character: 12,
+                },
+            },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        });
+
+        let data = match result {
+            Some(SemanticTokensRangeResult::Tokens(tokens)) => tokens.data,
+            result => panic!("unexpected result {:?}", result),
+        };
+        // The first token is encoded relatively to the start of the document.
+        assert_eq!((data[0].delta_line, data[0].delta_start), (1, 0));
+        assert_eq!(
+            decode(source, &data),
+            vec![
+                token("let", TokenType::Keyword, 0),
+                token("y", TokenType::Variable, DECLARATION),
+                token("x", TokenType::Variable, 0),
+                token("in", TokenType::Keyword, 0),
+            ]
+        );
+    }
+}
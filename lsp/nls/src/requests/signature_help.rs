@@ -0,0 +1,511 @@
+//! Signature help for function applications.
+//!
+//! The application being typed is found by lexing the document up to the cursor, as it usually
+//! doesn't parse while an argument is being written. The applied function is then looked up in
+//! the linearization of the document, or in the standard library, and its type is shown with the
+//! argument under the cursor highlighted.
+use codespan::{ByteIndex, FileId};
+use codespan_lsp::position_to_byte_index;
+use lsp_server::{RequestId, Response, ResponseError};
+use lsp_types::{
+    Documentation, MarkupContent, MarkupKind, ParameterInformation, ParameterLabel, SignatureHelp,
+    SignatureHelpParams, SignatureInformation,
+};
+use nickel_lang::{
+    identifier::Ident,
+    parser::lexer::{Lexer, MultiStringToken, NormalToken, StringToken, Token},
+    stdlib,
+    term::{MetaValue, RichTerm, Term},
+    types::{AbsType, Types},
+};
+use serde_json::Value;
+
+use crate::{
+    linearization::{
+        completed::Completed,
+        interface::{Resolved, TermKind, UsageState, ValueState},
+        LinearizationItem,
+    },
+    server::Server,
+    trace::{Enrich, Trace},
+};
+
+type Item = LinearizationItem<Resolved>;
+
+/// The maximum number of variables followed when looking for the definition of a function.
+const MAX_DEPTH: usize = 32;
+
+/// A function application being typed.
+struct Call {
+    /// The path of the applied function, such as `["array", "fold"]` for `array.fold`.
+    path: Vec<Ident>,
+    /// The index of the argument under the cursor.
+    argument: usize,
+}
+
+/// The type and the documentation of a function.
+struct Signature {
+    ty: Types,
+    doc: Option<String>,
+}
+
+pub fn handle_signature_help(
+    params: SignatureHelpParams,
+    id: RequestId,
+    server: &mut Server,
+) -> Result<(), ResponseError> {
+    let file_id = server
+        .cache
+        .id_of(
+            params
+                .text_document_position_params
+                .text_document
+                .uri
+                .as_str(),
+        )
+        .unwrap();
+
+    let offset = position_to_byte_index(
+        server.cache.files(),
+        file_id,
+        &params.text_document_position_params.position,
+    )
+    .unwrap();
+
+    let call = match call_at(server.cache.files().source(file_id), offset) {
+        Some(call) => call,
+        None => {
+            server.reply(Response::new_ok(id, Value::Null));
+            return Ok(());
+        }
+    };
+
+    let locator = (file_id, ByteIndex(offset as u32));
+    let linearization = server.lin_cache.get(&file_id);
+    if let Some(linearization) = linearization {
+        Trace::enrich(&id, linearization);
+    }
+
+    // Variables of the document shadow the standard library.
+    let declared =
+        linearization.and_then(|lin| Some((lin, declaration(lin, locator, &call.path[0])?)));
+    let signature = match declared {
+        Some((lin, decl)) => user_signature(lin, decl, &call.path[1..]),
+        None => stdlib_signature(server, &call.path),
+    };
+
+    let help = signature.map(|Signature { ty, doc }| {
+        let (label, parameters) = signature_label(&ty);
+        let active_parameter = (call.argument < parameters.len()).then(|| call.argument as u32);
+
+        SignatureHelp {
+            signatures: vec![SignatureInformation {
+                label,
+                documentation: doc.map(|doc| {
+                    Documentation::MarkupContent(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value: doc,
+                    })
+                }),
+                parameters: Some(
+                    parameters
+                        .into_iter()
+                        .map(|offsets| ParameterInformation {
+                            label: ParameterLabel::LabelOffsets(offsets),
+                            documentation: None,
+                        })
+                        .collect(),
+                ),
+                active_parameter,
+            }],
+            active_signature: Some(0),
+            active_parameter,
+        }
+    });
+
+    server.reply(Response::new_ok(id, help));
+    Ok(())
+}
+
+/// A group of tokens opened by a delimiter, such as a parenthesis or a string.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Group {
+    Root,
+    Delimited,
+    Str,
+    Interpolation,
+}
+
+/// An operand of an application, ending at the given offset: either a variable followed by a
+/// chain of field accesses, or any other atomic expression.
+enum Atom {
+    Path(Vec<Ident>, usize),
+    Other(usize),
+}
+
+impl Atom {
+    fn end(&self) -> usize {
+        match self {
+            Atom::Path(_, end) | Atom::Other(end) => *end,
+        }
+    }
+}
+
+/// Find the function application being typed at `offset`, if any. The source is lexed up to the
+/// cursor, and the operands of the application are the consecutive atomic expressions since the
+/// last operator, keyword or opening delimiter. If the cursor is right after an operand, this
+/// operand is the one being typed. Otherwise, the cursor is on the next one.
+///
+/// If the innermost group doesn't contain an application, the group itself is considered to be
+/// an argument of the enclosing one, such as in `f (x + |`.
+fn call_at(source: &str, offset: usize) -> Option<Call> {
+    let mut groups: Vec<(Group, Vec<Atom>)> = vec![(Group::Root, Vec::new())];
+    let mut previous = None;
+
+    for (start, token, end) in Lexer::new(source).map_while(Result::ok) {
+        if start >= offset {
+            break;
+        }
+
+        let (group, atoms) = groups.last_mut().unwrap();
+
+        match &token {
+            Token::Normal(NormalToken::Identifier(name)) => match (&previous, atoms.last_mut()) {
+                (Some(Token::Normal(NormalToken::Dot)), Some(Atom::Path(path, path_end))) => {
+                    path.push(Ident::from(*name));
+                    *path_end = end;
+                }
+                // Enum tags
+                (Some(Token::Normal(NormalToken::Backtick)), _) => atoms.push(Atom::Other(end)),
+                _ => atoms.push(Atom::Path(vec![Ident::from(*name)], end)),
+            },
+            Token::Normal(NormalToken::Dot | NormalToken::Backtick)
+            | Token::Str(
+                StringToken::Literal(_)
+                | StringToken::EscapedChar(_)
+                | StringToken::EscapedAscii(_),
+            )
+            | Token::MultiStr(MultiStringToken::Literal(_)) => (),
+            Token::Normal(
+                NormalToken::NumLiteral(_)
+                | NormalToken::True
+                | NormalToken::False
+                | NormalToken::Null,
+            ) => atoms.push(Atom::Other(end)),
+            Token::Normal(NormalToken::DoubleQuote) if *group == Group::Str => {
+                close(&mut groups, end)
+            }
+            Token::Normal(NormalToken::DoubleQuote | NormalToken::MultiStringStart(_)) => {
+                groups.push((Group::Str, Vec::new()))
+            }
+            Token::MultiStr(MultiStringToken::End) => close(&mut groups, end),
+            Token::Str(StringToken::Interpolation)
+            | Token::MultiStr(MultiStringToken::Interpolation) => {
+                groups.push((Group::Interpolation, Vec::new()))
+            }
+            Token::Normal(NormalToken::LParen | NormalToken::LBracket | NormalToken::LBrace) => {
+                groups.push((Group::Delimited, Vec::new()))
+            }
+            Token::Normal(NormalToken::RParen | NormalToken::RBracket | NormalToken::RBrace) => {
+                close(&mut groups, end)
+            }
+            // Any other token, such as an operator or a keyword, ends the application.
+            _ => atoms.clear(),
+        }
+
+        previous = Some(token);
+    }
+
+    let innermost = groups.len() - 1;
+    for (depth, (group, atoms)) in groups.into_iter().enumerate().rev() {
+        // The number of operands, including the one being typed.
+        let operands = match atoms.last() {
+            Some(atom) if depth == innermost && atom.end() == offset => atoms.len(),
+            _ => atoms.len() + 1,
+        };
+
+        match atoms.into_iter().next() {
+            Some(Atom::Path(path, _)) if operands >= 2 => {
+                return Some(Call {
+                    path,
+                    argument: operands - 2,
+                })
+            }
+            // The content of a string is not an argument.
+            _ if group == Group::Str => return None,
+            _ => (),
+        }
+    }
+
+    None
+}
+
+/// Close the innermost group. Strings and delimited groups are operands of the enclosing group,
+/// while interpolated expressions are part of their string.
+fn close(groups: &mut Vec<(Group, Vec<Atom>)>, end: usize) {
+    // Ignore unbalanced delimiters
+    if groups.len() == 1 {
+        return;
+    }
+
+    let (group, _) = groups.pop().unwrap();
+    if group != Group::Interpolation {
+        groups.last_mut().unwrap().1.push(Atom::Other(end));
+    }
+}
+
+/// Find the closest declaration of the variable `name` before the cursor.
+fn declaration<'a>(
+    linearization: &'a Completed,
+    locator: (FileId, ByteIndex),
+    name: &Ident,
+) -> Option<&'a Item> {
+    let candidates = match linearization.item_at(&locator) {
+        Some(item) => linearization.get_in_scope(item),
+        None => linearization.linearization.iter().collect(),
+    };
+
+    candidates
+        .into_iter()
+        .filter(|item| matches!(&item.kind, TermKind::Declaration(ident, ..) if ident == name))
+        .filter_map(|item| {
+            let span = item.pos.into_opt()?;
+            (span.src_id == locator.0 && span.start <= locator.1).then(|| (span.start, item))
+        })
+        .max_by_key(|(start, _)| *start)
+        .map(|(_, item)| item)
+}
+
+/// The value of a declaration, a record field or a variable, following variables.
+fn value_of<'a>(linearization: &'a Completed, mut item: &'a Item) -> Option<&'a Item> {
+    for _ in 0..MAX_DEPTH {
+        let next = match &item.kind {
+            TermKind::Declaration(_, _, ValueState::Known(next))
+            | TermKind::RecordField {
+                value: ValueState::Known(next),
+                ..
+            }
+            | TermKind::Usage(UsageState::Resolved(next))
+                if *next != item.id =>
+            {
+                *next
+            }
+            _ => return Some(item),
+        };
+        item = linearization.get_item(next)?;
+    }
+
+    None
+}
+
+/// The signature of the function defined by `decl`, or by its field at `path`.
+fn user_signature(linearization: &Completed, decl: &Item, path: &[Ident]) -> Option<Signature> {
+    let mut item = decl;
+    for field in path {
+        item = match &value_of(linearization, item)?.kind {
+            TermKind::Record(fields) => linearization.get_item(*fields.get(field)?)?,
+            _ => return None,
+        };
+    }
+
+    let value = match &item.kind {
+        TermKind::Declaration(_, _, ValueState::Known(value))
+        | TermKind::RecordField {
+            value: ValueState::Known(value),
+            ..
+        } => linearization.get_item(*value),
+        _ => None,
+    };
+
+    let metas: Vec<&MetaValue> = std::iter::once(item)
+        .chain(value)
+        .filter_map(|item| item.meta.as_ref())
+        .collect();
+    let inferred = std::iter::once(item)
+        .chain(value)
+        .map(|item| item.ty.clone())
+        .find(|ty| *ty != Types(AbsType::Dyn()));
+
+    Some(Signature {
+        ty: metas
+            .iter()
+            .find_map(|meta| annotation(meta))
+            .or(inferred)?,
+        doc: metas.iter().find_map(|meta| meta.doc.clone()),
+    })
+}
+
+/// The signature of the member of the standard library at `path`, such as `array.fold`.
+fn stdlib_signature(server: &Server, path: &[Ident]) -> Option<Signature> {
+    let term = stdlib::modules()
+        .into_iter()
+        .filter_map(|(name, _)| server.cache.get_ref(server.cache.id_of(name)?))
+        .find_map(|module| {
+            path.iter()
+                .try_fold(module, |record, field| record_field(record, field))
+        })?;
+
+    match term.as_ref() {
+        Term::MetaValue(meta) => Some(Signature {
+            ty: annotation(meta)?,
+            doc: meta.doc.clone(),
+        }),
+        _ => None,
+    }
+}
+
+/// The field `name` of a record literal.
+fn record_field<'a>(rt: &'a RichTerm, name: &Ident) -> Option<&'a RichTerm> {
+    match rt.as_ref() {
+        Term::Record(fields, _) | Term::RecRecord(fields, ..) => fields.get(name),
+        Term::MetaValue(MetaValue {
+            value: Some(value), ..
+        }) => record_field(value, name),
+        _ => None,
+    }
+}
+
+/// The type annotation of a value, or its first contract.
+fn annotation(meta: &MetaValue) -> Option<Types> {
+    meta.types
+        .iter()
+        .chain(meta.contracts.iter())
+        .next()
+        .map(|contract| contract.types.clone())
+}
+
+/// Render a function type, together with the offsets of each of its parameters in the rendered
+/// label, as expected by the protocol.
+fn signature_label(ty: &Types) -> (String, Vec<[u32; 2]>) {
+    let mut label = String::new();
+    let mut ty = ty;
+
+    if let Types(AbsType::Forall(..)) = ty {
+        label.push_str("forall");
+        while let Types(AbsType::Forall(var, body)) = ty {
+            label.push_str(&format!(" {}", var));
+            ty = body;
+        }
+        label.push_str(". ");
+    }
+
+    let utf16_len = |s: &str| s.encode_utf16().count() as u32;
+    let mut parameters = Vec::new();
+
+    while let Types(AbsType::Arrow(dom, codom)) = ty {
+        let start = utf16_len(&label);
+        match dom.as_ref() {
+            Types(AbsType::Arrow(..) | AbsType::Rec(..)) => label.push_str(&format!("({})", dom)),
+            _ => label.push_str(&dom.to_string()),
+        }
+        parameters.push([start, utf16_len(&label)]);
+        label.push_str(" -> ");
+        ty = codom;
+    }
+
+    label.push_str(&ty.to_string());
+    (label, parameters)
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{request::SignatureHelpRequest, WorkDoneProgressParams};
+
+    use super::*;
+    use crate::testing::{position_params, uri, TestServer};
+
+    /// The position of the cursor in the documents of the tests.
+    const CURSOR: &str = "<cursor>";
+
+    /// Open `text` and request the signature help at the position marked by `CURSOR`.
+    fn help(server: &mut TestServer, text: &str) -> Option<SignatureHelp> {
+        let main = uri("main.ncl");
+        let offset = text.find(CURSOR).unwrap();
+        let before = &text[..offset];
+        let line = before.matches('\n').count();
+        let character = before[before.rfind('\n').map_or(0, |i| i + 1)..]
+            .encode_utf16()
+            .count();
+        server.open(&main, &text.replacen(CURSOR, "", 1));
+
+        server.request::<SignatureHelpRequest>(SignatureHelpParams {
+            context: None,
+            text_document_position_params: position_params(&main, line as u32, character as u32),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        })
+    }
+
+    /// The label of the signature, its highlighted parameter and its documentation.
+    fn summary(help: SignatureHelp) -> (String, Option<String>, Option<String>) {
+        let signature = help.signatures.into_iter().next().unwrap();
+        let parameter = signature.active_parameter.map(|index| {
+            match &signature.parameters.as_ref().unwrap()[index as usize].label {
+                ParameterLabel::LabelOffsets([start, end]) => {
+                    let label: Vec<u16> = signature.label.encode_utf16().collect();
+                    String::from_utf16(&label[*start as usize..*end as usize]).unwrap()
+                }
+                ParameterLabel::Simple(label) => label.clone(),
+            }
+        });
+        let doc = signature.documentation.map(|doc| match doc {
+            Documentation::MarkupContent(content) => content.value,
+            Documentation::String(value) => value,
+        });
+
+        (signature.label, parameter, doc)
+    }
+
+    #[test]
+    fn standard_library() {
+        let mut server = TestServer::new();
+        let (label, parameter, doc) =
+            summary(help(&mut server, "array.fold (fun x acc => x) 0 <cursor>").unwrap());
+
+        assert_eq!(label, "forall a b. (a -> b -> b) -> b -> Array a -> b");
+        assert_eq!(parameter.as_deref(), Some("Array a"));
+        assert!(doc.is_some());
+    }
+
+    #[test]
+    fn annotated_function() {
+        let mut server = TestServer::new();
+        let definition = "let add : Num -> Str -> Num | doc \"Adds\" = fun x y => x + 1 in\n";
+
+        let (label, parameter, doc) =
+            summary(help(&mut server, &format!("{}add 1<cursor>", definition)).unwrap());
+        assert_eq!(label, "Num -> Str -> Num");
+        assert_eq!(parameter.as_deref(), Some("Num"));
+        assert_eq!(doc.as_deref(), Some("Adds"));
+
+        let (_, parameter, _) =
+            summary(help(&mut server, &format!("{}add 1 <cursor>", definition)).unwrap());
+        assert_eq!(parameter.as_deref(), Some("Str"));
+
+        let (_, parameter, _) = summary(
+            help(
+                &mut server,
+                &format!("{}add (add 1 \"a\") <cursor>", definition),
+            )
+            .unwrap(),
+        );
+        assert_eq!(parameter.as_deref(), Some("Str"));
+    }
+
+    #[test]
+    fn too_many_arguments() {
+        let mut server = TestServer::new();
+        let help = help(
+            &mut server,
+            "let f : Num -> Num = fun x => x in f 1 2 <cursor>",
+        )
+        .unwrap();
+        assert_eq!(help.active_parameter, None);
+    }
+
+    #[test]
+    fn outside_of_calls() {
+        let mut server = TestServer::new();
+        assert_eq!(help(&mut server, "1 + <cursor>"), None);
+        assert_eq!(help(&mut server, "array.map \"some <cursor>"), None);
+    }
+}
@@ -1,4 +1,7 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 
 use anyhow::Result;
 use codespan::FileId;
@@ -10,18 +13,30 @@ use lsp_types::{
     notification::Notification as _,
     notification::{DidChangeTextDocument, DidOpenTextDocument},
     request::{Request as RequestTrait, *},
+    CodeActionKind, CodeActionOptions, CodeActionParams, CodeActionProviderCapability,
     CompletionOptions, CompletionParams, DidChangeTextDocumentParams, DidOpenTextDocumentParams,
-    DocumentSymbolParams, GotoDefinitionParams, HoverOptions, HoverParams, HoverProviderCapability,
-    OneOf, ReferenceParams, ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind,
-    TextDocumentSyncOptions, WorkDoneProgressOptions,
+    DocumentLinkOptions, DocumentLinkParams, DocumentSymbolParams, GotoDefinitionParams,
+    HoverOptions, HoverParams, HoverProviderCapability, OneOf, ReferenceParams, RenameOptions,
+    RenameParams, SemanticTokensFullOptions, SemanticTokensOptions, SemanticTokensParams,
+    SemanticTokensRangeParams, SemanticTokensServerCapabilities, ServerCapabilities,
+    SignatureHelpOptions, SignatureHelpParams, TextDocumentPositionParams,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions, Url,
+    WorkDoneProgressOptions,
 };
 
 use nickel_lang::cache::{Cache, ErrorTolerance};
 use nickel_lang::typecheck::Environment;
+use serde_json::Value;
 
 use crate::{
+    evaluation::{EvalConfig, EvalWorker},
+    files::Pending,
     linearization::completed::Completed,
-    requests::{completion, goto, hover, symbols},
+    requests::{
+        code_actions, completion, goto, hover,
+        inlay_hints::{self, InlayHintParams, InlayHintRequest},
+        links, rename, semantic_tokens, signature_help, symbols,
+    },
     trace::Trace,
 };
 
@@ -30,15 +45,23 @@ pub struct Server {
     pub cache: Cache,
     pub lin_cache: HashMap<FileId, Completed>,
     pub initial_env: Environment,
+    /// The background evaluation of documents, if enabled.
+    pub eval: Option<EvalWorker>,
+    /// Whether to show the effective value of merged fields as inlay hints.
+    pub value_hints: bool,
+    /// The delay after the last change of a document before analyzing it.
+    pub debounce: Duration,
+    /// The documents changed since their last analysis.
+    pub pending: HashMap<Url, Pending>,
 }
 
 impl Server {
-    pub fn capabilities() -> ServerCapabilities {
-        ServerCapabilities {
+    pub fn capabilities() -> Value {
+        let capabilities = ServerCapabilities {
             text_document_sync: Some(TextDocumentSyncCapability::Options(
                 TextDocumentSyncOptions {
                     open_close: Some(true),
-                    change: Some(TextDocumentSyncKind::Full),
+                    change: Some(TextDocumentSyncKind::Incremental),
                     ..TextDocumentSyncOptions::default()
                 },
             )),
@@ -50,24 +73,85 @@ impl Server {
             definition_provider: Some(OneOf::Left(true)),
             references_provider: Some(OneOf::Left(true)),
             completion_provider: Some(CompletionOptions {
-                trigger_characters: Some(vec![]),
+                trigger_characters: Some(vec![".".to_owned()]),
                 ..Default::default()
             }),
+            signature_help_provider: Some(SignatureHelpOptions {
+                trigger_characters: Some(vec![" ".to_owned(), "(".to_owned()]),
+                retrigger_characters: None,
+                work_done_progress_options: WorkDoneProgressOptions {
+                    work_done_progress: Some(false),
+                },
+            }),
             document_symbol_provider: Some(OneOf::Left(true)),
+            document_link_provider: Some(DocumentLinkOptions {
+                resolve_provider: Some(false),
+                work_done_progress_options: WorkDoneProgressOptions {
+                    work_done_progress: Some(false),
+                },
+            }),
+            rename_provider: Some(OneOf::Right(RenameOptions {
+                prepare_provider: Some(true),
+                work_done_progress_options: WorkDoneProgressOptions {
+                    work_done_progress: Some(false),
+                },
+            })),
+            semantic_tokens_provider: Some(
+                SemanticTokensServerCapabilities::SemanticTokensOptions(SemanticTokensOptions {
+                    legend: semantic_tokens::legend(),
+                    range: Some(true),
+                    full: Some(SemanticTokensFullOptions::Bool(true)),
+                    work_done_progress_options: WorkDoneProgressOptions {
+                        work_done_progress: Some(false),
+                    },
+                }),
+            ),
+            code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
+                code_action_kinds: Some(vec![
+                    CodeActionKind::QUICKFIX,
+                    CodeActionKind::REFACTOR_EXTRACT,
+                    CodeActionKind::REFACTOR_REWRITE,
+                ]),
+                work_done_progress_options: WorkDoneProgressOptions {
+                    work_done_progress: Some(false),
+                },
+                resolve_provider: None,
+            })),
             ..ServerCapabilities::default()
-        }
+        };
+
+        // Inlay hints are not supported by lsp-types yet, see `inlay_hints`.
+        let mut capabilities = serde_json::to_value(&capabilities).unwrap();
+        capabilities["inlayHintProvider"] = Value::Bool(true);
+        capabilities
     }
 
-    pub fn new(connection: Connection) -> Server {
+    pub fn new(
+        connection: Connection,
+        eval_config: Option<EvalConfig>,
+        value_hints: bool,
+        debounce: Duration,
+    ) -> Server {
         let mut cache = Cache::new(ErrorTolerance::Tolerant);
         cache.load_stdlib().unwrap();
         let initial_env = cache.mk_type_env().unwrap();
         let lin_cache = HashMap::new();
+        let eval = eval_config.map(|config| {
+            let sender = connection.sender.clone();
+            EvalWorker::spawn(config, move |notification| {
+                trace!("Sending notification: {:#?}", notification);
+                let _ = sender.send(Message::Notification(notification));
+            })
+        });
         Server {
             connection,
             cache,
             lin_cache,
             initial_env,
+            eval,
+            value_hints,
+            debounce,
+            pending: HashMap::new(),
         }
     }
 
@@ -107,7 +191,25 @@ impl Server {
 
     pub fn run(&mut self) -> Result<()> {
         trace!("Running...");
-        while let Ok(msg) = self.connection.receiver.recv() {
+        loop {
+            let msg = match self.next_analysis() {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    match self.connection.receiver.recv_timeout(timeout) {
+                        Ok(msg) => msg,
+                        Err(err) if err.is_timeout() => {
+                            let _ = crate::files::analyze_pending(self, false);
+                            continue;
+                        }
+                        Err(_) => break,
+                    }
+                }
+                None => match self.connection.receiver.recv() {
+                    Ok(msg) => msg,
+                    Err(_) => break,
+                },
+            };
+
             trace!("Message: {:#?}", msg);
             match msg {
                 Message::Request(req) => {
@@ -135,6 +237,14 @@ impl Server {
         Ok(())
     }
 
+    /// The time at which the next pending analysis is due, if any.
+    fn next_analysis(&self) -> Option<Instant> {
+        self.pending
+            .values()
+            .map(|pending| pending.last_change + self.debounce)
+            .min()
+    }
+
     fn handle_notification(&mut self, notification: Notification) -> Result<()> {
         match notification.method.as_str() {
             DidOpenTextDocument::METHOD => {
@@ -145,8 +255,8 @@ impl Server {
                 )
             }
             DidChangeTextDocument::METHOD => {
-                trace!("handle save notification");
-                crate::files::handle_save(
+                trace!("handle change notification");
+                crate::files::handle_change(
                     self,
                     serde_json::from_value::<DidChangeTextDocumentParams>(notification.params)?,
                 )
@@ -155,39 +265,109 @@ impl Server {
         }
     }
 
-    fn handle_request(&mut self, req: lsp_server::Request) -> Result<()> {
+    /// Analyze a document if it changed since its last analysis, such that the positions of a
+    /// request about its current content match its linearization.
+    fn analyze(&mut self, uri: &Url) {
+        let _ = crate::files::analyze_document(self, uri);
+    }
+
+    pub(crate) fn handle_request(&mut self, req: lsp_server::Request) -> Result<()> {
+        // Requests about the positions of a document are answered against its current content, so
+        // a pending analysis of this document is done first. Document links and signature help
+        // only lex the current content, and don't interrupt the debouncing of changes.
         Trace::receive(req.id.clone(), req.method.clone());
 
         let res = match req.method.as_str() {
             HoverRequest::METHOD => {
                 let params: HoverParams = serde_json::from_value(req.params).unwrap();
+                self.analyze(&params.text_document_position_params.text_document.uri);
                 hover::handle(params, req.id.clone(), self)
             }
 
             GotoDefinition::METHOD => {
                 debug!("handle goto defnition");
                 let params: GotoDefinitionParams = serde_json::from_value(req.params).unwrap();
+                self.analyze(&params.text_document_position_params.text_document.uri);
                 goto::handle_to_definition(params, req.id.clone(), self)
             }
 
             References::METHOD => {
                 debug!("handle goto defnition");
                 let params: ReferenceParams = serde_json::from_value(req.params).unwrap();
+                self.analyze(&params.text_document_position.text_document.uri);
                 goto::handle_to_usages(params, req.id.clone(), self)
             }
 
             Completion::METHOD => {
                 debug!("handle completion");
                 let params: CompletionParams = serde_json::from_value(req.params).unwrap();
+                self.analyze(&params.text_document_position.text_document.uri);
                 completion::handle_completion(params, req.id.clone(), self)
             }
 
+            SignatureHelpRequest::METHOD => {
+                debug!("handle signature help");
+                let params: SignatureHelpParams = serde_json::from_value(req.params).unwrap();
+                signature_help::handle_signature_help(params, req.id.clone(), self)
+            }
+
             DocumentSymbolRequest::METHOD => {
                 debug!("handle completion");
                 let params: DocumentSymbolParams = serde_json::from_value(req.params).unwrap();
+                self.analyze(&params.text_document.uri);
                 symbols::handle_document_symbols(params, req.id.clone(), self)
             }
 
+            DocumentLinkRequest::METHOD => {
+                debug!("handle document links");
+                let params: DocumentLinkParams = serde_json::from_value(req.params).unwrap();
+                links::handle_document_links(params, req.id.clone(), self)
+            }
+
+            InlayHintRequest::METHOD => {
+                debug!("handle inlay hints");
+                let params: InlayHintParams = serde_json::from_value(req.params).unwrap();
+                self.analyze(&params.text_document.uri);
+                inlay_hints::handle_inlay_hints(params, req.id.clone(), self)
+            }
+
+            SemanticTokensFullRequest::METHOD => {
+                debug!("handle semantic tokens");
+                let params: SemanticTokensParams = serde_json::from_value(req.params).unwrap();
+                self.analyze(&params.text_document.uri);
+                semantic_tokens::handle_semantic_tokens(params, req.id.clone(), self)
+            }
+
+            SemanticTokensRangeRequest::METHOD => {
+                debug!("handle semantic tokens in range");
+                let params: SemanticTokensRangeParams = serde_json::from_value(req.params).unwrap();
+                self.analyze(&params.text_document.uri);
+                semantic_tokens::handle_semantic_tokens_range(params, req.id.clone(), self)
+            }
+
+            CodeActionRequest::METHOD => {
+                debug!("handle code actions");
+                let params: CodeActionParams = serde_json::from_value(req.params).unwrap();
+                self.analyze(&params.text_document.uri);
+                code_actions::handle_code_actions(params, req.id.clone(), self)
+            }
+
+            PrepareRenameRequest::METHOD => {
+                debug!("handle prepare rename");
+                let params: TextDocumentPositionParams =
+                    serde_json::from_value(req.params).unwrap();
+                self.analyze(&params.text_document.uri);
+                rename::handle_prepare_rename(params, req.id.clone(), self)
+            }
+
+            Rename::METHOD => {
+                debug!("handle rename");
+                let params: RenameParams = serde_json::from_value(req.params).unwrap();
+                // Renaming a field also edits the documents importing it.
+                let _ = crate::files::analyze_pending(self, true);
+                rename::handle_rename(params, req.id.clone(), self)
+            }
+
             _ => Ok(()),
         };
 
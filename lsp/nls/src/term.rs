@@ -1,7 +1,10 @@
 use std::ops::Range;
 
 use codespan::FileId;
-use nickel_lang::position::RawSpan;
+use nickel_lang::{
+    position::{RawSpan, TermPos},
+    term::{MetaValue, RichTerm, StrChunk, Term},
+};
 
 pub trait RawSpanExt {
     fn to_range(self) -> (FileId, Range<usize>);
@@ -12,3 +15,51 @@ impl RawSpanExt for RawSpan {
         (self.src_id, (self.start.to_usize()..self.end.to_usize()))
     }
 }
+
+/// The position of the record returned by a file, if any.
+pub fn root_record_pos(rt: &RichTerm) -> TermPos {
+    match rt.as_ref() {
+        Term::Let(_, _, body, _) | Term::LetPattern(_, _, _, body) => root_record_pos(body),
+        Term::MetaValue(MetaValue { value: Some(t), .. }) => root_record_pos(t),
+        Term::Record(..) | Term::RecRecord(..) => rt.pos,
+        _ => TermPos::None,
+    }
+}
+
+/// The direct subterms of a term. Terms appearing in types, such as contracts, are not included.
+pub fn subterms(rt: &RichTerm) -> Vec<&RichTerm> {
+    match rt.as_ref() {
+        Term::Fun(_, t)
+        | Term::FunPattern(_, _, t)
+        | Term::EnumVariant(_, t)
+        | Term::Op1(_, t)
+        | Term::Sealed(_, t, _) => vec![t],
+        Term::Let(_, t1, t2, _)
+        | Term::LetPattern(_, _, t1, t2)
+        | Term::App(t1, t2)
+        | Term::Op2(_, t1, t2) => vec![t1, t2],
+        Term::Record(fields, _) => fields.values().collect(),
+        Term::RecRecord(fields, dyn_fields, ..) => fields
+            .values()
+            .chain(
+                dyn_fields
+                    .iter()
+                    .flat_map(|(name, value)| vec![name, value]),
+            )
+            .collect(),
+        Term::Switch(exp, cases, default, _) => std::iter::once(exp)
+            .chain(cases.values())
+            .chain(default.iter())
+            .collect(),
+        Term::Array(ts, _) | Term::OpN(_, ts) => ts.iter().collect(),
+        Term::StrChunks(chunks) => chunks
+            .iter()
+            .filter_map(|chunk| match chunk {
+                StrChunk::Expr(t, _) => Some(t),
+                StrChunk::Literal(_) => None,
+            })
+            .collect(),
+        Term::MetaValue(meta) => meta.value.iter().collect(),
+        _ => Vec::new(),
+    }
+}
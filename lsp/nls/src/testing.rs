@@ -0,0 +1,174 @@
+//! A server running in memory, to test the handling of requests without a client.
+use std::{collections::HashMap, path::Path, time::Duration};
+
+use lsp_server::{Connection, Message, RequestId};
+use lsp_types::{
+    notification::{Notification, PublishDiagnostics},
+    request::Request,
+    Diagnostic, DidChangeTextDocumentParams, DidOpenTextDocumentParams, Position,
+    PublishDiagnosticsParams, TextDocumentContentChangeEvent, TextDocumentIdentifier,
+    TextDocumentItem, TextDocumentPositionParams, Url, VersionedTextDocumentIdentifier,
+};
+use serde_json::Value;
+
+use crate::{evaluation::EvalConfig, files, server::Server};
+
+/// The time to wait for the evaluation of a document.
+const EVAL_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct TestServer {
+    pub server: Server,
+    /// The client side of the connection, receiving the responses and notifications.
+    client: Connection,
+    next_id: i32,
+    /// The last diagnostics published for each document.
+    diagnostics: HashMap<Url, Vec<Diagnostic>>,
+}
+
+impl TestServer {
+    pub fn new() -> Self {
+        Self::with_eval_config(None)
+    }
+
+    /// A server evaluating the documents without static errors in the background.
+    pub fn with_evaluation() -> Self {
+        Self::with_eval_config(Some(EvalConfig {
+            timeout: EVAL_TIMEOUT,
+            steps: usize::MAX,
+        }))
+    }
+
+    fn with_eval_config(eval_config: Option<EvalConfig>) -> Self {
+        let (connection, client) = Connection::memory();
+        TestServer {
+            server: Server::new(connection, eval_config, false, Duration::ZERO),
+            client,
+            next_id: 0,
+            diagnostics: HashMap::new(),
+        }
+    }
+
+    /// The last diagnostics published for a document, without waiting for its evaluation.
+    pub fn diagnostics(&mut self, uri: &Url) -> Vec<Diagnostic> {
+        while let Ok(msg) = self.client.receiver.try_recv() {
+            self.receive(msg);
+        }
+
+        self.diagnostics.get(uri).cloned().unwrap_or_default()
+    }
+
+    /// Wait for the diagnostics of the evaluation of a document. Panic if none are published.
+    pub fn eval_diagnostics(&mut self, uri: &Url) -> Vec<Diagnostic> {
+        self.diagnostics(uri);
+        self.diagnostics.remove(uri);
+
+        while let Ok(msg) = self.client.receiver.recv_timeout(EVAL_TIMEOUT) {
+            self.receive(msg);
+            if let Some(diagnostics) = self.diagnostics.get(uri) {
+                return diagnostics.clone();
+            }
+        }
+
+        panic!("the evaluation of {} didn't report anything", uri)
+    }
+
+    /// Record the diagnostics carried by a message.
+    fn receive(&mut self, msg: Message) {
+        if let Message::Notification(notification) = msg {
+            if notification.method == PublishDiagnostics::METHOD {
+                let params: PublishDiagnosticsParams =
+                    serde_json::from_value(notification.params).unwrap();
+                self.diagnostics.insert(params.uri, params.diagnostics);
+            }
+        }
+    }
+
+    /// Open a document with the given content, and analyze it.
+    pub fn open(&mut self, uri: &Url, text: &str) {
+        files::handle_open(
+            &mut self.server,
+            DidOpenTextDocumentParams {
+                text_document: TextDocumentItem {
+                    uri: uri.clone(),
+                    language_id: String::from("nickel"),
+                    version: 0,
+                    text: text.to_owned(),
+                },
+            },
+        )
+        .unwrap();
+    }
+
+    /// Replace the content of an open document. The new content is analyzed before the next
+    /// request needing it.
+    pub fn change(&mut self, uri: &Url, text: &str) {
+        files::handle_change(
+            &mut self.server,
+            DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier {
+                    uri: uri.clone(),
+                    version: 1,
+                },
+                content_changes: vec![TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: text.to_owned(),
+                }],
+            },
+        )
+        .unwrap();
+    }
+
+    /// Send a request to the server and return the result of its response. Panic if the server
+    /// replies with an error.
+    pub fn request<R: Request>(&mut self, params: R::Params) -> R::Result {
+        self.try_request::<R>(params)
+            .unwrap_or_else(|message| panic!("{} failed: {}", R::METHOD, message))
+    }
+
+    /// Send a request to the server and return the result of its response, or the message of the
+    /// error it replies with.
+    pub fn try_request<R: Request>(&mut self, params: R::Params) -> Result<R::Result, String> {
+        self.next_id += 1;
+        let id = RequestId::from(self.next_id);
+        self.server
+            .handle_request(lsp_server::Request::new(
+                id.clone(),
+                R::METHOD.to_owned(),
+                params,
+            ))
+            .unwrap();
+
+        while let Ok(msg) = self.client.receiver.try_recv() {
+            match msg {
+                Message::Response(response) if response.id == id => {
+                    if let Some(error) = response.error {
+                        return Err(error.message);
+                    }
+                    return Ok(
+                        serde_json::from_value(response.result.unwrap_or(Value::Null)).unwrap(),
+                    );
+                }
+                msg => self.receive(msg),
+            }
+        }
+
+        panic!("{} wasn't answered", R::METHOD)
+    }
+}
+
+/// The URI of a file given by its path relative to the root of the crate. The file doesn't have
+/// to exist, but its directory does, such that the files it imports can be found.
+pub fn uri(path: &str) -> Url {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(path);
+    let dir = path.parent().unwrap().canonicalize().unwrap();
+    Url::from_file_path(dir.join(path.file_name().unwrap())).unwrap()
+}
+
+/// The parameters of a request at the given position of a document.
+pub fn position_params(uri: &Url, line: u32, character: u32) -> TextDocumentPositionParams {
+    TextDocumentPositionParams {
+        text_document: TextDocumentIdentifier { uri: uri.clone() },
+        position: Position { line, character },
+    }
+}
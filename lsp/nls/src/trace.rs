@@ -41,6 +41,7 @@ pub struct TraceItemParams {
     method: String,
     linearization_size: Option<usize>,
     file_size: Option<usize>,
+    changes: Option<usize>,
 }
 
 #[derive(Debug, Serialize)]
@@ -51,6 +52,7 @@ pub struct CsvTraceItem {
     method: String,
     linearization_size: Option<usize>,
     file_size: Option<usize>,
+    changes: Option<usize>,
 }
 
 impl From<TraceItem<Replied>> for CsvTraceItem {
@@ -61,6 +63,7 @@ impl From<TraceItem<Replied>> for CsvTraceItem {
             method: replied.params.method,
             linearization_size: replied.params.linearization_size,
             file_size: replied.params.file_size,
+            changes: replied.params.changes,
         }
     }
 }
@@ -237,6 +240,21 @@ pub mod param {
         }
     }
 
+    /// The number of changes of a document taken into account by an analysis.
+    pub struct Changes(pub usize);
+
+    impl Enrich<Changes> for Trace {
+        fn enrich(id: &RequestId, param: Changes) {
+            Self::with_trace(|mut t| {
+                t.received.entry(id.to_owned()).and_modify(|item| {
+                    item.params.changes = Some(param.0);
+                });
+                Ok(())
+            })
+            .report();
+        }
+    }
+
     pub struct FileUpdate<'a> {
         pub content: &'a str,
     }
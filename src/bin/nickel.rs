@@ -4,8 +4,8 @@ use nickel_lang::program::Program;
 use nickel_lang::repl::query_print;
 #[cfg(feature = "repl")]
 use nickel_lang::repl::rustyline_frontend;
-use nickel_lang::term::{RichTerm, Term};
-use nickel_lang::{serialize, serialize::ExportFormat};
+use nickel_lang::serialize::ExportFormat;
+use nickel_lang::term::Term;
 use std::path::{Path, PathBuf};
 use std::{
     fs::{self, File},
@@ -67,7 +67,15 @@ enum Command {
         value: bool,
     },
     /// Typechecks the program but do not run it
-    Typecheck,
+    Typecheck {
+        /// Prints the inferred type of wildcards, let bindings of typed blocks and top-level fields
+        #[structopt(long)]
+        show_types: bool,
+        /// Replaces the wildcards of the input file by their inferred types. Prints the result
+        /// to the standard output if the input is read from stdin
+        #[structopt(long)]
+        fill_wildcards: bool,
+    },
     /// Starts an REPL session
     Repl {
         #[structopt(long)]
@@ -150,7 +158,10 @@ fn main() {
                     query_print::write_query_result(&mut std::io::stdout(), &term, attrs).unwrap()
                 })
             }
-            Some(Command::Typecheck) => program.typecheck(),
+            Some(Command::Typecheck {
+                show_types,
+                fill_wildcards,
+            }) => typecheck(&mut program, show_types, fill_wildcards, opts.file),
             Some(Command::Repl { .. }) => unreachable!(),
             #[cfg(feature = "doc")]
             Some(Command::Doc { ref output }) => output
@@ -210,22 +221,40 @@ fn main() {
     }
 }
 
+fn typecheck(
+    program: &mut Program,
+    show_types: bool,
+    fill_wildcards: bool,
+    file: Option<PathBuf>,
+) -> Result<(), Error> {
+    if fill_wildcards {
+        let source = program.fill_wildcards()?;
+
+        match file {
+            Some(file) => fs::write(&file, source).map_err(IOError::from)?,
+            None => print!("{}", source),
+        }
+    }
+
+    if show_types {
+        program.show_types(&mut std::io::stdout())
+    } else if !fill_wildcards {
+        program.typecheck()
+    } else {
+        Ok(())
+    }
+}
+
 fn export(
     program: &mut Program,
     format: Option<ExportFormat>,
     output: Option<PathBuf>,
 ) -> Result<(), Error> {
-    let rt = program.eval_full().map(RichTerm::from)?;
     let format = format.unwrap_or_default();
 
-    serialize::validate(format, &rt)?;
-
     if let Some(file) = output {
-        let file = fs::File::create(&file).map_err(IOError::from)?;
-        serialize::to_writer(file, format, &rt)?;
+        program.export_to_file(&file, format)
     } else {
-        serialize::to_writer(std::io::stdout(), format, &rt)?;
+        program.export(std::io::stdout(), format)
     }
-
-    Ok(())
 }
@@ -7,8 +7,8 @@ use crate::stdlib as nickel_stdlib;
 use crate::term::{RichTerm, SharedTerm, Term};
 use crate::transform::import_resolution;
 use crate::typecheck::type_check;
-use crate::typecheck::{self, Wildcards};
-use crate::types::UnboundTypeVariableError;
+use crate::typecheck::{self, CheckedAnnots, Wildcards};
+use crate::types::{Types, UnboundTypeVariableError};
 use crate::{eval, parser, transform};
 use codespan::{FileId, Files};
 use io::Read;
@@ -32,7 +32,8 @@ pub enum InputFormat {
 }
 
 impl InputFormat {
-    fn from_path_buf(path_buf: &Path) -> Option<InputFormat> {
+    /// Determine the format of a file from the extension of its path.
+    pub fn from_path_buf(path_buf: &Path) -> Option<InputFormat> {
         match path_buf.extension().and_then(OsStr::to_str) {
             Some("ncl") => Some(InputFormat::Nickel),
             Some("json") => Some(InputFormat::Json),
@@ -70,6 +71,11 @@ pub struct Cache {
     stdlib_ids: Option<Vec<FileId>>,
     /// The inferred type of wildcards for each `FileId`.
     wildcards: HashMap<FileId, Wildcards>,
+    /// The statically proven type annotations for each `FileId`.
+    checked_annots: HashMap<FileId, CheckedAnnots>,
+    /// The interface type of each typechecked `FileId`, that is the type of its content as seen
+    /// by the files importing it (see [`typecheck::interface_type`]).
+    interfaces: HashMap<FileId, Types>,
     /// Whether processing should try to continue even in case of errors. Needed by the NLS.
     error_tolerance: ErrorTolerance,
 
@@ -149,7 +155,7 @@ pub enum EntryState {
     ImportsResolving,
     /// The imports of the entry and its transitive dependencies has been resolved.
     ImportsResolved,
-    /// The entry have been typechecked, and its (transitive) imports are being typechecked.
+    /// The (transitive) imports of the entry are being typechecked, followed by the entry itself.
     Typechecking,
     /// The entry and its transitive imports have been typechecked.
     Typechecked,
@@ -218,6 +224,8 @@ impl Cache {
             file_ids: HashMap::new(),
             terms: HashMap::new(),
             wildcards: HashMap::new(),
+            checked_annots: HashMap::new(),
+            interfaces: HashMap::new(),
             imports: HashMap::new(),
             stdlib_ids: None,
             error_tolerance,
@@ -467,36 +475,95 @@ impl Cache {
 
     /// Typecheck an entry of the cache and update its state accordingly, or do nothing if the
     /// entry has already been typechecked. Require that the corresponding source has been parsed.
-    /// If the source contains imports, recursively typecheck on the imports too.
+    ///
+    /// If the source contains imports, they are recursively typechecked first, such that their
+    /// interface type is known when typechecking the source (see [`typecheck::interface_type`]).
+    /// If the interface of the source has changed since the last time it was typechecked, the
+    /// files importing it are invalidated, and will be typechecked again.
     pub fn typecheck(
         &mut self,
         file_id: FileId,
         initial_env: &typecheck::Environment,
     ) -> Result<CacheOp<()>, CacheError<TypecheckError>> {
-        match self.terms.get(&file_id) {
-            Some(CachedTerm { state, .. }) if *state >= EntryState::Typechecked => {
-                Ok(CacheOp::Cached(()))
-            }
-            Some(CachedTerm { term, state, .. }) if *state >= EntryState::Parsed => {
-                if *state < EntryState::Typechecking {
-                    let wildcards = type_check(term, initial_env.clone(), self)?;
-                    self.update_state(file_id, EntryState::Typechecking);
-                    self.wildcards.insert(file_id, wildcards);
-                }
-
-                if let Some(imports) = self.imports.get(&file_id).cloned() {
-                    for f in imports.into_iter() {
-                        self.typecheck(f, initial_env)?;
+        match self.entry_state(file_id) {
+            // An entry in the `Typechecking` state is part of an import cycle: its imports are
+            // being typechecked.
+            Some(state) if state >= EntryState::Typechecking => Ok(CacheOp::Cached(())),
+            Some(state) if state >= EntryState::Parsed => {
+                self.update_state(file_id, EntryState::Typechecking);
+
+                let imports = self.imports.get(&file_id).cloned().unwrap_or_default();
+                let result = imports
+                    .into_iter()
+                    .try_for_each(|f| self.typecheck(f, initial_env).map(|_| ()))
+                    .and_then(|_| {
+                        let term = self.get_ref(file_id).unwrap();
+                        Ok(type_check(term, initial_env.clone(), self)?)
+                    });
+
+                let (wildcards, checked_annots) = match result {
+                    Ok(result) => result,
+                    Err(err) => {
+                        self.update_state(file_id, state);
+                        return Err(err);
                     }
-                }
+                };
 
-                self.update_state(file_id, EntryState::Typechecked);
+                let term = self.get_owned(file_id).unwrap();
+                self.set_typechecked(file_id, &term, wildcards, checked_annots);
                 Ok(CacheOp::Done(()))
             }
             _ => Err(CacheError::NotParsed),
         }
     }
 
+    /// Store the result of the typechecking of an entry, `term` being the typechecked term, and
+    /// update its state to [`EntryState::Typechecked`]. The interface type of the entry is
+    /// computed, and if it has changed, the files importing the entry are invalidated.
+    ///
+    /// This is done by [Self::typecheck], but is also needed by tools which typecheck the
+    /// entries of the cache themselves, such as the language server.
+    pub fn set_typechecked(
+        &mut self,
+        file_id: FileId,
+        term: &RichTerm,
+        wildcards: Wildcards,
+        checked_annots: CheckedAnnots,
+    ) {
+        let format = InputFormat::from_path_buf(Path::new(self.name(file_id)))
+            .unwrap_or(InputFormat::Nickel);
+        let interface = typecheck::interface_type(term, format, &wildcards, self);
+
+        self.wildcards.insert(file_id, wildcards);
+        self.checked_annots.insert(file_id, checked_annots);
+
+        if matches!(self.interfaces.insert(file_id, interface.clone()), Some(previous) if previous != interface)
+        {
+            self.invalidate_importers(file_id);
+        }
+
+        self.update_state(file_id, EntryState::Typechecked);
+    }
+
+    /// Reset the entries importing `file_id` which have been typechecked but not transformed yet to
+    /// the [`EntryState::ImportsResolved`] state, such that they are typechecked again against
+    /// the new interface of `file_id`. Transformed entries can't be typechecked again, but they
+    /// are only transformed once their imports have been typechecked.
+    fn invalidate_importers(&mut self, file_id: FileId) {
+        let importers: Vec<FileId> = self
+            .imports
+            .iter()
+            .filter(|(_, imports)| imports.contains(&file_id))
+            .map(|(importer, _)| *importer)
+            .collect();
+
+        for importer in importers {
+            if self.entry_state(importer) == Some(EntryState::Typechecked) {
+                self.update_state(importer, EntryState::ImportsResolved);
+            }
+        }
+    }
+
     /// Apply program transformations to an entry of the cache, and update its state accordingly,
     /// or do nothing if the entry has already been transformed. Require that the corresponding
     /// source has been parsed.
@@ -512,7 +579,11 @@ impl Cache {
                     let CachedTerm {
                         term, parse_errs, ..
                     } = self.terms.remove(&file_id).unwrap();
-                    let term = transform::transform(term, self.wildcards.get(&file_id))?;
+                    let term = transform::transform(
+                        term,
+                        self.wildcards.get(&file_id),
+                        self.checked_annots.get(&file_id),
+                    )?;
                     self.terms.insert(
                         file_id,
                         CachedTerm {
@@ -564,6 +635,7 @@ impl Cache {
                     parse_errs,
                 } = self.terms.remove(&file_id).unwrap();
                 let wildcards = self.wildcards.get(&file_id);
+                let checked_annots = self.checked_annots.get(&file_id);
 
                 if state < EntryState::Transforming {
                     let pos = term.pos;
@@ -572,7 +644,9 @@ impl Cache {
                         Term::Record(ref mut map, _) => {
                             let map_res: Result<_, UnboundTypeVariableError> = std::mem::take(map)
                                 .into_iter()
-                                .map(|(id, t)| Ok((id, transform::transform(t, wildcards)?)))
+                                .map(|(id, t)| {
+                                    Ok((id, transform::transform(t, wildcards, checked_annots)?))
+                                })
                                 .collect();
                             *map = map_res.map_err(|err| {
                                 CacheError::Error(ImportError::ParseErrors(err.into(), pos))
@@ -581,7 +655,9 @@ impl Cache {
                         Term::RecRecord(ref mut map, ref mut dyn_fields, ..) => {
                             let map_res: Result<_, UnboundTypeVariableError> = std::mem::take(map)
                                 .into_iter()
-                                .map(|(id, t)| Ok((id, transform::transform(t, wildcards)?)))
+                                .map(|(id, t)| {
+                                    Ok((id, transform::transform(t, wildcards, checked_annots)?))
+                                })
                                 .collect();
 
                             let dyn_fields_res: Result<_, UnboundTypeVariableError> =
@@ -589,8 +665,8 @@ impl Cache {
                                     .into_iter()
                                     .map(|(id_t, t)| {
                                         Ok((
-                                            transform::transform(id_t, wildcards)?,
-                                            transform::transform(t, wildcards)?,
+                                            transform::transform(id_t, wildcards, checked_annots)?,
+                                            transform::transform(t, wildcards, checked_annots)?,
                                         ))
                                     })
                                     .collect();
@@ -736,8 +812,8 @@ impl Cache {
             return Err(Error::ParseErrors(errs));
         }
         let (term, pending) = import_resolution::resolve_imports(term, self)?;
-        let wildcards = type_check(&term, initial_env.clone(), self)?;
-        let term = transform::transform(term, Some(&wildcards))
+        let (wildcards, checked_annots) = type_check(&term, initial_env.clone(), self)?;
+        let term = transform::transform(term, Some(&wildcards), Some(&checked_annots))
             .map_err(|err| Error::ParseErrors(err.into()))?;
         Ok((term, pending))
     }
@@ -990,6 +1066,10 @@ pub trait ImportResolver {
     fn get(&self, file_id: FileId) -> Option<RichTerm>;
 
     fn get_path(&self, file_id: FileId) -> &OsStr;
+
+    /// Get the interface type of a resolved import, that is the type of its content as seen by
+    /// the files importing it. Return `None` if the import hasn't been typechecked yet.
+    fn get_interface(&self, file_id: FileId) -> Option<Types>;
 }
 
 impl ImportResolver for Cache {
@@ -1043,6 +1123,10 @@ impl ImportResolver for Cache {
     fn get_path(&self, file_id: FileId) -> &OsStr {
         self.files.name(file_id)
     }
+
+    fn get_interface(&self, file_id: FileId) -> Option<Types> {
+        self.interfaces.get(&file_id).cloned()
+    }
 }
 
 /// Compute the path of a file relatively to a parent.
@@ -1090,6 +1174,10 @@ pub mod resolvers {
         fn get_path(&self, _file_id: FileId) -> &OsStr {
             panic!("cache::resolvers: dummy resolver should not have been invoked");
         }
+
+        fn get_interface(&self, _file_id: FileId) -> Option<Types> {
+            panic!("cache::resolvers: dummy resolver should not have been invoked");
+        }
     }
 
     /// Resolve imports from a mockup file database. Used to test imports without accessing the
@@ -1160,5 +1248,9 @@ pub mod resolvers {
         fn get_path(&self, file_id: FileId) -> &OsStr {
             self.files.name(file_id)
         }
+
+        fn get_interface(&self, _file_id: FileId) -> Option<Types> {
+            None
+        }
     }
 }
@@ -2,6 +2,7 @@
 
 use std::collections::HashMap;
 
+use num_traits::ToPrimitive;
 use serde::de::{
     Deserialize, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
     VariantAccess, Visitor,
@@ -17,7 +18,7 @@ macro_rules! deserialize_number {
             V: Visitor<'de>,
         {
             match unwrap_term(self)? {
-                Term::Num(n) => visitor.$visit(n as $type),
+                Term::Num(n) => visitor.$visit(n.to_f64() as $type),
                 other => Err(RustDeserializationError::InvalidType {
                     expected: "Num".to_string(),
                     occurred: other.type_of().unwrap_or_else(|| "Other".to_string()),
@@ -28,13 +29,20 @@ macro_rules! deserialize_number {
 }
 
 macro_rules! deserialize_number_round {
-    ($method:ident, $type:tt, $visit:ident) => {
+    ($method:ident, $type:tt, $visit:ident, $convert:ident) => {
         fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
         where
             V: Visitor<'de>,
         {
             match unwrap_term(self)? {
-                Term::Num(n) => visitor.$visit(n.round() as $type),
+                Term::Num(n) => match n.round().$convert() {
+                    Some(n) => visitor.$visit(n),
+                    None => Err(RustDeserializationError::Other(format!(
+                        "{} is out of the range of {}",
+                        n,
+                        stringify!($type)
+                    ))),
+                },
                 other => Err(RustDeserializationError::InvalidType {
                     expected: "Num".to_string(),
                     occurred: other.type_of().unwrap_or_else(|| "Other".to_string()),
@@ -67,12 +75,26 @@ impl<'de> serde::Deserializer<'de> for RichTerm {
         match unwrap_term(self)? {
             Term::Null => visitor.visit_unit(),
             Term::Bool(v) => visitor.visit_bool(v),
-            Term::Num(v) => visitor.visit_f64(v),
+            Term::Num(n) => {
+                let int = n.to_integer();
+
+                if let Some(n) = int.as_ref().and_then(ToPrimitive::to_i64) {
+                    visitor.visit_i64(n)
+                } else if let Some(n) = int.as_ref().and_then(ToPrimitive::to_u64) {
+                    visitor.visit_u64(n)
+                } else {
+                    visitor.visit_f64(n.to_f64())
+                }
+            }
             Term::Str(v) => visitor.visit_string(v),
             Term::Enum(v) => visitor.visit_enum(EnumDeserializer {
                 variant: v.label,
                 rich_term: None,
             }),
+            Term::EnumVariant(v, t) => visitor.visit_enum(EnumDeserializer {
+                variant: v.label,
+                rich_term: Some(t),
+            }),
             Term::Record(v, _) => visit_record(v, visitor),
             Term::Array(v, _) => visit_array(v, visitor),
             Term::MetaValue(_) => visitor.visit_unit(),
@@ -82,16 +104,16 @@ impl<'de> serde::Deserializer<'de> for RichTerm {
         }
     }
 
-    deserialize_number_round!(deserialize_i8, i8, visit_i8);
-    deserialize_number_round!(deserialize_i16, i16, visit_i16);
-    deserialize_number_round!(deserialize_i32, i32, visit_i32);
-    deserialize_number_round!(deserialize_i64, i64, visit_i64);
-    deserialize_number_round!(deserialize_i128, i128, visit_i128);
-    deserialize_number_round!(deserialize_u8, u8, visit_u8);
-    deserialize_number_round!(deserialize_u16, u16, visit_u16);
-    deserialize_number_round!(deserialize_u32, u32, visit_u32);
-    deserialize_number_round!(deserialize_u64, u64, visit_u64);
-    deserialize_number_round!(deserialize_u128, u128, visit_u128);
+    deserialize_number_round!(deserialize_i8, i8, visit_i8, to_i8);
+    deserialize_number_round!(deserialize_i16, i16, visit_i16, to_i16);
+    deserialize_number_round!(deserialize_i32, i32, visit_i32, to_i32);
+    deserialize_number_round!(deserialize_i64, i64, visit_i64, to_i64);
+    deserialize_number_round!(deserialize_i128, i128, visit_i128, to_i128);
+    deserialize_number_round!(deserialize_u8, u8, visit_u8, to_u8);
+    deserialize_number_round!(deserialize_u16, u16, visit_u16, to_u16);
+    deserialize_number_round!(deserialize_u32, u32, visit_u32, to_u32);
+    deserialize_number_round!(deserialize_u64, u64, visit_u64, to_u64);
+    deserialize_number_round!(deserialize_u128, u128, visit_u128, to_u128);
     deserialize_number!(deserialize_f32, f32, visit_f32);
     deserialize_number!(deserialize_f64, f64, visit_f64);
 
@@ -106,7 +128,8 @@ impl<'de> serde::Deserializer<'de> for RichTerm {
         }
     }
 
-    /// deserialize `RichTerm::Enum` tags or `RichTerm::Record`s with a single item.
+    /// deserialize `RichTerm::Enum` tags, `RichTerm::EnumVariant`s or `RichTerm::Record`s with a
+    /// single item.
     fn deserialize_enum<V>(
         self,
         _name: &str,
@@ -118,6 +141,7 @@ impl<'de> serde::Deserializer<'de> for RichTerm {
     {
         let (variant, rich_term) = match unwrap_term(self)? {
             Term::Enum(ident) => (ident.label, None),
+            Term::EnumVariant(ident, t) => (ident.label, Some(t)),
             Term::Record(v, _) => {
                 let mut iter = v.into_iter();
                 let (variant, value) = match iter.next() {
@@ -654,6 +678,37 @@ mod tests {
         )
     }
 
+    #[test]
+    fn rust_deserialize_enum_variants() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "lowercase")]
+        enum E {
+            Foo(u16),
+            Bar { x: String },
+            Baz,
+        }
+
+        assert_eq!(
+            Vec::<E>::deserialize(
+                Program::new_from_source(
+                    Cursor::new(br#"[`foo 1, `bar {x = "string"}, `baz]"#.to_vec()),
+                    "source"
+                )
+                .expect("program should't fail")
+                .eval_full()
+                .expect("evaluation should't fail")
+            )
+            .expect("deserialization should't fail"),
+            vec![
+                E::Foo(1),
+                E::Bar {
+                    x: "string".to_string()
+                },
+                E::Baz
+            ]
+        )
+    }
+
     #[test]
     fn rust_deserialize_array_of_numbers() {
         assert_eq!(
@@ -668,6 +723,31 @@ mod tests {
         )
     }
 
+    #[test]
+    fn rust_deserialize_big_integers() {
+        assert_eq!(
+            Vec::<u64>::deserialize(
+                Program::new_from_source(
+                    Cursor::new(br#"[18446744073709551615, 9007199254740993]"#.to_vec()),
+                    "source"
+                )
+                .expect("program should't fail")
+                .eval_full()
+                .expect("evaluation should't fail")
+            )
+            .expect("deserialization should't fail"),
+            vec![u64::MAX, 9007199254740993]
+        );
+
+        assert!(u8::deserialize(
+            Program::new_from_source(Cursor::new(br#"256"#.to_vec()), "source")
+                .expect("program should't fail")
+                .eval_full()
+                .expect("evaluation should't fail")
+        )
+        .is_err());
+    }
+
     #[test]
     fn rust_deserialize_fail_non_data() {
         #[derive(Debug, PartialEq, Deserialize)]
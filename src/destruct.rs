@@ -103,6 +103,34 @@ impl Destruct {
     pub fn is_empty(&self) -> bool {
         matches!(self, Destruct::Empty)
     }
+
+    /// Return the identifiers bound by this pattern, including the ones bound by nested patterns.
+    pub fn bound_idents(&self) -> Vec<&Ident> {
+        let mut idents = Vec::new();
+        self.collect_bound_idents(&mut idents);
+        idents
+    }
+
+    fn collect_bound_idents<'a>(&'a self, idents: &mut Vec<&'a Ident>) {
+        let matches = match self {
+            Destruct::Record { matches, rest, .. } => {
+                idents.extend(rest);
+                matches
+            }
+            Destruct::Array { matches, .. } => matches,
+            Destruct::Empty => return,
+        };
+
+        for m in matches {
+            match m {
+                Match::Assign(_, _, (id, sub_pat)) => {
+                    idents.extend(id);
+                    sub_pat.collect_bound_idents(idents);
+                }
+                Match::Simple(id, _) => idents.push(id),
+            }
+        }
+    }
 }
 
 impl Match {
@@ -124,6 +124,13 @@ impl<K: Hash + Eq, V: PartialEq> Environment<K, V> {
         }
     }
 
+    /// Returns the reference-counted parts of the environment: the current layer, and the previous
+    /// layers if any. The previous layers are cloned, which temporarily increments their strong
+    /// count. Used to traverse environments when collecting reference cycles.
+    pub(crate) fn rc_parts(&self) -> (&Rc<HashMap<K, V>>, Option<Rc<Environment<K, V>>>) {
+        (&self.current, self.previous.borrow().clone())
+    }
+
     /// Checks if `current` has been cloned. If it has, it is present both in current and in
     /// previous, making it Rc strong count bigger than 1.
     fn was_cloned(&self) -> bool {
@@ -9,7 +9,7 @@ use codespan_reporting::diagnostic::{Diagnostic, Label, LabelStyle};
 use lalrpop_util::ErrorRecovery;
 
 use crate::{
-    eval::callstack::CallStack,
+    eval::{budget::Exhausted, callstack::CallStack},
     identifier::Ident,
     label,
     label::ty_path,
@@ -95,6 +95,11 @@ pub enum EvalError {
     ),
     /// An unexpected internal error.
     InternalError(String, TermPos),
+    /// The evaluation exhausted its budget, or was cancelled. See [crate::eval::budget].
+    Interrupted(
+        Exhausted,
+        /* position of the term being evaluated */ TermPos,
+    ),
     /// Errors occurring rarely enough to not deserve a dedicated variant.
     Other(String, TermPos),
 }
@@ -209,6 +214,28 @@ pub enum TypecheckError {
         RichTerm, /* the inferred flat type */
         TermPos,
     ),
+    /// A switch without a default case doesn't handle all the tags of the closed enum type of the
+    /// tested expression.
+    NonExhaustiveSwitch(
+        /* the unhandled tags */ Vec<Ident>,
+        /* the type of the tested expression */ Types,
+        TermPos,
+    ),
+    /// Two records of statically known types which define the same field are merged together, or
+    /// a field is statically inserted into a record which already defines it.
+    OverlappingField(
+        Ident,
+        /* the type of the left operand */ Types,
+        /* the type of the right operand */ Types,
+        TermPos,
+    ),
+    /// An optional field of a record of a statically known type is accessed directly, without
+    /// checking first that it is defined.
+    OptionalFieldAccess(Ident, /* the type of the record */ Types, TermPos),
+    /// Several independent type errors were found in the same term. The typechecker recovers
+    /// from a type error to report as many errors as possible at once (see
+    /// [`crate::typecheck::MAX_ERRORS`]).
+    Multiple(Vec<TypecheckError>),
 }
 
 #[derive(Debug, PartialEq, Clone, Default)]
@@ -320,6 +347,12 @@ pub enum ParseError {
     /// A recursive let pattern was encountered. They are not currently supported because we
     /// decided it was too involved to implement them.
     RecursiveLetPattern(RawSpan),
+    /// A recursive type `rec id. ty` where `id` isn't guarded by an array, a record, a function or
+    /// an enum payload, or occurs in the tail of a row.
+    IllformedRecType(Ident, RawSpan),
+    /// A union type `s \/ t` where some values have both the shape of `s` and the shape of `t`.
+    /// The union contract couldn't tell which alternative such values belong to.
+    OverlappingUnion(Types, RawSpan),
 }
 
 /// An error occurring during the resolution of an import.
@@ -343,6 +376,9 @@ pub enum ImportError {
 pub enum SerializationError {
     /// Encountered a null value for a format that doesn't support them.
     UnsupportedNull(ExportFormat, RichTerm),
+    /// Encountered an integer which is too large for a format, which only supports 64 bits
+    /// integers.
+    IntegerOutOfRange(ExportFormat, RichTerm),
     /// Tried exporting something else than a `Str` to raw format.
     NotAString(RichTerm),
     /// A term contains constructs that cannot be serialized.
@@ -479,6 +515,12 @@ impl ParseError {
                 InternalParseError::RecursiveLetPattern(pos) => {
                     ParseError::RecursiveLetPattern(pos)
                 }
+                InternalParseError::IllformedRecType(id, pos) => {
+                    ParseError::IllformedRecType(id, pos)
+                }
+                InternalParseError::OverlappingUnion(ty, pos) => {
+                    ParseError::OverlappingUnion(ty, pos)
+                }
             },
         }
     }
@@ -1136,6 +1178,16 @@ impl ToDiagnostic<FileId> for EvalError {
                     .with_message("infinite recursion")
                     .with_labels(labels)]
             }
+            EvalError::Interrupted(reason, span_opt) => {
+                let labels = span_opt
+                    .as_opt_ref()
+                    .map(|span| vec![primary(span).with_message("evaluation stopped here")])
+                    .unwrap_or_default();
+
+                vec![Diagnostic::error()
+                    .with_message(format!("evaluation interrupted: {}", reason))
+                    .with_labels(labels)]
+            }
             EvalError::Other(msg, span_opt) => {
                 let labels = span_opt
                     .as_opt_ref()
@@ -1246,6 +1298,24 @@ impl ToDiagnostic<FileId> for ParseError {
                     String::from("A destructuring let-binding can't be recursive. Try removing the `rec` from `let rec`."),
                     String::from("Note: you can reference other fields of a record recursively from within a field, so you might not need the recursive let."),
                 ]),
+            ParseError::IllformedRecType(id, span) => Diagnostic::error()
+                .with_message(format!("ill-formed recursive type `rec {}. ...`", id))
+                .with_labels(vec![
+                    primary(span),
+                ])
+                .with_notes(vec![
+                    format!("The variable `{}` must only occur inside an array, a record field, a function or an enum payload.", id),
+                    format!("`{}` can't be used as the tail of a row.", id),
+                ]),
+            ParseError::OverlappingUnion(ty, span) => Diagnostic::error()
+                .with_message(format!("overlapping alternatives in the union type `{}`", ty))
+                .with_labels(vec![
+                    primary(span),
+                ])
+                .with_notes(vec![
+                    String::from("Some values have the shape of both alternatives, so the union contract can't tell which one they belong to."),
+                    String::from("The alternatives of a union must differ by their kind, the fields of a record or the tags of an enum."),
+                ]),
         };
 
         vec![diagnostic]
@@ -1342,8 +1412,8 @@ impl ToDiagnostic<FileId> for TypecheckError {
             ,
             TypecheckError::RowKindMismatch(ident, expd, actual, span_opt) => {
                 let (expd_str, actual_str) = match (expd, actual) {
-                    (Some(_), None) => ("an enum type", "a record type"),
-                    (None, Some(_)) => ("a record type", "an enum type"),
+                    (Some(_), None) => ("a record field or an enum variant with a payload", "an enum tag without payload"),
+                    (None, Some(_)) => ("an enum tag without payload", "a record field or an enum variant with a payload"),
                     _ => panic!("error::to_diagnostic()::RowKindMismatch: unexpected configuration for `expd` and `actual`"),
                 };
 
@@ -1352,8 +1422,8 @@ impl ToDiagnostic<FileId> for TypecheckError {
                         .with_message("incompatible row kinds")
                         .with_labels(mk_expr_label(span_opt))
                         .with_notes(vec![
-                            format!("The row type of `{}` was expected to be `{}`, but was inferred to be `{}`", ident, expd_str, actual_str),
-                            String::from("Enum row types and record row types are not compatible"),
+                            format!("The row `{}` was expected to be {}, but was inferred to be {}", ident, expd_str, actual_str),
+                            String::from("Enum tags without payload are not compatible with record fields and enum variants"),
                         ])]
             }
             TypecheckError::RowMismatch(ident, expd, actual, err_, span_opt) => {
@@ -1477,6 +1547,41 @@ impl ToDiagnostic<FileId> for TypecheckError {
                     ])
                 ]
             }
+            TypecheckError::NonExhaustiveSwitch(missing, ty, span_opt) => {
+                let tags: Vec<String> = missing.iter().map(|id| format!("`{}", id)).collect();
+
+                vec![Diagnostic::error()
+                    .with_message("non-exhaustive switch")
+                    .with_labels(mk_expr_label(span_opt))
+                    .with_notes(vec![
+                        format!("The type of the tested expression is the closed enum type `{}`", ty),
+                        format!("The following tags are not handled: {}", tags.join(", ")),
+                        String::from("Add the missing cases, or a default case `_ => ...`"),
+                    ])]
+            }
+            TypecheckError::OverlappingField(ident, left, right, span_opt) =>
+                vec![Diagnostic::error()
+                    .with_message(format!("type error: field `{}` is defined twice", ident))
+                    .with_labels(mk_expr_label(span_opt))
+                    .with_notes(vec![
+                        format!("The type of the first record was inferred to be `{}`, which contains the field `{}`", left, ident),
+                        format!("The type of the second record was inferred to be `{}`, which also contains the field `{}`", right, ident),
+                        String::from("Statically typed records can only be combined if they don't have any field in common"),
+                    ])]
+            ,
+            TypecheckError::OptionalFieldAccess(ident, ty, span_opt) =>
+                vec![Diagnostic::error()
+                    .with_message(format!("type error: access to the optional field `{}`", ident))
+                    .with_labels(mk_expr_label(span_opt))
+                    .with_notes(vec![
+                        format!("The type of the record was inferred to be `{}`, where the field `{}` is optional", ty, ident),
+                        format!("Test that the field is defined first, as in `if record.has_field \"{}\" r then r.{} else ...`", ident, ident),
+                    ])]
+            ,
+            TypecheckError::Multiple(errors) => errors
+                .iter()
+                .flat_map(|err| err.to_diagnostic(files, contract_id))
+                .collect(),
         }
     }
 }
@@ -1535,6 +1640,9 @@ impl ToDiagnostic<FileId> for SerializationError {
             SerializationError::UnsupportedNull(format, rt) => vec![Diagnostic::error()
                 .with_message(format!("{} doesn't support null values", format))
                 .with_labels(vec![primary_term(rt, files)])],
+            SerializationError::IntegerOutOfRange(format, rt) => vec![Diagnostic::error()
+                .with_message(format!("{} only supports integers of 64 bits", format))
+                .with_labels(vec![primary_term(rt, files)])],
             SerializationError::NonSerializable(rt) => vec![Diagnostic::error()
                 .with_message("non serializable term")
                 .with_labels(vec![primary_term(rt, files)])],
@@ -0,0 +1,129 @@
+//! Bounds on the resources consumed by an evaluation.
+//!
+//! Nickel programs may diverge, or take a long time to evaluate. Tools which evaluate programs on
+//! their own, such as the language server evaluating the document being edited in the background,
+//! can't afford to hang. They can run an evaluation under a [Budget], which limits the number of
+//! steps performed by the abstract machine and the time spent evaluating, and which can be
+//! cancelled from another thread. Once the budget is exhausted, evaluation stops with an
+//! [`EvalError::Interrupted`] error.
+//!
+//! The budget is stored in a thread-local variable, such that the signature of the evaluation
+//! functions doesn't change, and that evaluations running without a budget only pay for a check.
+use crate::{error::EvalError, position::TermPos};
+
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// The number of steps between two checks of the deadline and of the cancellation flag. Fetching
+/// the current time at each step would noticeably slow down evaluation.
+const CHECK_INTERVAL: usize = 1024;
+
+thread_local! {
+    static BUDGET: RefCell<Option<Budget>> = const { RefCell::new(None) };
+}
+
+/// The reason why an evaluation was interrupted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exhausted {
+    /// The maximum number of steps has been reached.
+    Steps,
+    /// The deadline has passed.
+    Time,
+    /// The evaluation was cancelled.
+    Cancelled,
+}
+
+impl std::fmt::Display for Exhausted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Exhausted::Steps => write!(f, "the maximum number of evaluation steps was reached"),
+            Exhausted::Time => write!(f, "the evaluation timed out"),
+            Exhausted::Cancelled => write!(f, "the evaluation was cancelled"),
+        }
+    }
+}
+
+/// The resources allotted to an evaluation. Each limit is optional.
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    /// The maximum number of steps of the abstract machine.
+    steps: Option<usize>,
+    /// The instant after which the evaluation is interrupted.
+    deadline: Option<Instant>,
+    /// A flag which interrupts the evaluation once set.
+    cancel: Option<Arc<AtomicBool>>,
+    /// The number of steps performed so far.
+    performed: usize,
+}
+
+impl Budget {
+    /// Create an unlimited budget.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the number of steps of the abstract machine.
+    pub fn with_steps(mut self, steps: usize) -> Self {
+        self.steps = Some(steps);
+        self
+    }
+
+    /// Limit the duration of the evaluation, starting from now.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Some(Instant::now() + timeout);
+        self
+    }
+
+    /// Interrupt the evaluation as soon as `flag` is set.
+    pub fn with_cancellation(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(flag);
+        self
+    }
+
+    /// Account for one more step, and check that the budget is not exhausted.
+    fn step(&mut self) -> Result<(), Exhausted> {
+        self.performed += 1;
+
+        if matches!(self.steps, Some(max) if self.performed > max) {
+            return Err(Exhausted::Steps);
+        }
+
+        if self.performed.is_multiple_of(CHECK_INTERVAL) {
+            if matches!(&self.cancel, Some(flag) if flag.load(Ordering::Relaxed)) {
+                return Err(Exhausted::Cancelled);
+            }
+
+            if matches!(self.deadline, Some(deadline) if Instant::now() >= deadline) {
+                return Err(Exhausted::Time);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Run `f` under the given budget. Every evaluation performed by `f` on the current thread
+/// consumes this budget. The previous budget, if any, is restored afterwards.
+pub fn with_budget<T>(budget: Budget, f: impl FnOnce() -> T) -> T {
+    let previous = BUDGET.with(|current| current.replace(Some(budget)));
+    let result = f();
+    BUDGET.with(|current| current.replace(previous));
+    result
+}
+
+/// Account for one step of the abstract machine. Called by the main loop of the evaluator, `pos`
+/// being the position of the term about to be evaluated.
+pub(crate) fn step(pos: TermPos) -> Result<(), EvalError> {
+    BUDGET.with(|current| match current.borrow_mut().as_mut() {
+        Some(budget) => budget
+            .step()
+            .map_err(|reason| EvalError::Interrupted(reason, pos)),
+        None => Ok(()),
+    })
+}
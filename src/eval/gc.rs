@@ -0,0 +1,340 @@
+//! Collection of reference cycles between thunks.
+//!
+//! Thunks and environments are reference-counted. Recursive records and recursive let-bindings
+//! create cycles: the environment of the content of a field contains the thunk of the field
+//! itself, or of its siblings (see [`super::fixpoint`]). Such cycles are never freed by reference
+//! counting alone, which is an issue for long-running processes evaluating many expressions, such
+//! as the REPL.
+//!
+//! Registering thunks has a cost in time and memory, which a one-shot evaluation doesn't need to
+//! pay: its cycles are freed together with the process. Long-running processes thus [enable] the
+//! registration, after which every thunk created by the thread is registered upon creation, and
+//! [collect_cycles] implements a cycle collector similar to the one of CPython, which doesn't need
+//! to know the roots of the program:
+//!
+//! 1. The graph of the registered thunks, and of the closures, environments and layers of
+//!    environments reachable from them, is traversed. For each node, we count the references
+//!    coming from the other nodes of the graph.
+//! 2. A node whose strong count is greater than the number of these internal references is
+//!    referenced from outside of the graph, by the evaluator, the cache or the environment of the
+//!    REPL for example. Such nodes are the roots, and all the nodes reachable from them are alive.
+//! 3. The remaining thunks are only referenced by each other, and are thus unreachable. Their
+//!    content is cleared, which breaks the cycles and lets reference counting free them.
+//!
+//! Because the collector only relies on reference counts, it is safe to call at any time, but it
+//! has to traverse all the live thunks, including the ones of the standard library. It is thus
+//! meant to be called after the evaluation of a whole expression, rather than during evaluation.
+use super::{
+    lazy::{InnerThunkData, Thunk, ThunkData},
+    Closure, Environment,
+};
+use crate::identifier::Ident;
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::{Rc, Weak},
+};
+
+/// The minimal size of the registry before dead entries are pruned.
+const MIN_PRUNE_SIZE: usize = 1024;
+
+/// The registry of all the thunks created by the current thread.
+struct Registry {
+    thunks: Vec<Weak<RefCell<ThunkData>>>,
+    /// The size at which the dead entries are pruned next. The registry only holds weak
+    /// references, but they still keep the allocation of freed thunks until pruned.
+    prune_at: usize,
+}
+
+impl Registry {
+    fn prune(&mut self) {
+        self.thunks.retain(|thunk| thunk.strong_count() > 0);
+        self.prune_at = MIN_PRUNE_SIZE.max(2 * self.thunks.len());
+    }
+}
+
+thread_local! {
+    static REGISTRY: RefCell<Registry> = const {
+        RefCell::new(Registry {
+            thunks: Vec::new(),
+            prune_at: MIN_PRUNE_SIZE,
+        })
+    };
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Enable the registration of the thunks created from now on by the current thread, such that
+/// their cycles can be collected by [collect_cycles]. The thunks created before are never
+/// collected, but the references they hold are accounted for by the collector.
+pub fn enable() {
+    ENABLED.with(|enabled| enabled.set(true));
+}
+
+/// Register the data of a newly created thunk if the registration is enabled, and return it.
+pub(super) fn register(data: Rc<RefCell<ThunkData>>) -> Rc<RefCell<ThunkData>> {
+    if !ENABLED.with(Cell::get) {
+        return data;
+    }
+
+    REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+
+        if registry.thunks.len() >= registry.prune_at {
+            registry.prune();
+        }
+
+        registry.thunks.push(Rc::downgrade(&data));
+    });
+
+    data
+}
+
+/// A node of the reference graph, identified by the address of the shared data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Node {
+    Thunk(*const RefCell<ThunkData>),
+    Closure(*const Closure),
+    Env(*const Environment),
+    Layer(*const HashMap<Ident, Thunk>),
+}
+
+#[derive(Debug)]
+struct NodeInfo {
+    node: Node,
+    strong_count: usize,
+    /// The number of references coming from other nodes of the graph.
+    internal_count: usize,
+    edges: Vec<usize>,
+    /// The content of the node couldn't be accessed, because a thunk was mutably borrowed. Such a
+    /// node is conservatively considered as a root.
+    opaque: bool,
+}
+
+#[derive(Default)]
+struct Graph {
+    nodes: Vec<NodeInfo>,
+    index: HashMap<Node, usize>,
+}
+
+impl Graph {
+    /// Add a node to the graph if it isn't already there, and return its index.
+    fn add(&mut self, node: Node, strong_count: usize) -> usize {
+        let nodes = &mut self.nodes;
+
+        *self.index.entry(node).or_insert_with(|| {
+            nodes.push(NodeInfo {
+                node,
+                strong_count,
+                internal_count: 0,
+                edges: Vec::new(),
+                opaque: false,
+            });
+            nodes.len() - 1
+        })
+    }
+
+    /// Add the nodes directly referenced by an environment and return their indices.
+    fn add_env(&mut self, env: &Environment) -> Vec<usize> {
+        let (current, previous) = env.rc_parts();
+        let mut edges = vec![self.add(Node::Layer(Rc::as_ptr(current)), Rc::strong_count(current))];
+
+        if let Some(previous) = previous {
+            // `previous` is a clone, which isn't accounted for.
+            edges.push(self.add(
+                Node::Env(Rc::as_ptr(&previous)),
+                Rc::strong_count(&previous) - 1,
+            ));
+        }
+
+        edges
+    }
+
+    /// Add the nodes directly referenced by a node, and return their indices or `None` if the
+    /// content of the node couldn't be accessed.
+    ///
+    /// # Safety
+    ///
+    /// The node must be alive, which is the case as long as the graph is traversed without
+    /// dropping anything.
+    unsafe fn add_children(&mut self, node: Node) -> Option<Vec<usize>> {
+        match node {
+            Node::Thunk(ptr) => {
+                let data = (*ptr).try_borrow().ok()?;
+
+                Some(match data.inner() {
+                    InnerThunkData::Standard(closure) => self.add_env(&closure.env),
+                    InnerThunkData::Revertible { orig, cached, .. } => vec![
+                        self.add(Node::Closure(Rc::as_ptr(orig)), Rc::strong_count(orig)),
+                        self.add(Node::Closure(Rc::as_ptr(cached)), Rc::strong_count(cached)),
+                    ],
+                    InnerThunkData::Moved => Vec::new(),
+                })
+            }
+            Node::Closure(ptr) => Some(self.add_env(&(*ptr).env)),
+            Node::Env(ptr) => Some(self.add_env(&*ptr)),
+            Node::Layer(ptr) => Some(
+                (*ptr)
+                    .values()
+                    .map(|thunk| {
+                        let data = thunk.data();
+                        self.add(Node::Thunk(Rc::as_ptr(data)), Rc::strong_count(data))
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Return the number of registered thunks which are still alive.
+#[cfg(test)]
+pub(crate) fn registered() -> usize {
+    REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        registry.prune();
+        registry.thunks.len()
+    })
+}
+
+/// Free the thunks which are only kept alive by reference cycles. Return the number of thunks
+/// which have been collected.
+pub fn collect_cycles() -> usize {
+    let registered: Vec<Weak<RefCell<ThunkData>>> = REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        registry.prune();
+        registry.thunks.clone()
+    });
+
+    let mut graph = Graph::default();
+    for thunk in registered.iter() {
+        graph.add(Node::Thunk(thunk.as_ptr()), thunk.strong_count());
+    }
+
+    // Nodes are discovered during the traversal and pushed at the end of `graph.nodes`.
+    let mut next = 0;
+    while next < graph.nodes.len() {
+        let node = graph.nodes[next].node;
+
+        // SAFETY: all the nodes of the graph are kept alive by the registered thunks, and nothing
+        // is dropped before the end of the traversal.
+        match unsafe { graph.add_children(node) } {
+            Some(edges) => {
+                for &child in edges.iter() {
+                    graph.nodes[child].internal_count += 1;
+                }
+                graph.nodes[next].edges = edges;
+            }
+            None => graph.nodes[next].opaque = true,
+        }
+
+        next += 1;
+    }
+
+    let mut alive = vec![false; graph.nodes.len()];
+    let mut stack: Vec<usize> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, info)| info.opaque || info.strong_count > info.internal_count)
+        .map(|(idx, _)| idx)
+        .collect();
+
+    while let Some(idx) = stack.pop() {
+        if !alive[idx] {
+            alive[idx] = true;
+            stack.extend(graph.nodes[idx].edges.iter().copied());
+        }
+    }
+
+    let garbage: Vec<Rc<RefCell<ThunkData>>> = registered
+        .iter()
+        .filter(|thunk| {
+            graph
+                .index
+                .get(&Node::Thunk(thunk.as_ptr()))
+                .is_some_and(|&idx| !alive[idx])
+        })
+        .filter_map(Weak::upgrade)
+        .collect();
+
+    for data in garbage.iter() {
+        // The previous content is only dropped once the thunk has been released, as dropping it
+        // may free other thunks.
+        let content = data.try_borrow_mut().map(|mut data| data.clear());
+        drop(content);
+    }
+
+    garbage.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::IdentKind;
+    use crate::term::{RichTerm, Term};
+
+    /// Build two thunks referencing each other through their environments.
+    fn mk_cycle() -> (Thunk, Thunk) {
+        let mut fst = Thunk::new(
+            Closure::atomic_closure(RichTerm::from(Term::Var(Ident::from("snd")))),
+            IdentKind::Let,
+        );
+        let mut snd = Thunk::new(
+            Closure::atomic_closure(RichTerm::from(Term::Var(Ident::from("fst")))),
+            IdentKind::Let,
+        );
+
+        fst.borrow_mut().env.insert(Ident::from("snd"), snd.clone());
+        snd.borrow_mut().env.insert(Ident::from("fst"), fst.clone());
+
+        (fst, snd)
+    }
+
+    #[test]
+    fn registration_is_disabled_by_default() {
+        std::thread::spawn(|| {
+            let (fst, snd) = mk_cycle();
+            assert_eq!(registered(), 0);
+            drop((fst, snd));
+            assert_eq!(collect_cycles(), 0);
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn collects_unreachable_cycles() {
+        enable();
+        let (fst, snd) = mk_cycle();
+        let weak_fst = Rc::downgrade(fst.data());
+        let weak_snd = Rc::downgrade(snd.data());
+        drop(fst);
+        drop(snd);
+
+        assert!(weak_fst.upgrade().is_some());
+        collect_cycles();
+        assert!(weak_fst.upgrade().is_none());
+        assert!(weak_snd.upgrade().is_none());
+    }
+
+    #[test]
+    fn keeps_reachable_cycles() {
+        enable();
+        let (fst, snd) = mk_cycle();
+        let weak_snd = Rc::downgrade(snd.data());
+        drop(snd);
+
+        let mut env = Environment::new();
+        env.insert(Ident::from("fst"), fst);
+        let cloned = env.clone();
+        drop(env);
+
+        collect_cycles();
+        let snd = weak_snd.upgrade().expect("reachable thunk was collected");
+        assert_eq!(
+            snd.borrow().closure().body.as_ref(),
+            &Term::Var(Ident::from("fst"))
+        );
+        assert!(cloned.get(&Ident::from("fst")).is_some());
+    }
+}
@@ -1,7 +1,10 @@
 //! Thunks and associated devices used to implement lazy evaluation.
-use super::{Closure, IdentKind};
-use crate::{identifier::Ident, term::FieldDeps};
-use std::cell::{Ref, RefCell, RefMut};
+use super::{gc, Closure, IdentKind};
+use crate::{
+    identifier::Ident,
+    term::{FieldDeps, Term},
+};
+use std::cell::{Cell, Ref, RefCell, RefMut};
 use std::collections::HashSet;
 use std::rc::{Rc, Weak};
 
@@ -45,6 +48,57 @@ pub enum InnerThunkData {
         cached: Rc<Closure>,
         deps: FieldDeps,
     },
+    /// The content has been moved out of thunk data being consumed or dropped. It is never
+    /// observed otherwise.
+    Moved,
+}
+
+/// The maximal number of nested drops of thunk data. Freeing a long chain of thunks referencing
+/// each other through their environments, such as the accumulator of a deep recursion, would
+/// otherwise recurse as deep as the chain and overflow the stack. Beyond this depth, the content
+/// of the thunks is stashed, and dropped iteratively by the outermost drop.
+const MAX_DROP_DEPTH: usize = 128;
+
+thread_local! {
+    static DROP_DEPTH: Cell<usize> = const { Cell::new(0) };
+    static DEFERRED_DROPS: RefCell<Vec<InnerThunkData>> = const { RefCell::new(Vec::new()) };
+}
+
+impl Drop for ThunkData {
+    fn drop(&mut self) {
+        let inner = std::mem::replace(&mut self.inner, InnerThunkData::Moved);
+
+        if matches!(inner, InnerThunkData::Moved) {
+            return;
+        }
+
+        // The thread locals aren't available anymore while the thread is being torn down.
+        let depth = match DROP_DEPTH.try_with(|depth| {
+            depth.set(depth.get() + 1);
+            depth.get()
+        }) {
+            Ok(depth) => depth,
+            Err(_) => return,
+        };
+
+        if depth > MAX_DROP_DEPTH {
+            DEFERRED_DROPS.with(|deferred| deferred.borrow_mut().push(inner));
+        } else {
+            drop(inner);
+
+            if depth == 1 {
+                loop {
+                    let batch = DEFERRED_DROPS.with(|deferred| deferred.take());
+                    if batch.is_empty() {
+                        break;
+                    }
+                    drop(batch);
+                }
+            }
+        }
+
+        DROP_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
 }
 
 impl ThunkData {
@@ -75,6 +129,7 @@ impl ThunkData {
         match self.inner {
             InnerThunkData::Standard(ref closure) => closure,
             InnerThunkData::Revertible { ref cached, .. } => cached,
+            InnerThunkData::Moved => unreachable!(),
         }
     }
 
@@ -83,17 +138,19 @@ impl ThunkData {
         match self.inner {
             InnerThunkData::Standard(ref mut closure) => closure,
             InnerThunkData::Revertible { ref mut cached, .. } => Rc::make_mut(cached),
+            InnerThunkData::Moved => unreachable!(),
         }
     }
 
     /// Consume the data and return the cached closure.
-    pub fn into_closure(self) -> Closure {
-        match self.inner {
+    pub fn into_closure(mut self) -> Closure {
+        match std::mem::replace(&mut self.inner, InnerThunkData::Moved) {
             InnerThunkData::Standard(closure) => closure,
             InnerThunkData::Revertible { orig, cached, .. } => {
                 std::mem::drop(orig);
                 Rc::try_unwrap(cached).unwrap_or_else(|rc| (*rc).clone())
             }
+            InnerThunkData::Moved => unreachable!(),
         }
     }
 
@@ -102,6 +159,7 @@ impl ThunkData {
         match self.inner {
             InnerThunkData::Standard(ref mut closure) => *closure = new,
             InnerThunkData::Revertible { ref mut cached, .. } => *cached = Rc::new(new),
+            InnerThunkData::Moved => unreachable!(),
         }
 
         self.state = ThunkState::Evaluated;
@@ -117,24 +175,38 @@ impl ThunkData {
     /// one of the thunks doesn't affect the other.
     pub fn revert(thunk: &Rc<RefCell<ThunkData>>) -> Rc<RefCell<ThunkData>> {
         match thunk.borrow().inner {
-            InnerThunkData::Standard(_) => Rc::clone(thunk),
+            InnerThunkData::Standard(_) | InnerThunkData::Moved => Rc::clone(thunk),
             InnerThunkData::Revertible {
                 ref orig, ref deps, ..
-            } => Rc::new(RefCell::new(ThunkData {
+            } => gc::register(Rc::new(RefCell::new(ThunkData {
                 inner: InnerThunkData::Revertible {
                     orig: Rc::clone(orig),
                     cached: Rc::clone(orig),
                     deps: deps.clone(),
                 },
                 state: ThunkState::Suspended,
-            })),
+            }))),
         }
     }
 
+    /// Return the inner data of the thunk.
+    pub(super) fn inner(&self) -> &InnerThunkData {
+        &self.inner
+    }
+
+    /// Replace the content of the thunk with a dummy closure and return the previous content. Used
+    /// to break the reference cycles of unreachable thunks.
+    pub(super) fn clear(&mut self) -> InnerThunkData {
+        std::mem::replace(
+            &mut self.inner,
+            InnerThunkData::Standard(Closure::atomic_closure(Term::Null.into())),
+        )
+    }
+
     /// Return the potential field dependencies stored in a revertible thunk. See [`crate::transform::free_vars`]
     pub fn deps(&self) -> ThunkDeps {
         match self.inner {
-            InnerThunkData::Standard(_) => ThunkDeps::Empty,
+            InnerThunkData::Standard(_) | InnerThunkData::Moved => ThunkDeps::Empty,
             InnerThunkData::Revertible { ref deps, .. } => deps
                 .as_ref()
                 .map(|deps| ThunkDeps::Known(Rc::clone(deps)))
@@ -168,7 +240,7 @@ impl Thunk {
     /// Create a new standard thunk.
     pub fn new(closure: Closure, ident_kind: IdentKind) -> Self {
         Thunk {
-            data: Rc::new(RefCell::new(ThunkData::new(closure))),
+            data: gc::register(Rc::new(RefCell::new(ThunkData::new(closure)))),
             ident_kind,
         }
     }
@@ -176,11 +248,16 @@ impl Thunk {
     /// Create a new revertible thunk.
     pub fn new_rev(closure: Closure, ident_kind: IdentKind, deps: FieldDeps) -> Self {
         Thunk {
-            data: Rc::new(RefCell::new(ThunkData::new_rev(closure, deps))),
+            data: gc::register(Rc::new(RefCell::new(ThunkData::new_rev(closure, deps)))),
             ident_kind,
         }
     }
 
+    /// Return the shared data of the thunk.
+    pub(super) fn data(&self) -> &Rc<RefCell<ThunkData>> {
+        &self.data
+    }
+
     pub fn state(&self) -> ThunkState {
         self.data.borrow().state
     }
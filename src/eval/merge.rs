@@ -145,7 +145,7 @@ pub fn merge(
             }
         }
         (Term::Num(n1), Term::Num(n2)) => {
-            if (n1 - n2).abs() < f64::EPSILON {
+            if n1 == n2 {
                 Ok(Closure::atomic_closure(RichTerm::new(
                     Term::Num(n1),
                     pos_op.into_inherited(),
@@ -224,6 +224,16 @@ pub fn merge(
                 ))
             }
         }
+        // Variants with the same tag are merged by merging their payloads
+        (Term::EnumVariant(i1, arg1), Term::EnumVariant(i2, arg2)) if i1 == i2 => {
+            let mut env = Environment::new();
+            let arg = merge_closurize(&mut env, arg1, env1, arg2, env2);
+
+            Ok(Closure {
+                body: RichTerm::new(Term::EnumVariant(i1, arg), pos_op.into_inherited()),
+                env,
+            })
+        }
         (Term::Array(arr1, _attrs1), Term::Array(arr2, _attrs2))
             if arr1.is_empty() && arr2.is_empty() =>
         {
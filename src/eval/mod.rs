@@ -81,12 +81,17 @@
 //!
 //! # Garbage collection
 //!
-//! Currently the machine relies on Rust's reference counting to manage memory. Precisely, the
-//! environment stores `Rc<RefCell<Closure>>` objects, which are reference-counted pointers to a
-//! mutable memory cell. This means that we do not deep copy everything everywhere, but this is
-//! probably suboptimal for a functional language and is unable to collect cyclic data, which may
-//! appear inside recursive records in the future. An adapted garbage collector is probably
-//! something to consider at some point.
+//! The machine relies on Rust's reference counting to manage memory. Precisely, the environment
+//! stores thunks, which are reference-counted pointers to a mutable memory cell holding a closure.
+//! This means that we do not deep copy everything everywhere, but reference counting alone is
+//! unable to collect cyclic data, which is created by recursive records and recursive
+//! let-bindings. The [gc] module implements a cycle collector for thunks and environments, which
+//! is run by long-running processes such as the REPL after each evaluation.
+//!
+//! # Budget
+//!
+//! The main loop of the machine accounts for each step in the current [budget], if any, which lets
+//! tools bound the time spent evaluating a program that may not terminate.
 
 use crate::{
     cache::ImportResolver,
@@ -101,8 +106,10 @@ use crate::{
     transform::Closurizable,
 };
 
+pub mod budget;
 pub mod callstack;
 pub mod fixpoint;
+pub mod gc;
 pub mod lazy;
 pub mod merge;
 pub mod operation;
@@ -301,6 +308,8 @@ where
             mut env,
         } = clos;
 
+        budget::step(pos)?;
+
         if let Some(strict) = stack.pop_strictness_marker() {
             enriched_strict = strict;
         }
@@ -407,7 +416,7 @@ where
                     env,
                 }
             }
-            Term::Switch(exp, cases, default) => {
+            Term::Switch(exp, cases, default, variants) => {
                 if !enriched_strict {
                     stack.push_strictness(enriched_strict);
                 }
@@ -434,7 +443,10 @@ where
                 );
 
                 Closure {
-                    body: RichTerm::new(Term::Op1(UnaryOp::Switch(has_default), exp.clone()), pos),
+                    body: RichTerm::new(
+                        Term::Op1(UnaryOp::Switch(has_default, variants.clone()), exp.clone()),
+                        pos,
+                    ),
                     env,
                 }
             }
@@ -769,7 +781,7 @@ pub fn subst(rt: RichTerm, initial_env: &Environment, env: &Environment) -> Rich
 
             RichTerm::new(Term::App(t1, t2), pos)
         }
-        Term::Switch(t, cases, default) => {
+        Term::Switch(t, cases, default, variants) => {
             let default =
                 default.map(|d| subst(d, initial_env, env));
             let cases = cases
@@ -783,7 +795,12 @@ pub fn subst(rt: RichTerm, initial_env: &Environment, env: &Environment) -> Rich
                 .collect();
             let t = subst(t, initial_env, env);
 
-            RichTerm::new(Term::Switch(t, cases, default), pos)
+            RichTerm::new(Term::Switch(t, cases, default, variants), pos)
+        }
+        Term::EnumVariant(id, t) => {
+            let t = subst(t, initial_env, env);
+
+            RichTerm::new(Term::EnumVariant(id, t), pos)
         }
         Term::Op1(op, t) => {
             let t = subst(t, initial_env, env);
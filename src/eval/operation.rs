@@ -18,6 +18,7 @@ use crate::{
     identifier::Ident,
     label::ty_path,
     match_sharedterm, mk_app, mk_fun, mk_opn, mk_record,
+    number::Number,
     position::TermPos,
     serialize,
     serialize::ExportFormat,
@@ -186,7 +187,7 @@ fn process_unary_operation(
                 Term::Num(_) => "Num",
                 Term::Bool(_) => "Bool",
                 Term::Str(_) => "Str",
-                Term::Enum(_) => "Enum",
+                Term::Enum(_) | Term::EnumVariant(..) => "Enum",
                 Term::Fun(..) => "Fun",
                 Term::Array(..) => "Array",
                 Term::Record(..) | Term::RecRecord(..) => "Record",
@@ -278,7 +279,7 @@ fn process_unary_operation(
                 ))
         },
         UnaryOp::Embed(_id) => {
-            if let Term::Enum(_) = &*t {
+            if let Term::Enum(_) | Term::EnumVariant(..) = &*t {
                 Ok(Closure::atomic_closure(RichTerm {
                     term: t,
                     pos: pos_op_inh,
@@ -292,7 +293,7 @@ fn process_unary_operation(
                 ))
             }
         }
-        UnaryOp::Switch(has_default) => {
+        UnaryOp::Switch(has_default, variants) => {
             let (cases_closure, ..) = stack.pop_arg().expect("missing arg for switch");
             let default = if has_default {
                 Some(
@@ -305,7 +306,13 @@ fn process_unary_operation(
                 None
             };
 
-            if let Term::Enum(en) = &*t {
+            let (tag, payload) = match &*t {
+                Term::Enum(id) => (Some(id), None),
+                Term::EnumVariant(id, arg) => (Some(id), Some(arg)),
+                _ => (None, None),
+            };
+
+            if let Some(tag) = tag {
                 let Closure {
                     body:
                         RichTerm {
@@ -319,24 +326,42 @@ fn process_unary_operation(
                     _ => panic!("invalid argument for switch"),
                 };
 
-                cases
-                    .remove(en)
-                    .map(|body| Closure {
+                // A case matching a variant only applies to a variant with the same tag, and
+                // vice-versa: the bare tag `foo` doesn't match the case `foo x => ..`.
+                let case = cases
+                    .remove(tag)
+                    .filter(|_| variants.contains(tag) == payload.is_some());
+
+                match (case, payload) {
+                    (Some(body), Some(arg)) => {
+                        stack.push_arg(
+                            Closure {
+                                body: arg.clone(),
+                                env,
+                            },
+                            arg.pos,
+                        );
+                        Ok(Closure {
+                            body,
+                            env: cases_env,
+                        })
+                    }
+                    (Some(body), None) => Ok(Closure {
                         body,
                         env: cases_env,
-                    })
-                    .or(default)
-                    .ok_or_else(||
+                    }),
+                    (None, _) => default.ok_or_else(||
                         // ? We should have a dedicated error for unmatched pattern
                         EvalError::TypeError(
                             String::from("Enum"),
                             String::from("switch"),
                             arg_pos,
                             RichTerm {
-                                term: t,
+                                term: t.clone(),
                                 pos,
                             },
-                        ))
+                        )),
+                }
             } else if let Some(clos) = default {
                 Ok(clos)
             } else {
@@ -562,17 +587,8 @@ fn process_unary_operation(
                 .pop_arg()
                 .ok_or_else(|| EvalError::NotEnoughArgs(2, String::from("generate"), pos_op))?;
 
-            if let Term::Num(n) = *t {
-                let n_int = n as usize;
-                if n < 0.0 || n.fract() != 0.0 {
-                    Err(EvalError::Other(
-                        format!(
-                            "generate: expected the 1st agument to be a positive integer, got {}",
-                            n
-                        ),
-                        pos_op,
-                    ))
-                } else {
+            if let Term::Num(n) = &*t {
+                if let Some(n_int) = n.to_usize() {
                     let mut shared_env = Environment::new();
                     let f_as_var = f.body.closurize(&mut env, f.env);
 
@@ -581,7 +597,7 @@ fn process_unary_operation(
                     // currently, variables).
                     let ts = (0..n_int)
                         .map(|n| {
-                            mk_app!(f_as_var.clone(), Term::Num(n as f64))
+                            mk_app!(f_as_var.clone(), Term::Num(Number::from(n)))
                                 .closurize(&mut shared_env, env.clone())
                         })
                         .collect();
@@ -593,6 +609,14 @@ fn process_unary_operation(
                         ),
                         env: shared_env,
                     })
+                } else {
+                    Err(EvalError::Other(
+                        format!(
+                            "generate: expected the 1st agument to be a positive integer, got {}",
+                            n
+                        ),
+                        pos_op,
+                    ))
                 }
             } else {
                 Err(EvalError::TypeError(
@@ -719,6 +743,10 @@ fn process_unary_operation(
                         env: shared_env,
                     })
                 }
+                Term::EnumVariant(_, arg) => Ok(Closure {
+                    body: seq_terms(std::iter::once((None, arg)), pos_op),
+                    env,
+                }),
                 _ => {
                     if let Some((next, ..)) = stack.pop_arg() {
                         Ok(next)
@@ -778,7 +806,7 @@ fn process_unary_operation(
             if let Term::Array(ts, _) = &*t {
                 // A num does not have any free variable so we can drop the environment
                 Ok(Closure {
-                    body: RichTerm::new(Term::Num(ts.len() as f64), pos_op_inh),
+                    body: RichTerm::new(Term::Num(Number::from(ts.len())), pos_op_inh),
                     env: Environment::new(),
                 })
             } else {
@@ -874,9 +902,9 @@ fn process_unary_operation(
         UnaryOp::CharCode() => {
             if let Term::Str(s) = &*t {
                 if s.len() == 1 {
-                    let code = (s.chars().next().unwrap() as u32) as f64;
+                    let code = s.chars().next().unwrap() as u32;
                     Ok(Closure::atomic_closure(RichTerm::new(
-                        Term::Num(code),
+                        Term::Num(Number::from(code)),
                         pos_op_inh,
                     )))
                 } else {
@@ -895,12 +923,12 @@ fn process_unary_operation(
             }
         }
         UnaryOp::CharFromCode() => {
-            if let Term::Num(code) = *t {
-                if code.fract() != 0.0 {
+            if let Term::Num(code) = &*t {
+                if !code.is_integer() {
                     Err(EvalError::Other(format!("charFromCode: expected the agument to be an integer, got the floating-point value {}", code), pos_op))
-                } else if code < 0.0 || code > (u32::MAX as f64) {
+                } else if code.to_u32().is_none() {
                     Err(EvalError::Other(format!("charFromCode: code out of bounds. Expected a value between 0 and {}, got {}", u32::MAX, code), pos_op))
-                } else if let Some(car) = std::char::from_u32(code as u32) {
+                } else if let Some(car) = code.to_u32().and_then(std::char::from_u32) {
                     Ok(Closure::atomic_closure(RichTerm::new(
                         Term::Str(String::from(car)),
                         pos_op_inh,
@@ -953,7 +981,7 @@ fn process_unary_operation(
         UnaryOp::StrLength() => {
             if let Term::Str(s) = &*t {
                 Ok(Closure::atomic_closure(RichTerm::new(
-                    Term::Num(s.len() as f64),
+                    Term::Num(Number::from(s.len())),
                     pos_op_inh,
                 )))
             } else {
@@ -985,7 +1013,7 @@ fn process_unary_operation(
         }
         UnaryOp::NumFromStr() => {
             if let Term::Str(s) = &*t {
-                let n = s.parse::<f64>().map_err(|_| {
+                let n = s.parse::<Number>().map_err(|_| {
                     EvalError::Other(format!("numFrom: invalid num literal `{}`", s), pos)
                 })?;
                 Ok(Closure::atomic_closure(RichTerm::new(
@@ -1100,7 +1128,7 @@ fn process_unary_operation(
 
                     mk_record!(
                         ("match", Term::Str(String::from(first_match.as_str()))),
-                        ("index", Term::Num(first_match.start() as f64)),
+                        ("index", Term::Num(Number::from(first_match.start()))),
                         (
                             "groups",
                             Term::Array(groups, ArrayAttrs::new().closurized())
@@ -1110,7 +1138,7 @@ fn process_unary_operation(
                     //FIXME: what should we return when there's no match?
                     mk_record!(
                         ("match", Term::Str(String::new())),
-                        ("index", Term::Num(-1.)),
+                        ("index", Term::Num(Number::from(-1))),
                         ("groups", Term::Array(Vec::new(), Default::default()))
                     )
                 };
@@ -1199,6 +1227,20 @@ fn process_unary_operation(
                             body: seq_terms(terms, pos_op, cont),
                             env: shared_env,
                         })
+                    },
+                    Term::EnumVariant(id, arg) => {
+                        let mut shared_env = Environment::new();
+                        let arg = mk_term::op1(UnaryOp::Force(None), arg)
+                            .closurize(&mut shared_env, env);
+                        let cont = RichTerm::new(
+                            Term::EnumVariant(id, arg.clone()),
+                            pos.into_inherited(),
+                        );
+
+                        Ok(Closure {
+                            body: seq_terms(std::iter::once(arg), pos_op, cont),
+                            env: shared_env,
+                        })
                     }
                 } else Ok(Closure {
                     body: RichTerm { term : t, pos},
@@ -1271,8 +1313,8 @@ fn process_binary_operation(
             }
         }
         BinaryOp::Plus() => {
-            if let Term::Num(n1) = *t1 {
-                if let Term::Num(n2) = *t2 {
+            if let Term::Num(n1) = &*t1 {
+                if let Term::Num(n2) = &*t2 {
                     Ok(Closure::atomic_closure(RichTerm::new(
                         Term::Num(n1 + n2),
                         pos_op_inh,
@@ -1301,8 +1343,8 @@ fn process_binary_operation(
             }
         }
         BinaryOp::Sub() => {
-            if let Term::Num(n1) = *t1 {
-                if let Term::Num(n2) = *t2 {
+            if let Term::Num(n1) = &*t1 {
+                if let Term::Num(n2) = &*t2 {
                     Ok(Closure::atomic_closure(RichTerm::new(
                         Term::Num(n1 - n2),
                         pos_op_inh,
@@ -1331,8 +1373,8 @@ fn process_binary_operation(
             }
         }
         BinaryOp::Mult() => {
-            if let Term::Num(n1) = *t1 {
-                if let Term::Num(n2) = *t2 {
+            if let Term::Num(n1) = &*t1 {
+                if let Term::Num(n2) = &*t2 {
                     Ok(Closure::atomic_closure(RichTerm::new(
                         Term::Num(n1 * n2),
                         pos_op_inh,
@@ -1361,9 +1403,9 @@ fn process_binary_operation(
             }
         }
         BinaryOp::Div() => {
-            if let Term::Num(n1) = *t1 {
-                if let Term::Num(n2) = *t2 {
-                    if n2 == 0.0 {
+            if let Term::Num(n1) = &*t1 {
+                if let Term::Num(n2) = &*t2 {
+                    if n2.is_zero() {
                         Err(EvalError::Other(String::from("division by zero"), pos_op))
                     } else {
                         Ok(Closure::atomic_closure(RichTerm::new(
@@ -1395,12 +1437,16 @@ fn process_binary_operation(
             }
         }
         BinaryOp::Modulo() => {
-            if let Term::Num(n1) = *t1 {
-                if let Term::Num(n2) = *t2 {
-                    Ok(Closure::atomic_closure(RichTerm::new(
-                        Term::Num(n1 % n2),
-                        pos_op_inh,
-                    )))
+            if let Term::Num(n1) = &*t1 {
+                if let Term::Num(n2) = &*t2 {
+                    if n2.is_zero() {
+                        Err(EvalError::Other(String::from("division by zero"), pos_op))
+                    } else {
+                        Ok(Closure::atomic_closure(RichTerm::new(
+                            Term::Num(n1 % n2),
+                            pos_op_inh,
+                        )))
+                    }
                 } else {
                     Err(EvalError::TypeError(
                         String::from("Num"),
@@ -1425,12 +1471,19 @@ fn process_binary_operation(
             }
         }
         BinaryOp::Pow() => {
-            if let Term::Num(n1) = *t1 {
-                if let Term::Num(n2) = *t2 {
-                    Ok(Closure::atomic_closure(RichTerm::new(
-                        Term::Num(n1.powf(n2)),
-                        pos_op_inh,
-                    )))
+            if let Term::Num(n1) = &*t1 {
+                if let Term::Num(n2) = &*t2 {
+                    if let Some(result) = n1.pow(n2) {
+                        Ok(Closure::atomic_closure(RichTerm::new(
+                            Term::Num(result),
+                            pos_op_inh,
+                        )))
+                    } else {
+                        Err(EvalError::Other(
+                            format!("pow: {} ^ {} is not a finite number", n1, n2),
+                            pos_op,
+                        ))
+                    }
                 } else {
                     Err(EvalError::TypeError(
                         String::from("Num"),
@@ -1670,8 +1723,8 @@ fn process_binary_operation(
             }
         }
         BinaryOp::LessThan() => {
-            if let Term::Num(n1) = *t1 {
-                if let Term::Num(n2) = *t2 {
+            if let Term::Num(n1) = &*t1 {
+                if let Term::Num(n2) = &*t2 {
                     Ok(Closure::atomic_closure(RichTerm::new(
                         Term::Bool(n1 < n2),
                         pos_op_inh,
@@ -1700,8 +1753,8 @@ fn process_binary_operation(
             }
         }
         BinaryOp::LessOrEq() => {
-            if let Term::Num(n1) = *t1 {
-                if let Term::Num(n2) = *t2 {
+            if let Term::Num(n1) = &*t1 {
+                if let Term::Num(n2) = &*t2 {
                     Ok(Closure::atomic_closure(RichTerm::new(
                         Term::Bool(n1 <= n2),
                         pos_op_inh,
@@ -1730,8 +1783,8 @@ fn process_binary_operation(
             }
         }
         BinaryOp::GreaterThan() => {
-            if let Term::Num(n1) = *t1 {
-                if let Term::Num(n2) = *t2 {
+            if let Term::Num(n1) = &*t1 {
+                if let Term::Num(n2) = &*t2 {
                     Ok(Closure::atomic_closure(RichTerm::new(
                         Term::Bool(n1 > n2),
                         pos_op_inh,
@@ -1760,8 +1813,8 @@ fn process_binary_operation(
             }
         }
         BinaryOp::GreaterOrEq() => {
-            if let Term::Num(n1) = *t1 {
-                if let Term::Num(n2) = *t2 {
+            if let Term::Num(n1) = &*t1 {
+                if let Term::Num(n2) = &*t2 {
                     Ok(Closure::atomic_closure(RichTerm::new(
                         Term::Bool(n1 >= n2),
                         pos_op_inh,
@@ -2074,12 +2127,9 @@ fn process_binary_operation(
         },
         BinaryOp::ArrayElemAt() => match (&*t1, &*t2) {
             (Term::Array(ts, attrs), Term::Num(n)) => {
-                let n_int = *n as usize;
-                if n.fract() != 0.0 {
+                if !n.is_integer() {
                     Err(EvalError::Other(format!("elemAt: expected the 2nd agument to be an integer, got the floating-point value {}", n), pos_op))
-                } else if *n < 0.0 || n_int >= ts.len() {
-                    Err(EvalError::Other(format!("elemAt: index out of bounds. Expected a value between 0 and {}, got {}", ts.len(), n), pos_op))
-                } else {
+                } else if let Some(n_int) = n.to_usize().filter(|n_int| *n_int < ts.len()) {
                     let elem_with_ctr = apply_contracts(
                         ts[n_int].clone(),
                         attrs.pending_contracts.iter().cloned(),
@@ -2089,6 +2139,8 @@ fn process_binary_operation(
                         body: elem_with_ctr,
                         env: env1,
                     })
+                } else {
+                    Err(EvalError::Other(format!("elemAt: index out of bounds. Expected a value between 0 and {}, got {}", ts.len(), n), pos_op))
                 }
             }
             (Term::Array(..), _) => Err(EvalError::TypeError(
@@ -2470,22 +2522,28 @@ fn process_nary_operation(
 
             match (&*fst, &*snd, &*thd) {
                 (Term::Str(s), Term::Num(start), Term::Num(end)) => {
-                    let start_int = *start as usize;
-                    let end_int = *end as usize;
-
-                    if start.fract() != 0.0 {
-                        Err(EvalError::Other(format!("substring: expected the 2nd agument (start) to be an integer, got the floating-point value {}", start), pos_op))
-                    } else if !s.is_char_boundary(start_int) {
-                        Err(EvalError::Other(format!("substring: index out of bounds. Expected the 2nd argument (start) to be between 0 and {}, got {}", s.len(), start), pos_op))
-                    } else if end.fract() != 0.0 {
-                        Err(EvalError::Other(format!("substring: expected the 3nd argument (end) to be an integer, got the floating-point value {}", end), pos_op))
-                    } else if end <= start || !s.is_char_boundary(end_int) {
-                        Err(EvalError::Other(format!("substring: index out of bounds. Expected the 3rd argument (end) to be between {} and {}, got {}", start+1., s.len(), end), pos_op))
-                    } else {
-                        Ok(Closure::atomic_closure(RichTerm::new(
-                            Term::Str(s[start_int..end_int].to_owned()),
-                            pos_op_inh,
-                        )))
+                    let start_int = start.to_usize().filter(|i| s.is_char_boundary(*i));
+                    let end_int = end.to_usize().filter(|i| s.is_char_boundary(*i));
+
+                    match (start_int, end_int) {
+                        _ if !start.is_integer() => {
+                            Err(EvalError::Other(format!("substring: expected the 2nd agument (start) to be an integer, got the floating-point value {}", start), pos_op))
+                        }
+                        (None, _) => {
+                            Err(EvalError::Other(format!("substring: index out of bounds. Expected the 2nd argument (start) to be between 0 and {}, got {}", s.len(), start), pos_op))
+                        }
+                        _ if !end.is_integer() => {
+                            Err(EvalError::Other(format!("substring: expected the 3nd argument (end) to be an integer, got the floating-point value {}", end), pos_op))
+                        }
+                        (Some(start_int), Some(end_int)) if start_int < end_int => {
+                            Ok(Closure::atomic_closure(RichTerm::new(
+                                Term::Str(s[start_int..end_int].to_owned()),
+                                pos_op_inh,
+                            )))
+                        }
+                        _ => {
+                            Err(EvalError::Other(format!("substring: index out of bounds. Expected the 3rd argument (end) to be between {} and {}, got {}", start + &Number::from(1), s.len(), end), pos_op))
+                        }
                     }
                 }
                 (Term::Str(_), Term::Num(_), _) => Err(EvalError::TypeError(
@@ -2639,6 +2697,13 @@ fn eq(env: &mut Environment, c1: Closure, c2: Closure) -> EqResult {
         (Term::Lbl(l1), Term::Lbl(l2)) => EqResult::Bool(l1 == l2),
         (Term::SealingKey(s1), Term::SealingKey(s2)) => EqResult::Bool(s1 == s2),
         (Term::Enum(id1), Term::Enum(id2)) => EqResult::Bool(id1 == id2),
+        (Term::EnumVariant(id1, t1), Term::EnumVariant(id2, t2)) => {
+            if id1 == id2 {
+                gen_eqs(std::iter::once((t1, t2)), env, env1, env2)
+            } else {
+                EqResult::Bool(false)
+            }
+        }
         (Term::Record(m1, _), Term::Record(m2, _)) => {
             let (left, center, right) = merge::hashmap::split(m1, m2);
 
@@ -2718,11 +2783,11 @@ mod tests {
         let cont = OperationCont::Op1(UnaryOp::Ite(), TermPos::None);
         let mut stack = Stack::new();
         stack.push_arg(
-            Closure::atomic_closure(Term::Num(5.0).into()),
+            Closure::atomic_closure(Term::Num(Number::from(5)).into()),
             TermPos::None,
         );
         stack.push_arg(
-            Closure::atomic_closure(Term::Num(46.0).into()),
+            Closure::atomic_closure(Term::Num(Number::from(46)).into()),
             TermPos::None,
         );
 
@@ -2739,7 +2804,7 @@ mod tests {
         assert_eq!(
             clos,
             Closure {
-                body: Term::Num(46.0).into(),
+                body: Term::Num(Number::from(46)).into(),
                 env: Environment::new()
             }
         );
@@ -2751,14 +2816,14 @@ mod tests {
         let cont = OperationCont::Op2First(
             BinaryOp::Plus(),
             Closure {
-                body: Term::Num(6.0).into(),
+                body: Term::Num(Number::from(6)).into(),
                 env: Environment::new(),
             },
             TermPos::None,
         );
 
         let mut clos = Closure {
-            body: Term::Num(7.0).into(),
+            body: Term::Num(Number::from(7)).into(),
             env: Environment::new(),
         };
         let mut stack = Stack::new();
@@ -2770,7 +2835,7 @@ mod tests {
         assert_eq!(
             clos,
             Closure {
-                body: Term::Num(6.0).into(),
+                body: Term::Num(Number::from(6)).into(),
                 env: Environment::new()
             }
         );
@@ -2781,7 +2846,7 @@ mod tests {
                 OperationCont::Op2Second(
                     BinaryOp::Plus(),
                     Closure {
-                        body: Term::Num(7.0).into(),
+                        body: Term::Num(Number::from(7)).into(),
                         env: Environment::new(),
                     },
                     TermPos::None,
@@ -2799,14 +2864,14 @@ mod tests {
         let cont = OperationCont::Op2Second(
             BinaryOp::Plus(),
             Closure {
-                body: Term::Num(7.0).into(),
+                body: Term::Num(Number::from(7)).into(),
                 env: Environment::new(),
             },
             TermPos::None,
             TermPos::None,
         );
         let mut clos = Closure {
-            body: Term::Num(6.0).into(),
+            body: Term::Num(Number::from(6)).into(),
             env: Environment::new(),
         };
         let mut stack = Stack::new();
@@ -2818,7 +2883,7 @@ mod tests {
         assert_eq!(
             clos,
             Closure {
-                body: Term::Num(13.0).into(),
+                body: Term::Num(Number::from(13)).into(),
                 env: Environment::new()
             }
         );
@@ -2,6 +2,7 @@ use super::*;
 use crate::cache::resolvers::{DummyResolver, SimpleResolver};
 use crate::error::ImportError;
 use crate::label::Label;
+use crate::number::Number;
 use crate::parser::{grammar, lexer};
 use crate::term::make as mk_term;
 use crate::term::{BinaryOp, StrChunk, UnaryOp};
@@ -26,7 +27,7 @@ fn parse(s: &str) -> Option<RichTerm> {
 
 #[test]
 fn identity_over_values() {
-    let num = Term::Num(45.3);
+    let num = Term::Num(Number::from_f64(45.3).unwrap());
     assert_eq!(Ok(num.clone()), eval_no_import(num.into()));
 
     let boolean = Term::Bool(true);
@@ -56,36 +57,50 @@ fn lone_var_panics() {
 
 #[test]
 fn only_fun_are_applicable() {
-    eval_no_import(mk_app!(Term::Bool(true), Term::Num(45.))).unwrap_err();
+    eval_no_import(mk_app!(Term::Bool(true), Term::Num(Number::from(45)))).unwrap_err();
 }
 
 #[test]
 fn simple_app() {
-    let t = mk_app!(mk_term::id(), Term::Num(5.0));
-    assert_eq!(Ok(Term::Num(5.0)), eval_no_import(t));
+    let t = mk_app!(mk_term::id(), Term::Num(Number::from(5)));
+    assert_eq!(Ok(Term::Num(Number::from(5))), eval_no_import(t));
 }
 
 #[test]
 fn simple_let() {
-    let t = mk_term::let_in("x", Term::Num(5.0), mk_term::var("x"));
-    assert_eq!(Ok(Term::Num(5.0)), eval_no_import(t));
+    let t = mk_term::let_in("x", Term::Num(Number::from(5)), mk_term::var("x"));
+    assert_eq!(Ok(Term::Num(Number::from(5))), eval_no_import(t));
 }
 
 #[test]
 fn simple_ite() {
-    let t = mk_term::if_then_else(Term::Bool(true), Term::Num(5.0), Term::Bool(false));
-    assert_eq!(Ok(Term::Num(5.0)), eval_no_import(t));
+    let t = mk_term::if_then_else(
+        Term::Bool(true),
+        Term::Num(Number::from(5)),
+        Term::Bool(false),
+    );
+    assert_eq!(Ok(Term::Num(Number::from(5))), eval_no_import(t));
 }
 
 #[test]
 fn simple_plus() {
-    let t = mk_term::op2(BinaryOp::Plus(), Term::Num(5.0), Term::Num(7.5));
-    assert_eq!(Ok(Term::Num(12.5)), eval_no_import(t));
+    let t = mk_term::op2(
+        BinaryOp::Plus(),
+        Term::Num(Number::from(5)),
+        Term::Num(Number::from_f64(7.5).unwrap()),
+    );
+    assert_eq!(
+        Ok(Term::Num(Number::from_f64(12.5).unwrap())),
+        eval_no_import(t)
+    );
 }
 
 #[test]
 fn asking_for_various_types() {
-    let num = mk_term::op1(UnaryOp::Typeof(), Term::Num(45.3));
+    let num = mk_term::op1(
+        UnaryOp::Typeof(),
+        Term::Num(Number::from_f64(45.3).unwrap()),
+    );
     assert_eq!(Ok(Term::Enum("Num".into())), eval_no_import(num));
 
     let boolean = mk_term::op1(UnaryOp::Typeof(), Term::Bool(true));
@@ -126,18 +141,18 @@ fn enriched_terms_unwrapping() {
 fn merge_enriched_default() {
     let t = mk_term::op2(
         BinaryOp::Merge(),
-        Term::Num(1.0),
-        mk_default(Term::Num(2.0).into()),
+        Term::Num(Number::from(1)),
+        mk_default(Term::Num(Number::from(2)).into()),
     );
-    assert_eq!(Ok(Term::Num(1.0)), eval_no_import(t));
+    assert_eq!(Ok(Term::Num(Number::from(1))), eval_no_import(t));
 }
 
 #[test]
 fn merge_incompatible_defaults() {
     let t = mk_term::op2(
         BinaryOp::Merge(),
-        mk_default(Term::Num(1.0).into()),
-        mk_default(Term::Num(2.0).into()),
+        mk_default(Term::Num(Number::from(1)).into()),
+        mk_default(Term::Num(Number::from(2)).into()),
     );
 
     eval_no_import(t).unwrap_err();
@@ -199,7 +214,7 @@ fn imports() {
         )
         .map(Term::from)
         .unwrap(),
-        Term::Num(2.0)
+        Term::Num(Number::from(2))
     );
 
     // let x = import "lib" in x.f
@@ -291,28 +306,28 @@ fn initial_env() {
     initial_env.insert(
         Ident::from("g"),
         Thunk::new(
-            Closure::atomic_closure(Term::Num(1.0).into()),
+            Closure::atomic_closure(Term::Num(Number::from(1)).into()),
             IdentKind::Let,
         ),
     );
 
-    let t = mk_term::let_in("x", Term::Num(2.0), mk_term::var("x"));
+    let t = mk_term::let_in("x", Term::Num(Number::from(2)), mk_term::var("x"));
     assert_eq!(
         eval(t, &initial_env, &mut resolver).map(Term::from),
-        Ok(Term::Num(2.0))
+        Ok(Term::Num(Number::from(2)))
     );
 
-    let t = mk_term::let_in("x", Term::Num(2.0), mk_term::var("g"));
+    let t = mk_term::let_in("x", Term::Num(Number::from(2)), mk_term::var("g"));
     assert_eq!(
         eval(t, &initial_env, &mut resolver).map(Term::from),
-        Ok(Term::Num(1.0))
+        Ok(Term::Num(Number::from(1)))
     );
 
     // Shadowing of the initial environment
-    let t = mk_term::let_in("g", Term::Num(2.0), mk_term::var("g"));
+    let t = mk_term::let_in("g", Term::Num(Number::from(2)), mk_term::var("g"));
     assert_eq!(
         eval(t, &initial_env, &mut resolver).map(Term::from),
-        Ok(Term::Num(2.0))
+        Ok(Term::Num(Number::from(2)))
     );
 }
 
@@ -331,7 +346,7 @@ fn mk_env(bindings: Vec<(&str, RichTerm)>) -> Environment {
 #[test]
 fn substitution() {
     let initial_env = mk_env(vec![
-        ("glob1", Term::Num(1.0).into()),
+        ("glob1", Term::Num(Number::from(1)).into()),
         ("glob2", parse("\"Glob2\"").unwrap()),
         ("glob3", Term::Bool(false).into()),
     ]);
@@ -353,3 +368,73 @@ fn substitution() {
         parse("switch {`x => [1, 1], `y => (if false then 1 else \"Glob2\"), `z => {id = true, other = false}} true").unwrap()
     );
 }
+
+#[test]
+fn recursive_records_are_collected() {
+    gc::enable();
+    let t = parse("{f = fun n => if n == 0 then 0 else f (n - 1), res = f 3}.res").unwrap();
+    let t = crate::transform::transform(t, None, None).unwrap();
+
+    assert_eq!(eval_no_import(t), Ok(Term::Num(Number::from(0))));
+    assert!(gc::collect_cycles() > 0);
+    assert_eq!(gc::collect_cycles(), 0);
+}
+
+#[test]
+fn long_thunk_chains_are_dropped() {
+    // Run on a small stack, such that a recursive drop of the chain would overflow it.
+    std::thread::Builder::new()
+        .stack_size(256 * 1024)
+        .spawn(|| {
+            let mut first = Thunk::new(Closure::atomic_closure(Term::Null.into()), IdentKind::Let);
+            let frame = first.mk_update_frame().unwrap();
+
+            let mut chain = first;
+            for _ in 0..1_000_000 {
+                let mut env = Environment::new();
+                env.insert(Ident::from("prev"), chain);
+                chain = Thunk::new(
+                    Closure {
+                        body: Term::Var(Ident::from("prev")).into(),
+                        env,
+                    },
+                    IdentKind::Let,
+                );
+            }
+
+            drop(chain);
+            assert!(!frame.update(Closure::atomic_closure(Term::Null.into())));
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn budget_interrupts_evaluation() {
+    use assert_matches::assert_matches;
+    use budget::{Budget, Exhausted};
+    use std::sync::{atomic::AtomicBool, Arc};
+
+    let diverging = || {
+        let t = parse("{f = fun n => f (n + 1), res = f 0}.res").unwrap();
+        crate::transform::transform(t, None, None).unwrap()
+    };
+
+    let res = budget::with_budget(Budget::new().with_steps(10_000), || {
+        eval_no_import(diverging())
+    });
+    assert_matches!(res, Err(EvalError::Interrupted(Exhausted::Steps, _)));
+
+    let res = budget::with_budget(
+        Budget::new().with_cancellation(Arc::new(AtomicBool::new(true))),
+        || eval_no_import(diverging()),
+    );
+    assert_matches!(res, Err(EvalError::Interrupted(Exhausted::Cancelled, _)));
+
+    let res = budget::with_budget(Budget::new().with_steps(10_000), || {
+        eval_no_import(parse("1 + 1").unwrap())
+    });
+    assert_eq!(res, Ok(Term::Num(Number::from(2))));
+    gc::collect_cycles();
+}
@@ -113,7 +113,7 @@ pub mod ty_path {
                 // In this case, the position of the sub-type "Num -> Num" starts at 1 instead of
                 // 0.
                 let paren_offset = match dom.0 {
-                    AbsType::Arrow(_, _) => 1,
+                    AbsType::Arrow(_, _) | AbsType::Rec(..) => 1,
                     _ => 0,
                 };
 
@@ -152,7 +152,9 @@ pub mod ty_path {
                 loop {
                     match row {
                         AbsType::RowExtend(id, Some(ty), _) if id == ident => {
-                            let (sub_start, sub_end) = span(path_it, ty);
+                            // The type of an optional field is printed first, followed by
+                            // `| optional`, so the offsets are the same as for the underlying type.
+                            let (sub_start, sub_end) = span(path_it, ty.split_optional().0);
                             let full_offset = start_offset + format!("{}", id).len() + id_offset;
                             break (full_offset + sub_start, full_offset + sub_end);
                         }
@@ -184,6 +186,13 @@ pub mod ty_path {
                     start_offset + paren_offset + sub_end,
                 )
             }
+            // The path doesn't record which alternative of a union or an intersection a contract
+            // was checking, nor how many times a recursive type has been unfolded, so the best we
+            // can do is to point to the whole type.
+            (ty @ (AbsType::Union(..) | AbsType::Intersection(..) | AbsType::Rec(..)), Some(_)) => {
+                let repr = format!("{}", Types(ty.clone()));
+                (forall_offset, forall_offset + repr.len())
+            }
             (ty, next) => panic!(
                 "label::span: unexpected type {} with path element {:?}",
                 Types(ty.clone()),
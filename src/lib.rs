@@ -6,6 +6,7 @@ pub mod error;
 pub mod eval;
 pub mod identifier;
 pub mod label;
+pub mod number;
 pub mod parser;
 pub mod position;
 pub mod pretty;
@@ -0,0 +1,448 @@
+//! Arbitrary-precision numbers.
+//!
+//! Nickel numbers are exact rationals: integers of any size and decimal literals such as `0.1` are
+//! represented without loss of precision, and the arithmetic operations on them are exact.
+//! Floating-point numbers are only used where an exact result can't be computed in general or
+//! would be too large, that is for powers with a fractional or a very large exponent, and to print
+//! numbers which don't have a finite decimal expansion, such as `1/3`.
+//!
+//! Integers and decimals are distinguished by their value rather than by their representation:
+//! `1.0` and `1` are the same number, which is an integer, and is exported as `1`. Nickel has a
+//! single number type, and `1.0 == 1` must hold, so keeping track of how a number was written
+//! would only change the way it is exported, depending on the operations which computed it.
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Pow, Signed, ToPrimitive, Zero};
+use serde::{
+    de::{self, Deserialize, Deserializer, Visitor},
+    ser::{Serialize, Serializer},
+};
+
+use std::{
+    fmt,
+    ops::{Add, Div, Mul, Neg, Rem, Sub},
+    str::FromStr,
+};
+
+/// The maximum size, in bits, of the numerator or the denominator of the result of an exact
+/// power. Above this size, powers are computed with floating-point numbers instead, such that
+/// `10 ^ 2000000000` doesn't exhaust the memory.
+const MAX_EXACT_POW_BITS: u64 = 1 << 16;
+
+/// The maximum absolute value of the exponent of a decimal literal, such as `1e300`, which is
+/// parsed exactly. Literals with a larger exponent are parsed as floating-point numbers.
+const MAX_EXACT_DECIMAL_EXP: i64 = 4096;
+
+/// The key under which `serde_json` passes numbers to deserializers with the `arbitrary_precision`
+/// feature.
+pub(crate) const JSON_NUMBER_TOKEN: &str = "$serde_json::private::Number";
+
+/// An arbitrary-precision rational number.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Number(BigRational);
+
+/// The error returned when parsing an invalid number literal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseNumberError;
+
+impl fmt::Display for ParseNumberError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid number literal")
+    }
+}
+
+impl Number {
+    pub fn zero() -> Self {
+        Number(BigRational::zero())
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.0.is_integer()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.0.is_negative()
+    }
+
+    /// Convert a finite float to the number with the shortest decimal representation which rounds
+    /// to it, such that `0.1_f64` is converted to exactly `1/10`. Return `None` for infinite
+    /// values and `NaN`.
+    pub fn from_f64(f: f64) -> Option<Self> {
+        if f.is_finite() {
+            // The `Display` implementation of `f64` never uses the scientific notation, and
+            // prints the shortest representation which roundtrips.
+            f.to_string().parse().ok()
+        } else {
+            None
+        }
+    }
+
+    /// Return the closest float to this number.
+    pub fn to_f64(&self) -> f64 {
+        self.0.to_f64().unwrap_or(f64::NAN)
+    }
+
+    /// Return the value of this number as an integer, or `None` if it has a fractional part.
+    pub fn to_integer(&self) -> Option<BigInt> {
+        if self.is_integer() {
+            Some(self.0.to_integer())
+        } else {
+            None
+        }
+    }
+
+    /// Return the value of this number as a `usize`, or `None` if it isn't an integer or is out
+    /// of range.
+    pub fn to_usize(&self) -> Option<usize> {
+        self.to_integer().and_then(|n| n.to_usize())
+    }
+
+    /// Return the value of this number as a `u32`, or `None` if it isn't an integer or is out of
+    /// range.
+    pub fn to_u32(&self) -> Option<u32> {
+        self.to_integer().and_then(|n| n.to_u32())
+    }
+
+    /// Round this number to the nearest integer, rounding half-way cases away from zero.
+    pub fn round(&self) -> BigInt {
+        self.0.round().to_integer()
+    }
+
+    /// Raise this number to the power `exp`. The result is exact when `exp` is an integer which
+    /// fits in an `i32` and the result isn't too large (see [`MAX_EXACT_POW_BITS`]), and computed
+    /// with floating-point numbers otherwise. Return `None` if the result isn't a finite number,
+    /// as for `0 ^ -1` or `(-1) ^ 0.5`.
+    pub fn pow(&self, exp: &Number) -> Option<Number> {
+        let bits = self.0.numer().bits().max(self.0.denom().bits());
+
+        match exp.to_integer().and_then(|n| n.to_i32()) {
+            Some(_) if self.is_zero() && exp.is_negative() => None,
+            Some(exp)
+                if bits.saturating_mul(u64::from(exp.unsigned_abs())) <= MAX_EXACT_POW_BITS =>
+            {
+                Some(Number(Pow::pow(&self.0, exp)))
+            }
+            _ => Number::from_f64(self.to_f64().powf(exp.to_f64())),
+        }
+    }
+
+    /// Return the decimal expansion of this number if it is finite, that is if the denominator
+    /// is only divisible by 2 and 5.
+    fn to_exact_decimal(&self) -> Option<String> {
+        let two = BigInt::from(2);
+        let five = BigInt::from(5);
+        let mut denom = self.0.denom().clone();
+        let mut twos = 0usize;
+        let mut fives = 0usize;
+
+        while (&denom % &two).is_zero() {
+            denom /= &two;
+            twos += 1;
+        }
+        while (&denom % &five).is_zero() {
+            denom /= &five;
+            fives += 1;
+        }
+
+        if !denom.is_one() {
+            return None;
+        }
+
+        let scale = twos.max(fives);
+        let scaled = (&self.0 * BigRational::from_integer(Pow::pow(BigInt::from(10), scale)))
+            .to_integer()
+            .abs()
+            .to_string();
+        let sign = if self.is_negative() { "-" } else { "" };
+
+        if scale == 0 {
+            Some(format!("{}{}", sign, scaled))
+        } else {
+            let digits = format!("{:0>width$}", scaled, width = scale + 1);
+            let (int_part, frac_part) = digits.split_at(digits.len() - scale);
+            Some(format!("{}{}.{}", sign, int_part, frac_part))
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    /// Print the number exactly if it has a finite decimal expansion, and the closest float
+    /// otherwise.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.to_exact_decimal() {
+            Some(s) => write!(f, "{}", s),
+            None => write!(f, "{}", self.to_f64()),
+        }
+    }
+}
+
+impl FromStr for Number {
+    type Err = ParseNumberError;
+
+    /// Parse a decimal number with an optional sign, fractional part and exponent, such as `42`,
+    /// `-0.5` or `1.5e-3`. Numbers with a very large exponent (see [`MAX_EXACT_DECIMAL_EXP`]) are
+    /// parsed as the closest float, and are invalid if this float is infinite.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let literal = s;
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (mantissa, exp) = match s.find(['e', 'E']) {
+            Some(idx) => (
+                &s[..idx],
+                s[idx + 1..].parse::<i32>().map_err(|_| ParseNumberError)?,
+            ),
+            None => (s, 0),
+        };
+        let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+
+        if (int_part.is_empty() && frac_part.is_empty())
+            || !int_part
+                .chars()
+                .chain(frac_part.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err(ParseNumberError);
+        }
+
+        let digits: BigInt = format!("0{}{}", int_part, frac_part)
+            .parse()
+            .map_err(|_| ParseNumberError)?;
+        let exp = i64::from(exp) - frac_part.len() as i64;
+
+        if exp.abs() > MAX_EXACT_DECIMAL_EXP {
+            return literal
+                .parse::<f64>()
+                .ok()
+                .and_then(Number::from_f64)
+                .ok_or(ParseNumberError);
+        }
+
+        let exp = i32::try_from(exp).map_err(|_| ParseNumberError)?;
+        let ten = BigRational::from_integer(BigInt::from(10));
+        let value = BigRational::from_integer(digits) * Pow::pow(&ten, exp);
+
+        Ok(Number(if negative { -value } else { value }))
+    }
+}
+
+macro_rules! impl_from_int {
+    ($($int:ty),*) => {
+        $(
+            impl From<$int> for Number {
+                fn from(n: $int) -> Self {
+                    Number(BigRational::from_integer(BigInt::from(n)))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_int!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+impl From<BigInt> for Number {
+    fn from(n: BigInt) -> Self {
+        Number(BigRational::from_integer(n))
+    }
+}
+
+macro_rules! impl_binop {
+    ($($trait:ident, $method:ident);*) => {
+        $(
+            impl $trait<&Number> for &Number {
+                type Output = Number;
+
+                fn $method(self, other: &Number) -> Number {
+                    Number($trait::$method(&self.0, &other.0))
+                }
+            }
+
+            impl $trait for Number {
+                type Output = Number;
+
+                fn $method(self, other: Number) -> Number {
+                    Number($trait::$method(self.0, other.0))
+                }
+            }
+        )*
+    };
+}
+
+// Division and remainder panic on a zero divisor, which must be checked beforehand.
+impl_binop!(Add, add; Sub, sub; Mul, mul; Div, div; Rem, rem);
+
+impl Neg for Number {
+    type Output = Number;
+
+    fn neg(self) -> Number {
+        Number(-self.0)
+    }
+}
+
+impl Serialize for Number {
+    /// Serialize integers in the range of 128 bits integers as integers, without loss of
+    /// precision. JSON and YAML support such integers, but TOML only supports 64 bits integers
+    /// (see [crate::serialize::validate]). Other numbers are serialized as floats, as the supported
+    /// formats don't have a standard representation for arbitrary-precision numbers. Decimals
+    /// with up to 15 significant digits, such as `0.1`, are still exported exactly.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if let Some(n) = self.to_integer() {
+            if let Some(n) = n.to_i64() {
+                return serializer.serialize_i64(n);
+            } else if let Some(n) = n.to_u64() {
+                return serializer.serialize_u64(n);
+            } else if let Some(n) = n.to_i128() {
+                return serializer.serialize_i128(n);
+            } else if let Some(n) = n.to_u128() {
+                return serializer.serialize_u128(n);
+            }
+        }
+
+        serializer.serialize_f64(self.to_f64())
+    }
+}
+
+/// The visitor of the deserialization of numbers, also used to deserialize terms (see
+/// [crate::serialize]).
+pub(crate) struct NumberVisitor;
+
+impl NumberVisitor {
+    /// Parse the literal of a number passed by `serde_json` (see [JSON_NUMBER_TOKEN]).
+    pub(crate) fn visit_json_literal<E: de::Error>(self, literal: &str) -> Result<Number, E> {
+        literal
+            .parse()
+            .map_err(|_| E::invalid_value(de::Unexpected::Str(literal), &self))
+    }
+}
+
+impl<'de> Visitor<'de> for NumberVisitor {
+    type Value = Number;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a number")
+    }
+
+    fn visit_i64<E: de::Error>(self, n: i64) -> Result<Number, E> {
+        Ok(Number::from(n))
+    }
+
+    fn visit_u64<E: de::Error>(self, n: u64) -> Result<Number, E> {
+        Ok(Number::from(n))
+    }
+
+    fn visit_i128<E: de::Error>(self, n: i128) -> Result<Number, E> {
+        Ok(Number::from(n))
+    }
+
+    fn visit_u128<E: de::Error>(self, n: u128) -> Result<Number, E> {
+        Ok(Number::from(n))
+    }
+
+    fn visit_f64<E: de::Error>(self, f: f64) -> Result<Number, E> {
+        Number::from_f64(f).ok_or_else(|| E::custom(format!("{} is not a finite number", f)))
+    }
+
+    /// With the `arbitrary_precision` feature, `serde_json` passes numbers as a map with a single
+    /// entry, whose value is the number as written in the source. Parsing it is exact, whatever
+    /// the size of the number.
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Number, A::Error> {
+        match map.next_key::<String>()? {
+            Some(key) if key == JSON_NUMBER_TOKEN => {
+                let literal: String = map.next_value()?;
+                self.visit_json_literal(&literal)
+            }
+            _ => Err(de::Error::invalid_type(de::Unexpected::Map, &self)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Number {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(NumberVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(s: &str) -> Number {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_and_print() {
+        assert_eq!(num("42"), Number::from(42));
+        assert_eq!(num("0.1") + num("0.2"), num("0.3"));
+        assert_eq!(num("1.5e3"), Number::from(1500));
+        assert_eq!(num("-2.5E-1").to_string(), "-0.25");
+        assert_eq!(num(".5").to_string(), "0.5");
+        assert_eq!(
+            num("18446744073709551615").to_string(),
+            "18446744073709551615"
+        );
+        assert_eq!(
+            (Number::from(1) / Number::from(3)).to_string(),
+            "0.3333333333333333"
+        );
+        assert!("1.2.3".parse::<Number>().is_err());
+        assert!("inf".parse::<Number>().is_err());
+        assert!("".parse::<Number>().is_err());
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(Number::from_f64(0.1), Some(num("0.1")));
+        assert_eq!(Number::from_f64(1e21), Some(num("1e21")));
+        assert_eq!(Number::from_f64(f64::NAN), None);
+        assert_eq!(num("0.25").to_f64(), 0.25);
+    }
+
+    #[test]
+    fn pow() {
+        assert_eq!(
+            Number::from(2).pow(&Number::from(64)),
+            Some(num("18446744073709551616"))
+        );
+        assert_eq!(Number::from(2).pow(&Number::from(-2)), Some(num("0.25")));
+        assert_eq!(Number::from(4).pow(&num("0.5")), Some(Number::from(2)));
+        assert_eq!(Number::zero().pow(&Number::from(-1)), None);
+        assert_eq!(Number::from(-1).pow(&num("0.5")), None);
+    }
+
+    #[test]
+    fn large_exponents() {
+        assert_eq!(Number::from(10).pow(&Number::from(2_000_000_000)), None);
+        assert_eq!(
+            Number::from(10).pow(&Number::from(-2_000_000_000)),
+            Some(Number::zero())
+        );
+        assert_eq!(
+            Number::from(-1).pow(&Number::from(2_000_000_001)),
+            Some(Number::from(-1))
+        );
+        assert_eq!(
+            Number::from(2).pow(&Number::from(1000)),
+            Some(num(&format!("{}", BigInt::from(2).pow(1000u32))))
+        );
+
+        assert!("1e999999999".parse::<Number>().is_err());
+        assert_eq!(num("1e-999999999"), Number::zero());
+        assert_eq!(
+            num("1e300"),
+            Number::from(10).pow(&Number::from(300)).unwrap()
+        );
+        assert!("-1.5e5000".parse::<Number>().is_err());
+    }
+}
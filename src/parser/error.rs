@@ -1,4 +1,4 @@
-use crate::{identifier::Ident, position::RawSpan};
+use crate::{identifier::Ident, position::RawSpan, types::Types};
 
 #[derive(Clone, PartialEq, Debug)]
 pub enum LexicalError {
@@ -36,4 +36,10 @@ pub enum ParseError {
     /// A recursive let pattern was encountered. They are not currently supported because we
     /// decided it was too involved to implement them.
     RecursiveLetPattern(RawSpan),
+    /// A recursive type `rec id. ty` where `id` occurs in `ty` outside of a lazy type constructor
+    /// (an array, a record, a function or an enum payload), or in the tail of a row.
+    IllformedRecType(Ident, RawSpan),
+    /// A union type `s \/ t` whose alternatives can't be told apart by the union contract, because
+    /// the shapes of `s` and `t` both match some values.
+    OverlappingUnion(Types, RawSpan),
 }
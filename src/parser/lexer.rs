@@ -29,6 +29,7 @@
 //! `0`, this is the end of the current interpolated expressions, and we leave the normal mode and
 //! go back to string mode. In our example, this is the second `}`: at this point, the lexer knows
 //! that the coming characters must be lexed as string tokens, and not as normal tokens.
+use crate::number::Number;
 use crate::parser::error::{LexicalError, ParseError};
 use logos::Logos;
 use std::ops::Range;
@@ -43,7 +44,7 @@ pub enum NormalToken<'input> {
     #[regex("_?[a-zA-Z][_a-zA-Z0-9-']*")]
     Identifier(&'input str),
     #[regex("[0-9]*\\.?[0-9]+", |lex| lex.slice().parse())]
-    NumLiteral(f64),
+    NumLiteral(Number),
 
     #[token("Dyn")]
     Dyn,
@@ -138,6 +139,10 @@ pub enum NormalToken<'input> {
     SimpleArrow,
     #[token("=>")]
     DoubleArrow,
+    #[token("\\/")]
+    Union,
+    #[token("/\\")]
+    Intersection,
     #[token("`")]
     Backtick,
     #[token("_")]
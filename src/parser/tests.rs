@@ -1,6 +1,8 @@
 use super::lexer::{Lexer, MultiStringToken, NormalToken, StringToken, Token};
+use crate::destruct::Destruct;
 use crate::error::ParseError;
 use crate::identifier::Ident;
+use crate::number::Number;
 use crate::parser::error::ParseError as InternalParseError;
 use crate::term::make as mk_term;
 use crate::term::Term::*;
@@ -36,11 +38,14 @@ fn mk_single_chunk(s: &str) -> RichTerm {
 
 #[test]
 fn numbers() {
-    assert_eq!(parse_without_pos("22"), Num(22.0).into());
-    assert_eq!(parse_without_pos("22.0"), Num(22.0).into());
-    assert_eq!(parse_without_pos("22.22"), Num(22.22).into());
-    assert_eq!(parse_without_pos("(22)"), Num(22.0).into());
-    assert_eq!(parse_without_pos("((22))"), Num(22.0).into());
+    assert_eq!(parse_without_pos("22"), Num(Number::from(22)).into());
+    assert_eq!(parse_without_pos("22.0"), Num(Number::from(22)).into());
+    assert_eq!(
+        parse_without_pos("22.22"),
+        Num(Number::from_f64(22.22).unwrap()).into()
+    );
+    assert_eq!(parse_without_pos("(22)"), Num(Number::from(22)).into());
+    assert_eq!(parse_without_pos("((22))"), Num(Number::from(22)).into());
 }
 
 #[test]
@@ -78,14 +83,19 @@ fn strings() {
 fn plus() {
     assert_eq!(
         parse_without_pos("3 + 4"),
-        Op2(BinaryOp::Plus(), Num(3.0).into(), Num(4.).into()).into()
+        Op2(
+            BinaryOp::Plus(),
+            Num(Number::from(3)).into(),
+            Num(Number::from(4)).into()
+        )
+        .into()
     );
     assert_eq!(
         parse_without_pos("(true + false) + 4"),
         Op2(
             BinaryOp::Plus(),
             Op2(BinaryOp::Plus(), Bool(true).into(), Bool(false).into()).into(),
-            Num(4.).into(),
+            Num(Number::from(4)).into(),
         )
         .into()
     );
@@ -101,7 +111,11 @@ fn booleans() {
 fn ite() {
     assert_eq!(
         parse_without_pos("if true then 3 else 4"),
-        mk_app!(mk_term::op1(UnaryOp::Ite(), Bool(true)), Num(3.0), Num(4.0))
+        mk_app!(
+            mk_term::op1(UnaryOp::Ite(), Bool(true)),
+            Num(Number::from(3)),
+            Num(Number::from(4))
+        )
     );
 }
 
@@ -109,12 +123,16 @@ fn ite() {
 fn applications() {
     assert_eq!(
         parse_without_pos("1 true 2"),
-        mk_app!(Num(1.0), Bool(true), Num(2.0))
+        mk_app!(Num(Number::from(1)), Bool(true), Num(Number::from(2)))
     );
 
     assert_eq!(
         parse_without_pos("1 (2 3) 4"),
-        mk_app!(Num(1.0), mk_app!(Num(2.0), Num(3.0)), Num(4.0))
+        mk_app!(
+            Num(Number::from(1)),
+            mk_app!(Num(Number::from(2)), Num(Number::from(3))),
+            Num(Number::from(4))
+        )
     );
 }
 
@@ -162,8 +180,31 @@ fn enum_terms() {
 
     assert_eq!(
         parse_without_pos("switch { `foo => true, `bar => false, _ => 456, } 123"),
-        mk_switch!(Num(123.), ("foo", Bool(true)), ("bar", Bool(false)) ; Num(456.))
-    )
+        mk_switch!(Num(Number::from(123)), ("foo", Bool(true)), ("bar", Bool(false)) ; Num(Number::from(456)))
+    );
+
+    assert_eq!(
+        parse_without_pos("`foo 1"),
+        EnumVariant(Ident::from("foo"), Num(Number::from(1)).into()).into(),
+    );
+    assert_eq!(
+        parse_without_pos("switch { `foo x => x, `bar => null } y"),
+        Switch(
+            mk_term::var("y"),
+            vec![
+                (
+                    Ident::from("foo"),
+                    FunPattern(Some(Ident::from("x")), Destruct::Empty, mk_term::var("x")).into(),
+                ),
+                (Ident::from("bar"), Null.into()),
+            ]
+            .into_iter()
+            .collect(),
+            None,
+            std::iter::once(Ident::from("foo")).collect(),
+        )
+        .into(),
+    );
 }
 
 #[test]
@@ -172,9 +213,9 @@ fn record_terms() {
         parse_without_pos("{ a = 1, b = 2, c = 3}"),
         RecRecord(
             vec![
-                (Ident::from("a"), Num(1.).into()),
-                (Ident::from("b"), Num(2.).into()),
-                (Ident::from("c"), Num(3.).into()),
+                (Ident::from("a"), Num(Number::from(1)).into()),
+                (Ident::from("b"), Num(Number::from(2)).into()),
+                (Ident::from("c"), Num(Number::from(3)).into()),
             ]
             .into_iter()
             .collect(),
@@ -189,14 +230,18 @@ fn record_terms() {
         parse_without_pos("{ a = 1, \"%{123}\" = (if 4 then 5 else 6), d = 42}"),
         RecRecord(
             vec![
-                (Ident::from("a"), Num(1.).into()),
-                (Ident::from("d"), Num(42.).into()),
+                (Ident::from("a"), Num(Number::from(1)).into()),
+                (Ident::from("d"), Num(Number::from(42)).into()),
             ]
             .into_iter()
             .collect(),
             vec![(
-                StrChunks(vec![StrChunk::expr(RichTerm::from(Num(123.)))]).into(),
-                mk_app!(mk_term::op1(UnaryOp::Ite(), Num(4.)), Num(5.), Num(6.))
+                StrChunks(vec![StrChunk::expr(RichTerm::from(Num(Number::from(123))))]).into(),
+                mk_app!(
+                    mk_term::op1(UnaryOp::Ite(), Num(Number::from(4))),
+                    Num(Number::from(5)),
+                    Num(Number::from(6))
+                )
             )],
             Default::default(),
             None,
@@ -208,8 +253,8 @@ fn record_terms() {
         parse_without_pos("{ a = 1, \"\\\"%}%\" = 2}"),
         RecRecord(
             vec![
-                (Ident::from("a"), Num(1.).into()),
-                (Ident::from("\"%}%"), Num(2.).into()),
+                (Ident::from("a"), Num(Number::from(1)).into()),
+                (Ident::from("\"%}%"), Num(Number::from(2)).into()),
             ]
             .into_iter()
             .collect(),
@@ -254,7 +299,7 @@ fn string_lexing() {
             Token::Normal(NormalToken::DoubleQuote),
             Token::Str(StringToken::Literal("1 + ")),
             Token::Str(StringToken::Interpolation),
-            Token::Normal(NormalToken::NumLiteral(1.0)),
+            Token::Normal(NormalToken::NumLiteral(Number::from(1))),
             Token::Normal(NormalToken::RBrace),
             Token::Str(StringToken::Literal(" + 2")),
             Token::Normal(NormalToken::DoubleQuote),
@@ -269,7 +314,7 @@ fn string_lexing() {
             Token::Str(StringToken::Interpolation),
             Token::Normal(NormalToken::DoubleQuote),
             Token::Str(StringToken::Interpolation),
-            Token::Normal(NormalToken::NumLiteral(1.0)),
+            Token::Normal(NormalToken::NumLiteral(Number::from(1))),
             Token::Normal(NormalToken::RBrace),
             Token::Normal(NormalToken::DoubleQuote),
             Token::Normal(NormalToken::RBrace),
@@ -158,9 +158,9 @@ pub struct InvalidRecordTypeError(pub TermPos);
 
 impl UniRecord {
     /// Try to convert a `UniRecord` to a type. The strict part means that the `UniRecord` must be
-    /// a plain record type, uniquely containing fields of the form `fields: Type`. Currently, it
-    /// doesn't support the field path syntax: `{foo.bar.baz : Type}.into_type_strict()` returns an
-    /// `Err`.
+    /// a plain record type, uniquely containing fields of the form `fields: Type` or `field: Type |
+    /// optional`. Currently, it doesn't support the field path syntax: `{foo.bar.baz :
+    /// Type}.into_type_strict()` returns an `Err`.
     pub fn into_type_strict(self) -> Result<Types, InvalidRecordTypeError> {
         // An open record (with an ellipsis `..` at the end) can't be translated to a record type.
         // `pos_ellipsis` should be set iff `attrs.open` is true.
@@ -210,14 +210,22 @@ impl UniRecord {
                                         doc: None,
                                         types: Some(ctrt),
                                         contracts,
-                                        opt: false,
+                                        opt,
                                         priority: MergePriority::Normal,
                                         value: None,
-                                    }) if contracts.is_empty() => Ok(Types(AbsType::RowExtend(
-                                        id,
-                                        Some(Box::new(ctrt.types)),
-                                        Box::new(acc),
-                                    ))),
+                                    }) if contracts.is_empty() => {
+                                        let ty = if opt {
+                                            Types(AbsType::Optional(Box::new(ctrt.types)))
+                                        } else {
+                                            ctrt.types
+                                        };
+
+                                        Ok(Types(AbsType::RowExtend(
+                                            id,
+                                            Some(Box::new(ty)),
+                                            Box::new(acc),
+                                        )))
+                                    }
                                     _ => {
                                         // Position of identifiers must always be set at this stage
                                         // (parsing)
@@ -355,7 +363,9 @@ pub fn fix_type_vars(ty: &mut Types) {
             | AbsType::Flat(_)
             | AbsType::RowEmpty()
             | AbsType::Wildcard(_) => (),
-            AbsType::Arrow(ref mut s, ref mut t) => {
+            AbsType::Arrow(ref mut s, ref mut t)
+            | AbsType::Union(ref mut s, ref mut t)
+            | AbsType::Intersection(ref mut s, ref mut t) => {
                 fix_type_vars_aux(s.as_mut(), Cow::Borrowed(bound_vars.as_ref()));
                 fix_type_vars_aux(t.as_mut(), bound_vars);
             }
@@ -366,7 +376,7 @@ pub fn fix_type_vars(ty: &mut Types) {
                     ty.0 = AbsType::Flat(RichTerm::new(Term::Var(id), pos));
                 }
             }
-            AbsType::Forall(ref id, ref mut ty) => {
+            AbsType::Forall(ref id, ref mut ty) | AbsType::Rec(ref id, ref mut ty) => {
                 bound_vars.to_mut().insert(id.clone());
                 fix_type_vars_aux(&mut *ty, bound_vars);
             }
@@ -386,6 +396,7 @@ pub fn fix_type_vars(ty: &mut Types) {
                 }
             }
             AbsType::DynRecord(ref mut ty)
+            | AbsType::Optional(ref mut ty)
             | AbsType::Array(ref mut ty)
             | AbsType::Enum(ref mut ty)
             | AbsType::StaticRecord(ref mut ty) => fix_type_vars_aux(ty.as_mut(), bound_vars),
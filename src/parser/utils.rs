@@ -16,7 +16,7 @@ use crate::{
     mk_app, mk_fun,
     position::{RawSpan, TermPos},
     term::{make as mk_term, BinaryOp, MetaValue, RecordAttrs, RichTerm, StrChunk, Term, UnaryOp},
-    types::Types,
+    types::{AbsType, Types},
 };
 
 /// Distinguish between the standard string separators `"`/`"` and the multi-line string separators
@@ -27,10 +27,12 @@ pub enum StringKind {
     Multiline,
 }
 
-/// Distinguish between a normal case `id => exp` and a default case `_ => exp`.
+/// Distinguish between a normal case `id => exp`, a variant case `id pat => exp`, whose body is
+/// stored as the function `fun pat => exp`, and a default case `_ => exp`.
 #[derive(Clone, Debug)]
 pub enum SwitchCase {
     Normal(Ident, RichTerm),
+    Variant(Ident, RichTerm),
     Default(RichTerm),
 }
 
@@ -352,6 +354,71 @@ pub fn mk_let(
     Ok(result)
 }
 
+/// Generate a recursive type `rec id. ty`. This function fails if the type is not contractive,
+/// that is if the recursive variable `id` may occur in `ty` without being guarded by a type
+/// constructor which is checked lazily (an array, a record, a function or an enum payload), as in
+/// `rec T. T \/ Num`, or if it occurs in the tail of a row, as in `rec T. {foo: Num ; T}`. Such
+/// types either don't make sense or have contracts which would loop forever.
+pub fn mk_rec_type(id: Ident, ty: Types, span: RawSpan) -> Result<Types, ParseError> {
+    // Return `false` if `id` occurs in an ill-formed position in `ty`. `guarded` indicates if
+    // we are under a lazy type constructor.
+    fn is_contractive(id: &Ident, ty: &Types, guarded: bool) -> bool {
+        match &ty.0 {
+            AbsType::Var(var) => guarded || var != id,
+            AbsType::Forall(var, _) | AbsType::Rec(var, _) if var == id => true,
+            AbsType::Forall(_, ty) | AbsType::Rec(_, ty) | AbsType::Optional(ty) => {
+                is_contractive(id, ty, guarded)
+            }
+            AbsType::Union(s, t) | AbsType::Intersection(s, t) => {
+                is_contractive(id, s, guarded) && is_contractive(id, t, guarded)
+            }
+            AbsType::Arrow(s, t) => is_contractive(id, s, true) && is_contractive(id, t, true),
+            AbsType::Array(ty)
+            | AbsType::DynRecord(ty)
+            | AbsType::StaticRecord(ty)
+            | AbsType::Enum(ty) => is_contractive(id, ty, true),
+            AbsType::RowExtend(_, ty_opt, tail) => {
+                let tail_ok = match &tail.0 {
+                    AbsType::Var(var) => var != id,
+                    _ => is_contractive(id, tail, guarded),
+                };
+
+                tail_ok
+                    && ty_opt
+                        .as_ref()
+                        .map(|ty| is_contractive(id, ty, guarded))
+                        .unwrap_or(true)
+            }
+            AbsType::Dyn()
+            | AbsType::Num()
+            | AbsType::Bool()
+            | AbsType::Str()
+            | AbsType::Sym()
+            | AbsType::Flat(_)
+            | AbsType::RowEmpty()
+            | AbsType::Wildcard(_) => true,
+        }
+    }
+
+    if is_contractive(&id, &ty, false) {
+        Ok(Types(AbsType::Rec(id, Box::new(ty))))
+    } else {
+        Err(ParseError::IllformedRecType(id, span))
+    }
+}
+
+/// Make a union type `s \/ t`, checking that the union contract can tell the alternatives apart.
+pub fn mk_union_type(s: Types, t: Types, span: RawSpan) -> Result<Types, ParseError> {
+    let overlapping = s.shape_overlaps(&t);
+    let ty = Types(AbsType::Union(Box::new(s), Box::new(t)));
+
+    if overlapping {
+        Err(ParseError::OverlappingUnion(ty, span))
+    } else {
+        Ok(ty)
+    }
+}
+
 /// Determine the minimal level of indentation of a multi-line string.
 ///
 /// The result is determined by computing the minimum indentation level among all lines, where the
@@ -1,6 +1,6 @@
 use crate::destruct::{self, Destruct};
 use crate::term::{BinaryOp, MetaValue, RichTerm, Term, UnaryOp};
-use crate::types::{AbsType, Types};
+use crate::types::{AbsType, RowIteratorItem, Types};
 pub use pretty::{DocAllocator, DocBuilder, Pretty};
 use regex::Regex;
 use std::collections::HashMap;
@@ -108,6 +108,14 @@ where
         .group()
     }
 
+    fn type_operand(&'a self, ty: Types, parens: bool) -> DocBuilder<'a, Self, A> {
+        if parens {
+            ty.pretty(self).parens()
+        } else {
+            ty.pretty(self)
+        }
+    }
+
     fn atom(&'a self, rt: &RichTerm) -> DocBuilder<'a, Self, A> {
         if rt.as_ref().is_atom() {
             rt.to_owned().pretty(self)
@@ -178,6 +186,9 @@ where
 
             DynAccess() => allocator.text("."),
             ArrayElemAt() => allocator.text("%elem_at%"),
+            DynExtend() => allocator.text("%record_insert%"),
+            DynRemove() => allocator.text("%record_remove%"),
+            HasField() => allocator.text("%has_field%"),
 
             op => allocator.as_string(format!("%{:?}%", op).to_lowercase()),
         }
@@ -459,6 +470,11 @@ where
             },
             Var(id) => allocator.as_string(id),
             Enum(id) => allocator.text("`").append(allocator.quote_if_needed(id)),
+            EnumVariant(id, arg) => allocator
+                .text("`")
+                .append(allocator.quote_if_needed(id))
+                .append(allocator.space())
+                .append(allocator.atom(arg)),
             Record(fields, attr) => allocator
                 .line()
                 .append(allocator.intersperse(
@@ -577,20 +593,43 @@ where
                 .append(allocator.line())
                 .group()
                 .braces(),
-            Switch(tst, cases, def) => allocator
+            Switch(tst, cases, def, variants) => allocator
                 .text("switch")
                 .append(allocator.space())
                 .append(
                     allocator
                         .intersperse(
                             sorted_map(cases).iter().map(|&(id, t)| {
+                                // The body of a case matching a variant is a function of the
+                                // payload, whose parameter is printed as the pattern of the case.
+                                let (pattern, body) = match t.as_ref() {
+                                    Fun(param, body) if variants.contains(id) => {
+                                        (allocator.space().append(allocator.as_string(param)), body)
+                                    }
+                                    FunPattern(param, dst, body) if variants.contains(id) => (
+                                        allocator.space().append(match param {
+                                            Some(param) if dst.is_empty() => {
+                                                allocator.as_string(param)
+                                            }
+                                            Some(param) => allocator
+                                                .as_string(param)
+                                                .append(allocator.text("@"))
+                                                .append(dst.pretty(allocator)),
+                                            None => dst.pretty(allocator),
+                                        }),
+                                        body,
+                                    ),
+                                    _ => (allocator.nil(), t),
+                                };
+
                                 allocator
                                     .text("`")
                                     .append(allocator.quote_if_needed(id))
+                                    .append(pattern)
                                     .append(allocator.space())
                                     .append(allocator.text("=>"))
                                     .append(allocator.space())
-                                    .append(t.to_owned().pretty(allocator))
+                                    .append(body.to_owned().pretty(allocator))
                                     .append(allocator.text(","))
                             }),
                             allocator.line(),
@@ -644,7 +683,7 @@ where
                     .append(op.pretty(allocator))
                     .append(rtl.to_owned().pretty(allocator))
             } else {
-                if (&BinaryOp::Sub(), &Num(0.0)) == (op, rtl.as_ref()) {
+                if matches!((op, rtl.as_ref()), (BinaryOp::Sub(), Num(n)) if n.is_zero()) {
                     allocator.text("-")
                 } else if let crate::term::OpPos::Prefix = op.pos() {
                     op.pretty(allocator)
@@ -749,7 +788,46 @@ where
                     .append(allocator.line())
                     .append(curr.to_owned().pretty(allocator))
             }
-            Enum(row) => row.pretty(allocator).enclose("[|", "|]"),
+            Rec(id, ty) => allocator
+                .text("rec")
+                .append(allocator.space())
+                .append(allocator.as_string(id))
+                .append(allocator.text("."))
+                .append(allocator.line())
+                .append(ty.pretty(allocator)),
+            Enum(row) => row
+                .iter_as_rows()
+                .enumerate()
+                .fold(allocator.nil(), |doc, (i, item)| match item {
+                    RowIteratorItem::Row(id, ty_opt) => doc
+                        .append(if i > 0 {
+                            allocator.text(",").append(allocator.space())
+                        } else {
+                            allocator.nil()
+                        })
+                        .append(allocator.text("`"))
+                        .append(allocator.quote_if_needed(id))
+                        .append(match ty_opt {
+                            Some(ty) if ty.fmt_is_payload_atom() => {
+                                allocator.space().append(ty.clone().pretty(allocator))
+                            }
+                            Some(ty) => allocator
+                                .space()
+                                .append(ty.clone().pretty(allocator).nest(2).parens()),
+                            None => allocator.nil(),
+                        }),
+                    RowIteratorItem::Tail(tail) => doc
+                        .append(if i > 0 {
+                            allocator
+                                .space()
+                                .append(allocator.text(";"))
+                                .append(allocator.space())
+                        } else {
+                            allocator.nil()
+                        })
+                        .append(tail.clone().pretty(allocator)),
+                })
+                .enclose("[|", "|]"),
             StaticRecord(row) => match &row.0 {
                 AbsType::Var(id) => allocator
                     .space()
@@ -799,22 +877,52 @@ where
                 }
                 .append(tail.pretty(allocator))
             }
-            Arrow(dom, codom) => match dom.0 {
-                Arrow(..) | Forall(..) => dom
-                    .pretty(allocator)
-                    .parens()
-                    .append(allocator.softline())
+            Arrow(dom, codom) => {
+                let dom = match dom.0 {
+                    Arrow(..) | Forall(..) | Rec(..) => dom.pretty(allocator).parens(),
+                    _ => dom.pretty(allocator),
+                };
+                // The grammar doesn't accept a binder directly on the right of an arrow.
+                let codom = match codom.0 {
+                    Forall(..) | Rec(..) => codom.pretty(allocator).parens(),
+                    _ => codom.pretty(allocator),
+                };
+
+                dom.append(allocator.softline())
                     .append(allocator.text("->"))
                     .append(allocator.space())
-                    .append(codom.pretty(allocator)),
-                _ => dom
-                    .pretty(allocator)
+                    .append(codom)
+            }
+            Wildcard(_) => allocator.text("_"),
+            Optional(ty) => ty
+                .pretty(allocator)
+                .append(allocator.space())
+                .append(allocator.text("|"))
+                .append(allocator.space())
+                .append(allocator.text("optional")),
+            Union(s, t) => {
+                let parens_s = matches!(s.0, Arrow(..) | Forall(..) | Rec(..));
+                let parens_t = matches!(t.0, Arrow(..) | Forall(..) | Rec(..) | Union(..));
+                allocator
+                    .type_operand(*s, parens_s)
                     .append(allocator.softline())
-                    .append(allocator.text("->"))
+                    .append(allocator.text("\\/"))
                     .append(allocator.space())
-                    .append(codom.pretty(allocator)),
-            },
-            Wildcard(_) => allocator.text("_"),
+                    .append(allocator.type_operand(*t, parens_t))
+            }
+            Intersection(s, t) => {
+                let parens_s = matches!(s.0, Arrow(..) | Forall(..) | Rec(..) | Union(..));
+                let parens_t = matches!(
+                    t.0,
+                    Arrow(..) | Forall(..) | Rec(..) | Union(..) | Intersection(..)
+                );
+                allocator
+                    .type_operand(*s, parens_s)
+                    .append(allocator.softline())
+                    .append(allocator.text("/\\"))
+                    .append(allocator.space())
+                    .append(allocator.type_operand(*t, parens_t))
+            }
         }
     }
 }
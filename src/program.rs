@@ -21,15 +21,22 @@
 //! functions in [`crate::cache`] (see [`crate::cache::Cache::mk_eval_env`]).
 //! Each such value is added to the initial environment before the evaluation of the program.
 use crate::cache::*;
-use crate::error::{Error, ToDiagnostic};
+use crate::error::{Error, IOError, ToDiagnostic};
 use crate::identifier::Ident;
-use crate::parser::lexer::Lexer;
-use crate::term::{RichTerm, Term};
+use crate::parser::lexer::{Lexer, NormalToken, Token};
+use crate::position::RawSpan;
+use crate::serialize::{self, ExportFormat};
+use crate::term::{RichTerm, Term, TraverseOrder};
+use crate::typecheck::{self, TypeReport};
+use crate::types::{AbsType, Types};
 use crate::{eval, parser};
-use codespan::FileId;
+use codespan::{FileId, Files};
 use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
+use std::collections::HashMap;
 use std::ffi::OsString;
+use std::fs;
 use std::io::{self, Read};
+use std::path::Path;
 use std::result::Result;
 
 /// A Nickel program.
@@ -94,6 +101,39 @@ impl Program {
         eval::eval_deep(t, &initial_env, &mut self.cache).map_err(|e| e.into())
     }
 
+    /// Evaluate the program and serialize the result to `writer`, forcing the values as they are
+    /// written out. See [`serialize::eval_to_writer`].
+    pub fn export<W: io::Write>(&mut self, writer: W, format: ExportFormat) -> Result<(), Error> {
+        let (t, initial_env) = self.prepare_eval()?;
+        serialize::eval_to_writer(writer, format, t, &initial_env, &mut self.cache)
+    }
+
+    /// Same as [`export`](Program::export), but write the result to a file. The output is first
+    /// written to a temporary file in the same directory, which only replaces `path` once the
+    /// export has succeeded: `path` is left untouched if an error occurs midway.
+    pub fn export_to_file(&mut self, path: &Path, format: ExportFormat) -> Result<(), Error> {
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| IOError(format!("{} is not a file", path.display())))?;
+        let mut tmp_name = OsString::from(".");
+        tmp_name.push(file_name);
+        tmp_name.push(format!(".{}.tmp", std::process::id()));
+        let tmp_path = path.with_file_name(tmp_name);
+
+        let result = fs::File::create(&tmp_path)
+            .map_err(|err| Error::from(IOError::from(err)))
+            .and_then(|file| self.export(file, format))
+            .and_then(|()| {
+                fs::rename(&tmp_path, path).map_err(|err| Error::from(IOError::from(err)))
+            });
+
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+
+        result
+    }
+
     /// Wrapper for [`query`].
     pub fn query(&mut self, path: Option<String>) -> Result<Term, Error> {
         let initial_env = self.cache.prepare_stdlib()?;
@@ -118,6 +158,76 @@ impl Program {
         Ok(())
     }
 
+    /// Typecheck the program, and return the types inferred for the wildcards, the let bindings
+    /// of statically typed blocks and the top-level fields of the main source. See
+    /// [`typecheck::type_report`].
+    pub fn type_report(&mut self) -> Result<TypeReport, Error> {
+        self.typecheck()?;
+        let initial_env = self.cache.mk_type_env().expect("program::type_report(): stdlib has been loaded but was not found in cache on mk_types_env()");
+        let term = self
+            .cache
+            .get_ref(self.main_id)
+            .expect("program::type_report(): expected source to be parsed");
+
+        Ok(typecheck::type_report(term, initial_env, &self.cache)?)
+    }
+
+    /// Write the types inferred by the typechecker (see [`Self::type_report`]) to `out`, one per
+    /// line, prefixed with their location in the source.
+    pub fn show_types(&mut self, out: &mut impl io::Write) -> Result<(), Error> {
+        let report = self.type_report()?;
+        let files = self.cache.files();
+        let term = self.cache.get_ref(self.main_id).unwrap();
+        let wildcard_spans = wildcard_spans(term, files);
+
+        for (id, ty) in report.wildcards.iter().enumerate() {
+            let location = wildcard_spans
+                .get(&id)
+                .map(|span| fmt_location(files, span))
+                .unwrap_or_else(|| String::from("<unknown>"));
+            writeln!(out, "{}: _ : {}", location, ty).map_err(IOError::from)?;
+        }
+
+        for (id, ty) in report.bindings.iter() {
+            let location = id
+                .pos
+                .as_opt_ref()
+                .map(|span| fmt_location(files, span))
+                .unwrap_or_else(|| String::from("<unknown>"));
+            writeln!(out, "{}: {} : {}", location, id, ty).map_err(IOError::from)?;
+        }
+
+        Ok(())
+    }
+
+    /// Typecheck the program, and return the source of the main input where each wildcard has
+    /// been replaced by its inferred type. Wildcards whose type couldn't be determined are replaced
+    /// by `Dyn`.
+    pub fn fill_wildcards(&mut self) -> Result<String, Error> {
+        let report = self.type_report()?;
+        let files = self.cache.files();
+        let term = self.cache.get_ref(self.main_id).unwrap();
+        let mut spans: Vec<_> = wildcard_spans(term, files).into_iter().collect();
+        let mut source = files.source(self.main_id).clone();
+
+        // We replace the wildcards from the end of the source, so that the offsets of the
+        // remaining wildcards stay valid.
+        spans.sort_by_key(|(_, span)| std::cmp::Reverse(span.start));
+
+        for (id, span) in spans {
+            let ty = &report.wildcards[id];
+            let replacement = if ty.fmt_is_payload_atom() {
+                format!("{}", ty)
+            } else {
+                format!("({})", ty)
+            };
+
+            source.replace_range(span.start.to_usize()..span.end.to_usize(), &replacement);
+        }
+
+        Ok(source)
+    }
+
     /// Wrapper for [`report`].
     pub fn report<E>(&mut self, error: E)
     where
@@ -150,7 +260,7 @@ impl Program {
 
         let rt = cache.parse_nocache(*main_id)?.0;
         let rt = if apply_transforms {
-            crate::transform::transform(rt, None).unwrap()
+            crate::transform::transform(rt, None, None).unwrap()
         } else {
             rt
         };
@@ -160,6 +270,101 @@ impl Program {
     }
 }
 
+/// Find the location of the wildcards of a term in the source, indexed by wildcard ID.
+///
+/// Wildcards don't store their position, but they are numbered in the order they appear in the
+/// source. For each type annotation containing wildcards, we thus lex the source of the annotation
+/// and match the `_` tokens with the wildcards, in order. The `_` of a dynamic record type `{_: T}`
+/// and of a default case `_ => exp` are not wildcards and are skipped. If the numbers of
+/// wildcards and of `_` tokens don't match, which may happen if the annotation contains a custom
+/// contract with type annotations inside, the wildcards of this annotation are ignored.
+fn wildcard_spans(rt: &RichTerm, files: &Files<String>) -> HashMap<usize, RawSpan> {
+    let mut labels = Vec::new();
+    rt.clone()
+        .traverse::<_, _, std::convert::Infallible>(
+            &mut |rt: RichTerm, labels: &mut Vec<_>| {
+                if let Term::MetaValue(meta) = rt.as_ref() {
+                    labels.extend(
+                        meta.types
+                            .iter()
+                            .chain(meta.contracts.iter())
+                            .map(|ctr| ctr.label.clone()),
+                    );
+                }
+                Ok(rt)
+            },
+            &mut labels,
+            TraverseOrder::TopDown,
+        )
+        .unwrap();
+
+    let mut spans = HashMap::new();
+
+    for label in labels {
+        let mut ids = Vec::new();
+        (*label.types)
+            .clone()
+            .traverse::<_, _, std::convert::Infallible>(
+                &mut |ty: Types, ids: &mut Vec<usize>| {
+                    if let AbsType::Wildcard(id) = ty.0 {
+                        ids.push(id);
+                    }
+                    Ok(ty)
+                },
+                &mut ids,
+                TraverseOrder::TopDown,
+            )
+            .unwrap();
+
+        if ids.is_empty() {
+            continue;
+        }
+
+        ids.sort_unstable();
+        let start = label.span.start.to_usize();
+        let end = label.span.end.to_usize();
+        let tokens: Vec<_> = Lexer::new(&files.source(label.span.src_id)[start..end])
+            .map_while(Result::ok)
+            .collect();
+        let underscores: Vec<_> = tokens
+            .iter()
+            .enumerate()
+            .filter(|(i, (_, tok, _))| {
+                matches!(tok, Token::Normal(NormalToken::Underscore))
+                    && !matches!(
+                        tokens.get(i + 1),
+                        Some((
+                            _,
+                            Token::Normal(NormalToken::Colon | NormalToken::DoubleArrow),
+                            _
+                        ))
+                    )
+            })
+            .map(|(_, (l, _, r))| RawSpan {
+                src_id: label.span.src_id,
+                start: ((start + l) as u32).into(),
+                end: ((start + r) as u32).into(),
+            })
+            .collect();
+
+        if underscores.len() == ids.len() {
+            spans.extend(ids.into_iter().zip(underscores));
+        }
+    }
+
+    spans
+}
+
+/// Format the location of the start of a span as `file:line:column`.
+fn fmt_location(files: &Files<String>, span: &RawSpan) -> String {
+    let name = files.name(span.src_id).to_string_lossy();
+
+    match files.location(span.src_id, span.start) {
+        Ok(loc) => format!("{}:{}:{}", name, loc.line.number(), loc.column.number()),
+        Err(_) => name.into_owned(),
+    }
+}
+
 /// Query the metadata of a path of a term in the cache.
 ///
 /// The path is a list of dot separated identifiers. For example, querying `{a = {b  = ..}}` with
@@ -355,6 +560,7 @@ mod doc {
 mod tests {
     use super::*;
     use crate::error::EvalError;
+    use crate::number::Number;
     use crate::parser::{grammar, lexer};
     use crate::position::TermPos;
     use crate::term::SharedTerm;
@@ -429,7 +635,7 @@ mod tests {
         // Records are parsed as RecRecords, so we need to build one by hand
         let expd = mk_record!((
             "foo",
-            mk_record!(("bar", mk_record!(("baz", Term::Num(2.0)))))
+            mk_record!(("bar", mk_record!(("baz", Term::Num(Number::from(2))))))
         ));
         assert_eq!(t.without_pos(), expd);
 
@@ -449,4 +655,42 @@ mod tests {
             Err(Error::ParseErrors(_))
         );
     }
+
+    fn program(s: &str) -> Program {
+        Program::new_from_source(Cursor::new(s), "<test>").unwrap()
+    }
+
+    #[test]
+    fn show_types() {
+        let mut p = program("let f : _ -> Num = fun x => x + 1 in\n{foo : _ = 1 + 1, bar = f 1}");
+        let mut out = Vec::new();
+        p.show_types(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.contains("<test>:1:9: _ : Num"));
+        assert!(out.contains("<test>:2:8: _ : Num"));
+        assert!(out.contains("<test>:2:2: foo : Num"));
+        assert!(out.contains("<test>:2:19: bar : Dyn"));
+    }
+
+    #[test]
+    fn fill_wildcards() {
+        let mut p = program("let f : _ -> Num = fun x => x + 1 in f 2");
+        assert_eq!(
+            p.fill_wildcards().unwrap(),
+            "let f : Num -> Num = fun x => x + 1 in f 2"
+        );
+
+        let mut p = program("let r : {_ : _} = {a = 1} in r");
+        assert_eq!(
+            p.fill_wildcards().unwrap(),
+            "let r : {_ : Num} = {a = 1} in r"
+        );
+
+        let mut p = program("let g : _ -> _ = fun x => x ++ \"a\" in g \"b\"");
+        assert_eq!(
+            p.fill_wildcards().unwrap(),
+            "let g : Str -> Str = fun x => x ++ \"a\" in g \"b\""
+        );
+    }
 }
@@ -16,6 +16,7 @@ use crate::types::Types;
 use crate::{eval, transform, typecheck};
 use codespan::FileId;
 use simple_counter::*;
+use std::collections::HashSet;
 use std::ffi::{OsStr, OsString};
 use std::io::Write;
 use std::result::Result;
@@ -77,6 +78,9 @@ pub struct ReplImpl {
     /// The initial type environment, without the toplevel declarations made inside the REPL. Used
     /// to typecheck imports in a fresh environment.
     initial_type_env: typecheck::Environment,
+    /// The identifiers bound by the toplevel declarations made inside the REPL, which may shadow
+    /// the stdlib.
+    toplevel_ids: HashSet<Ident>,
 }
 
 impl ReplImpl {
@@ -87,6 +91,7 @@ impl ReplImpl {
             parser: grammar::ExtendedTermParser::new(),
             env: Envs::new(),
             initial_type_env: typecheck::Environment::new(),
+            toplevel_ids: HashSet::new(),
         }
     }
 
@@ -95,6 +100,10 @@ impl ReplImpl {
     pub fn load_stdlib(&mut self) -> Result<(), Error> {
         self.env = self.cache.prepare_stdlib()?;
         self.initial_type_env = self.env.type_env.clone();
+        // The REPL evaluates many expressions in the same process, whose cycles must be freed.
+        // The thunks of the stdlib live as long as the REPL, so they are not registered: each
+        // collection would have to traverse them for nothing.
+        eval::gc::enable();
         Ok(())
     }
 
@@ -134,11 +143,17 @@ impl ReplImpl {
                 repl_impl.cache.resolve_imports(*id).unwrap();
             }
 
-            let wildcards =
+            let (wildcards, checked_annots) =
                 typecheck::type_check(&t, repl_impl.env.type_env.clone(), &repl_impl.cache)?;
 
             if let Some(id) = id {
-                typecheck::env_add(&mut repl_impl.env.type_env, id, &t, &repl_impl.cache);
+                typecheck::env_add(
+                    &mut repl_impl.env.type_env,
+                    id.clone(),
+                    &t,
+                    &repl_impl.cache,
+                );
+                repl_impl.toplevel_ids.insert(id);
             }
 
             for id in &pending {
@@ -150,8 +165,13 @@ impl ReplImpl {
                     })?;
             }
 
-            let t = transform::transform(t, Some(&wildcards))
-                .map_err(|err| Error::ParseErrors(err.into()))?;
+            let t = transform::transform_shadowing(
+                t,
+                &repl_impl.toplevel_ids,
+                Some(&wildcards),
+                Some(&checked_annots),
+            )
+            .map_err(|err| Error::ParseErrors(err.into()))?;
             for id in &pending {
                 repl_impl
                     .cache
@@ -165,7 +185,10 @@ impl ReplImpl {
         match term {
             ExtendedTerm::RichTerm(t) => {
                 let t = prepare(self, None, t)?;
-                Ok(eval_function(t, &self.env.eval_env, &mut self.cache)?.into())
+                let result = eval_function(t, &self.env.eval_env, &mut self.cache);
+                // Free the recursive records and let-bindings created by the evaluation.
+                eval::gc::collect_cycles();
+                Ok(result?.into())
             }
             ExtendedTerm::ToplevelLet(id, t) => {
                 let t = prepare(self, Some(id.clone()), t)?;
@@ -234,7 +257,7 @@ impl Repl for ReplImpl {
         for id in &pending {
             self.cache.resolve_imports(*id).unwrap();
         }
-        let wildcards = typecheck::type_check(&term, self.env.type_env.clone(), &self.cache)?;
+        let (wildcards, _) = typecheck::type_check(&term, self.env.type_env.clone(), &self.cache)?;
         // Substitute the wildcard types for their inferred types
         // We need to `traverse` the term, in case the type depends on inner terms that also contain wildcards
         let term = term
@@ -259,7 +282,9 @@ impl Repl for ReplImpl {
         use crate::program;
 
         let file_id = self.cache.add_tmp("<repl-query>", String::from(exp));
-        program::query(&mut self.cache, file_id, &self.env, None)
+        let result = program::query(&mut self.cache, file_id, &self.env, None);
+        eval::gc::collect_cycles();
+        result
     }
 
     fn cache_mut(&mut self) -> &mut Cache {
@@ -420,3 +445,37 @@ pub fn print_help(out: &mut impl Write, arg: Option<&str>) -> std::io::Result<()
         writeln!(out, "Available commands: help query load typecheck exit")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_evaluation_frees_cycles() {
+        let mut repl = ReplImpl::new();
+        repl.load_stdlib().unwrap();
+        assert_eq!(eval::gc::registered(), 0);
+
+        let input = "{f = fun n => if n == 0 then 0 else f (n - 1), res = f 3}.res";
+        repl.eval_full(input).unwrap();
+        let live = eval::gc::registered();
+
+        for _ in 0..10 {
+            repl.eval_full(input).unwrap();
+        }
+        assert_eq!(eval::gc::registered(), live);
+    }
+
+    #[test]
+    fn toplevel_declarations_shadow_the_stdlib() {
+        let mut repl = ReplImpl::new();
+        repl.load_stdlib().unwrap();
+
+        repl.eval_full("let string = {is_match = fun x y => x == y}")
+            .unwrap();
+        assert!(matches!(
+            repl.eval_full("string.is_match \"a+\" \"aa\"").unwrap(),
+            EvalResult::Evaluated(t) if t.as_ref() == &Term::Bool(false)
+        ));
+    }
+}
@@ -1,17 +1,28 @@
 //! Serialization of an evaluated program to various data format.
 use crate::{
-    error::SerializationError,
-    eval::{self, is_empty_optional},
+    cache::ImportResolver,
+    error::{self, EvalError, SerializationError},
+    eval::{self, is_empty_optional, lazy::Thunk, Closure, IdentKind},
     identifier::Ident,
+    match_sharedterm,
+    number::{NumberVisitor, JSON_NUMBER_TOKEN},
     term::{ArrayAttrs, MetaValue, RecordAttrs, RichTerm, Term},
+    transform::{apply_contracts::apply_contracts, fresh_var},
 };
 
+use num_traits::ToPrimitive;
 use serde::{
-    de::{Deserialize, Deserializer},
+    de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor},
     ser::{Error, Serialize, SerializeMap, SerializeSeq, Serializer},
 };
 
-use std::{collections::HashMap, fmt, io, str::FromStr};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt,
+    io::{self, Write},
+    str::FromStr,
+};
 
 /// Available export formats.
 // If you add or remove variants, remember to update the CLI docs in `src/bin/nickel.rs'
@@ -63,25 +74,6 @@ impl FromStr for ExportFormat {
     }
 }
 
-/// Implicitly convert float to integers when possible to avoid trailing zeros. Note this this
-/// only work if the float is in range of either `i64` or `f64`. It seems there's no easy general
-/// solution (working for both YAML, TOML, and JSON) to choose the way floating point values are
-/// formatted.
-pub fn serialize_num<S>(n: &f64, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-{
-    if n.fract() == 0.0 {
-        if *n < 0.0 && *n >= (i64::MIN as f64) && *n <= (i64::MAX as f64) {
-            return (*n as i64).serialize(serializer);
-        } else if *n >= 0.0 && *n <= (u64::MAX as f64) {
-            return (*n as u64).serialize(serializer);
-        }
-    }
-
-    n.serialize(serializer)
-}
-
 /// Serializer for metavalues.
 pub fn serialize_meta_value<S>(meta: &MetaValue, serializer: S) -> Result<S::Ok, S::Error>
 where
@@ -149,6 +141,17 @@ where
     seq.end()
 }
 
+/// Serializer for an enum variant. The variant is serialized as a map with a single entry, from
+/// the tag to the payload.
+pub fn serialize_enum_variant<S>(id: &Ident, t: &RichTerm, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let mut map_ser = serializer.serialize_map(Some(1))?;
+    map_ser.serialize_entry(&id.to_string(), t)?;
+    map_ser.end()
+}
+
 /// Deserialize for an Array. Required to set the default attributes.
 pub fn deserialize_array<'de, D>(deserializer: D) -> Result<(Vec<RichTerm>, ArrayAttrs), D::Error>
 where
@@ -169,12 +172,96 @@ impl Serialize for RichTerm {
 }
 
 impl<'de> Deserialize<'de> for RichTerm {
+    /// Deserialize a value directly, rather than through the untagged representation of [Term],
+    /// which doesn't support integers of 128 bits.
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        let t: Term = Term::deserialize(deserializer)?;
-        Ok(RichTerm::from(t))
+        deserializer
+            .deserialize_any(TermVisitor)
+            .map(RichTerm::from)
+    }
+}
+
+/// The visitor of the deserialization of a value, which is either null, a boolean, a number, a
+/// string, an array or a record.
+struct TermVisitor;
+
+impl<'de> Visitor<'de> for TermVisitor {
+    type Value = Term;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a value")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Term, E> {
+        Ok(Term::Null)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Term, E> {
+        Ok(Term::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Term, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_bool<E: de::Error>(self, b: bool) -> Result<Term, E> {
+        Ok(Term::Bool(b))
+    }
+
+    fn visit_i64<E: de::Error>(self, n: i64) -> Result<Term, E> {
+        NumberVisitor.visit_i64(n).map(Term::Num)
+    }
+
+    fn visit_u64<E: de::Error>(self, n: u64) -> Result<Term, E> {
+        NumberVisitor.visit_u64(n).map(Term::Num)
+    }
+
+    fn visit_i128<E: de::Error>(self, n: i128) -> Result<Term, E> {
+        NumberVisitor.visit_i128(n).map(Term::Num)
+    }
+
+    fn visit_u128<E: de::Error>(self, n: u128) -> Result<Term, E> {
+        NumberVisitor.visit_u128(n).map(Term::Num)
+    }
+
+    fn visit_f64<E: de::Error>(self, f: f64) -> Result<Term, E> {
+        NumberVisitor.visit_f64(f).map(Term::Num)
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Term, E> {
+        Ok(Term::Str(String::from(s)))
+    }
+
+    fn visit_string<E: de::Error>(self, s: String) -> Result<Term, E> {
+        Ok(Term::Str(s))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Term, A::Error> {
+        let mut terms = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(term) = seq.next_element()? {
+            terms.push(term);
+        }
+
+        Ok(Term::Array(terms, Default::default()))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Term, A::Error> {
+        let mut fields = HashMap::with_capacity(map.size_hint().unwrap_or(0));
+
+        while let Some(key) = map.next_key::<String>()? {
+            // A number passed by `serde_json` with the `arbitrary_precision` feature
+            if key == JSON_NUMBER_TOKEN && fields.is_empty() {
+                let literal: String = map.next_value()?;
+                return NumberVisitor.visit_json_literal(&literal).map(Term::Num);
+            }
+
+            fields.insert(Ident::from(key), map.next_value()?);
+        }
+
+        Ok(Term::Record(fields, Default::default()))
     }
 }
 
@@ -195,7 +282,15 @@ pub fn validate(format: ExportFormat, t: &RichTerm) -> Result<(), SerializationE
             // TOML doesn't support null values
             Null if format == ExportFormat::Json || format == ExportFormat::Yaml => Ok(()),
             Null => Err(SerializationError::UnsupportedNull(format, t.clone())),
+            // TOML integers are 64 bits integers
+            Num(n)
+                if format == ExportFormat::Toml
+                    && n.to_integer().is_some_and(|n| n.to_i64().is_none()) =>
+            {
+                Err(SerializationError::IntegerOutOfRange(format, t.clone()))
+            }
             Bool(_) | Num(_) | Str(_) | Enum(_) => Ok(()),
+            EnumVariant(_, t) => validate(format, t),
             Record(map, _) => {
                 map.iter().try_for_each(|(_, t)| validate(format, t))?;
                 Ok(())
@@ -265,14 +360,227 @@ pub fn to_string(format: ExportFormat, rt: &RichTerm) -> Result<String, Serializ
     }
 }
 
+/// Evaluate a term and serialize the result to a writer, without ever building the whole
+/// evaluated term in memory.
+///
+/// Contrary to [`eval::eval_full`] followed by [`to_writer`], the values are forced one at a time,
+/// right before being written out: a record field or an array element is only evaluated when the
+/// serializer reaches it, and no fully evaluated copy of the term is ever built. Forced values are
+/// memoized in their thunk as during a normal evaluation, such that a value shared by several
+/// parts of the configuration is only evaluated once. The memory of a value is released once it
+/// has been written out and no other part of the configuration refers to it anymore, which for a
+/// field or an element is at the latest when its enclosing record or array has been written out.
+///
+/// The TOML format can't be written incrementally, as the tables must come after the other
+/// values: the output is built in memory in this case.
+///
+/// Values are validated as they are serialized, so the output may have been partially written
+/// when an error occurs. Use [`crate::program::Program::export_to_file`] to only write a file once
+/// the export has succeeded.
+pub fn eval_to_writer<W, R>(
+    writer: W,
+    format: ExportFormat,
+    rt: RichTerm,
+    initial_env: &eval::Environment,
+    resolver: &mut R,
+) -> Result<(), error::Error>
+where
+    W: io::Write,
+    R: ImportResolver,
+{
+    let mut writer = io::BufWriter::new(writer);
+
+    if format == ExportFormat::Raw {
+        let (rt, _) = eval::eval_closure(Closure::atomic_closure(rt), initial_env, resolver, true)?;
+
+        return match rt.as_ref() {
+            Term::Str(s) => writer
+                .write_all(s.as_bytes())
+                .and_then(|_| writer.flush())
+                .map_err(|err| SerializationError::Other(err.to_string()).into()),
+            _ => Err(SerializationError::NotAString(rt).into()),
+        };
+    }
+
+    let state = StreamState {
+        format,
+        initial_env,
+        resolver: RefCell::new(resolver),
+        error: RefCell::new(None),
+    };
+    let result = {
+        let value = state.stream(Closure::atomic_closure(rt));
+
+        match format {
+            ExportFormat::Json => serde_json::to_writer_pretty(&mut writer, &value)
+                .map_err(|err| SerializationError::Other(err.to_string())),
+            ExportFormat::Yaml => serde_yaml::to_writer(&mut writer, &value)
+                .map_err(|err| SerializationError::Other(err.to_string())),
+            ExportFormat::Toml => toml::Value::try_from(&value)
+                .map_err(|err| SerializationError::Other(err.to_string()))
+                .and_then(|v| {
+                    write!(writer, "{}", v)
+                        .map_err(|err| SerializationError::Other(err.to_string()))
+                }),
+            ExportFormat::Raw => unreachable!(),
+        }
+    };
+
+    // The error reported by the serializer is a mere message: the original error, if any, takes
+    // precedence.
+    if let Some(err) = state.error.into_inner() {
+        return Err(err);
+    }
+
+    result?;
+    writer
+        .flush()
+        .map_err(|err| SerializationError::Other(err.to_string()).into())
+}
+
+/// The state shared by the values of a streamed export.
+struct StreamState<'a, R> {
+    format: ExportFormat,
+    initial_env: &'a eval::Environment,
+    resolver: RefCell<&'a mut R>,
+    /// The first evaluation or serialization error encountered. Serializers only accept errors
+    /// built from a message, so the original error is stored here to be reported at the end.
+    error: RefCell<Option<error::Error>>,
+}
+
+impl<'a, R: ImportResolver> StreamState<'a, R> {
+    fn stream(&self, clos: Closure) -> Streamed<'_, 'a, R> {
+        Streamed {
+            clos: Cell::new(Some(clos)),
+            state: self,
+        }
+    }
+
+    /// Evaluate a closure to a weak head normal form.
+    fn eval(&self, clos: Closure) -> Result<(RichTerm, eval::Environment), EvalError> {
+        let mut resolver = self.resolver.borrow_mut();
+        eval::eval_closure(clos, self.initial_env, &mut **resolver, true)
+    }
+
+    /// Record an error, and return a serializer error which aborts the serialization.
+    fn fail<E: Error>(&self, err: impl Into<error::Error>) -> E {
+        self.error.borrow_mut().get_or_insert(err.into());
+        E::custom("export aborted")
+    }
+}
+
+/// A value which is evaluated when serialized. Its subvalues are themselves streamed, and the
+/// closure is consumed by the serialization, such that the evaluated value can be freed as soon as
+/// it has been written out.
+struct Streamed<'s, 'a, R> {
+    clos: Cell<Option<Closure>>,
+    state: &'s StreamState<'a, R>,
+}
+
+impl<'s, 'a, R: ImportResolver> Serialize for Streamed<'s, 'a, R> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let clos = self
+            .clos
+            .take()
+            .ok_or_else(|| S::Error::custom("streamed value serialized twice"))?;
+        let (rt, env) = self
+            .state
+            .eval(clos)
+            .map_err(|err| self.state.fail::<S::Error>(err))?;
+        let pos = rt.pos;
+
+        match_sharedterm! {rt.term, with {
+                Term::Record(map, _) => {
+                    let mut entries: Vec<_> = map
+                        .into_iter()
+                        .filter(|(_, t)| !is_empty_optional(t, &env))
+                        .collect();
+                    entries.sort_by(|(id1, _), (id2, _)| id1.cmp(id2));
+
+                    let mut map_ser = serializer.serialize_map(Some(entries.len()))?;
+                    for (id, t) in entries {
+                        let value = self.state.stream(unshared(t, &env));
+                        map_ser.serialize_entry(&id.to_string(), &value)?;
+                    }
+
+                    map_ser.end()
+                },
+                Term::EnumVariant(id, t) => {
+                    let value = self.state.stream(unshared(t, &env));
+                    let mut map_ser = serializer.serialize_map(Some(1))?;
+                    map_ser.serialize_entry(&id.to_string(), &value)?;
+                    map_ser.end()
+                },
+                Term::Array(ts, attrs) => {
+                    let mut seq = serializer.serialize_seq(Some(ts.len()))?;
+                    for t in ts {
+                        let value = if attrs.pending_contracts.is_empty() {
+                            unshared(t, &env)
+                        } else {
+                            let id = fresh_var();
+                            let mut env = env.clone();
+                            env.insert(
+                                id.clone(),
+                                Thunk::new(unshared(t, &env), IdentKind::Let),
+                            );
+
+                            Closure {
+                                body: apply_contracts(
+                                    RichTerm::new(Term::Var(id), pos.into_inherited()),
+                                    attrs.pending_contracts.iter().cloned(),
+                                    pos.into_inherited(),
+                                ),
+                                env,
+                            }
+                        };
+                        seq.serialize_element(&self.state.stream(value))?;
+                    }
+
+                    seq.end()
+                }
+            } else {
+                // The remaining terms are leaves, which are checked by `validate` as in a
+                // non-streamed export.
+                validate(self.state.format, &rt).map_err(|err| self.state.fail::<S::Error>(err))?;
+                rt.serialize(serializer)
+            }
+        }
+    }
+}
+
+/// Return the closure of a subvalue of a streamed value. The subvalues of an evaluated value are
+/// usually variables bound in its environment: the content of their thunk is then evaluated on its
+/// own, without updating the thunk. Otherwise, the evaluated subvalue would be kept alive by the
+/// environment of its parent until the parent has been entirely written out.
+fn unshared(t: RichTerm, env: &eval::Environment) -> Closure {
+    match t.as_ref() {
+        Term::Var(id) => match env.get(id) {
+            Some(thunk) => thunk.get_owned(),
+            None => Closure {
+                body: t,
+                env: env.clone(),
+            },
+        },
+        _ => Closure {
+            body: t,
+            env: env.clone(),
+        },
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::error::{Error, EvalError};
     use crate::eval::Environment;
+    use crate::number::Number;
     use crate::position::TermPos;
     use crate::program::Program;
     use crate::term::{make as mk_term, BinaryOp};
+    use assert_matches::assert_matches;
     use serde_json::json;
     use std::io::Cursor;
 
@@ -371,6 +679,8 @@ mod tests {
         assert_json_eq!("if true then false else true", false);
         assert_json_eq!(r##""Hello, %{"world"}!""##, "Hello, world!");
         assert_json_eq!("`foo", "foo");
+        assert_json_eq!("`foo (1 + 1)", json!({"foo": 2}));
+        assert_json_eq!("`foo {bar = `baz}", json!({"foo": {"bar": "baz"}}));
     }
 
     #[test]
@@ -434,6 +744,9 @@ mod tests {
         );
         assert_pass_validation!(ExportFormat::Json, "{foo = null}", true);
         assert_pass_validation!(ExportFormat::Toml, "{foo = null}", false);
+        assert_pass_validation!(ExportFormat::Toml, "{foo = 9223372036854775807}", true);
+        assert_pass_validation!(ExportFormat::Toml, "{foo = 9223372036854775808}", false);
+        assert_pass_validation!(ExportFormat::Json, "{foo = 9223372036854775808}", true);
     }
 
     #[test]
@@ -442,5 +755,194 @@ mod tests {
         assert_involutory!("{val = \"Some string\"}");
         assert_involutory!("{val = [\"a\", 3, []]}");
         assert_involutory!("{a.foo.bar = \"2\", b = false, c = [{d = \"e\"}, {d = \"f\"}]}");
+        assert_involutory!("{val = 9007199254740993, other = 0.1}");
+    }
+
+    #[test]
+    fn exact_numbers() {
+        assert_json_eq!("18446744073709551614 + 1", json!(18446744073709551615u64));
+        assert_json_eq!("9007199254740992 + 1", json!(9007199254740993u64));
+        assert_json_eq!("0.1 + 0.2", json!(0.3));
+
+        let from_json: RichTerm = serde_json::from_str("18446744073709551615").unwrap();
+        assert_eq!(from_json.as_ref(), &Term::Num(Number::from(u64::MAX)));
+        let from_yaml: RichTerm = serde_yaml::from_str("18446744073709551615").unwrap();
+        assert_eq!(from_yaml.as_ref(), &Term::Num(Number::from(u64::MAX)));
+
+        // Numbers beyond 64 bits round-trip through JSON and YAML
+        assert_json_eq!("18446744073709551615 + 2", json!(18446744073709551617u128));
+        for literal in [
+            "18446744073709551617",
+            "-170141183460469231731687303715884105728",
+            "340282366920938463463374607431768211455",
+        ] {
+            let from_json: RichTerm = serde_json::from_str(literal).unwrap();
+            assert_eq!(
+                from_json.as_ref(),
+                &Term::Num(Number::from_str(literal).unwrap())
+            );
+            assert_eq!(serde_json::to_string(&from_json).unwrap(), literal);
+            let from_yaml: RichTerm = serde_yaml::from_str(literal).unwrap();
+            assert_eq!(from_yaml, from_json);
+            assert_eq!(
+                serde_yaml::to_string(&from_json).unwrap(),
+                format!("---\n{}\n", literal)
+            );
+        }
+
+        // JSON numbers are parsed exactly, whatever their size
+        let from_json: RichTerm =
+            serde_json::from_str("[0.1, 1e-30, 123456789012345678901234567890123456789012]")
+                .unwrap();
+        let expected: Vec<_> = ["0.1", "1e-30", "123456789012345678901234567890123456789012"]
+            .iter()
+            .map(|n| Term::Num(Number::from_str(n).unwrap()))
+            .collect();
+        assert_matches!(from_json.as_ref(), Term::Array(ts, _) if ts.iter().map(|t| t.as_ref().clone()).collect::<Vec<_>>() == expected);
+    }
+
+    #[test]
+    fn streamed_export() {
+        let stream = |format, s: &str| {
+            let mut out = Vec::new();
+            mk_program(s)
+                .and_then(|mut p| p.export(&mut out, format))
+                .map(|_| String::from_utf8(out).unwrap())
+        };
+        let eager = |format, s: &str| {
+            let mut out = Vec::new();
+            let rt = mk_program(s).and_then(|mut p| p.eval_full()).unwrap();
+            to_writer(&mut out, format, &rt).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        for s in [
+            "{foo = {bar = [1, 2 + 2, {baz = \"a\" ++ \"b\"}]}, opt | optional | Num, x = `tag}",
+            "let base = {a | default = 1, b = a + 1} in [base, base & {a = 5}, base.b]",
+            "{arr | Array Num = array.map (fun x => x * 2) [1, 2, 3], y | Str = \"s\"}",
+            "array.generate (fun i => {id = i, name = \"item-%{string.from_num i}\"}) 50",
+            "[`local {path = \"/var\"}, `s3 {bucket = \"b\" ++ \"c\"}, `none]",
+        ] {
+            assert_eq!(
+                stream(ExportFormat::Json, s),
+                Ok(eager(ExportFormat::Json, s))
+            );
+            assert_eq!(
+                stream(ExportFormat::Yaml, s),
+                Ok(eager(ExportFormat::Yaml, s))
+            );
+        }
+
+        let s = "{a = {b = 1, c = \"x\"}, d = [true, false]}";
+        assert_eq!(
+            stream(ExportFormat::Toml, s),
+            Ok(eager(ExportFormat::Toml, s))
+        );
+        assert_eq!(
+            stream(ExportFormat::Raw, "\"a\" ++ \"b\""),
+            Ok(String::from("ab"))
+        );
+
+        assert_matches!(
+            stream(ExportFormat::Json, "{a = 1, b = {c = fun x => x}}"),
+            Err(Error::SerializationError(
+                SerializationError::NonSerializable(..)
+            ))
+        );
+        assert_matches!(
+            stream(ExportFormat::Toml, "{foo = null}"),
+            Err(Error::SerializationError(
+                SerializationError::UnsupportedNull(..)
+            ))
+        );
+        assert_matches!(
+            stream(ExportFormat::Json, "{a = [1, 2 + \"a\"]}"),
+            Err(Error::EvalError(EvalError::TypeError(..)))
+        );
+        assert_matches!(
+            stream(ExportFormat::Json, "{a | Array Str = [\"a\", 1]}"),
+            Err(Error::EvalError(EvalError::BlameError(..)))
+        );
+        assert_matches!(
+            stream(ExportFormat::Raw, "1"),
+            Err(Error::SerializationError(SerializationError::NotAString(
+                ..
+            )))
+        );
+    }
+
+    #[test]
+    fn streamed_export_frees_written_values() {
+        use crate::eval::gc;
+
+        /// A writer recording the maximal number of live thunks seen when written to.
+        struct Sampler(usize);
+
+        impl io::Write for Sampler {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0 = self.0.max(gc::registered());
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let peak = |s: &str| {
+            let mut sampler = Sampler(0);
+            mk_program(s)
+                .and_then(|mut p| p.export(&mut sampler, ExportFormat::Json))
+                .unwrap();
+            sampler.0
+        };
+
+        gc::enable();
+        let big = "array.generate (fun i => \"item-%{string.from_num i}\") 5000";
+        let one = peak(&format!("{{b = {}}}", big));
+        // `a` is freed once written out, so the peak isn't significantly higher with it.
+        let two = peak(&format!("{{a = {}, b = {}}}", big, big));
+        assert!(
+            two < one + one / 10,
+            "{} live thunks instead of {}",
+            two,
+            one
+        );
+    }
+
+    #[test]
+    fn streamed_export_error() {
+        // The error is only hit when the serializer reaches `b`, once `a` has been written out.
+        let s = "{a = [1, 2], b = 1 + \"a\", c = 3}";
+
+        let mut out = Vec::new();
+        assert_matches!(
+            mk_program(s).and_then(|mut p| p.export(&mut out, ExportFormat::Json)),
+            Err(Error::EvalError(EvalError::TypeError(..)))
+        );
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("{\n  \"a\": [\n    1,\n    2\n  ],"));
+        assert!(!out.contains("\"c\""));
+
+        // Exporting to a file doesn't leave partial output behind.
+        let dir = std::env::temp_dir().join(format!("nickel-export-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.json");
+        std::fs::write(&path, "previous").unwrap();
+
+        assert_matches!(
+            mk_program(s).and_then(|mut p| p.export_to_file(&path, ExportFormat::Json)),
+            Err(Error::EvalError(EvalError::TypeError(..)))
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "previous");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        mk_program("{a = 1}")
+            .and_then(|mut p| p.export_to_file(&path, ExportFormat::Json))
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{\n  \"a\": 1\n}");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -48,10 +48,22 @@ pub mod contract {
     generate_accessor!(fail);
     generate_accessor!(enums);
     generate_accessor!(enum_fail);
+    generate_accessor!(enum_payload);
     generate_accessor!(record);
     generate_accessor!(dyn_record);
     generate_accessor!(record_extend);
+    generate_accessor!(record_extend_optional);
     generate_accessor!(forall_tail);
     generate_accessor!(dyn_tail);
     generate_accessor!(empty_tail);
+    generate_accessor!(union);
+    generate_accessor!(intersection);
+    generate_accessor!(shape_any);
+    generate_accessor!(shape_unknown);
+    generate_accessor!(shape_typeof);
+    generate_accessor!(shape_flat);
+    generate_accessor!(shape_enum);
+    generate_accessor!(shape_record);
+    generate_accessor!(shape_or);
+    generate_accessor!(shape_and);
 }
@@ -21,6 +21,7 @@ use crate::error::ParseError;
 use crate::identifier::Ident;
 use crate::label::Label;
 use crate::match_sharedterm;
+use crate::number::Number;
 use crate::position::TermPos;
 use crate::types::{AbsType, Types, UnboundTypeVariableError};
 use codespan::FileId;
@@ -43,9 +44,8 @@ pub enum Term {
     Null,
     /// A boolean value.
     Bool(bool),
-    /// A floating-point value.
-    #[serde(serialize_with = "crate::serialize::serialize_num")]
-    Num(f64),
+    /// An arbitrary-precision number.
+    Num(Number),
     /// A literal string.
     Str(String),
     /// A string containing interpolated expressions, represented as a list of either literals or
@@ -81,8 +81,12 @@ pub enum Term {
     #[serde(skip)]
     Var(Ident),
 
-    /// An enum variant.
+    /// An enum tag.
     Enum(Ident),
+    /// An enum variant carrying a payload.
+    #[serde(serialize_with = "crate::serialize::serialize_enum_variant")]
+    #[serde(skip_deserializing)]
+    EnumVariant(Ident, RichTerm),
 
     /// A record, mapping identifiers to terms.
     #[serde(serialize_with = "crate::serialize::serialize_record")]
@@ -103,6 +107,7 @@ pub enum Term {
         RichTerm,                 /* tested expression */
         HashMap<Ident, RichTerm>, /* cases */
         Option<RichTerm>,         /* default */
+        HashSet<Ident>, /* cases matching a variant, whose body is a function of the payload */
     ),
 
     /// An array.
@@ -395,6 +400,17 @@ impl<E> StrChunk<E> {
     }
 }
 
+/// Format an enum tag, quoting it if it isn't a valid identifier.
+fn fmt_enum_tag(id: &Ident) -> String {
+    let re = regex::Regex::new("_?[a-zA-Z][_a-zA-Z0-9]*").unwrap();
+    let s = id.to_string();
+    if re.is_match(&s) {
+        format!("`{}", s)
+    } else {
+        format!("`\"{}\"", s)
+    }
+}
+
 impl Term {
     //#[cfg(test)]
     /// Recursively apply a function to all `Term`s contained in a `RichTerm`.
@@ -405,7 +421,7 @@ impl Term {
         use self::Term::*;
         match self {
             Null | ParseError(_) => (),
-            Switch(ref mut t, ref mut cases, ref mut def) => {
+            Switch(ref mut t, ref mut cases, ref mut def, _) => {
                 cases.iter_mut().for_each(|c| {
                     let (_, t) = c;
                     func(t);
@@ -429,6 +445,7 @@ impl Term {
             | ResolvedImport(_) => {}
             Fun(_, ref mut t)
             | FunPattern(_, _, ref mut t)
+            | EnumVariant(_, ref mut t)
             | Op1(_, ref mut t)
             | Sealed(_, ref mut t, _) => {
                 func(t);
@@ -474,7 +491,7 @@ impl Term {
             Term::Str(_) => Some("Str"),
             Term::Fun(_, _) | Term::FunPattern(_, _, _) => Some("Fun"),
             Term::Lbl(_) => Some("Label"),
-            Term::Enum(_) => Some("Enum"),
+            Term::Enum(_) | Term::EnumVariant(..) => Some("Enum"),
             Term::Record(..) | Term::RecRecord(..) => Some("Record"),
             Term::Array(..) => Some("Array"),
             Term::SealingKey(_) => Some("SealingKey"),
@@ -518,15 +535,8 @@ impl Term {
             }
             Term::Fun(_, _) | Term::FunPattern(_, _, _) => String::from("<func>"),
             Term::Lbl(_) => String::from("<label>"),
-            Term::Enum(id) => {
-                let re = regex::Regex::new("_?[a-zA-Z][_a-zA-Z0-9]*").unwrap();
-                let s = id.to_string();
-                if re.is_match(&s) {
-                    format!("`{}", s)
-                } else {
-                    format!("`\"{}\"", s)
-                }
-            }
+            Term::Enum(id) => fmt_enum_tag(id),
+            Term::EnumVariant(id, _) => format!("{} ...", fmt_enum_tag(id)),
             Term::Record(..) | Term::RecRecord(..) => String::from("{ ... }"),
             Term::Array(..) => String::from("[ ... ]"),
             Term::SealingKey(_) => String::from("<sealing key>"),
@@ -591,6 +601,9 @@ impl Term {
                     .collect();
                 format!("[ {} ]", elements_str.join(", "))
             }
+            Term::EnumVariant(id, arg) => {
+                format!("{} ({})", fmt_enum_tag(id), arg.as_ref().deep_repr())
+            }
             _ => self.shallow_repr(),
         }
     }
@@ -605,6 +618,7 @@ impl Term {
             | Term::Fun(_, _)
             | Term::Lbl(_)
             | Term::Enum(_)
+            | Term::EnumVariant(..)
             | Term::Record(..)
             | Term::Array(..)
             | Term::SealingKey(_) => true,
@@ -647,6 +661,7 @@ impl Term {
             | Term::SealingKey(_) => true,
             Term::Let(..)
             | Term::LetPattern(..)
+            | Term::EnumVariant(..)
             | Term::Record(..)
             | Term::Array(..)
             | Term::Fun(_, _)
@@ -685,6 +700,7 @@ impl Term {
             | Term::SealingKey(..) => true,
             Term::Let(..)
             | Term::Switch(..)
+            | Term::EnumVariant(..)
             | Term::LetPattern(..)
             | Term::Fun(..)
             | Term::FunPattern(..)
@@ -780,7 +796,12 @@ pub enum UnaryOp {
     /// `embed someId` act like the identity.
     Embed(Ident),
     /// A switch block. Used to match on a enumeration.
-    Switch(bool /* presence of a default case */), //HashMap<Ident, CapturedTerm>, Option<CapturedTerm>),
+    ///
+    /// The set contains the cases matching a variant, whose body is a function of the payload.
+    Switch(
+        bool,           /* presence of a default case */
+        HashSet<Ident>, /* cases matching a variant */
+    ),
 
     /// Static access to a record field.
     ///
@@ -1053,8 +1074,8 @@ impl BinaryOp {
         use BinaryOp::*;
         match self {
             Plus() | Sub() | Mult() | Div() | Modulo() | Pow() | StrConcat() | Eq()
-            | LessThan() | LessOrEq() | GreaterThan() | GreaterOrEq() | DynExtend()
-            | DynRemove() | DynAccess() | ArrayConcat() | Merge() => OpPos::Infix,
+            | LessThan() | LessOrEq() | GreaterThan() | GreaterOrEq() | DynAccess()
+            | ArrayConcat() | Merge() => OpPos::Infix,
             _ => OpPos::Prefix,
         }
     }
@@ -1200,7 +1221,7 @@ impl RichTerm {
                     pos,
                 )
             },
-            Term::Switch(t, cases, default) => {
+            Term::Switch(t, cases, default, variants) => {
                 // The annotation on `map_res` use Result's corresponding trait to convert from
                 // Iterator<Result> to a Result<Iterator>
                 let cases_res: Result<HashMap<Ident, RichTerm>, E> = cases
@@ -1214,7 +1235,14 @@ impl RichTerm {
                 let t = t.traverse(f, state, order)?;
 
                 RichTerm::new(
-                    Term::Switch(t, cases_res?, default),
+                    Term::Switch(t, cases_res?, default, variants),
+                    pos,
+                )
+            },
+            Term::EnumVariant(id, t) => {
+                let t = t.traverse(f, state, order)?;
+                RichTerm::new(
+                    Term::EnumVariant(id, t),
                     pos,
                 )
             },
@@ -1425,10 +1453,10 @@ impl std::fmt::Display for RichTerm {
 /// It is used somehow as a match statement, going from
 /// ```
 /// # use nickel_lang::term::{RichTerm, Term};
-/// let rt = RichTerm::from(Term::Num(5.0));
+/// let rt = RichTerm::from(Term::Bool(true));
 ///
 /// match rt.term.into_owned() {
-///     Term::Num(x) => x as usize,
+///     Term::Bool(b) => b as usize,
 ///     Term::Str(s) => s.len(),
 ///     _ => 42,
 /// };
@@ -1437,10 +1465,10 @@ impl std::fmt::Display for RichTerm {
 /// ```
 /// # use nickel_lang::term::{RichTerm, Term};
 /// # use nickel_lang::match_sharedterm;
-/// let rt = RichTerm::from(Term::Num(5.0));
+/// let rt = RichTerm::from(Term::Bool(true));
 ///
 /// match_sharedterm!{rt.term, with {
-///         Term::Num(x) => x as usize,
+///         Term::Bool(b) => b as usize,
 ///         Term::Str(s) => s.len(),
 ///     } else 42
 /// };
@@ -1536,7 +1564,7 @@ pub mod make {
                 $(
                     map.insert($id.into(), $body.into());
                 )*
-                $crate::term::RichTerm::from($crate::term::Term::Switch($crate::term::RichTerm::from($exp), map, Some($crate::term::RichTerm::from($default))))
+                $crate::term::RichTerm::from($crate::term::Term::Switch($crate::term::RichTerm::from($exp), map, Some($crate::term::RichTerm::from($default)), std::collections::HashSet::new()))
             }
         };
         ( $exp:expr, $( ($id:expr, $body:expr) ),*) => {
@@ -1544,7 +1572,7 @@ pub mod make {
                 $(
                     map.insert($id.into(), $body.into());
                 )*
-                $crate::term::RichTerm::from($crate::term::Term::Switch($crate::term::RichTerm::from($exp), map, None))
+                $crate::term::RichTerm::from($crate::term::Term::Switch($crate::term::RichTerm::from($exp), map, None, std::collections::HashSet::new()))
         };
     }
 
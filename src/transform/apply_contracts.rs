@@ -8,17 +8,26 @@
 //!
 //! It must be run before `share_normal_form` to avoid rechecking contracts each time the inner
 //! value is unwrapped.
+//!
+//! Type annotations that have been statically proven by the typechecker (see
+//! [`crate::typecheck::CheckedAnnots`]) are an exception: their contract can't fail, and is not
+//! applied.
 use crate::{
     match_sharedterm, mk_app,
     position::TermPos,
     term::{make as mk_term, BinaryOp, PendingContract, RichTerm, Term},
+    typecheck::CheckedAnnots,
     types::UnboundTypeVariableError,
 };
 
 /// If the top-level node of the AST is a meta-value, apply the meta-value's contracts to the inner
-/// value.  Otherwise, return the term unchanged.
+/// value, excepted for a type annotation which belongs to `checked_annots`. Otherwise, return the
+/// term unchanged.
 /// Fail if an unbound type variable is encountered.
-pub fn transform_one(rt: RichTerm) -> Result<RichTerm, UnboundTypeVariableError> {
+pub fn transform_one(
+    rt: RichTerm,
+    checked_annots: Option<&CheckedAnnots>,
+) -> Result<RichTerm, UnboundTypeVariableError> {
     let pos = rt.pos;
     let result = match_sharedterm! {rt.term,
         with {
@@ -29,6 +38,9 @@ pub fn transform_one(rt: RichTerm) -> Result<RichTerm, UnboundTypeVariableError>
                 let ctrs = meta
                     .types
                     .iter()
+                    .filter(|ctr| {
+                        !checked_annots.is_some_and(|checked| checked.contains(&ctr.label.span))
+                    })
                     .chain(meta.contracts.iter())
                     .map(|ctr| Ok(PendingContract::new(ctr.types.contract()?, ctr.label.clone())))
                     .collect::<Result<Vec<_>, _>>()?;
@@ -83,13 +83,13 @@ fn collect_free_vars(rt: &mut RichTerm, free_vars: &mut HashSet<Ident>) {
             collect_free_vars(t1, free_vars);
             collect_free_vars(t2, free_vars);
         }
-        Term::Switch(t, cases, default) => {
+        Term::Switch(t, cases, default, _) => {
             collect_free_vars(t, free_vars);
             for t in cases.values_mut().chain(default.iter_mut()) {
                 collect_free_vars(t, free_vars);
             }
         }
-        Term::Op1(_, t) => collect_free_vars(t, free_vars),
+        Term::Op1(_, t) | Term::EnumVariant(_, t) => collect_free_vars(t, free_vars),
         Term::Op2(_, t1, t2) => {
             collect_free_vars(t1, free_vars);
             collect_free_vars(t2, free_vars);
@@ -180,11 +180,13 @@ fn collect_type_free_vars(ty: &mut Types, set: &mut HashSet<Ident>) {
         | AbsType::RowEmpty()
         | AbsType::Wildcard(_) => (),
         AbsType::Forall(_, ty)
+        | AbsType::Rec(_, ty)
         | AbsType::Enum(ty)
         | AbsType::StaticRecord(ty)
         | AbsType::DynRecord(ty)
+        | AbsType::Optional(ty)
         | AbsType::Array(ty) => collect_type_free_vars(ty.as_mut(), set),
-        AbsType::Arrow(ty1, ty2) => {
+        AbsType::Arrow(ty1, ty2) | AbsType::Union(ty1, ty2) | AbsType::Intersection(ty1, ty2) => {
             collect_type_free_vars(ty1.as_mut(), set);
             collect_type_free_vars(ty2.as_mut(), set);
         }
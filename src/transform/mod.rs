@@ -4,7 +4,7 @@ use crate::{
     eval::{lazy::Thunk, Closure, Environment, IdentKind},
     identifier::Ident,
     term::{Contract, RichTerm, Term, TraverseOrder},
-    typecheck::Wildcards,
+    typecheck::{CheckedAnnots, Wildcards},
     types::{AbsType, Types, UnboundTypeVariableError},
 };
 
@@ -16,6 +16,7 @@ pub mod apply_contracts;
 pub mod desugar_destructuring;
 pub mod free_vars;
 pub mod import_resolution;
+pub mod partial_eval;
 pub mod share_normal_form;
 pub mod substitute_wildcards;
 
@@ -25,18 +26,38 @@ pub mod substitute_wildcards;
 /// Do not perform transformations on the imported files. If needed, either do it yourself using
 /// pending imports returned by [`resolve_imports`][import_resolution::resolve_imports] or use the
 /// [cache][crate::cache::Cache].
+///
+/// `wildcards` and `checked_annots` are the results of typechecking `rt`, if it has been
+/// typechecked.
 pub fn transform(
-    mut rt: RichTerm,
+    rt: RichTerm,
     wildcards: Option<&Wildcards>,
+    checked_annots: Option<&CheckedAnnots>,
 ) -> Result<RichTerm, UnboundTypeVariableError> {
+    transform_shadowing(rt, std::iter::empty(), wildcards, checked_annots)
+}
+
+/// Same as [`transform`], for a term evaluated in an environment where `shadowed` are bound in
+/// addition to the standard library. See [`partial_eval::transform_shadowing`].
+pub fn transform_shadowing<'a, I>(
+    rt: RichTerm,
+    shadowed: I,
+    wildcards: Option<&Wildcards>,
+    checked_annots: Option<&CheckedAnnots>,
+) -> Result<RichTerm, UnboundTypeVariableError>
+where
+    I: IntoIterator<Item = &'a Ident>,
+{
+    let mut rt = partial_eval::transform_shadowing(rt, shadowed);
     free_vars::transform(&mut rt);
-    transform_no_free_vars(rt, wildcards)
+    transform_no_free_vars(rt, wildcards, checked_annots)
 }
 
 /// Same as [`transform`], but doesn't apply the free vars transformation.
 pub fn transform_no_free_vars(
     rt: RichTerm,
     wildcards: Option<&Wildcards>,
+    checked_annots: Option<&CheckedAnnots>,
 ) -> Result<RichTerm, UnboundTypeVariableError> {
     let rt = rt.traverse(
         &mut |mut rt: RichTerm, _| -> Result<RichTerm, UnboundTypeVariableError> {
@@ -47,7 +68,7 @@ pub fn transform_no_free_vars(
             // before anything, we have to desugar the syntax
             let rt = desugar_destructuring::transform_one(rt);
             // We need to do contract generation before wrapping stuff in variables
-            let rt = apply_contracts::transform_one(rt)?;
+            let rt = apply_contracts::transform_one(rt, checked_annots)?;
             Ok(rt)
         },
         &mut (),
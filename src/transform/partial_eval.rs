@@ -0,0 +1,451 @@
+//! Partial evaluation of the statically known parts of a program.
+//!
+//! This pass performs a few simple reductions ahead of evaluation, which don't change the
+//! semantics of the program but save work at runtime, in particular for generated configurations
+//! full of constants:
+//!
+//! - Primitive operations whose arguments are constants are folded: `1 + 2` becomes `3` and `"a" ++
+//!   "b"` becomes `"ab"`. An operation which would fail at runtime, such as `1 / 0` or `1 + "a"`,
+//!   is left untouched, so that the error is reported exactly as before.
+//! - Variables bound by a `let` to a constant, that is a literal or a record whose fields are all
+//!   constants, are inlined.
+//! - Static field accesses on constant records, conditionals on a boolean literal and switches on
+//!   an enum tag literal are resolved.
+//! - String literals without interpolation are turned into plain strings.
+//! - The partial applications of the regex primitive operators `%str_is_match%` and `%str_match%`
+//!   to a literal pattern are replaced by a function embedding the compiled regex, which is then
+//!   compiled only once, at transformation time. So is the partial application of the function
+//!   `string.is_match` of the standard library, as long as `string` isn't rebound. The compiled
+//!   function still checks that its argument is a string, and blames the caller as the contract of
+//!   `string.is_match` would. `string.match` is left alone.
+//!
+//! The pass is run after import resolution and typechecking, before the other program
+//! transformations. The `let` bindings of inlined variables are kept, as they may still be
+//! referenced from parts of the term which aren't rewritten, such as the default values of
+//! destructuring patterns.
+use super::fresh_var;
+use crate::{
+    identifier::Ident,
+    label::{ty_path, Label},
+    position::TermPos,
+    term::{
+        make as mk_term, BinaryOp, CompiledRegex, RichTerm, SharedTerm, StrChunk, Term, UnaryOp,
+    },
+    types::{AbsType, Types},
+};
+
+use std::{borrow::Cow, collections::HashMap, iter, rc::Rc};
+
+/// The name of the string module of the standard library.
+const STRING_MODULE: &str = "string";
+
+/// The variables in scope.
+#[derive(Clone, Debug)]
+struct Scope {
+    /// A variable bound to a constant is mapped to this constant, while a local binding which
+    /// shadows a constant is mapped to `None`.
+    constants: HashMap<Ident, Option<RichTerm>>,
+    /// Whether `string` still refers to the string module of the standard library.
+    string_module: bool,
+}
+
+/// Apply the partial evaluation to a whole term.
+pub fn transform(rt: RichTerm) -> RichTerm {
+    transform_shadowing(rt, iter::empty())
+}
+
+/// Same as [`transform`], for a term evaluated in an environment where `shadowed` are bound in
+/// addition to the standard library, such as the toplevel declarations of the REPL.
+pub fn transform_shadowing<'a, I>(mut rt: RichTerm, shadowed: I) -> RichTerm
+where
+    I: IntoIterator<Item = &'a Ident>,
+{
+    let scope = Scope {
+        constants: HashMap::new(),
+        string_module: true,
+    };
+    fold(&mut rt, &shadow(&scope, shadowed));
+    rt
+}
+
+/// Partially evaluate the subterms of a term, and then try to reduce the term itself.
+fn fold(rt: &mut RichTerm, scope: &Scope) {
+    match SharedTerm::make_mut(&mut rt.term) {
+        Term::ParseError(_)
+        | Term::Null
+        | Term::Bool(_)
+        | Term::Num(_)
+        | Term::Str(_)
+        | Term::Lbl(_)
+        | Term::SealingKey(_)
+        | Term::Enum(_)
+        | Term::Var(_)
+        | Term::Import(_)
+        | Term::ResolvedImport(_) => (),
+        Term::Fun(id, t) => fold(t, &shadow(scope, iter::once(&*id))),
+        Term::FunPattern(id, dest_pat, t) => {
+            let scope = shadow(scope, id.iter().chain(dest_pat.bound_idents()));
+            fold(t, &scope);
+        }
+        Term::Let(id, t1, t2, attrs) => {
+            if attrs.rec {
+                fold(t1, &shadow(scope, iter::once(&*id)));
+            } else {
+                fold(t1, scope);
+            }
+
+            if is_constant(t1.as_ref()) {
+                let mut scope = shadow(scope, iter::once(&*id)).into_owned();
+                scope.constants.insert(id.clone(), Some(t1.clone()));
+                fold(t2, &scope);
+            } else {
+                fold(t2, &shadow(scope, iter::once(&*id)));
+            }
+        }
+        Term::LetPattern(id, dest_pat, t1, t2) => {
+            fold(t1, scope);
+            let scope = shadow(scope, id.iter().chain(dest_pat.bound_idents()));
+            fold(t2, &scope);
+        }
+        Term::App(t1, t2) => {
+            fold(t1, scope);
+            fold(t2, scope);
+        }
+        Term::Switch(t, cases, default, _) => {
+            fold(t, scope);
+            for t in cases.values_mut().chain(default.iter_mut()) {
+                fold(t, scope);
+            }
+        }
+        Term::Op1(_, t) | Term::EnumVariant(_, t) => fold(t, scope),
+        Term::Op2(_, t1, t2) => {
+            fold(t1, scope);
+            fold(t2, scope);
+        }
+        Term::OpN(_, ts) => {
+            for t in ts {
+                fold(t, scope);
+            }
+        }
+        Term::Sealed(_, t, _) => fold(t, scope),
+        Term::Record(map, _) => {
+            for t in map.values_mut() {
+                fold(t, scope);
+            }
+        }
+        Term::RecRecord(map, dyn_fields, ..) => {
+            let rec_scope = shadow(scope, map.keys());
+
+            for t in map.values_mut() {
+                fold(t, &rec_scope);
+            }
+            // As for free variables, the identifier part of a dynamic definition is not
+            // recursive: it is evaluated in the outer scope.
+            for (t1, t2) in dyn_fields.iter_mut() {
+                fold(t1, scope);
+                fold(t2, &rec_scope);
+            }
+        }
+        Term::Array(ts, _) => {
+            for t in ts {
+                fold(t, scope);
+            }
+        }
+        Term::StrChunks(chunks) => {
+            for chunk in chunks {
+                if let StrChunk::Expr(t, _) = chunk {
+                    fold(t, scope);
+                }
+            }
+        }
+        Term::MetaValue(meta) => {
+            for ctr in meta.contracts.iter_mut().chain(meta.types.iter_mut()) {
+                fold_type(&mut ctr.types, scope);
+            }
+
+            if let Some(ref mut t) = meta.value {
+                fold(t, scope);
+            }
+        }
+    }
+
+    if let Some(folded) = fold_one(rt, scope) {
+        *rt = folded;
+    }
+}
+
+/// Partially evaluate the terms appearing inside a type (custom contracts).
+fn fold_type(ty: &mut Types, scope: &Scope) {
+    match &mut ty.0 {
+        AbsType::Dyn()
+        | AbsType::Num()
+        | AbsType::Bool()
+        | AbsType::Str()
+        | AbsType::Sym()
+        | AbsType::Var(_)
+        | AbsType::RowEmpty()
+        | AbsType::Wildcard(_) => (),
+        AbsType::Forall(_, ty)
+        | AbsType::Rec(_, ty)
+        | AbsType::Enum(ty)
+        | AbsType::StaticRecord(ty)
+        | AbsType::DynRecord(ty)
+        | AbsType::Optional(ty)
+        | AbsType::Array(ty) => fold_type(ty.as_mut(), scope),
+        AbsType::Arrow(ty1, ty2) | AbsType::Union(ty1, ty2) | AbsType::Intersection(ty1, ty2) => {
+            fold_type(ty1.as_mut(), scope);
+            fold_type(ty2.as_mut(), scope);
+        }
+        AbsType::RowExtend(_, ty_opt, tail) => {
+            if let Some(ref mut ty) = ty_opt {
+                fold_type(ty, scope);
+            }
+            fold_type(tail.as_mut(), scope);
+        }
+        AbsType::Flat(ref mut rt) => fold(rt, scope),
+    }
+}
+
+/// Return a scope where the given identifiers are shadowed. The original scope is only copied if
+/// one of the identifiers actually shadows something.
+fn shadow<'a, 'b, I>(scope: &'a Scope, ids: I) -> Cow<'a, Scope>
+where
+    I: IntoIterator<Item = &'b Ident>,
+{
+    let mut scope = Cow::Borrowed(scope);
+
+    for id in ids {
+        if matches!(scope.constants.get(id), Some(Some(_))) {
+            scope.to_mut().constants.insert(id.clone(), None);
+        }
+
+        if scope.string_module && id.label == STRING_MODULE {
+            scope.to_mut().string_module = false;
+        }
+    }
+
+    scope
+}
+
+/// Determine if a term is a constant that can be freely duplicated: a literal, or a record without
+/// dynamic fields whose fields are all constants.
+fn is_constant(t: &Term) -> bool {
+    match t {
+        Term::Null | Term::Bool(_) | Term::Num(_) | Term::Str(_) | Term::Enum(_) => true,
+        Term::Record(map, _) => map.values().all(|t| is_constant(t.as_ref())),
+        Term::RecRecord(map, dyn_fields, ..) => {
+            dyn_fields.is_empty() && map.values().all(|t| is_constant(t.as_ref()))
+        }
+        _ => false,
+    }
+}
+
+/// Try to reduce the top-level node of a term whose subterms have already been partially
+/// evaluated. Return `None` if the term can't be reduced.
+fn fold_one(rt: &RichTerm, scope: &Scope) -> Option<RichTerm> {
+    let pos = rt.pos;
+
+    match rt.as_ref() {
+        // The inlined constant keeps its own position, which is where it is defined.
+        Term::Var(id) => scope.constants.get(id).cloned().flatten(),
+        Term::StrChunks(chunks) => {
+            // Chunks are stored in reverse order
+            let literal: Option<String> = chunks
+                .iter()
+                .rev()
+                .map(|chunk| match chunk {
+                    StrChunk::Literal(s) => Some(s.as_str()),
+                    StrChunk::Expr(..) => None,
+                })
+                .collect();
+            literal.map(|s| RichTerm::new(Term::Str(s), pos))
+        }
+        Term::Op1(op, t) => fold_op1(op, t, pos),
+        Term::Op2(op, t1, t2) => fold_op2(op, t1.as_ref(), t2.as_ref(), pos),
+        Term::App(t1, t2) => fold_app(t1, t2, scope, pos),
+        Term::Switch(t, cases, default, variants) => match t.as_ref() {
+            Term::Enum(id) => cases
+                .get(id)
+                .filter(|_| !variants.contains(id))
+                .or(default.as_ref())
+                .cloned(),
+            Term::EnumVariant(id, arg) => match cases.get(id) {
+                Some(body) if variants.contains(id) => {
+                    Some(RichTerm::new(Term::App(body.clone(), arg.clone()), pos))
+                }
+                _ => default.clone(),
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Try to fold the application of a unary operator.
+fn fold_op1(op: &UnaryOp, arg: &RichTerm, pos: TermPos) -> Option<RichTerm> {
+    let result = match (op, arg.as_ref()) {
+        (UnaryOp::BoolNot(), Term::Bool(b)) => Term::Bool(!b),
+        (UnaryOp::Typeof(), t) if is_constant(t) => {
+            let tag = match t {
+                Term::Num(_) => "Num",
+                Term::Bool(_) => "Bool",
+                Term::Str(_) => "Str",
+                Term::Enum(_) => "Enum",
+                Term::Record(..) | Term::RecRecord(..) => "Record",
+                _ => "Other",
+            };
+            Term::Enum(Ident::from(tag))
+        }
+        (UnaryOp::StaticAccess(id), t @ (Term::Record(map, _) | Term::RecRecord(map, ..)))
+            if is_constant(t) =>
+        {
+            return map.get(id).cloned();
+        }
+        (UnaryOp::StrIsMatch(), Term::Str(pattern)) => {
+            return compiled_matcher(pattern, UnaryOp::StrIsMatchCompiled, pos)
+        }
+        (UnaryOp::StrMatch(), Term::Str(pattern)) => {
+            return compiled_matcher(pattern, UnaryOp::StrMatchCompiled, pos)
+        }
+        _ => return None,
+    };
+
+    Some(RichTerm::new(result, pos))
+}
+
+/// Try to fold the application of a binary operator.
+fn fold_op2(op: &BinaryOp, fst: &Term, snd: &Term, pos: TermPos) -> Option<RichTerm> {
+    let result = match (op, fst, snd) {
+        (BinaryOp::Plus(), Term::Num(n1), Term::Num(n2)) => Term::Num(n1 + n2),
+        (BinaryOp::Sub(), Term::Num(n1), Term::Num(n2)) => Term::Num(n1 - n2),
+        (BinaryOp::Mult(), Term::Num(n1), Term::Num(n2)) => Term::Num(n1 * n2),
+        (BinaryOp::Div(), Term::Num(n1), Term::Num(n2)) if !n2.is_zero() => Term::Num(n1 / n2),
+        (BinaryOp::Modulo(), Term::Num(n1), Term::Num(n2)) if !n2.is_zero() => Term::Num(n1 % n2),
+        (BinaryOp::Pow(), Term::Num(n1), Term::Num(n2)) => Term::Num(n1.pow(n2)?),
+        (BinaryOp::StrConcat(), Term::Str(s1), Term::Str(s2)) => Term::Str(format!("{}{}", s1, s2)),
+        (BinaryOp::LessThan(), Term::Num(n1), Term::Num(n2)) => Term::Bool(n1 < n2),
+        (BinaryOp::LessOrEq(), Term::Num(n1), Term::Num(n2)) => Term::Bool(n1 <= n2),
+        (BinaryOp::GreaterThan(), Term::Num(n1), Term::Num(n2)) => Term::Bool(n1 > n2),
+        (BinaryOp::GreaterOrEq(), Term::Num(n1), Term::Num(n2)) => Term::Bool(n1 >= n2),
+        (BinaryOp::Eq(), t1, t2) => Term::Bool(literal_eq(t1, t2)?),
+        _ => return None,
+    };
+
+    Some(RichTerm::new(result, pos))
+}
+
+/// Compare two literals of the same kind. Return `None` if the terms aren't both literals of the
+/// same kind.
+fn literal_eq(t1: &Term, t2: &Term) -> Option<bool> {
+    match (t1, t2) {
+        (Term::Null, Term::Null) => Some(true),
+        (Term::Bool(b1), Term::Bool(b2)) => Some(b1 == b2),
+        (Term::Num(n1), Term::Num(n2)) => Some(n1 == n2),
+        (Term::Str(s1), Term::Str(s2)) => Some(s1 == s2),
+        (Term::Enum(id1), Term::Enum(id2)) => Some(id1 == id2),
+        _ => None,
+    }
+}
+
+/// Try to fold an application: boolean operators and conditionals with a literal first argument,
+/// and `string.is_match` applied to a literal pattern.
+fn fold_app(t1: &RichTerm, t2: &RichTerm, scope: &Scope, pos: TermPos) -> Option<RichTerm> {
+    match (t1.as_ref(), t2.as_ref()) {
+        (Term::Op1(UnaryOp::StaticAccess(f), module), Term::Str(pattern))
+            if scope.string_module
+                && f.label == "is_match"
+                && matches!(module.as_ref(), Term::Var(id) if id.label == STRING_MODULE) =>
+        {
+            compiled_is_match(pattern, pos)
+        }
+        (Term::Op1(UnaryOp::BoolAnd(), fst), _) => match fst.as_ref() {
+            Term::Bool(true) => Some(t2.clone()),
+            Term::Bool(false) => Some(RichTerm::new(Term::Bool(false), pos)),
+            _ => None,
+        },
+        (Term::Op1(UnaryOp::BoolOr(), fst), _) => match fst.as_ref() {
+            Term::Bool(true) => Some(RichTerm::new(Term::Bool(true), pos)),
+            Term::Bool(false) => Some(t2.clone()),
+            _ => None,
+        },
+        (Term::App(head, then_branch), _) => match head.as_ref() {
+            Term::Op1(UnaryOp::Ite(), cond) => match cond.as_ref() {
+                Term::Bool(true) => Some(then_branch.clone()),
+                Term::Bool(false) => Some(t2.clone()),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Compile a regex and generate the function `fun x => %op% (x | Str)`, where `op` is the compiled
+/// version of `%str_is_match%`. This is what the evaluation of `string.is_match pattern` would
+/// produce: the contract blames the caller of `string.is_match`, with the label that the contract
+/// `Str -> Str -> Bool` of the standard library would give to its second argument, but located at
+/// the application. Return `None` if the regex is invalid or if the application has no position.
+fn compiled_is_match(pattern: &str, pos: TermPos) -> Option<RichTerm> {
+    let regex = regex::Regex::new(pattern).ok()?;
+    let pos_inh = pos.into_inherited();
+    let param = fresh_var();
+
+    let str_ty = || Box::new(Types(AbsType::Str()));
+    let label = Label {
+        types: Rc::new(Types(AbsType::Arrow(
+            str_ty(),
+            Box::new(Types(AbsType::Arrow(
+                str_ty(),
+                Box::new(Types(AbsType::Bool())),
+            ))),
+        ))),
+        tag: String::new(),
+        span: pos.into_opt()?,
+        arg_thunk: None,
+        arg_pos: TermPos::None,
+        polarity: false,
+        path: vec![ty_path::Elem::Codomain, ty_path::Elem::Domain],
+    };
+    let arg = mk_term::assume(
+        Types(AbsType::Str()),
+        label,
+        RichTerm::new(Term::Var(param.clone()), pos_inh),
+    )
+    .ok()?;
+
+    Some(RichTerm::new(
+        Term::Fun(
+            param,
+            RichTerm::new(
+                Term::Op1(UnaryOp::StrIsMatchCompiled(regex.into()), arg),
+                pos_inh,
+            ),
+        ),
+        pos,
+    ))
+}
+
+/// Compile a regex and generate the function `fun x => %op% x`, where `op` is the compiled version
+/// of a regex primitive operator. This is what the evaluation of the uncompiled operator applied to
+/// `pattern` would produce. Return `None` if the regex is invalid, leaving the error to be reported
+/// at runtime.
+fn compiled_matcher(
+    pattern: &str,
+    op: fn(CompiledRegex) -> UnaryOp,
+    pos: TermPos,
+) -> Option<RichTerm> {
+    let regex = regex::Regex::new(pattern).ok()?;
+    let pos_inh = pos.into_inherited();
+    let param = fresh_var();
+
+    Some(RichTerm::new(
+        Term::Fun(
+            param.clone(),
+            RichTerm::new(
+                Term::Op1(op(regex.into()), RichTerm::new(Term::Var(param), pos_inh)),
+                pos_inh,
+            ),
+        ),
+        pos,
+    ))
+}
@@ -15,7 +15,8 @@
 //! two times here.
 //!
 //! The transformation replaces such subexpressions, namely the content of the fields
-//! of records and the elements of arrays - `(1 + 1)` in our example -, with fresh variables
+//! of records, the elements of arrays and the payloads of enum variants - `(1 + 1)` in our
+//! example -, with fresh variables
 //! introduced by `let`  added at the head of the term:
 //!
 //! ```text
@@ -158,6 +159,19 @@ pub fn transform_one(rt: RichTerm) -> RichTerm {
 
                 with_bindings(Term::Array(ts, attrs), bindings, pos)
             },
+            Term::EnumVariant(id, t) if should_share(&t.term) => {
+                let fresh_var = fresh_var();
+                let pos_t = t.pos;
+                let inner = RichTerm::new(
+                    Term::EnumVariant(id, RichTerm::new(Term::Var(fresh_var.clone()), pos_t)),
+                    pos,
+                );
+                let attrs = LetAttrs {
+                    binding_type: BindingType::Normal,
+                    rec: false,
+                };
+                RichTerm::new(Term::Let(fresh_var, t, inner, attrs), pos)
+            },
             Term::MetaValue(meta) if meta.value.as_ref().map(|t| should_share(&t.term)).unwrap_or(false) => {
                     let mut meta = meta;
                     let fresh_var = fresh_var();
@@ -161,6 +161,9 @@ fn contract_eq_bounded(
         (Num(n1), Num(n2)) => n1 == n2,
         (Str(s1), Str(s2)) => s1 == s2,
         (Enum(id1), Enum(id2)) => id1 == id2,
+        (EnumVariant(id1, arg1), EnumVariant(id2, arg2)) => {
+            id1 == id2 && contract_eq_bounded(state, arg1, env1, arg2, env2)
+        }
         (SealingKey(s1), SealingKey(s2)) => s1 == s2,
         (Sealed(key1, inner1, _), Sealed(key2, inner2, _)) => {
             key1 == key2 && contract_eq_bounded(state, inner1, env1, inner2, env2)
@@ -357,10 +360,13 @@ fn type_eq_bounded(
             | (AbsType::Sym(), AbsType::Sym())
             | (AbsType::Str(), AbsType::Str()) => true,
             (AbsType::DynRecord(tyw1), AbsType::DynRecord(tyw2))
+            | (AbsType::Optional(tyw1), AbsType::Optional(tyw2))
             | (AbsType::Array(tyw1), AbsType::Array(tyw2)) => {
                 type_eq_bounded(state, tyw1, env1, tyw2, env2)
             }
-            (AbsType::Arrow(s1, t1), AbsType::Arrow(s2, t2)) => {
+            (AbsType::Arrow(s1, t1), AbsType::Arrow(s2, t2))
+            | (AbsType::Union(s1, t1), AbsType::Union(s2, t2))
+            | (AbsType::Intersection(s1, t1), AbsType::Intersection(s2, t2)) => {
                 type_eq_bounded(state, s1, env1, s2, env2)
                     && type_eq_bounded(state, t1, env1, t2, env2)
             }
@@ -390,7 +396,8 @@ fn type_eq_bounded(
             (AbsType::Flat(t1), AbsType::Flat(t2)) => {
                 contract_eq_bounded(state, t1, env1, t2, env2)
             }
-            (AbsType::Forall(i1, tyw1), AbsType::Forall(i2, tyw2)) => {
+            (AbsType::Forall(i1, tyw1), AbsType::Forall(i2, tyw2))
+            | (AbsType::Rec(i1, tyw1), AbsType::Rec(i2, tyw2)) => {
                 let constant_type: TypeWrapper = state.fresh_cst();
 
                 type_eq_bounded(
@@ -105,6 +105,17 @@ pub trait Linearizer {
     ) {
     }
 
+    /// Refine the type of an ident in the current scope, without changing the type of its
+    /// declaration. Used when the typechecker knows a more precise type for a variable in a
+    /// specific branch, such as `x` in `if builtin.is_num x then x + 1 else 0`.
+    fn refine_ident(
+        &mut self,
+        _lin: &mut Linearization<Self::Building>,
+        _ident: &Ident,
+        _new_type: TypeWrapper,
+    ) {
+    }
+
     /// Defines how to turn a [Self::Building] Linearization of the tracked type into
     /// a [Self::Completed] linearization.
     /// By default creates an entirely empty [Self::Completed] object
@@ -46,12 +46,14 @@
 //! In non-strict mode, the type of let-bound expressions is inferred in a shallow way (see
 //! [`apparent_type`]).
 use crate::{
-    cache::ImportResolver,
+    cache::{ImportResolver, InputFormat},
     destruct::*,
     environment::Environment as GenericEnvironment,
     error::TypecheckError,
     identifier::Ident,
-    term::{Contract, MetaValue, RichTerm, StrChunk, Term, TraverseOrder},
+    position::{RawSpan, TermPos},
+    term::{BinaryOp, Contract, MetaValue, RichTerm, StrChunk, Term, TraverseOrder, UnaryOp},
+    transform::substitute_wildcards,
     types::{AbsType, RowIterator, RowIteratorItem, Types},
     {mk_tyw_arrow, mk_tyw_enum, mk_tyw_enum_row, mk_tyw_record, mk_tyw_row},
 };
@@ -59,6 +61,8 @@ use crate::{
 use std::{
     collections::{HashMap, HashSet},
     convert::TryInto,
+    path::Path,
+    rc::Rc,
 };
 
 use self::linearization::{Linearization, Linearizer, StubHost};
@@ -81,6 +85,23 @@ pub type Environment = GenericEnvironment<Ident, TypeWrapper>;
 /// Mapping from wildcard ID to inferred type
 pub type Wildcards = Vec<Types>;
 
+/// The type annotations which have been statically proven by the typechecker, identified by the
+/// span of their label. The contracts corresponding to these annotations can't ever fail, and
+/// don't need to be checked at run-time (see [`crate::transform::apply_contracts`]).
+///
+/// An annotation `exp : type` is proven if it occurs inside a statically typed block (the
+/// boundary annotation, which switches the typechecker to strict mode, is never proven, as the
+/// surrounding code is untyped) and if either:
+///
+/// - `type` is first-order, that is, it doesn't contain any arrow, polymorphism or user-defined
+///   contract. Such a contract only checks the value itself, which has been proven to be of the
+///   right type.
+/// - `type` is higher-order, but the annotation is not a record field, and the enclosing
+///   statically typed block doesn't contain any untyped part. In this case, the value can only
+///   flow to statically typed code, or go through the boundary of the block, which is protected by
+///   the contract of the boundary annotation.
+pub type CheckedAnnots = HashSet<RawSpan>;
+
 /// A structure holding the typing context, that is, scoped, environment-like data.
 #[derive(Debug, PartialEq, Clone)]
 pub struct Context {
@@ -170,6 +191,86 @@ pub struct State<'a> {
     names: &'a mut HashMap<usize, Ident>,
     /// A mapping from wildcard ID to unification variable.
     wildcard_vars: &'a mut Vec<TypeWrapper>,
+    /// The annotations proven so far.
+    checked_annots: &'a mut CheckedAnnots,
+    /// The statically typed blocks being currently checked, the innermost one being the last.
+    typed_blocks: &'a mut Vec<TypedBlock>,
+    /// The bindings whose type is reported in the [`TypeReport`].
+    reported: &'a mut ReportedBindings,
+    /// The type errors the typechecker has recovered from so far.
+    errors: &'a mut Vec<TypecheckError>,
+    /// The layer of the initial typing environment which binds the modules of the standard
+    /// library, if any. Used to recognize calls to stdlib functions, such as `builtin.is_num`,
+    /// when the module isn't shadowed.
+    stdlib: Option<&'a Rc<HashMap<Ident, TypeWrapper>>>,
+}
+
+/// The maximum number of type errors reported for a single term. Once this number is reached,
+/// typechecking is aborted.
+pub const MAX_ERRORS: usize = 20;
+
+/// Recover from the type error of a term whose expected type is `ty`, if any. The error is recorded
+/// in the state, and `ty` is unified with the error type [`TypeWrapper::Error`], so that the errors
+/// entailed by this one are not reported. The typechecker can then go on with the rest of the
+/// term.
+///
+/// Once [`MAX_ERRORS`] errors have been found, the error is returned instead.
+fn recover(
+    state: &mut State,
+    ctxt: &Context,
+    result: Result<(), TypecheckError>,
+    ty: TypeWrapper,
+) -> Result<(), TypecheckError> {
+    match result {
+        Err(err) if state.errors.len() + 1 < MAX_ERRORS => {
+            state.errors.push(err);
+            // The error type unifies with anything, so this can't fail.
+            let _ = unify(state, ctxt, ty, TypeWrapper::Error);
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// A statically typed block being checked, used to determine which annotations are proven (see
+/// [`CheckedAnnots`]).
+#[derive(Debug, Default)]
+struct TypedBlock {
+    /// The higher-order annotations found in this block. They are proven only if the block doesn't
+    /// contain any untyped part.
+    higher_order: Vec<RawSpan>,
+    /// The annotations of the record fields of this block, whose higher-order annotations are
+    /// never proven.
+    fields: HashSet<RawSpan>,
+    /// Whether the typechecker went back to walk mode at some point inside this block.
+    has_untyped: bool,
+}
+
+/// The types inferred by the typechecker which don't appear in the source, as shown by `nickel
+/// typecheck --show-types`.
+#[derive(Debug, Clone, Default)]
+pub struct TypeReport {
+    /// The types inferred for wildcards, indexed by wildcard ID.
+    pub wildcards: Wildcards,
+    /// The types of the let bindings without a type annotation inside statically typed blocks,
+    /// and of the fields of the top-level record, in the order they were encountered.
+    pub bindings: Vec<(Ident, Types)>,
+}
+
+/// The bindings collected during typechecking for the [`TypeReport`].
+#[derive(Debug, Default)]
+struct ReportedBindings {
+    /// The position of the top-level record, if the term is a record.
+    top_level: Option<RawSpan>,
+    /// The bindings, with their type at the time they were encountered.
+    bindings: Vec<(Ident, TypeWrapper)>,
+}
+
+impl ReportedBindings {
+    /// Determine if the record at position `pos` is the top-level record.
+    fn is_top_level(&self, pos: TermPos) -> bool {
+        pos.is_def() && pos.into_opt() == self.top_level
+    }
 }
 
 /// Typecheck a term.
@@ -180,14 +281,27 @@ pub struct State<'a> {
 /// Note that this function doesn't recursively typecheck imports (anymore), but just the current
 /// file. It however still needs the resolver to get the apparent type of imports.
 ///
-/// Return the type inferred for type wildcards.
+/// Return the type inferred for type wildcards, together with the annotations which have been
+/// statically proven. The typechecker doesn't stop at the first type error: if several independent
+/// errors are found, a [`TypecheckError::Multiple`] error is returned.
 pub fn type_check(
     t: &RichTerm,
     initial_env: Environment,
     resolver: &impl ImportResolver,
-) -> Result<Wildcards, TypecheckError> {
+) -> Result<(Wildcards, CheckedAnnots), TypecheckError> {
     type_check_linearize(t, initial_env, resolver, StubHost::<(), (), _>::new())
-        .map(|(wildcards, _)| wildcards)
+        .map(|(wildcards, checked_annots, _)| (wildcards, checked_annots))
+}
+
+/// Typecheck a term and return the types inferred for wildcards, let bindings and top-level
+/// fields. See [`TypeReport`].
+pub fn type_report(
+    t: &RichTerm,
+    initial_env: Environment,
+    resolver: &impl ImportResolver,
+) -> Result<TypeReport, TypecheckError> {
+    type_check_impl(t, initial_env, resolver, StubHost::<(), (), _>::new())
+        .map(|(_, report, _)| report)
 }
 
 /// Typecheck a term and build its linearization. A linearization is a sequential data structure
@@ -196,25 +310,53 @@ pub fn type_check(
 ///
 /// Linearization is solely used by the LSP server.
 pub fn type_check_linearize<LL>(
+    t: &RichTerm,
+    initial_env: Environment,
+    resolver: &impl ImportResolver,
+    linearizer: LL,
+) -> Result<(Wildcards, CheckedAnnots, LL::Completed), TypecheckError>
+where
+    LL: Linearizer<CompletionExtra = (UnifTable, HashMap<usize, Ident>)>,
+{
+    type_check_impl(t, initial_env, resolver, linearizer)
+        .map(|(checked_annots, report, lin)| (report.wildcards, checked_annots, lin))
+}
+
+fn type_check_impl<LL>(
     t: &RichTerm,
     initial_env: Environment,
     resolver: &impl ImportResolver,
     mut linearizer: LL,
-) -> Result<(Wildcards, LL::Completed), TypecheckError>
+) -> Result<(CheckedAnnots, TypeReport, LL::Completed), TypecheckError>
 where
     LL: Linearizer<CompletionExtra = (UnifTable, HashMap<usize, Ident>)>,
 {
     let (mut table, mut names) = (UnifTable::new(), HashMap::new());
     let mut building = Linearization::new(LL::Building::default());
     let mut wildcard_vars = Vec::new();
-
-    {
+    let mut checked_annots = CheckedAnnots::new();
+    let mut reported = ReportedBindings {
+        top_level: top_level_record(t).and_then(|rt| rt.pos.into_opt()),
+        bindings: Vec::new(),
+    };
+    let mut errors = Vec::new();
+    // The standard library modules are bound in the oldest layer of the initial environment.
+    // Cloning the environment freezes its current layer, so that the bindings of the term are
+    // always added to new layers.
+    let stdlib = initial_env.clone().iter_layers().last();
+
+    let result = {
         let mut state: State = State {
             resolver,
             table: &mut table,
             constr: &mut RowConstr::new(),
             names: &mut names,
             wildcard_vars: &mut wildcard_vars,
+            checked_annots: &mut checked_annots,
+            typed_blocks: &mut Vec::new(),
+            reported: &mut reported,
+            errors: &mut errors,
+            stdlib: stdlib.as_ref(),
         };
 
         walk(
@@ -226,13 +368,43 @@ where
             &mut building,
             linearizer.scope(),
             t,
-        )?;
+        )
+    };
+
+    errors.extend(result.err());
+
+    match errors.len() {
+        0 => (),
+        1 => return Err(errors.pop().unwrap()),
+        _ => return Err(TypecheckError::Multiple(errors)),
     }
 
-    let result = wildcard_vars_to_type(wildcard_vars, &table);
+    let mut name_reg = reporting::NameReg::new();
+    let report = TypeReport {
+        wildcards: wildcard_vars_to_type(wildcard_vars, &table),
+        bindings: reported
+            .bindings
+            .into_iter()
+            .map(|(id, tyw)| {
+                let ty = reporting::to_type(&table, &names, &mut name_reg, tyw);
+                (id, ty)
+            })
+            .collect(),
+    };
     let lin = linearizer.complete(building, (table, names)).into_inner();
 
-    Ok((result, lin))
+    Ok((checked_annots, report, lin))
+}
+
+/// Return the top-level record of a term, ignoring the metadata and the let bindings around it,
+/// if any.
+fn top_level_record(rt: &RichTerm) -> Option<&RichTerm> {
+    match rt.as_ref() {
+        Term::Record(..) | Term::RecRecord(..) => Some(rt),
+        Term::MetaValue(MetaValue { value: Some(v), .. }) => top_level_record(v),
+        Term::Let(_, _, body, _) | Term::LetPattern(_, _, _, body) => top_level_record(body),
+        _ => None,
+    }
 }
 
 /// Walk the AST of a term looking for statically typed block to check. Fill the linearization
@@ -255,6 +427,11 @@ fn walk<L: Linearizer>(
         ),
     );
 
+    // Walking a term inside a statically typed block means that this block has an untyped part.
+    if let Some(block) = state.typed_blocks.last_mut() {
+        block.has_untyped = true;
+    }
+
     match t.as_ref() {
         Term::ParseError(_)
         | Term::Null
@@ -268,10 +445,13 @@ fn walk<L: Linearizer>(
         // caller.
         | Term::Import(_)
         | Term::ResolvedImport(_) => Ok(()),
-        Term::Var(x) => ctxt.type_env
-            .get(x)
-            .ok_or_else(|| TypecheckError::UnboundIdentifier(x.clone(), *pos))
-            .map(|_| ()),
+        Term::Var(x) => {
+            let result = ctxt.type_env
+                .get(x)
+                .ok_or_else(|| TypecheckError::UnboundIdentifier(x.clone(), *pos))
+                .map(|_| ());
+            recover(state, &ctxt, result, mk_typewrapper::dynamic())
+        }
         Term::StrChunks(chunks) => {
             chunks
                 .iter()
@@ -340,11 +520,42 @@ fn walk<L: Linearizer>(
 
             walk(state, ctxt, lin, linearizer, rt)
         }
+        // If the condition of an `if` tests the type of a variable, its type is refined in the
+        // branches.
+        Term::App(e, t_else) if as_ite(e).is_some() => {
+            let (cond, t_then) = as_ite(e).unwrap();
+            let cond_linearizer = linearizer.scope();
+            let mut then_linearizer = linearizer.scope();
+            let (then_ctxt, else_ctxt) = match ite_refinement(state, &ctxt, cond.as_ref()) {
+                Some(refinement) => (
+                    refine_ctxt(
+                        &ctxt,
+                        lin,
+                        &mut then_linearizer,
+                        &refinement.var,
+                        refinement.then_ty,
+                    ),
+                    refine_ctxt(
+                        &ctxt,
+                        lin,
+                        &mut linearizer,
+                        &refinement.var,
+                        refinement.else_ty,
+                    ),
+                ),
+                None => (ctxt.clone(), ctxt.clone()),
+            };
+
+            walk(state, ctxt, lin, cond_linearizer, cond)?;
+            walk(state, then_ctxt, lin, then_linearizer, t_then)?;
+            walk(state, else_ctxt, lin, linearizer, t_else)
+        }
         Term::App(e, t) => {
             walk(state, ctxt.clone(), lin, linearizer.scope(), e)?;
             walk(state, ctxt, lin, linearizer, t)
         }
-        Term::Switch(exp, cases, default) => {
+        Term::EnumVariant(_, t) => walk(state, ctxt, lin, linearizer, t),
+        Term::Switch(exp, cases, default, _) => {
             cases.values().chain(default.iter()).try_for_each(|case| {
                 walk(state, ctxt.clone(), lin, linearizer.scope(), case)
             })?;
@@ -352,6 +563,8 @@ fn walk<L: Linearizer>(
             walk(state, ctxt, lin, linearizer, exp)
         }
         Term::RecRecord(stat_map, dynamic, ..) => {
+            let top_level = state.reported.is_top_level(*pos);
+
             for (id, field) in stat_map.iter() {
                 let binding_type = binding_type(
                     state,
@@ -360,6 +573,12 @@ fn walk<L: Linearizer>(
                     false,
                 );
                 ctxt.type_env.insert(id.clone(), binding_type.clone());
+
+                if top_level {
+                    let ty = reported_type(state, field.as_ref(), &ctxt, binding_type.clone());
+                    state.reported.bindings.push((id.clone(), ty));
+                }
+
                 linearizer.retype_ident(lin, id, binding_type);
             }
 
@@ -375,6 +594,14 @@ fn walk<L: Linearizer>(
                 })
         }
         Term::Record(stat_map, _) => {
+            if state.reported.is_top_level(*pos) {
+                for (id, field) in stat_map.iter() {
+                    let binding_type = binding_type(state, field.as_ref(), &ctxt, false);
+                    let ty = reported_type(state, field.as_ref(), &ctxt, binding_type);
+                    state.reported.bindings.push((id.clone(), ty));
+                }
+            }
+
             stat_map
                 .iter()
                 .try_for_each(|(_, t)| -> Result<(), TypecheckError> {
@@ -410,7 +637,17 @@ fn walk<L: Linearizer>(
                 } => {
                     let tyw2 = TypeWrapper::from_type(ty2.clone(), &ctxt.term_env);
                     let instantiated = instantiate_foralls(state, tyw2, ForallInst::Constant);
-                    type_check_(state, ctxt, lin, linearizer, t, instantiated)
+
+                    let errors_before = state.errors.len();
+                    state.typed_blocks.push(TypedBlock::default());
+                    let result = type_check_(state, ctxt.clone(), lin, linearizer, t, instantiated.clone());
+                    let block = state.typed_blocks.pop().unwrap();
+
+                    if result.is_ok() && state.errors.len() == errors_before && !block.has_untyped {
+                        state.checked_annots.extend(block.higher_order);
+                    }
+
+                    recover(state, &ctxt, result, instantiated)
                 }
                 MetaValue {value: Some(t), .. } =>  walk(state, ctxt, lin, linearizer, t),
                 // A metavalue without a body nor a type annotation is a record field without definition.
@@ -444,7 +681,9 @@ fn walk_type<L: Linearizer>(
        | AbsType::Var(_)
        | AbsType::Wildcard(_)
        | AbsType::RowEmpty() => Ok(()),
-       AbsType::Arrow(ty1, ty2) => {
+       AbsType::Arrow(ty1, ty2)
+       | AbsType::Union(ty1, ty2)
+       | AbsType::Intersection(ty1, ty2) => {
            walk_type(state, ctxt.clone(), lin, linearizer.scope(), ty1.as_ref())?;
            walk_type(state, ctxt, lin, linearizer, ty2.as_ref())
        }
@@ -454,10 +693,12 @@ fn walk_type<L: Linearizer>(
        }
        AbsType::Flat(t) => walk(state, ctxt, lin, linearizer, t),
        AbsType::Enum(ty2)
+       | AbsType::Optional(ty2)
        | AbsType::DynRecord(ty2)
        | AbsType::StaticRecord(ty2)
        | AbsType::Array(ty2)
-       | AbsType::Forall(_, ty2) => walk_type(state, ctxt, lin, linearizer, ty2),
+       | AbsType::Forall(_, ty2)
+       | AbsType::Rec(_, ty2) => walk_type(state, ctxt, lin, linearizer, ty2),
     }
 }
 
@@ -481,6 +722,368 @@ fn inject_pat_vars(pat: &Destruct, env: &mut Environment) {
     }
 }
 
+/// Return the condition and the `then` branch of an `if`, given the function part `e` of the
+/// application `if cond then t1 else t2`, which is represented as `%ite% cond t1 t2`.
+fn as_ite(e: &RichTerm) -> Option<(&RichTerm, &RichTerm)> {
+    match e.as_ref() {
+        Term::App(f, t1) => match f.as_ref() {
+            Term::Op1(UnaryOp::Ite(), cond) => Some((cond, t1)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Return the variable `x` if `t` is a call to the `typeof` primitive operation or function on
+/// `x`, that is `%typeof% x` or `builtin.typeof x`.
+fn typeof_var<'a>(state: &State, ctxt: &Context, t: &'a Term) -> Option<&'a Ident> {
+    match t {
+        Term::Op1(UnaryOp::Typeof(), arg) => match arg.as_ref() {
+            Term::Var(x) => Some(x),
+            _ => None,
+        },
+        Term::App(f, arg) => match (
+            stdlib_function(state, ctxt, f.as_ref(), "builtin"),
+            arg.as_ref(),
+        ) {
+            (Some("typeof"), Term::Var(x)) => Some(x),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Return the name of the function if `t` is an access `module.f` to a function of the given
+/// module of the stdlib, such as `builtin.is_num`. The module must not be shadowed, that is
+/// `module` must be bound by the standard library layer of the typing environment.
+fn stdlib_function<'a>(
+    state: &State,
+    ctxt: &Context,
+    t: &'a Term,
+    module: &str,
+) -> Option<&'a str> {
+    match t {
+        Term::Op1(UnaryOp::StaticAccess(f), record) => match record.as_ref() {
+            Term::Var(id) if id.label == module && is_stdlib_module(state, ctxt, id) => {
+                Some(f.label.as_str())
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Determine if the variable `id` refers to a module of the standard library, that is if the
+/// innermost binding of `id` in the typing environment is the one of the standard library.
+fn is_stdlib_module(state: &State, ctxt: &Context, id: &Ident) -> bool {
+    let binding = ctxt
+        .type_env
+        .iter_layers()
+        .find(|layer| layer.contains_key(id));
+
+    match (binding, state.stdlib) {
+        (Some(binding), Some(stdlib)) => Rc::ptr_eq(&binding, stdlib),
+        _ => false,
+    }
+}
+
+/// Recognize a test on the type of a variable, that is either a call to a `builtin.is_xxx`
+/// predicate such as `builtin.is_num x`, or a comparison of the result of `typeof` with a tag
+/// such as `builtin.typeof x == `Num`. Return the variable and the tested tag, as returned by
+/// `typeof`.
+fn type_test(state: &State, ctxt: &Context, t: &Term) -> Option<(Ident, Ident)> {
+    match t {
+        Term::App(f, arg) => {
+            let tag = match stdlib_function(state, ctxt, f.as_ref(), "builtin")? {
+                "is_num" => "Num",
+                "is_bool" => "Bool",
+                "is_str" => "Str",
+                "is_enum" => "Enum",
+                "is_fun" => "Fun",
+                "is_array" => "Array",
+                "is_record" => "Record",
+                _ => return None,
+            };
+
+            match arg.as_ref() {
+                Term::Var(x) => Some((x.clone(), Ident::from(tag))),
+                _ => None,
+            }
+        }
+        Term::Op2(BinaryOp::Eq(), t1, t2) => match (t1.as_ref(), t2.as_ref()) {
+            (t, Term::Enum(tag)) | (Term::Enum(tag), t) => {
+                typeof_var(state, ctxt, t).map(|x| (x.clone(), tag.clone()))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// An operation inserting or removing a statically known field of a record.
+enum RecordOp<'a> {
+    /// Insert a field with the given value in a record: `record.insert "foo" value r` or
+    /// `%record_insert% "foo" r value`.
+    Insert(
+        Ident,
+        /* record */ &'a RichTerm,
+        /* value */ &'a RichTerm,
+    ),
+    /// Remove a field from a record: `record.remove "foo" r` or `%record_remove% "foo" r`.
+    Remove(Ident, /* record */ &'a RichTerm),
+}
+
+/// Return the field name denoted by a string literal without interpolation, if `t` is one.
+fn static_field(t: &Term) -> Option<Ident> {
+    match t {
+        Term::Str(s) => Some(Ident::from(s.as_str())),
+        Term::StrChunks(chunks) => match chunks.as_slice() {
+            [StrChunk::Literal(s)] => Some(Ident::from(s.as_str())),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Recognize the insertion or the removal of a statically known field, either through the
+/// functions of the `record` module of the stdlib or through the corresponding primitive
+/// operations.
+fn record_op<'a>(state: &State, ctxt: &Context, t: &'a Term) -> Option<RecordOp<'a>> {
+    let is_record_function =
+        |t: &Term, name: &str| stdlib_function(state, ctxt, t, "record") == Some(name);
+
+    match t {
+        Term::App(f, arg) => match f.as_ref() {
+            Term::App(g, field) if is_record_function(g.as_ref(), "remove") => {
+                Some(RecordOp::Remove(static_field(field.as_ref())?, arg))
+            }
+            Term::App(g, value) => match g.as_ref() {
+                Term::App(h, field) if is_record_function(h.as_ref(), "insert") => {
+                    Some(RecordOp::Insert(static_field(field.as_ref())?, arg, value))
+                }
+                _ => None,
+            },
+            Term::Op2(BinaryOp::DynExtend(), field, r) => {
+                Some(RecordOp::Insert(static_field(field.as_ref())?, r, arg))
+            }
+            _ => None,
+        },
+        Term::Op2(BinaryOp::DynRemove(), field, r) => {
+            Some(RecordOp::Remove(static_field(field.as_ref())?, r))
+        }
+        _ => None,
+    }
+}
+
+/// The type of the values for which `typeof` returns `tag`, if it can be expressed as a type.
+fn tag_type(tag: &Ident) -> Option<TypeWrapper> {
+    match tag.label.as_str() {
+        "Num" => Some(mk_typewrapper::num()),
+        "Bool" => Some(mk_typewrapper::bool()),
+        "Str" => Some(mk_typewrapper::str()),
+        "Fun" => Some(mk_tyw_arrow!(AbsType::Dyn(), AbsType::Dyn())),
+        "Array" => Some(mk_typewrapper::array(AbsType::Dyn())),
+        "Record" => Some(mk_typewrapper::dyn_record(AbsType::Dyn())),
+        _ => None,
+    }
+}
+
+/// The tag returned by `typeof` on the values of a type, if it is determined by the type.
+fn type_tag(ty: &TypeWrapper) -> Option<&'static str> {
+    match ty {
+        TypeWrapper::Concrete(AbsType::Num()) => Some("Num"),
+        TypeWrapper::Concrete(AbsType::Bool()) => Some("Bool"),
+        TypeWrapper::Concrete(AbsType::Str()) => Some("Str"),
+        TypeWrapper::Concrete(AbsType::Enum(_)) => Some("Enum"),
+        TypeWrapper::Concrete(AbsType::Arrow(..)) => Some("Fun"),
+        TypeWrapper::Concrete(AbsType::Array(_)) => Some("Array"),
+        TypeWrapper::Concrete(AbsType::StaticRecord(_) | AbsType::DynRecord(_)) => Some("Record"),
+        _ => None,
+    }
+}
+
+/// Refine the type `ty` of a variable according to a test on its type (occurrence typing).
+/// Return the type of the variable when `typeof` returns one of `tags`, and its type otherwise.
+/// `None` means that the type can't be refined.
+///
+/// A variable of type `Dyn` is given the type corresponding to the tag, if there's a single one. A
+/// variable of a union type is given the union of the members with a matching tag, and the
+/// union of the other members otherwise.
+fn refine(
+    table: &UnifTable,
+    ty: TypeWrapper,
+    tags: &[&Ident],
+) -> (Option<TypeWrapper>, Option<TypeWrapper>) {
+    fn members(table: &UnifTable, ty: TypeWrapper, acc: &mut Vec<TypeWrapper>) {
+        match ty {
+            TypeWrapper::Ptr(p) => members(table, table.root(p), acc),
+            TypeWrapper::Concrete(AbsType::Union(ty1, ty2)) => {
+                members(table, *ty1, acc);
+                members(table, *ty2, acc);
+            }
+            ty => acc.push(ty),
+        }
+    }
+
+    fn mk_union(tys: Vec<TypeWrapper>) -> Option<TypeWrapper> {
+        tys.into_iter()
+            .rev()
+            .reduce(|acc, ty| TypeWrapper::Concrete(AbsType::Union(Box::new(ty), Box::new(acc))))
+    }
+
+    let ty = match ty {
+        TypeWrapper::Ptr(p) => table.root(p),
+        ty => ty,
+    };
+
+    match ty {
+        TypeWrapper::Concrete(AbsType::Dyn()) => match tags {
+            [tag] => (tag_type(tag), None),
+            _ => (None, None),
+        },
+        ty @ TypeWrapper::Concrete(AbsType::Union(..)) => {
+            let mut tys = Vec::new();
+            members(table, ty, &mut tys);
+            let (matching, others): (Vec<_>, Vec<_>) = tys.into_iter().partition(|ty| {
+                type_tag(ty).map_or(false, |tag| tags.iter().any(|t| t.label == tag))
+            });
+
+            (mk_union(matching), mk_union(others))
+        }
+        _ => (None, None),
+    }
+}
+
+/// Recognize a test that a record bound to a variable defines a field, that is either
+/// `record.has_field "foo" r` or `%has_field% "foo" r`. Return the variable and the field.
+fn field_test(state: &State, ctxt: &Context, t: &Term) -> Option<(Ident, Ident)> {
+    let (field, r) = match t {
+        Term::Op2(BinaryOp::HasField(), field, r) => (field, r),
+        Term::App(f, r) => match f.as_ref() {
+            Term::App(g, field)
+                if stdlib_function(state, ctxt, g.as_ref(), "record") == Some("has_field") =>
+            {
+                (field, r)
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    match r.as_ref() {
+        Term::Var(x) => Some((x.clone(), static_field(field.as_ref())?)),
+        _ => None,
+    }
+}
+
+/// Return the record type `ty` where the optional field `field` is made mandatory, if `ty` is a
+/// record type with such a field.
+fn require_field(table: &UnifTable, ty: TypeWrapper, field: &Ident) -> Option<TypeWrapper> {
+    fn require_row(table: &UnifTable, row: TypeWrapper, field: &Ident) -> Option<TypeWrapper> {
+        match resolve(table, row) {
+            TypeWrapper::Concrete(AbsType::RowExtend(id, Some(ty), tail)) if id == *field => {
+                match resolve(table, *ty) {
+                    TypeWrapper::Concrete(AbsType::Optional(ty)) => Some(TypeWrapper::Concrete(
+                        AbsType::RowExtend(id, Some(ty), tail),
+                    )),
+                    _ => None,
+                }
+            }
+            TypeWrapper::Concrete(AbsType::RowExtend(id, ty, tail)) => {
+                require_row(table, *tail, field)
+                    .map(|tail| TypeWrapper::Concrete(AbsType::RowExtend(id, ty, Box::new(tail))))
+            }
+            _ => None,
+        }
+    }
+
+    match resolve(table, ty) {
+        TypeWrapper::Concrete(AbsType::StaticRecord(row)) => require_row(table, *row, field)
+            .map(|row| TypeWrapper::Concrete(AbsType::StaticRecord(Box::new(row)))),
+        _ => None,
+    }
+}
+
+/// Determine if `ty` is a record type with an optional field `field`.
+fn is_optional_field(table: &UnifTable, ty: TypeWrapper, field: &Ident) -> bool {
+    fn is_optional_row(table: &UnifTable, row: TypeWrapper, field: &Ident) -> bool {
+        match resolve(table, row) {
+            TypeWrapper::Concrete(AbsType::RowExtend(id, Some(ty), _)) if id == *field => {
+                is_optional(&resolve(table, *ty))
+            }
+            TypeWrapper::Concrete(AbsType::RowExtend(_, _, tail)) => {
+                is_optional_row(table, *tail, field)
+            }
+            _ => false,
+        }
+    }
+
+    match resolve(table, ty) {
+        TypeWrapper::Concrete(AbsType::StaticRecord(row)) => is_optional_row(table, *row, field),
+        _ => false,
+    }
+}
+
+/// The refined types of a variable tested in the condition of an `if` (see [`ite_refinement`]).
+/// `None` means that the type isn't refined.
+struct IteRefinement {
+    /// The tested variable.
+    var: Ident,
+    /// The type of the variable in the condition. A test accepts any value, whatever the type of
+    /// the tested variable, as long as it is a record for a field test.
+    cond_ty: TypeWrapper,
+    /// The type of the variable in the `then` branch.
+    then_ty: Option<TypeWrapper>,
+    /// The type of the variable in the `else` branch.
+    else_ty: Option<TypeWrapper>,
+}
+
+/// Recognize a type test or a field test in the condition of an `if`, and return the tested
+/// variable together with its refined types (see [`type_test`], [`refine`], [`field_test`] and
+/// [`require_field`]).
+fn ite_refinement(state: &State, ctxt: &Context, cond: &Term) -> Option<IteRefinement> {
+    if let Some((var, field)) = field_test(state, ctxt, cond) {
+        let ty = ctxt.type_env.get(&var)?;
+
+        return Some(IteRefinement {
+            var,
+            cond_ty: mk_typewrapper::dyn_record(AbsType::Dyn()),
+            then_ty: require_field(state.table, ty, &field),
+            else_ty: None,
+        });
+    }
+
+    let (var, tag) = type_test(state, ctxt, cond)?;
+    let ty = ctxt.type_env.get(&var)?;
+    let (then_ty, else_ty) = refine(&state.table, ty, &[&tag]);
+
+    Some(IteRefinement {
+        var,
+        cond_ty: mk_typewrapper::dynamic(),
+        then_ty,
+        else_ty,
+    })
+}
+
+/// Return a copy of the context where the variable `x` has the type `ty`, and register this
+/// refined type in the linearizer.
+fn refine_ctxt<L: Linearizer>(
+    ctxt: &Context,
+    lin: &mut Linearization<L::Building>,
+    linearizer: &mut L,
+    x: &Ident,
+    ty: Option<TypeWrapper>,
+) -> Context {
+    let mut ctxt = ctxt.clone();
+
+    if let Some(ty) = ty {
+        linearizer.refine_ident(lin, x, ty.clone());
+        ctxt.type_env.insert(x.clone(), ty);
+    }
+
+    ctxt
+}
+
 /// Typecheck a term against a specific type.
 ///
 /// # Arguments
@@ -600,15 +1203,23 @@ fn type_check_<L: Linearizer>(
                 ctxt.type_env.insert(x.clone(), ty_let.clone());
             }
 
+            if !matches!(
+                apparent_type(re.as_ref(), None, None),
+                ApparentType::Annotated(_)
+            ) {
+                state.reported.bindings.push((x.clone(), ty_let.clone()));
+            }
+
             linearizer.retype_ident(lin, x, ty_let.clone());
-            type_check_(
+            let result = type_check_(
                 state,
                 ctxt.clone(),
                 lin,
                 linearizer.scope(),
                 re,
                 ty_let.clone(),
-            )?;
+            );
+            recover(state, &ctxt, result, ty_let.clone())?;
 
             if !attrs.rec {
                 ctxt.type_env.insert(x.clone(), ty_let);
@@ -617,14 +1228,15 @@ fn type_check_<L: Linearizer>(
         }
         Term::LetPattern(x, pat, re, rt) => {
             let ty_let = binding_type(state, re.as_ref(), &ctxt, true);
-            type_check_(
+            let result = type_check_(
                 state,
                 ctxt.clone(),
                 lin,
                 linearizer.scope(),
                 re,
                 ty_let.clone(),
-            )?;
+            );
+            recover(state, &ctxt, result, ty_let.clone())?;
 
             if let Some(x) = x {
                 linearizer.retype_ident(lin, x, ty_let.clone());
@@ -633,6 +1245,141 @@ fn type_check_<L: Linearizer>(
             inject_pat_vars(pat, &mut ctxt.type_env);
             type_check_(state, ctxt, lin, linearizer, rt, ty)
         }
+        // `if cond then t1 else t2` is represented as `%ite% cond t1 t2`. It is handled directly,
+        // to refine the type of the variable tested by the condition in each branch, if any.
+        Term::App(e, t_else) if as_ite(e).is_some() => {
+            let (cond, t_then) = as_ite(e).unwrap();
+            let mut cond_ctxt = ctxt.clone();
+            let cond_linearizer = linearizer.scope();
+            let mut then_linearizer = linearizer.scope();
+            let (then_ctxt, else_ctxt) = match ite_refinement(state, &ctxt, cond.as_ref()) {
+                Some(refinement) => {
+                    cond_ctxt
+                        .type_env
+                        .insert(refinement.var.clone(), refinement.cond_ty);
+                    (
+                        refine_ctxt(
+                            &ctxt,
+                            lin,
+                            &mut then_linearizer,
+                            &refinement.var,
+                            refinement.then_ty,
+                        ),
+                        refine_ctxt(
+                            &ctxt,
+                            lin,
+                            &mut linearizer,
+                            &refinement.var,
+                            refinement.else_ty,
+                        ),
+                    )
+                }
+                None => (ctxt.clone(), ctxt),
+            };
+
+            type_check_(
+                state,
+                cond_ctxt,
+                lin,
+                cond_linearizer,
+                cond,
+                mk_typewrapper::bool(),
+            )?;
+            type_check_(state, then_ctxt, lin, then_linearizer, t_then, ty.clone())?;
+            type_check_(state, else_ctxt, lin, linearizer, t_else, ty)
+        }
+        // Inserting or removing a statically known field of a record of a closed record type
+        // gives the updated record type. Otherwise, the record is typed as a dictionary.
+        term if record_op(state, &ctxt, term).is_some() => {
+            // If a dictionary is expected, there is no need to infer a more precise type.
+            let ty_record = match ty {
+                TypeWrapper::Ptr(p) => state.table.root(p),
+                ref ty => ty.clone(),
+            };
+            let ty_record = match ty_record {
+                TypeWrapper::Concrete(AbsType::DynRecord(_)) => ty_record,
+                _ => state.table.fresh_unif_var(),
+            };
+
+            let ty_res = match record_op(state, &ctxt, term).unwrap() {
+                RecordOp::Insert(id, r, value) => {
+                    let ty_value = state.table.fresh_unif_var();
+                    type_check_(
+                        state,
+                        ctxt.clone(),
+                        lin,
+                        linearizer.scope(),
+                        r,
+                        ty_record.clone(),
+                    )?;
+                    type_check_(
+                        state,
+                        ctxt.clone(),
+                        lin,
+                        linearizer,
+                        value,
+                        ty_value.clone(),
+                    )?;
+
+                    match record_fields(&state.table, ty_record.clone()) {
+                        Some(fields) if fields.iter().any(|(field, _)| *field == id) => {
+                            return Err(TypecheckError::OverlappingField(
+                                id.clone(),
+                                to_type(&state.table, ty_record),
+                                to_type(&state.table, mk_tyw_record!((id, ty_value))),
+                                rt.pos,
+                            ));
+                        }
+                        Some(fields) => {
+                            closed_record(fields.into_iter().chain(std::iter::once((id, ty_value))))
+                        }
+                        None => {
+                            unify(
+                                state,
+                                &ctxt,
+                                ty_record.clone(),
+                                mk_typewrapper::dyn_record(ty_value),
+                            )
+                            .map_err(|err| err.into_typecheck_err(state, r.pos))?;
+                            ty_record
+                        }
+                    }
+                }
+                RecordOp::Remove(id, r) => {
+                    type_check_(state, ctxt.clone(), lin, linearizer, r, ty_record.clone())?;
+
+                    match record_fields(&state.table, ty_record.clone()) {
+                        Some(fields) if fields.iter().all(|(field, _)| *field != id) => {
+                            let expd =
+                                closed_record(fields.into_iter().chain(std::iter::once((
+                                    id.clone(),
+                                    mk_typewrapper::dynamic(),
+                                ))));
+                            return Err(TypecheckError::MissingRow(
+                                id,
+                                to_type(&state.table, expd),
+                                to_type(&state.table, ty_record),
+                                r.pos,
+                            ));
+                        }
+                        Some(fields) => closed_record(fields.into_iter().filter(|(f, _)| *f != id)),
+                        None => {
+                            let ty_elt = state.table.fresh_unif_var();
+                            unify(
+                                state,
+                                &ctxt,
+                                ty_record.clone(),
+                                mk_typewrapper::dyn_record(ty_elt),
+                            )
+                            .map_err(|err| err.into_typecheck_err(state, r.pos))?;
+                            ty_record
+                        }
+                    }
+                }
+            };
+
+            unify(state, &ctxt, ty, ty_res).map_err(|err| err.into_typecheck_err(state, rt.pos))
+        }
         Term::App(e, t) => {
             let src = state.table.fresh_unif_var();
             let arr = mk_tyw_arrow!(src.clone(), ty);
@@ -640,37 +1387,104 @@ fn type_check_<L: Linearizer>(
             type_check_(state, ctxt.clone(), lin, linearizer.scope(), e, arr)?;
             type_check_(state, ctxt, lin, linearizer, t, src)
         }
-        Term::Switch(exp, cases, default) => {
-            // Currently, if it has a default value, we typecheck the whole thing as
-            // taking ANY enum, since it's more permissive and there's no loss of information
+        Term::Switch(exp, cases, default, variants) => {
             let res = state.table.fresh_unif_var();
+            // The types of the payloads of the variants matched by the cases.
+            let mut payloads = HashMap::new();
+            // If the tested expression is `typeof x`, the type of `x` is refined in each case.
+            let tested = typeof_var(state, &ctxt, exp.as_ref())
+                .and_then(|x| Some((x.clone(), ctxt.type_env.get(x)?)));
+
+            for (id, case) in cases.iter() {
+                // The body of a case matching a variant is a function of the payload
+                let case_ty = if variants.contains(id) {
+                    let payload = state.table.fresh_unif_var();
+                    payloads.insert(id.clone(), payload.clone());
+                    mk_tyw_arrow!(payload, res.clone())
+                } else {
+                    res.clone()
+                };
+
+                let mut case_linearizer = linearizer.scope();
+                let case_ctxt = match &tested {
+                    Some((x, x_ty)) if !variants.contains(id) => {
+                        let (refined, _) = refine(&state.table, x_ty.clone(), &[id]);
+                        refine_ctxt(&ctxt, lin, &mut case_linearizer, x, refined)
+                    }
+                    _ => ctxt.clone(),
+                };
 
-            for case in cases.values() {
-                type_check_(
-                    state,
-                    ctxt.clone(),
-                    lin,
-                    linearizer.scope(),
-                    case,
-                    res.clone(),
-                )?;
+                type_check_(state, case_ctxt, lin, case_linearizer, case, case_ty)?;
             }
 
             let row = match default {
+                // If there is a default case, the tested expression can be any enum, as long as
+                // the variants matched by the cases have the right payload.
                 Some(t) => {
-                    type_check_(state, ctxt.clone(), lin, linearizer.scope(), t, res.clone())?;
-                    state.table.fresh_unif_var()
+                    let mut default_linearizer = linearizer.scope();
+                    let default_ctxt = match &tested {
+                        Some((x, x_ty)) => {
+                            let tags: Vec<_> = cases.keys().collect();
+                            let (_, refined) = refine(&state.table, x_ty.clone(), &tags);
+                            refine_ctxt(&ctxt, lin, &mut default_linearizer, x, refined)
+                        }
+                        None => ctxt.clone(),
+                    };
+
+                    type_check_(state, default_ctxt, lin, default_linearizer, t, res.clone())?;
+                    payloads
+                        .into_iter()
+                        .fold(state.table.fresh_unif_var(), |acc, (id, ty)| {
+                            TypeWrapper::Concrete(AbsType::RowExtend(
+                                id,
+                                Some(Box::new(ty)),
+                                Box::new(acc),
+                            ))
+                        })
                 }
-                None => cases.iter().try_fold(
-                    mk_typewrapper::row_empty(),
-                    |acc, x| -> Result<TypeWrapper, TypecheckError> {
-                        Ok(mk_tyw_enum_row!(x.0.clone(); acc))
-                    },
-                )?,
+                None => cases.keys().fold(mk_typewrapper::row_empty(), |acc, id| {
+                    TypeWrapper::Concrete(AbsType::RowExtend(
+                        id.clone(),
+                        payloads.remove(id).map(Box::new),
+                        Box::new(acc),
+                    ))
+                }),
             };
 
             unify(state, &ctxt, ty, res).map_err(|err| err.into_typecheck_err(state, rt.pos))?;
-            type_check_(state, ctxt, lin, linearizer, exp, mk_tyw_enum!(; row))
+
+            let exp_ty = state.table.fresh_unif_var();
+            let mut exp_ctxt = ctxt.clone();
+
+            // `typeof` accepts any value, whatever the type of the tested variable.
+            if let Some((x, _)) = tested {
+                exp_ctxt.type_env.insert(x, mk_typewrapper::dynamic());
+            }
+
+            type_check_(state, exp_ctxt, lin, linearizer, exp, exp_ty.clone())?;
+
+            // Without a default case, the switch must handle all the tags of the tested
+            // expression. We report the unhandled tags of a closed enum type specifically, rather
+            // than the failure to unify the enum types.
+            if default.is_none() {
+                let unhandled = unhandled_tags(&state.table, exp_ty.clone(), cases);
+
+                if !unhandled.is_empty() {
+                    return Err(TypecheckError::NonExhaustiveSwitch(
+                        unhandled,
+                        to_type(&state.table, exp_ty),
+                        exp.pos,
+                    ));
+                }
+            }
+
+            unify(
+                state,
+                &ctxt,
+                TypeWrapper::Concrete(AbsType::Enum(Box::new(row))),
+                exp_ty,
+            )
+            .map_err(|err| err.into_typecheck_err(state, exp.pos))
         }
         Term::Var(x) => {
             let x_ty = ctxt
@@ -687,9 +1501,21 @@ fn type_check_<L: Linearizer>(
             unify(state, &ctxt, ty, mk_tyw_enum!(id.clone(); row))
                 .map_err(|err| err.into_typecheck_err(state, rt.pos))
         }
+        Term::EnumVariant(id, arg) => {
+            let payload = state.table.fresh_unif_var();
+            let row = state.table.fresh_unif_var();
+            let variant_ty = TypeWrapper::Concrete(AbsType::Enum(Box::new(TypeWrapper::Concrete(
+                AbsType::RowExtend(id.clone(), Some(Box::new(payload.clone())), Box::new(row)),
+            ))));
+
+            unify(state, &ctxt, ty, variant_ty)
+                .map_err(|err| err.into_typecheck_err(state, rt.pos))?;
+            type_check_(state, ctxt, lin, linearizer, arg, payload)
+        }
         // If some fields are defined dynamically, the only potential type that works is `{_ : a}`
         // for some `a`
         Term::RecRecord(stat_map, dynamic, ..) if !dynamic.is_empty() => {
+            register_fields(state, stat_map.values());
             let ty_dyn = state.table.fresh_unif_var();
 
             for id in stat_map.keys() {
@@ -702,20 +1528,23 @@ fn type_check_<L: Linearizer>(
             stat_map
                 .iter()
                 .try_for_each(|(_, t)| -> Result<(), TypecheckError> {
-                    type_check_(
+                    let result = type_check_(
                         state,
                         ctxt.clone(),
                         lin,
                         linearizer.scope(),
                         t,
                         ty_dyn.clone(),
-                    )
+                    );
+                    recover(state, &ctxt, result, ty_dyn.clone())
                 })?;
 
             unify(state, &ctxt, ty, mk_typewrapper::dyn_record(ty_dyn))
                 .map_err(|err| err.into_typecheck_err(state, rt.pos))
         }
         Term::Record(stat_map, _) | Term::RecRecord(stat_map, ..) => {
+            register_fields(state, stat_map.values());
+
             // For recursive records, we look at the apparent type of each field and bind it in
             // ctxt before actually typechecking the content of fields.
             // Fields defined by interpolation are ignored.
@@ -732,22 +1561,32 @@ fn type_check_<L: Linearizer>(
             } else {
                 ty.clone()
             };
+            let top_level = state.reported.is_top_level(rt.pos);
 
             if let TypeWrapper::Concrete(AbsType::DynRecord(rec_ty)) = root_ty {
                 // Checking for a dynamic record
                 stat_map
                     .iter()
-                    .try_for_each(|(_, t)| -> Result<(), TypecheckError> {
-                        type_check_(
+                    .try_for_each(|(id, t)| -> Result<(), TypecheckError> {
+                        if top_level {
+                            state
+                                .reported
+                                .bindings
+                                .push((id.clone(), (*rec_ty).clone()));
+                        }
+
+                        let result = type_check_(
                             state,
                             ctxt.clone(),
                             lin,
                             linearizer.scope(),
                             t,
                             (*rec_ty).clone(),
-                        )
+                        );
+                        recover(state, &ctxt, result, (*rec_ty).clone())
                     })
             } else {
+                let ty_expected = ty.clone();
                 let row = stat_map.iter().try_fold(
                     mk_tyw_row!(),
                     |acc, (id, field)| -> Result<TypeWrapper, TypecheckError> {
@@ -760,14 +1599,27 @@ fn type_check_<L: Linearizer>(
                             state.table.fresh_unif_var()
                         };
 
-                        type_check_(
+                        if top_level {
+                            state.reported.bindings.push((id.clone(), ty.clone()));
+                        }
+
+                        let result = type_check_(
                             state,
                             ctxt.clone(),
                             lin,
                             linearizer.scope(),
                             field,
                             ty.clone(),
-                        )?;
+                        );
+                        recover(state, &ctxt, result, ty.clone())?;
+
+                        // A defined field is accepted where an optional one is expected, but an
+                        // optional type never unifies with a mandatory one.
+                        let ty = if is_optional_field(state.table, ty_expected.clone(), id) {
+                            TypeWrapper::Concrete(AbsType::Optional(Box::new(ty)))
+                        } else {
+                            ty
+                        };
 
                         Ok(mk_tyw_row!((id.clone(), ty); acc))
                     },
@@ -780,12 +1632,77 @@ fn type_check_<L: Linearizer>(
         Term::Op1(op, t) => {
             let (ty_arg, ty_res) = get_uop_type(state, op)?;
 
-            type_check_(state, ctxt.clone(), lin, linearizer.scope(), t, ty_arg)?;
+            type_check_(
+                state,
+                ctxt.clone(),
+                lin,
+                linearizer.scope(),
+                t,
+                ty_arg.clone(),
+            )?;
+
+            // An optional field can only be accessed once it is known to be defined (see
+            // `ite_refinement`).
+            if let UnaryOp::StaticAccess(id) = op {
+                if is_optional(&resolve(state.table, ty_res.clone())) {
+                    return Err(TypecheckError::OptionalFieldAccess(
+                        id.clone(),
+                        to_type(state.table, ty_arg),
+                        rt.pos,
+                    ));
+                }
+            }
 
             let instantiated = instantiate_foralls(state, ty_res, ForallInst::Ptr);
             unify(state, &ctxt, ty, instantiated)
                 .map_err(|err| err.into_typecheck_err(state, rt.pos))
         }
+        // Merging two records of closed record types without common fields gives the record type
+        // with the fields of both. Otherwise, merge is an operation on `Dyn`.
+        Term::Op2(BinaryOp::Merge(), t1, t2) => {
+            let ty1 = state.table.fresh_unif_var();
+            let ty2 = state.table.fresh_unif_var();
+
+            type_check_(
+                state,
+                ctxt.clone(),
+                lin,
+                linearizer.scope(),
+                t1,
+                ty1.clone(),
+            )?;
+            type_check_(state, ctxt.clone(), lin, linearizer, t2, ty2.clone())?;
+
+            let ty_res = match (
+                record_fields(&state.table, ty1.clone()),
+                record_fields(&state.table, ty2.clone()),
+            ) {
+                (Some(fields1), Some(fields2)) => {
+                    if let Some((id, _)) = fields2
+                        .iter()
+                        .find(|(id, _)| fields1.iter().any(|(field, _)| field == id))
+                    {
+                        return Err(TypecheckError::OverlappingField(
+                            id.clone(),
+                            to_type(&state.table, ty1),
+                            to_type(&state.table, ty2),
+                            rt.pos,
+                        ));
+                    }
+
+                    closed_record(fields1.into_iter().chain(fields2))
+                }
+                _ => {
+                    unify(state, &ctxt, ty1, mk_typewrapper::dynamic())
+                        .map_err(|err| err.into_typecheck_err(state, t1.pos))?;
+                    unify(state, &ctxt, ty2, mk_typewrapper::dynamic())
+                        .map_err(|err| err.into_typecheck_err(state, t2.pos))?;
+                    mk_typewrapper::dynamic()
+                }
+            };
+
+            unify(state, &ctxt, ty, ty_res).map_err(|err| err.into_typecheck_err(state, rt.pos))
+        }
         Term::Op2(op, t1, t2) => {
             let (ty_arg1, ty_arg2, ty_res) = get_bop_type(state, op)?;
 
@@ -817,17 +1734,19 @@ fn type_check_<L: Linearizer>(
 
             match meta {
                 MetaValue {
-                    types: Some(Contract { types: ty2, .. }),
+                    types: Some(ctr),
                     value: Some(t),
                     ..
                 } => {
-                    let tyw2 = TypeWrapper::from_type(ty2.clone(), &ctxt.term_env);
+                    let tyw2 = TypeWrapper::from_type(ctr.types.clone(), &ctxt.term_env);
                     let instantiated =
                         instantiate_foralls(state, tyw2.clone(), ForallInst::Constant);
 
                     unify(state, &ctxt, tyw2, ty)
                         .map_err(|err| err.into_typecheck_err(state, rt.pos))?;
-                    type_check_(state, ctxt, lin, linearizer, t, instantiated)
+                    type_check_(state, ctxt, lin, linearizer, t, instantiated)?;
+                    register_checked_annot(state, ctr);
+                    Ok(())
                 }
                 // A metavalue without a type annotation but with a contract annotation switches
                 // the typechecker back to walk mode. If there are several contracts, we
@@ -876,22 +1795,94 @@ fn type_check_<L: Linearizer>(
         Term::Sealed(_, t, _) => type_check_(state, ctxt, lin, linearizer, t, ty),
         Term::Import(_) => unify(state, &ctxt, ty, mk_typewrapper::dynamic())
             .map_err(|err| err.into_typecheck_err(state, rt.pos)),
-        // We use the apparent type of the import for checking. This function doesn't recursively
-        // typecheck imports: this is the responsibility of the caller.
+        // We use the interface type of the import for checking, or its apparent type if it
+        // hasn't been typechecked yet. This function doesn't recursively typecheck imports: this
+        // is the responsibility of the caller.
         Term::ResolvedImport(file_id) => {
-            let t = state
-                .resolver
-                .get(*file_id)
-                .expect("Internal error: resolved import not found during typechecking.");
-            let ty_import: TypeWrapper = TypeWrapper::from_apparent_type(
-                apparent_type(t.as_ref(), Some(&ctxt.type_env), Some(state.resolver)),
-                &ctxt.term_env,
-            );
+            let ty_import = match state.resolver.get_interface(*file_id) {
+                // The contracts of the interface are defined in another file: we don't compare them
+                // with the contracts of the current term environment.
+                Some(ty) => TypeWrapper::from_type(ty, &TermEnvironment::new()),
+                None => {
+                    let t = state
+                        .resolver
+                        .get(*file_id)
+                        .expect("Internal error: resolved import not found during typechecking.");
+                    TypeWrapper::from_apparent_type(
+                        apparent_type(t.as_ref(), Some(&ctxt.type_env), Some(state.resolver)),
+                        &ctxt.term_env,
+                    )
+                }
+            };
             unify(state, &ctxt, ty, ty_import).map_err(|err| err.into_typecheck_err(state, rt.pos))
         }
     }
 }
 
+/// Register a type annotation which has been successfully checked in strict mode as a proven
+/// annotation. Higher-order annotations are only proven at the end of the enclosing statically
+/// typed block (see [`CheckedAnnots`]).
+fn register_checked_annot(state: &mut State, ctr: &Contract) {
+    let span = ctr.label.span;
+
+    if is_first_order(&ctr.types) {
+        state.checked_annots.insert(span);
+    } else if let Some(block) = state.typed_blocks.last_mut() {
+        if !block.fields.contains(&span) {
+            block.higher_order.push(span);
+        }
+    }
+}
+
+/// Register the type annotations of record fields in the current statically typed block. Values of
+/// fields can be accessed from anywhere, and higher-order annotations of fields are never proven.
+fn register_fields<'a, I>(state: &mut State, fields: I)
+where
+    I: Iterator<Item = &'a RichTerm>,
+{
+    if let Some(block) = state.typed_blocks.last_mut() {
+        block
+            .fields
+            .extend(fields.filter_map(|field| match field.as_ref() {
+                Term::MetaValue(MetaValue {
+                    types: Some(Contract { label, .. }),
+                    ..
+                }) => Some(label.span),
+                _ => None,
+            }));
+    }
+}
+
+/// Determine if a type is first-order, that is if it doesn't contain any arrow, polymorphism,
+/// user-defined contract, type variable or wildcard.
+fn is_first_order(ty: &Types) -> bool {
+    match &ty.0 {
+        AbsType::Dyn()
+        | AbsType::Num()
+        | AbsType::Bool()
+        | AbsType::Str()
+        | AbsType::RowEmpty() => true,
+        AbsType::Array(ty)
+        | AbsType::Enum(ty)
+        | AbsType::Optional(ty)
+        | AbsType::StaticRecord(ty)
+        | AbsType::DynRecord(ty) => is_first_order(ty),
+        AbsType::RowExtend(_, ty_row, tail) => {
+            ty_row.as_ref().map_or(true, |ty| is_first_order(ty)) && is_first_order(tail)
+        }
+        AbsType::Union(ty1, ty2) | AbsType::Intersection(ty1, ty2) => {
+            is_first_order(ty1) && is_first_order(ty2)
+        }
+        AbsType::Sym()
+        | AbsType::Flat(_)
+        | AbsType::Arrow(..)
+        | AbsType::Var(_)
+        | AbsType::Forall(..)
+        | AbsType::Rec(..)
+        | AbsType::Wildcard(_) => false,
+    }
+}
+
 /// Determine the type of a let-bound expression, or more generally of any binding (e.g. fields)
 /// that may be stored in a typing environment at some point.
 ///
@@ -920,6 +1911,24 @@ fn binding_type(state: &mut State, t: &Term, ctxt: &Context, strict: bool) -> Ty
     }
 }
 
+/// Return the type of a field of the top-level record to show in the [`TypeReport`], given its
+/// binding type (see [`binding_type`]). Outside of a statically typed block, the wildcards of an
+/// annotation are assigned `Dyn` in the typing environment, but they are still inferred when the
+/// annotation is checked: the report shows the inferred type.
+fn reported_type(
+    state: &mut State,
+    t: &Term,
+    ctxt: &Context,
+    binding_type: TypeWrapper,
+) -> TypeWrapper {
+    match apparent_type(t, Some(&ctxt.type_env), Some(state.resolver)) {
+        ApparentType::Annotated(ty) if has_wildcards(&ty) => {
+            replace_wildcards_with_var(state.table, state.wildcard_vars, ty, &ctxt.term_env)
+        }
+        _ => binding_type,
+    }
+}
+
 /// Substitute wildcards in a type for their unification variable.
 fn replace_wildcards_with_var(
     table: &mut UnifTable,
@@ -981,8 +1990,11 @@ impl From<ApparentType> for Types {
 /// - if `bound_exp` is a constant (string, number, boolean or symbol) which type can be deduced
 ///   directly without unfolding the expression further, return the corresponding exact type.
 /// - if `bound_exp` is an array, return `Array Dyn`.
-/// - if `bound_exp` is a resolved import, return the apparent type of the imported term. Returns
-///   `Dyn` if the resolver is not passed as a parameter to the function.
+/// - if `bound_exp` is a resolved import, return the interface type of the imported file if it has
+///   already been typechecked (see [`interface_type`]), or the apparent type of the imported term
+///   otherwise. If the imported file is a data file (JSON, YAML or TOML), its type is rather
+///   inferred from its whole content (see [`infer_data_type`]). Returns `Dyn` if the resolver is
+///   not passed as a parameter to the function.
 /// - Otherwise, return an approximation of the type (currently `Dyn`, but could be more precise in
 ///   the future, such as `Dyn -> Dyn` for functions, `{ | Dyn}` for records, and so on).
 pub fn apparent_type(
@@ -1014,11 +2026,20 @@ pub fn apparent_type(
             .map(ApparentType::FromEnv)
             .unwrap_or(ApparentType::Approximated(Types(AbsType::Dyn()))),
         Term::ResolvedImport(f) => {
-            if let Some(r) = resolver {
+            if let Some(ty) = resolver.and_then(|r| r.get_interface(*f)) {
+                ApparentType::Inferred(ty)
+            } else if let Some(r) = resolver {
                 let t = r
                     .get(*f)
                     .expect("Internal error: resolved import not found during typechecking.");
-                apparent_type(&t.term, env, Some(r))
+                let format = InputFormat::from_path_buf(Path::new(r.get_path(*f)));
+
+                match format {
+                    Some(InputFormat::Json | InputFormat::Yaml | InputFormat::Toml) => {
+                        ApparentType::Inferred(infer_data_type(&t.term))
+                    }
+                    _ => apparent_type(&t.term, env, Some(r)),
+                }
             } else {
                 ApparentType::Approximated(Types(AbsType::Dyn()))
             }
@@ -1027,6 +2048,25 @@ pub fn apparent_type(
     }
 }
 
+/// Compute the interface type of a file, that is the type of its content as seen by the files
+/// importing it. For a Nickel file, this is the type of its top-level annotation, where the
+/// wildcards are replaced by their inferred type, or its apparent type if there is no annotation.
+/// The type of a data file is inferred from its whole content (see [`infer_data_type`]).
+pub fn interface_type(
+    t: &RichTerm,
+    format: InputFormat,
+    wildcards: &Wildcards,
+    resolver: &dyn ImportResolver,
+) -> Types {
+    match format {
+        InputFormat::Json | InputFormat::Yaml | InputFormat::Toml => infer_data_type(&t.term),
+        InputFormat::Nickel => {
+            let t = substitute_wildcards::transform_one(t.clone(), wildcards);
+            apparent_type(t.as_ref(), None, Some(resolver)).into()
+        }
+    }
+}
+
 /// Infer the type of a non annotated record by gathering the apparent type of the fields. It's
 /// currently used essentially to type the stdlib.
 pub fn infer_record_type(t: &Term, term_env: &TermEnvironment) -> TypeWrapper {
@@ -1059,6 +2099,125 @@ pub fn infer_record_type(t: &Term, term_env: &TermEnvironment) -> TypeWrapper {
     }
 }
 
+/// Infer the type of a term coming from a data file (JSON, YAML or TOML).
+///
+/// Data is made only of records, arrays, and constants, so we can give it a precise type without
+/// any annotation: records are given a closed record type, and arrays are given the type `Array
+/// T`, where `T` is the common type of the elements. Records with the same fields have a common
+/// type if their fields do. Records with different fields are joined as a dictionary `{_: T}`,
+/// where `T` is the common type of all their fields. If the elements don't have a common type, or
+/// if the array is empty, `Array Dyn` is returned instead. `null` is of type `Dyn`.
+pub fn infer_data_type(t: &Term) -> Types {
+    // Compute a type which is compatible with both `ty1` and `ty2`, if any.
+    fn join(ty1: Types, ty2: Types) -> Option<Types> {
+        match (ty1, ty2) {
+            (Types(AbsType::StaticRecord(row1)), Types(AbsType::StaticRecord(row2))) => {
+                let mut fields1 = row_fields(*row1);
+                let mut fields2 = row_fields(*row2);
+
+                if fields1.len() == fields2.len()
+                    && fields1.keys().all(|id| fields2.contains_key(id))
+                {
+                    let mut ids: Vec<Ident> = fields1.keys().cloned().collect();
+                    ids.sort_by(|id1, id2| id1.label.cmp(&id2.label));
+
+                    let row = ids.into_iter().rev().try_fold(
+                        Types(AbsType::RowEmpty()),
+                        |tail, id| -> Option<Types> {
+                            let ty1 = fields1.remove(&id).unwrap();
+                            let ty2 = fields2.remove(&id).unwrap();
+
+                            Some(Types(AbsType::RowExtend(
+                                id,
+                                Some(Box::new(join(ty1, ty2)?)),
+                                Box::new(tail),
+                            )))
+                        },
+                    )?;
+
+                    Some(Types(AbsType::StaticRecord(Box::new(row))))
+                } else {
+                    fields1
+                        .into_values()
+                        .chain(fields2.into_values())
+                        .try_fold(None, |acc: Option<Types>, ty| match acc {
+                            None => Some(Some(ty)),
+                            Some(acc) => join(acc, ty).map(Some),
+                        })
+                        .map(|ty| {
+                            Types(AbsType::DynRecord(Box::new(
+                                ty.unwrap_or(Types(AbsType::Dyn())),
+                            )))
+                        })
+                }
+            }
+            (Types(AbsType::DynRecord(ty)), Types(AbsType::StaticRecord(row)))
+            | (Types(AbsType::StaticRecord(row)), Types(AbsType::DynRecord(ty))) => {
+                row_fields(*row)
+                    .into_values()
+                    .try_fold(*ty, join)
+                    .map(|ty| Types(AbsType::DynRecord(Box::new(ty))))
+            }
+            (Types(AbsType::DynRecord(ty1)), Types(AbsType::DynRecord(ty2))) => {
+                join(*ty1, *ty2).map(|ty| Types(AbsType::DynRecord(Box::new(ty))))
+            }
+            (Types(AbsType::Array(elt1)), Types(AbsType::Array(elt2))) => {
+                Some(Types(AbsType::Array(Box::new(
+                    join(*elt1, *elt2).unwrap_or(Types(AbsType::Dyn())),
+                ))))
+            }
+            (ty1, ty2) if ty1 == ty2 => Some(ty1),
+            _ => None,
+        }
+    }
+
+    fn row_fields(row: Types) -> HashMap<Ident, Types> {
+        let mut fields = HashMap::new();
+        let mut row = row;
+
+        while let Types(AbsType::RowExtend(id, Some(ty), tail)) = row {
+            fields.insert(id, *ty);
+            row = *tail;
+        }
+
+        fields
+    }
+
+    match t {
+        Term::Num(_) => Types(AbsType::Num()),
+        Term::Bool(_) => Types(AbsType::Bool()),
+        Term::Str(_) => Types(AbsType::Str()),
+        Term::Record(fields, _) => {
+            let mut fields: Vec<_> = fields.iter().collect();
+            fields.sort_by(|(id1, _), (id2, _)| id1.label.cmp(&id2.label));
+
+            let row =
+                fields
+                    .into_iter()
+                    .rev()
+                    .fold(Types(AbsType::RowEmpty()), |tail, (id, rt)| {
+                        Types(AbsType::RowExtend(
+                            id.clone(),
+                            Some(Box::new(infer_data_type(rt.as_ref()))),
+                            Box::new(tail),
+                        ))
+                    });
+
+            Types(AbsType::StaticRecord(Box::new(row)))
+        }
+        Term::Array(elts, _) => {
+            let elt_ty = elts
+                .iter()
+                .map(|rt| infer_data_type(rt.as_ref()))
+                .reduce(|acc, ty| join(acc, ty).unwrap_or(Types(AbsType::Dyn())))
+                .unwrap_or(Types(AbsType::Dyn()));
+
+            Types(AbsType::Array(Box::new(elt_ty)))
+        }
+        _ => Types(AbsType::Dyn()),
+    }
+}
+
 /// Deeply check whether a type contains a wildcard.
 fn has_wildcards(ty: &Types) -> bool {
     let mut has_wildcard = false;
@@ -1091,6 +2250,9 @@ pub enum TypeWrapper {
     Constant(usize),
     /// A unification variable.
     Ptr(usize),
+    /// The type of a term which failed to typecheck. It unifies with any other type, so that
+    /// typechecking can go on after an error without reporting the errors it entails.
+    Error,
 }
 
 impl std::convert::TryInto<Types> for TypeWrapper {
@@ -1149,6 +2311,13 @@ impl TypeWrapper {
                     Concrete(AbsType::Forall(i, Box::new(tt.subst(id, to))))
                 }
             }
+            Concrete(AbsType::Rec(i, t)) => {
+                if i == id {
+                    Concrete(AbsType::Rec(i, t))
+                } else {
+                    Concrete(AbsType::Rec(i, Box::new(t.subst(id, to))))
+                }
+            }
             Concrete(AbsType::Arrow(s, t)) => {
                 let fs = s.subst(id.clone(), to.clone());
                 let ft = t.subst(id, to);
@@ -1168,6 +2337,21 @@ impl TypeWrapper {
                 Concrete(AbsType::DynRecord(Box::new(def_ty.subst(id, to))))
             }
             Concrete(AbsType::Array(ty)) => Concrete(AbsType::Array(Box::new(ty.subst(id, to)))),
+            Concrete(AbsType::Optional(ty)) => {
+                Concrete(AbsType::Optional(Box::new(ty.subst(id, to))))
+            }
+            Concrete(AbsType::Union(s, t)) => {
+                let fs = s.subst(id.clone(), to.clone());
+                let ft = t.subst(id, to);
+
+                Concrete(AbsType::Union(Box::new(fs), Box::new(ft)))
+            }
+            Concrete(AbsType::Intersection(s, t)) => {
+                let fs = s.subst(id.clone(), to.clone());
+                let ft = t.subst(id, to);
+
+                Concrete(AbsType::Intersection(Box::new(fs), Box::new(ft)))
+            }
             // Cases are spelled out instead of using a catch-all case `_ => ` to force
             // contributors to patch this code when they add new types constructors.
             Concrete(AbsType::Var(_))
@@ -1181,7 +2365,8 @@ impl TypeWrapper {
             | Concrete(AbsType::Wildcard(_))
             | Contract(..)
             | Constant(_)
-            | Ptr(_) => self,
+            | Ptr(_)
+            | Error => self,
         }
     }
 
@@ -1274,10 +2459,19 @@ fn row_add(
             );
             Ok((ty, new_row))
         }
+        // The error type behaves as a row containing any field.
+        TypeWrapper::Error => Ok((ty.map(|_| Box::new(TypeWrapper::Error)), TypeWrapper::Error)),
         other => Err(RowUnifError::IllformedRow(other)),
     }
 }
 
+/// Unfold a recursive type `rec id. body` once, that is substitute `rec id. body` for `id` in
+/// `body`.
+fn unfold_rec(id: Ident, body: Box<TypeWrapper>) -> TypeWrapper {
+    let rec_type = TypeWrapper::Concrete(AbsType::Rec(id.clone(), body.clone()));
+    body.subst(id, rec_type)
+}
+
 /// Try to unify two types.
 pub fn unify(
     state: &mut State,
@@ -1305,7 +2499,10 @@ pub fn unify(
             (AbsType::Num(), AbsType::Num()) => Ok(()),
             (AbsType::Bool(), AbsType::Bool()) => Ok(()),
             (AbsType::Str(), AbsType::Str()) => Ok(()),
-            (AbsType::Array(tyw1), AbsType::Array(tyw2)) => unify(state, ctxt, *tyw1, *tyw2),
+            (AbsType::Array(tyw1), AbsType::Array(tyw2))
+            | (AbsType::Optional(tyw1), AbsType::Optional(tyw2)) => {
+                unify(state, ctxt, *tyw1, *tyw2)
+            }
             (AbsType::Sym(), AbsType::Sym()) => Ok(()),
             (AbsType::Arrow(s1s, s1t), AbsType::Arrow(s2s, s2t)) => {
                 unify(state, ctxt, (*s1s).clone(), (*s2s).clone()).map_err(|err| {
@@ -1375,6 +2572,50 @@ pub fn unify(
                     t2t.subst(i2, constant_type),
                 )
             }
+            // Two recursive types are compared by replacing both variables with the same constant,
+            // as for foralls. This is conservative: a recursive type and another unfolding of the
+            // same type, such as `rec T. {next: T}` and `rec T. {next: {next: T}}`, are rejected.
+            (AbsType::Rec(i1, t1t), AbsType::Rec(i2, t2t)) => {
+                let constant_type = state.table.fresh_const();
+
+                unify(
+                    state,
+                    &ctxt,
+                    t1t.subst(i1, constant_type.clone()),
+                    t2t.subst(i2, constant_type),
+                )
+            }
+            // Recursive types are equi-recursive: otherwise, a recursive type is unfolded once and
+            // unified with the other side. This terminates, because the parser ensures that the
+            // recursive variable is always guarded by a type constructor.
+            (AbsType::Rec(i, t), ty) => {
+                unify(state, ctxt, unfold_rec(i, t), TypeWrapper::Concrete(ty))
+            }
+            (ty, AbsType::Rec(i, t)) => {
+                unify(state, ctxt, TypeWrapper::Concrete(ty), unfold_rec(i, t))
+            }
+            // Union and intersection types are opaque: without subtyping, they only unify with
+            // a union (respectively an intersection) of the same types.
+            (AbsType::Union(s1, t1), AbsType::Union(s2, t2)) => {
+                unify(state, ctxt, (*s1).clone(), (*s2).clone())
+                    .and_then(|_| unify(state, ctxt, (*t1).clone(), (*t2).clone()))
+                    .map_err(|_| {
+                        UnifError::TypeMismatch(
+                            TypeWrapper::Concrete(AbsType::Union(s1, t1)),
+                            TypeWrapper::Concrete(AbsType::Union(s2, t2)),
+                        )
+                    })
+            }
+            (AbsType::Intersection(s1, t1), AbsType::Intersection(s2, t2)) => {
+                unify(state, ctxt, (*s1).clone(), (*s2).clone())
+                    .and_then(|_| unify(state, ctxt, (*t1).clone(), (*t2).clone()))
+                    .map_err(|_| {
+                        UnifError::TypeMismatch(
+                            TypeWrapper::Concrete(AbsType::Intersection(s1, t1)),
+                            TypeWrapper::Concrete(AbsType::Intersection(s2, t2)),
+                        )
+                    })
+            }
             (AbsType::Var(ident), _) | (_, AbsType::Var(ident)) => {
                 Err(UnifError::UnboundTypeVariable(ident))
             }
@@ -1399,6 +2640,7 @@ pub fn unify(
             state.table.assign(p, tyw);
             Ok(())
         }
+        (TypeWrapper::Error, _) | (_, TypeWrapper::Error) => Ok(()),
         (TypeWrapper::Constant(i1), TypeWrapper::Constant(i2)) if i1 == i2 => Ok(()),
         (TypeWrapper::Constant(i1), TypeWrapper::Constant(i2)) => {
             Err(UnifError::ConstMismatch(i1, i2))
@@ -1419,6 +2661,12 @@ pub fn unify(
 
 /// Try to unify two row types. Return an [`RowUnifError::IllformedRow`] error if one of the given
 /// type is not a row type.
+///
+/// An optional field (see [`AbsType::Optional`]) of the expected row `t1` may be missing from the
+/// inferred row `t2`, as long as `t2` can't be extended with it anymore, that is if it is closed or
+/// ends with `Dyn`. The converse doesn't hold: a value which may have the field can't be used
+/// where a row without it is expected. When both rows have the field, it must be optional in both
+/// or in neither.
 pub fn unify_rows(
     state: &mut State,
     ctxt: &Context,
@@ -1430,6 +2678,12 @@ pub fn unify_rows(
         (AbsType::RowEmpty(), AbsType::RowEmpty()) | (AbsType::Dyn(), AbsType::Dyn()) => Ok(()),
         (AbsType::RowEmpty(), AbsType::Dyn()) => Err(RowUnifError::ExtraDynTail()),
         (AbsType::Dyn(), AbsType::RowEmpty()) => Err(RowUnifError::MissingDynTail()),
+        (AbsType::RowExtend(_, Some(ty), tail), r2 @ AbsType::RowEmpty())
+        | (AbsType::RowExtend(_, Some(ty), tail), r2 @ AbsType::Dyn())
+            if is_optional(&ty) =>
+        {
+            unify_row_tails(state, ctxt, *tail, TypeWrapper::Concrete(r2))
+        }
         (AbsType::RowEmpty(), AbsType::RowExtend(ident, _, _))
         | (AbsType::Dyn(), AbsType::RowExtend(ident, _, _)) => Err(RowUnifError::ExtraRow(ident)),
         (AbsType::RowExtend(ident, _, _), AbsType::Dyn())
@@ -1437,11 +2691,25 @@ pub fn unify_rows(
             Err(RowUnifError::MissingRow(ident))
         }
         (AbsType::RowExtend(id, ty, t), r2 @ AbsType::RowExtend(_, _, _)) => {
-            let (ty2, t2_tail) = row_add(state, &id, ty.clone(), TypeWrapper::Concrete(r2))?;
+            let (ty2, t2_tail) =
+                match row_add(state, &id, ty.clone(), TypeWrapper::Concrete(r2.clone())) {
+                    Err(RowUnifError::MissingRow(_))
+                        if ty.as_deref().map_or(false, is_optional) =>
+                    {
+                        return unify_row_tails(state, ctxt, *t, TypeWrapper::Concrete(r2));
+                    }
+                    result => result?,
+                };
+
             match (ty, ty2) {
                 (None, None) => Ok(()),
-                (Some(ty), Some(ty2)) => unify(state, ctxt, *ty, *ty2)
-                    .map_err(|err| RowUnifError::RowMismatch(id.clone(), Box::new(err))),
+                (Some(ty), Some(ty2)) => {
+                    let ty = resolve(state.table, *ty);
+                    let ty2 = resolve(state.table, *ty2);
+
+                    unify(state, ctxt, ty, ty2)
+                        .map_err(|err| RowUnifError::RowMismatch(id.clone(), Box::new(err)))
+                }
                 (ty1, ty2) => Err(RowUnifError::RowKindMismatch(
                     id,
                     ty1.map(|t| *t),
@@ -1449,37 +2717,121 @@ pub fn unify_rows(
                 )),
             }?;
 
-            match (*t, t2_tail) {
-                (TypeWrapper::Concrete(r1_tail), TypeWrapper::Concrete(r2_tail)) => {
-                    unify_rows(state, ctxt, r1_tail, r2_tail)
-                }
-                // If one of the tail is not a concrete type, it is either a unification variable
-                // or a constant (rigid type variable). `unify` already knows how to treat these
-                // cases, so we delegate the work. However it returns `UnifError` instead of
-                // `RowUnifError`, hence we have a bit of wrapping and unwrapping to do. Note that
-                // since we are unifying types with a constant or a unification variable somewhere,
-                // the only unification errors that should be possible are related to constants or
-                // row constraints.
-                (t1_tail, t2_tail) => {
-                    unify(state, ctxt, t1_tail, t2_tail).map_err(|err| match err {
-                        UnifError::ConstMismatch(c1, c2) => RowUnifError::ConstMismatch(c1, c2),
-                        UnifError::WithConst(c1, tyw) => RowUnifError::WithConst(c1, tyw),
-                        UnifError::RowConflict(id, tyw_opt, _, _) => {
-                            RowUnifError::UnsatConstr(id, tyw_opt)
-                        }
-                        err => panic!(
-                        "typechecker::unify_rows(): unexpected error while unifying row tails {:?}",
-                        err
-                    ),
-                    })
-                }
-            }
+            unify_row_tails(state, ctxt, *t, t2_tail)
         }
         (ty, _) if !ty.is_row_type() => Err(RowUnifError::IllformedRow(TypeWrapper::Concrete(ty))),
         (_, ty) => Err(RowUnifError::IllformedRow(TypeWrapper::Concrete(ty))),
     }
 }
 
+/// Unify the tails of two rows, which may be concrete rows, unification variables or constants.
+fn unify_row_tails(
+    state: &mut State,
+    ctxt: &Context,
+    t1_tail: TypeWrapper,
+    t2_tail: TypeWrapper,
+) -> Result<(), RowUnifError> {
+    match (t1_tail, t2_tail) {
+        (TypeWrapper::Concrete(r1_tail), TypeWrapper::Concrete(r2_tail)) => {
+            unify_rows(state, ctxt, r1_tail, r2_tail)
+        }
+        // If one of the tail is not a concrete type, it is either a unification variable
+        // or a constant (rigid type variable). `unify` already knows how to treat these
+        // cases, so we delegate the work. However it returns `UnifError` instead of
+        // `RowUnifError`, hence we have a bit of wrapping and unwrapping to do. Note that
+        // since we are unifying types with a constant or a unification variable somewhere,
+        // the only unification errors that should be possible are related to constants or
+        // row constraints.
+        (t1_tail, t2_tail) => unify(state, ctxt, t1_tail, t2_tail).map_err(|err| match err {
+            UnifError::ConstMismatch(c1, c2) => RowUnifError::ConstMismatch(c1, c2),
+            UnifError::WithConst(c1, tyw) => RowUnifError::WithConst(c1, tyw),
+            UnifError::RowConflict(id, tyw_opt, _, _) => RowUnifError::UnsatConstr(id, tyw_opt),
+            err => panic!(
+                "typechecker::unify_rows(): unexpected error while unifying row tails {:?}",
+                err
+            ),
+        }),
+    }
+}
+
+/// Determine if the type of a row is the type of an optional field.
+fn is_optional(tyw: &TypeWrapper) -> bool {
+    matches!(tyw, TypeWrapper::Concrete(AbsType::Optional(_)))
+}
+
+/// Return the root of a type if it is a unification variable, or the type itself otherwise.
+fn resolve(table: &UnifTable, tyw: TypeWrapper) -> TypeWrapper {
+    match tyw {
+        TypeWrapper::Ptr(p) => table.root(p),
+        tyw => tyw,
+    }
+}
+
+/// Return the tags of a closed enum type which aren't handled by the cases of a switch. Return an
+/// empty vector if all the tags are handled, or if the type isn't known to be a closed enum type.
+fn unhandled_tags(
+    table: &UnifTable,
+    ty: TypeWrapper,
+    cases: &HashMap<Ident, RichTerm>,
+) -> Vec<Ident> {
+    let resolve = |ty: TypeWrapper| match ty {
+        TypeWrapper::Ptr(p) => table.root(p),
+        ty => ty,
+    };
+
+    let mut row = match resolve(ty) {
+        TypeWrapper::Concrete(AbsType::Enum(row)) => resolve(*row),
+        _ => return Vec::new(),
+    };
+    let mut unhandled = Vec::new();
+
+    loop {
+        match row {
+            TypeWrapper::Concrete(AbsType::RowExtend(id, _, tail)) => {
+                if !cases.contains_key(&id) {
+                    unhandled.push(id);
+                }
+                row = resolve(*tail);
+            }
+            TypeWrapper::Concrete(AbsType::RowEmpty()) => return unhandled,
+            _ => return Vec::new(),
+        }
+    }
+}
+
+/// Return the fields of `ty` if it is a closed record type `{id1: ty1, .., idn: tyn}`.
+fn record_fields(table: &UnifTable, ty: TypeWrapper) -> Option<Vec<(Ident, TypeWrapper)>> {
+    let resolve = |ty: TypeWrapper| match ty {
+        TypeWrapper::Ptr(p) => table.root(p),
+        ty => ty,
+    };
+
+    let mut row = match resolve(ty) {
+        TypeWrapper::Concrete(AbsType::StaticRecord(row)) => resolve(*row),
+        _ => return None,
+    };
+    let mut fields = Vec::new();
+
+    loop {
+        match row {
+            TypeWrapper::Concrete(AbsType::RowExtend(id, Some(ty), tail)) => {
+                fields.push((id, *ty));
+                row = resolve(*tail);
+            }
+            TypeWrapper::Concrete(AbsType::RowEmpty()) => return Some(fields),
+            _ => return None,
+        }
+    }
+}
+
+/// Build the closed record type with the given fields.
+fn closed_record(fields: impl IntoIterator<Item = (Ident, TypeWrapper)>) -> TypeWrapper {
+    let row = fields
+        .into_iter()
+        .fold(mk_tyw_row!(), |acc, (id, ty)| mk_tyw_row!((id, ty); acc));
+    mk_tyw_record!(; row)
+}
+
 /// Extract the concrete type corresponding to a type wrapper. Free unification variables as well
 /// as type constants are replaced with the type `Dyn`.
 fn to_type(table: &UnifTable, ty: TypeWrapper) -> Types {
@@ -1488,7 +2840,7 @@ fn to_type(table: &UnifTable, ty: TypeWrapper) -> Types {
             t @ TypeWrapper::Concrete(_) => to_type(table, t),
             _ => Types(AbsType::Dyn()),
         },
-        TypeWrapper::Constant(_) => Types(AbsType::Dyn()),
+        TypeWrapper::Constant(_) | TypeWrapper::Error => Types(AbsType::Dyn()),
         TypeWrapper::Concrete(t) => {
             let mapped = t.map(|btyp| Box::new(to_type(table, *btyp)));
             Types(mapped)
@@ -1633,7 +2985,9 @@ fn constraint(state: &mut State, x: TypeWrapper, id: Ident) -> Result<(), RowUni
             tyw @ TypeWrapper::Constant(_) | tyw @ TypeWrapper::Contract(..) => {
                 Err(RowUnifError::IllformedRow(tyw))
             }
+            TypeWrapper::Error => Ok(()),
         },
+        TypeWrapper::Error => Ok(()),
         TypeWrapper::Concrete(AbsType::RowEmpty()) => Ok(()),
         TypeWrapper::Concrete(AbsType::RowExtend(id2, tyw, t)) => {
             if id2 == id {
@@ -1670,11 +3024,15 @@ fn constrain_var(state: &mut State, tyw: &TypeWrapper, p: usize) {
                 tyw => constrain_var_(state, constr, &tyw, p),
             },
             TypeWrapper::Concrete(ty) => match ty {
-                AbsType::Arrow(tyw1, tyw2) => {
+                AbsType::Arrow(tyw1, tyw2)
+                | AbsType::Union(tyw1, tyw2)
+                | AbsType::Intersection(tyw1, tyw2) => {
                     constrain_var_(state, HashSet::new(), tyw1.as_ref(), p);
                     constrain_var_(state, HashSet::new(), tyw2.as_ref(), p);
                 }
-                AbsType::Forall(_, tyw) => constrain_var_(state, HashSet::new(), tyw.as_ref(), p),
+                AbsType::Forall(_, tyw) | AbsType::Rec(_, tyw) => {
+                    constrain_var_(state, HashSet::new(), tyw.as_ref(), p)
+                }
                 AbsType::Dyn()
                 | AbsType::Num()
                 | AbsType::Bool()
@@ -1684,7 +3042,9 @@ fn constrain_var(state: &mut State, tyw: &TypeWrapper, p: usize) {
                 | AbsType::RowEmpty()
                 | AbsType::Var(_)
                 | AbsType::Wildcard(_) => (),
-                AbsType::Array(tyw) => constrain_var_(state, HashSet::new(), tyw.as_ref(), p),
+                AbsType::Array(tyw) | AbsType::Optional(tyw) => {
+                    constrain_var_(state, HashSet::new(), tyw.as_ref(), p)
+                }
                 AbsType::RowExtend(id, tyw, rest) => {
                     constr.insert(id.clone());
                     tyw.iter()
@@ -1695,7 +3055,7 @@ fn constrain_var(state: &mut State, tyw: &TypeWrapper, p: usize) {
                 AbsType::StaticRecord(row) => constrain_var_(state, constr, row, p),
                 AbsType::DynRecord(tyw) => constrain_var_(state, constr, tyw, p),
             },
-            TypeWrapper::Constant(_) | TypeWrapper::Contract(..) => (),
+            TypeWrapper::Constant(_) | TypeWrapper::Contract(..) | TypeWrapper::Error => (),
         }
     }
 
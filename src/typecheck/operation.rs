@@ -50,7 +50,7 @@ pub fn get_uop_type(
             (mk_tyw_enum!(; row.clone()), mk_tyw_enum!(id.clone(); row))
         }
         // This should not happen, as Switch() is only produced during evaluation.
-        UnaryOp::Switch(_) => panic!("cannot typecheck Switch()"),
+        UnaryOp::Switch(..) => panic!("cannot typecheck Switch()"),
         // Dyn -> Dyn
         UnaryOp::ChangePolarity() | UnaryOp::GoDom() | UnaryOp::GoCodom() | UnaryOp::GoArray() => {
             (mk_typewrapper::dynamic(), mk_typewrapper::dynamic())
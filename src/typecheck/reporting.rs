@@ -123,5 +123,6 @@ pub fn to_type(
             Types(mapped)
         }
         TypeWrapper::Contract(t, _) => Types(AbsType::Flat(t)),
+        TypeWrapper::Error => Types(AbsType::Dyn()),
     }
 }
@@ -12,7 +12,13 @@
 //!
 //! - `->`: the function type, or arrow
 //! - `forall a. type`: polymorphic type
+//! - `rec a. type`: recursive type, where `a` stands for the whole type inside `type`
 //! - `#customContract`: an opaque type created from an user-defined contract
+//! - `A \/ B`: union type, for values which are either of type `A` or of type `B`
+//! - `A /\ B`: intersection type, for values which are both of type `A` and of type `B`
+//!
+//! Union and intersection types are only checked at runtime: the typechecker doesn't implement
+//! subtyping, and considers them as opaque types which are only equal to themselves.
 //!
 //! # Record types
 //!
@@ -32,6 +38,9 @@
 //! The type `{ myField : Num, a }` indicates that any argument must have at least the field
 //! `myField` of type `Num`, but may contain any other fields (or no additional field at all).
 //!
+//! A field of a row can be optional, as in `{ myField : Num | optional }`. An optional field may
+//! be absent, but if it is present, it must have the given type.
+//!
 //! ## Dynamic records
 //!
 //! A second type available for records is the dynamic record type `{ _ : Type }`. A record of this
@@ -59,7 +68,10 @@ use crate::{
     term::{RichTerm, Term, TraverseOrder},
 };
 
-use std::{collections::HashMap, fmt};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
 
 /// A Nickel type.
 #[derive(Clone, PartialEq, Debug)]
@@ -84,6 +96,11 @@ pub enum AbsType<Ty> {
     Var(Ident),
     /// A forall binder.
     Forall(Ident, Ty),
+    /// A recursive type `rec id. ty`, where `id` stands for the whole type inside `ty`.
+    ///
+    /// Recursive types are equi-recursive: `rec id. ty` is equal to its unfolding, `ty` where `id`
+    /// has been substituted for `rec id. ty`.
+    Rec(Ident, Ty),
 
     /// An empty row, terminating a row type.
     RowEmpty(),
@@ -104,6 +121,13 @@ pub enum AbsType<Ty> {
     Array(Ty),
     /// A type wildcard, wrapping an ID unique within a given file.
     Wildcard(usize),
+    /// A union type `A \/ B`.
+    Union(Ty, Ty),
+    /// An intersection type `A /\ B`.
+    Intersection(Ty, Ty),
+    /// The type of an optional field `id: Ty | optional` of a record row. It only appears directly
+    /// as the type of a row, as in `RowExtend(id, Some(Optional(ty)), tail)`.
+    Optional(Ty),
 }
 
 impl<Ty> AbsType<Ty> {
@@ -121,6 +145,7 @@ impl<Ty> AbsType<Ty> {
             AbsType::Arrow(s, t) => Ok(AbsType::Arrow(f(s)?, f(t)?)),
             AbsType::Var(i) => Ok(AbsType::Var(i)),
             AbsType::Forall(i, t) => Ok(AbsType::Forall(i, f(t)?)),
+            AbsType::Rec(i, t) => Ok(AbsType::Rec(i, f(t)?)),
             AbsType::RowEmpty() => Ok(AbsType::RowEmpty()),
             AbsType::RowExtend(id, t1, t2) => {
                 let t1_mapped = match t1 {
@@ -135,6 +160,9 @@ impl<Ty> AbsType<Ty> {
             AbsType::DynRecord(t) => Ok(AbsType::DynRecord(f(t)?)),
             AbsType::Array(t) => Ok(AbsType::Array(f(t)?)),
             AbsType::Wildcard(i) => Ok(AbsType::Wildcard(i)),
+            AbsType::Union(s, t) => Ok(AbsType::Union(f(s)?, f(t)?)),
+            AbsType::Intersection(s, t) => Ok(AbsType::Intersection(f(s)?, f(t)?)),
+            AbsType::Optional(t) => Ok(AbsType::Optional(f(t)?)),
         }
     }
 
@@ -296,16 +324,35 @@ impl Types {
             AbsType::RowEmpty() | AbsType::RowExtend(..) => contract::fail(),
             AbsType::Enum(ref r) => {
                 let mut cases = HashMap::new();
+                let mut variants = HashSet::new();
                 let mut has_tail = false;
                 let value_arg = Ident::from("x");
                 let label_arg = Ident::from("l");
+                let payload_arg = Ident::from("p");
 
                 for row in r.iter_as_rows() {
                     match row {
-                        RowIteratorItem::Row(id, _ty) => {
-                            debug_assert!(_ty.is_none());
+                        RowIteratorItem::Row(id, None) => {
                             cases.insert(id.clone(), mk_term::var(value_arg.clone()));
                         }
+                        // A variant is rebuilt with the contract of the payload applied to its
+                        // argument.
+                        RowIteratorItem::Row(id, Some(ty)) => {
+                            let payload = mk_app!(
+                                contract::enum_payload(),
+                                ty.subcontract(h.clone(), pol, sy)?,
+                                mk_term::var(label_arg.clone()),
+                                mk_term::var(payload_arg.clone())
+                            );
+                            cases.insert(
+                                id.clone(),
+                                mk_fun!(
+                                    payload_arg.clone(),
+                                    Term::EnumVariant(id.clone(), payload)
+                                ),
+                            );
+                            variants.insert(id.clone());
+                        }
                         RowIteratorItem::Tail(tail) => {
                             // We only expect a type variable in tail position
                             debug_assert!(matches!(tail, Types(AbsType::Var(_))));
@@ -315,34 +362,40 @@ impl Types {
                     }
                 }
 
-                // If the enum type has a tail, the tail must be a universally quantified variable,
-                // and this means that the tag can be anything.
-                let case_body = if has_tail {
+                // If the enum type has a tail and no variant, the tail must be a universally
+                // quantified variable, and this means that the tag can be anything.
+                let case_body = if has_tail && variants.is_empty() {
                     mk_term::var(value_arg.clone())
                 }
                 // Otherwise, we build a switch with all the tags as cases, which just returns the
-                // original argument, and a default case that blames.
+                // original argument, or rebuild the variant with the payload contract applied.
+                // The default case blames if the enum type is closed, and returns the original
+                // argument otherwise.
                 //
-                // For example, for an enum type [| `foo, `bar, `baz |], the `case` function looks
-                // like:
+                // For example, for an enum type [| `foo, `bar Num, `baz |], the `case` function
+                // looks like:
                 //
                 // ```
                 // fun l x =>
                 //   switch {
                 //     `foo => x,
-                //     `bar => x,
+                //     `bar p => `bar ($enum_payload $num l p),
                 //     `baz => x,
                 //     _ => $enum_fail l
                 //   } x
                 // ```
                 else {
+                    let default = if has_tail {
+                        mk_term::var(value_arg.clone())
+                    } else {
+                        mk_app!(contract::enum_fail(), mk_term::var(label_arg.clone()))
+                    };
+
                     RichTerm::from(Term::Switch(
                         mk_term::var(value_arg.clone()),
                         cases,
-                        Some(mk_app!(
-                            contract::enum_fail(),
-                            mk_term::var(label_arg.clone())
-                        )),
+                        Some(default),
+                        variants,
                     ))
                 };
                 let case = mk_fun!(label_arg, value_arg, case_body);
@@ -362,13 +415,15 @@ impl Types {
                         AbsType::Var(id) => get_var(&h, id, false)?,
                         AbsType::RowExtend(id, Some(ty), rest) => {
                             let cont = form(sy, pol, rest.as_ref(), h.clone())?;
+                            let (ty, optional) = ty.split_optional();
                             let row_contr = ty.subcontract(h, pol, sy)?;
-                            mk_app!(
-                                contract::record_extend(),
-                                mk_term::string(format!("{}", id)),
-                                row_contr,
-                                cont
-                            )
+                            let extend = if optional {
+                                contract::record_extend_optional()
+                            } else {
+                                contract::record_extend()
+                            };
+
+                            mk_app!(extend, mk_term::string(format!("{}", id)), row_contr, cont)
                         }
                         ty => panic!(
                             "types::contract_open(): invalid row type {}",
@@ -385,11 +440,156 @@ impl Types {
                 mk_app!(contract::dyn_record(), ty.subcontract(h, pol, sy)?)
             }
             AbsType::Wildcard(_) => contract::dynamic(),
+            AbsType::Union(ref s, ref t) => mk_app!(
+                contract::union(),
+                s.shape_check(),
+                s.subcontract(h.clone(), pol, sy)?,
+                t.shape_check(),
+                t.subcontract(h, pol, sy)?
+            ),
+            AbsType::Intersection(ref s, ref t) => mk_app!(
+                contract::intersection(),
+                s.subcontract(h.clone(), pol, sy)?,
+                t.subcontract(h, pol, sy)?
+            ),
+            AbsType::Optional(ref ty) => ty.subcontract(h, pol, sy)?,
+            // The contract of a recursive type is a recursive contract, where each occurrence of
+            // the type variable refers back to the whole contract. Because the variable may only
+            // appear under a lazy contract (arrays, records, functions or enum payloads), the
+            // contract is only unfolded as the value is explored, and thus doesn't diverge on
+            // infinite structures.
+            AbsType::Rec(ref id, ref ty) => {
+                let rec_var = crate::transform::fresh_var();
+                h.insert(
+                    id.clone(),
+                    (mk_term::var(rec_var.clone()), contract::dyn_tail()),
+                );
+                let body = ty.subcontract(h, pol, sy)?;
+                mk_term::let_rec_in(rec_var.clone(), body, mk_term::var(rec_var))
+            }
         };
 
         Ok(ctr)
     }
 
+    /// Return the shape check of a type, which is used by union contracts to decide which
+    /// alternative a value belongs to.
+    ///
+    /// A shape check is a function taking a value and returning `` `yes `` if the value passes the
+    /// first-order part of the contract of the type, `` `no `` if it doesn't, and `` `maybe `` if
+    /// it can't tell. The first-order part is what a contract checks immediately, before
+    /// delegating the rest of the work to lazy checks: the kind of the value, the fields of a
+    /// record or the tag of an enum, but neither the elements of an array nor the content of the
+    /// fields. In consequence, a shape check never forces more of the value than the contract
+    /// itself would.
+    fn shape_check(&self) -> RichTerm {
+        use crate::stdlib::contract;
+
+        match &self.0 {
+            AbsType::Dyn() | AbsType::Wildcard(_) => contract::shape_any(),
+            AbsType::Num() => mk_app!(contract::shape_typeof(), Term::Enum(Ident::from("Num"))),
+            AbsType::Bool() => mk_app!(contract::shape_typeof(), Term::Enum(Ident::from("Bool"))),
+            AbsType::Str() => mk_app!(contract::shape_typeof(), Term::Enum(Ident::from("Str"))),
+            AbsType::Array(_) => {
+                mk_app!(contract::shape_typeof(), Term::Enum(Ident::from("Array")))
+            }
+            AbsType::Arrow(..) => mk_app!(contract::shape_typeof(), Term::Enum(Ident::from("Fun"))),
+            AbsType::DynRecord(_) => {
+                mk_app!(contract::shape_typeof(), Term::Enum(Ident::from("Record")))
+            }
+            // A sealed value or a symbol can be anything, and a custom contract can't be checked
+            // without being applied.
+            AbsType::Sym() | AbsType::Var(_) | AbsType::Forall(..) => contract::shape_unknown(),
+            // The variable of a recursive type is guarded by a lazy type constructor, so it never
+            // occurs in the first-order part of the body.
+            AbsType::Rec(_, ty) => ty.shape_check(),
+            AbsType::Flat(t) => mk_app!(contract::shape_flat(), t.clone()),
+            AbsType::Enum(row) => {
+                let value_arg = Ident::from("x");
+                let mut cases = HashMap::new();
+                let mut variants = HashSet::new();
+                let mut open = false;
+
+                for item in row.iter_as_rows() {
+                    match item {
+                        RowIteratorItem::Row(id, None) => {
+                            cases.insert(id.clone(), Term::Enum(Ident::from("yes")).into());
+                        }
+                        RowIteratorItem::Row(id, Some(_)) => {
+                            cases.insert(id.clone(), mk_fun!("p", Term::Enum(Ident::from("yes"))));
+                            variants.insert(id.clone());
+                        }
+                        RowIteratorItem::Tail(_) => open = true,
+                    }
+                }
+
+                if open {
+                    mk_app!(contract::shape_typeof(), Term::Enum(Ident::from("Enum")))
+                } else {
+                    let case = mk_fun!(
+                        value_arg.clone(),
+                        Term::Switch(
+                            mk_term::var(value_arg),
+                            cases,
+                            Some(Term::Enum(Ident::from("no")).into()),
+                            variants,
+                        )
+                    );
+                    mk_app!(contract::shape_enum(), case)
+                }
+            }
+            AbsType::StaticRecord(row) => {
+                let mut fields = Vec::new();
+                let mut optionals = Vec::new();
+                let mut open = false;
+
+                for item in row.iter_as_rows() {
+                    match item {
+                        RowIteratorItem::Row(id, Some(ty)) if ty.split_optional().1 => {
+                            optionals.push(mk_term::string(id.to_string()))
+                        }
+                        RowIteratorItem::Row(id, _) => fields.push(mk_term::string(id.to_string())),
+                        RowIteratorItem::Tail(_) => open = true,
+                    }
+                }
+
+                mk_app!(
+                    contract::shape_record(),
+                    Term::Array(fields, Default::default()),
+                    Term::Array(optionals, Default::default()),
+                    Term::Bool(open)
+                )
+            }
+            AbsType::Union(s, t) => mk_app!(contract::shape_or(), s.shape_check(), t.shape_check()),
+            AbsType::Intersection(s, t) => {
+                mk_app!(contract::shape_and(), s.shape_check(), t.shape_check())
+            }
+            AbsType::Optional(ty) => ty.shape_check(),
+            AbsType::RowEmpty() | AbsType::RowExtend(..) => contract::shape_unknown(),
+        }
+    }
+
+    /// Return `true` if the shape checks of two types both answer `` `yes `` on at least one
+    /// value. Such types can't be used as the alternatives of a union: the union contract relies on
+    /// shape checks to select an alternative, so it would always pick the first one for these
+    /// values, and blame the ones which only satisfy the second (see
+    /// [`shape_check`](Types::shape_check)).
+    ///
+    /// The analysis is conservative: shapes which can't be decided statically, such as the ones
+    /// of custom contracts or of intersections, are never considered to overlap.
+    pub fn shape_overlaps(&self, other: &Types) -> bool {
+        Shape::of(self).overlaps(&Shape::of(other))
+    }
+
+    /// Return the type of a row, stripped of the optional marker if any, together with a boolean
+    /// indicating if the corresponding field is optional.
+    pub fn split_optional(&self) -> (&Types, bool) {
+        match &self.0 {
+            AbsType::Optional(ty) => (ty.as_ref(), true),
+            _ => (self, false),
+        }
+    }
+
     /// Find a binding in a record row type. Return `None` if there is no such binding, if the type
     /// is not a row type, or if the row is an enum row.
     pub fn row_find(&self, ident: &Ident) -> Option<Self> {
@@ -439,6 +639,16 @@ impl Types {
         }
     }
 
+    /// Determine if a type can be printed as the payload of an enum variant without parentheses.
+    /// Record and enum types are delimited, and don't need them either.
+    pub(crate) fn fmt_is_payload_atom(&self) -> bool {
+        self.fmt_is_atom()
+            || matches!(
+                self.0,
+                AbsType::StaticRecord(_) | AbsType::DynRecord(_) | AbsType::Enum(_)
+            )
+    }
+
     /// Apply a transformation on a whole type by mapping a faillible function `f` on each node in
     /// manner as prescribed by the order.
     /// `f` may return a generic error `E` and use the state `S` which is passed around.
@@ -466,9 +676,26 @@ impl Types {
             | AbsType::RowEmpty()
             | AbsType::Flat(_)
             | AbsType::Wildcard(_) => Ok(ty.0),
+            AbsType::Union(ty1, ty2) => {
+                let ty1 = (*ty1).traverse(f, state, order)?;
+                let ty2 = (*ty2).traverse(f, state, order)?;
+                Ok(AbsType::Union(Box::new(ty1), Box::new(ty2)))
+            }
+            AbsType::Intersection(ty1, ty2) => {
+                let ty1 = (*ty1).traverse(f, state, order)?;
+                let ty2 = (*ty2).traverse(f, state, order)?;
+                Ok(AbsType::Intersection(Box::new(ty1), Box::new(ty2)))
+            }
+            AbsType::Optional(ty_inner) => (*ty_inner)
+                .traverse(f, state, order)
+                .map(Box::new)
+                .map(AbsType::Optional),
             AbsType::Forall(id, ty_inner) => (*ty_inner)
                 .traverse(f, state, order)
                 .map(|ty| AbsType::Forall(id, Box::new(ty))),
+            AbsType::Rec(id, ty_inner) => (*ty_inner)
+                .traverse(f, state, order)
+                .map(|ty| AbsType::Rec(id, Box::new(ty))),
             AbsType::Enum(ty_inner) => (*ty_inner)
                 .traverse(f, state, order)
                 .map(Box::new)
@@ -517,6 +744,126 @@ impl Types {
     }
 }
 
+/// A static approximation of the shape check of a type, describing the set of values for which it
+/// answers `` `yes ``. See [`Types::shape_overlaps`].
+enum Shape<'a> {
+    /// Every value.
+    Any,
+    /// The values of a given kind, as returned by `typeof`.
+    Kind(&'static str),
+    /// The enum values with one of the given tags.
+    Tags(Vec<&'a Ident>),
+    /// The records having all the `required` fields, and no other field than the `optional` ones
+    /// if the record isn't `open`.
+    Record {
+        required: Vec<&'a Ident>,
+        optional: Vec<&'a Ident>,
+        open: bool,
+    },
+    /// The union of two shapes.
+    Or(Box<Shape<'a>>, Box<Shape<'a>>),
+    /// A shape which can't be decided statically.
+    Unknown,
+}
+
+impl<'a> Shape<'a> {
+    fn of(ty: &'a Types) -> Self {
+        match &ty.0 {
+            AbsType::Dyn() | AbsType::Wildcard(_) => Shape::Any,
+            AbsType::Num() => Shape::Kind("Num"),
+            AbsType::Bool() => Shape::Kind("Bool"),
+            AbsType::Str() => Shape::Kind("Str"),
+            AbsType::Array(_) => Shape::Kind("Array"),
+            AbsType::Arrow(..) => Shape::Kind("Fun"),
+            AbsType::DynRecord(_) => Shape::Kind("Record"),
+            AbsType::Rec(_, ty) | AbsType::Optional(ty) => Shape::of(ty),
+            AbsType::Enum(row) => {
+                let mut tags = Vec::new();
+
+                for item in row.iter_as_rows() {
+                    match item {
+                        RowIteratorItem::Row(id, _) => tags.push(id),
+                        RowIteratorItem::Tail(_) => return Shape::Kind("Enum"),
+                    }
+                }
+
+                Shape::Tags(tags)
+            }
+            AbsType::StaticRecord(row) => {
+                let mut required = Vec::new();
+                let mut optional = Vec::new();
+                let mut open = false;
+
+                for item in row.iter_as_rows() {
+                    match item {
+                        RowIteratorItem::Row(id, Some(ty)) if ty.split_optional().1 => {
+                            optional.push(id)
+                        }
+                        RowIteratorItem::Row(id, _) => required.push(id),
+                        RowIteratorItem::Tail(_) => open = true,
+                    }
+                }
+
+                Shape::Record {
+                    required,
+                    optional,
+                    open,
+                }
+            }
+            AbsType::Union(s, t) => Shape::Or(Box::new(Shape::of(s)), Box::new(Shape::of(t))),
+            AbsType::Sym()
+            | AbsType::Var(_)
+            | AbsType::Forall(..)
+            | AbsType::Flat(_)
+            | AbsType::Intersection(..)
+            | AbsType::RowEmpty()
+            | AbsType::RowExtend(..) => Shape::Unknown,
+        }
+    }
+
+    fn overlaps(&self, other: &Shape) -> bool {
+        match (self, other) {
+            (Shape::Unknown, _) | (_, Shape::Unknown) => false,
+            (Shape::Or(s, t), other) | (other, Shape::Or(s, t)) => {
+                s.overlaps(other) || t.overlaps(other)
+            }
+            (Shape::Any, _) | (_, Shape::Any) => true,
+            (Shape::Kind(k1), Shape::Kind(k2)) => k1 == k2,
+            (Shape::Kind(kind), Shape::Tags(_)) | (Shape::Tags(_), Shape::Kind(kind)) => {
+                *kind == "Enum"
+            }
+            (Shape::Kind(kind), Shape::Record { .. })
+            | (Shape::Record { .. }, Shape::Kind(kind)) => *kind == "Record",
+            (Shape::Tags(tags1), Shape::Tags(tags2)) => tags1.iter().any(|id| tags2.contains(id)),
+            (
+                Shape::Record {
+                    required: req1,
+                    optional: opt1,
+                    open: open1,
+                },
+                Shape::Record {
+                    required: req2,
+                    optional: opt2,
+                    open: open2,
+                },
+            ) => {
+                // The smallest candidate is the record with exactly the fields required by both
+                // sides: it must be allowed by each closed record.
+                let allows = |required: &[&Ident], optional: &[&Ident], fields: &[&Ident]| {
+                    fields
+                        .iter()
+                        .all(|id| required.contains(id) || optional.contains(id))
+                };
+
+                (*open1 || allows(req1, opt1, req2)) && (*open2 || allows(req2, opt2, req1))
+            }
+            (Shape::Tags(_), Shape::Record { .. }) | (Shape::Record { .. }, Shape::Tags(_)) => {
+                false
+            }
+        }
+    }
+}
+
 impl fmt::Display for Types {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match &self.0 {
@@ -545,7 +892,31 @@ impl fmt::Display for Types {
                 }
                 write!(f, ". {}", curr)
             }
-            AbsType::Enum(row) => write!(f, "[|{}|]", row),
+            AbsType::Rec(i, ref ty) => write!(f, "rec {}. {}", i, ty),
+            AbsType::Enum(row) => {
+                write!(f, "[|")?;
+
+                for (i, item) in row.iter_as_rows().enumerate() {
+                    match item {
+                        RowIteratorItem::Row(id, ty_opt) => {
+                            if i > 0 {
+                                write!(f, ", ")?;
+                            }
+                            write!(f, "`{}", id)?;
+
+                            match ty_opt {
+                                Some(ty) if ty.fmt_is_payload_atom() => write!(f, " {}", ty)?,
+                                Some(ty) => write!(f, " ({})", ty)?,
+                                None => (),
+                            }
+                        }
+                        RowIteratorItem::Tail(tail) if i == 0 => write!(f, "{}", tail)?,
+                        RowIteratorItem::Tail(tail) => write!(f, " ; {}", tail)?,
+                    }
+                }
+
+                write!(f, "|]")
+            }
             AbsType::StaticRecord(row) => write!(f, "{{{}}}", row),
             AbsType::DynRecord(ty) => write!(f, "{{_: {}}}", ty),
             AbsType::RowEmpty() => Ok(()),
@@ -564,14 +935,62 @@ impl fmt::Display for Types {
                 }
             }
             AbsType::Arrow(dom, codom) => match dom.0 {
-                AbsType::Arrow(_, _) => write!(f, "({}) -> {}", dom, codom),
+                AbsType::Arrow(_, _) | AbsType::Rec(..) => write!(f, "({}) -> {}", dom, codom),
                 _ => write!(f, "{} -> {}", dom, codom),
             },
             AbsType::Wildcard(_) => write!(f, "_"),
+            AbsType::Optional(ty) => write!(f, "{} | optional", ty),
+            // `/\` binds tighter than `\/`, which binds tighter than `->`. Both are left
+            // associative.
+            AbsType::Union(s, t) => {
+                let parens_s = matches!(
+                    s.0,
+                    AbsType::Arrow(..) | AbsType::Forall(..) | AbsType::Rec(..)
+                );
+                let parens_t = matches!(
+                    t.0,
+                    AbsType::Arrow(..)
+                        | AbsType::Forall(..)
+                        | AbsType::Rec(..)
+                        | AbsType::Union(..)
+                );
+                fmt_operand(f, s, parens_s)?;
+                write!(f, " \\/ ")?;
+                fmt_operand(f, t, parens_t)
+            }
+            AbsType::Intersection(s, t) => {
+                let parens_s = matches!(
+                    s.0,
+                    AbsType::Arrow(..)
+                        | AbsType::Forall(..)
+                        | AbsType::Rec(..)
+                        | AbsType::Union(..)
+                );
+                let parens_t = matches!(
+                    t.0,
+                    AbsType::Arrow(..)
+                        | AbsType::Forall(..)
+                        | AbsType::Rec(..)
+                        | AbsType::Union(..)
+                        | AbsType::Intersection(..)
+                );
+                fmt_operand(f, s, parens_s)?;
+                write!(f, " /\\ ")?;
+                fmt_operand(f, t, parens_t)
+            }
         }
     }
 }
 
+/// Format the operand of a binary type operator, wrapping it in parentheses if required.
+fn fmt_operand(f: &mut fmt::Formatter, ty: &Types, parens: bool) -> fmt::Result {
+    if parens {
+        write!(f, "({})", ty)
+    } else {
+        write!(f, "{}", ty)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::Types;
@@ -628,6 +1047,8 @@ mod test {
 
         assert_format_eq("[|`a, `b, `c, `d|]");
         assert_format_eq("forall r. [|`tag1, `tag2, `tag3 ; r|]");
+        assert_format_eq("[|`a Num, `b, `c {x: Str}, `d (Array Num)|]");
+        assert_format_eq("forall r. [|`tag1 (Num -> Num), `tag2 ; r|]");
 
         assert_format_eq("Array Num");
         assert_format_eq("Array (Array Num)");
@@ -639,5 +1060,20 @@ mod test {
         assert_format_eq("_ -> _");
         assert_format_eq("{x: _, y: Bool}");
         assert_format_eq("{_: _}");
+
+        assert_format_eq("Num \\/ Str");
+        assert_format_eq("Num \\/ Str \\/ Bool");
+        assert_format_eq("Num \\/ (Str \\/ Bool)");
+        assert_format_eq("Num /\\ Str \\/ Bool");
+        assert_format_eq("Num /\\ (Str \\/ Bool)");
+        assert_format_eq("(Num -> Num) \\/ Array Str -> Bool");
+        assert_format_eq("{x: Num \\/ Str} /\\ {_: Dyn}");
+
+        assert_format_eq("{x: Num, y: Str | optional}");
+        assert_format_eq("forall r. {x: Num | optional ; r} -> Num");
+
+        assert_format_eq("rec T. {children: Array T, name: Str}");
+        assert_format_eq("(rec T. [|`nil, `cons {head: Num, tail: T}|]) -> Num");
+        assert_format_eq("rec T. Array T \\/ Num");
     }
 }
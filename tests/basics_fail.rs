@@ -62,3 +62,15 @@ fn string_chunks() {
         Err(Error::EvalError(EvalError::TypeError(..)))
     );
 }
+
+#[test]
+fn large_exponents() {
+    assert_matches!(
+        eval("num.pow 10 2000000000"),
+        Err(Error::EvalError(EvalError::Other(..)))
+    );
+    assert_matches!(
+        eval("string.to_num \"1e999999999\""),
+        Err(Error::EvalError(EvalError::Other(..)))
+    );
+}
@@ -0,0 +1,50 @@
+use nickel_lang::{
+    cache::resolvers::DummyResolver,
+    typecheck::{self, Environment},
+};
+
+use nickel_lang_utilities::parse;
+
+/// Typecheck an expression and return the number of type annotations proven statically.
+fn count_checked(s: &str) -> usize {
+    let rt = parse(s).unwrap();
+    let (_, checked_annots) =
+        typecheck::type_check(&rt, Environment::new(), &DummyResolver {}).unwrap();
+    checked_annots.len()
+}
+
+#[test]
+fn untyped_annotations_are_not_proven() {
+    assert_eq!(count_checked("let x : Num = 1 in x"), 0);
+    assert_eq!(count_checked("1 : Num"), 0);
+}
+
+#[test]
+fn first_order_annotations() {
+    assert_eq!(count_checked("(let x : Num = 1 in x) : Num"), 1);
+    assert_eq!(count_checked("((1 : Num) + (2 : Num)) : Num"), 2);
+    assert_eq!(count_checked("{a : Num = 1} : {a : Num}"), 1);
+    assert_eq!(count_checked("(let x : Num = 1 in (x + 1 | Num)) : Num"), 1);
+}
+
+#[test]
+fn higher_order_annotations() {
+    assert_eq!(
+        count_checked("(let f : Num -> Num = fun x => x + 1 in f 1) : Num"),
+        1
+    );
+    assert_eq!(
+        count_checked("(let f : forall a. a -> a = fun x => x in f 1) : Num"),
+        1
+    );
+    // The function may escape through the untyped part of the block.
+    assert_eq!(
+        count_checked("(let f : Num -> Num = fun x => x + 1 in (f 1 | Num)) : Num"),
+        0
+    );
+    // Record fields may be accessed from outside of the block.
+    assert_eq!(
+        count_checked("{f : Num -> Num = fun x => x + 1} : {f : Num -> Num}"),
+        0
+    );
+}
@@ -30,6 +30,14 @@ fn id_fail() {
     );
 }
 
+#[test]
+fn typed_block_with_untyped_part() {
+    assert_raise_blame!("(let f : Num -> Num = fun x => x + 1 in (f \"a\" | Num)) : Num");
+    assert_raise_blame!(
+        "let r = ((let f : Num -> Num = fun x => x + 1 in {g = f}) : {g : Num -> Num}) in r.g \"a\""
+    );
+}
+
 #[test]
 fn enum_simple() {
     assert_raise_blame!("`far | [|`foo, `bar|]");
@@ -37,6 +45,45 @@ fn enum_simple() {
     assert_raise_blame!("`foo | [| |]");
 }
 
+#[test]
+fn enum_variants() {
+    assert_raise_blame!("%deep_seq% (`foo \"a\" | [| `foo Num |]) null");
+    assert_raise_blame!("`foo | [| `foo Num |]");
+    assert_raise_blame!("`foo 1 | [| `foo, `bar |]");
+    assert_raise_blame!("%deep_seq% (`bar {a = \"a\"} | forall r. [| `bar {a: Num} ; r |]) null");
+}
+
+#[test]
+fn optional_fields() {
+    assert_raise_blame!("({port = \"80\"} | {port: Num | optional}).port");
+    assert_raise_blame!("{host = \"a\"} | {port: Num | optional}");
+    assert_raise_blame!("{port = 80} | {host: Str, port: Num | optional}");
+}
+
+#[test]
+fn unions_and_intersections() {
+    assert_raise_blame!("true | Num \\/ Str");
+    assert_raise_blame!("%deep_seq% ([1, true] | Array (Num \\/ Str)) null");
+    assert_raise_blame!("({a = \"a\"} | {a: Num} \\/ Str).a");
+    assert_raise_blame!("{c = 1} | {a: Num} \\/ {b: Str}");
+    assert_raise_blame!("let f | (Num -> Num) \\/ Str = fun x => x in f \"a\"");
+    assert_raise_blame!("1 | Num /\\ Str");
+    assert_raise_blame!("({a = 1, b = 1} | {a: Num, ..} /\\ {b: Str, ..}).b");
+}
+
+#[test]
+fn recursive_types() {
+    assert_raise_blame!(
+        "let tree = {name = \"root\", children = [{name = 1, children = []}]} in
+         %deep_seq% (tree | rec T. {name: Str, children: Array T}) null"
+    );
+    assert_raise_blame!(
+        "let rec s = {head = 1, tail = {head = \"a\", tail = s}} in
+         (s | rec T. {head: Num, tail: T}).tail.head"
+    );
+    assert_raise_blame!("%deep_seq% ([1, [true]] | rec T. Array (Num \\/ T)) null");
+}
+
 #[test]
 fn metavalue_contract_default_fail() {
     assert_raise_blame!("true | default | Num");
@@ -56,7 +56,8 @@ fn mixed() {
 fn assign_fail() {
     assert_matches!(
         eval_file("destructuring/assign_fail.ncl"),
-        Err(Error::TypecheckError(TypecheckError::UnboundIdentifier(..)))
+        Err(Error::TypecheckError(TypecheckError::Multiple(errors)))
+            if errors.iter().all(|err| matches!(err, TypecheckError::UnboundIdentifier(..)))
     );
 }
 
@@ -1,5 +1,6 @@
 use assert_matches::assert_matches;
 use nickel_lang::error::{Error, EvalError, ImportError, TypecheckError};
+use nickel_lang::number::Number;
 use nickel_lang::program::Program;
 use nickel_lang::term::Term;
 use std::io::BufReader;
@@ -21,7 +22,7 @@ fn nested() {
         "should_be = 3",
     )
     .unwrap();
-    assert_eq!(prog.eval().map(Term::from), Ok(Term::Num(3.)));
+    assert_eq!(prog.eval().map(Term::from), Ok(Term::Num(Number::from(3))));
 }
 
 #[test]
@@ -31,7 +32,7 @@ fn root_path() {
         "should_be = 44",
     )
     .unwrap();
-    assert_eq!(prog.eval().map(Term::from), Ok(Term::Num(44.)));
+    assert_eq!(prog.eval().map(Term::from), Ok(Term::Num(Number::from(44))));
 }
 
 #[test]
@@ -41,7 +42,7 @@ fn multi_imports() {
         "should_be = 5",
     )
     .unwrap();
-    assert_eq!(prog.eval().map(Term::from), Ok(Term::Num(5.)));
+    assert_eq!(prog.eval().map(Term::from), Ok(Term::Num(Number::from(5))));
 }
 
 #[test]
@@ -83,6 +84,93 @@ fn static_typing_fail() {
     );
 }
 
+#[test]
+fn typed_data_imports() {
+    let mut prog = Program::new_from_source(
+        BufReader::new(
+            format!(
+                "let data = {} in
+                 let yaml = {} in
+                 let toml = {} in
+                 (array.length data.servers + data.port + array.length yaml.ports
+                   + string.length (array.head data.servers).host
+                   + string.length toml.owner.name : Num) == 8102
+                 && (record.has_field \"PATH\" (array.elem_at 1 data.env) : Bool)",
+                mk_import("data.json"),
+                mk_import("data.yaml"),
+                mk_import("data.toml")
+            )
+            .as_bytes(),
+        ),
+        "should_succeed",
+    )
+    .unwrap();
+    assert_eq!(prog.eval().map(Term::from), Ok(Term::Bool(true)));
+}
+
+#[test]
+fn typed_data_imports_fail() {
+    let mut prog = Program::new_from_source(
+        BufReader::new(format!("({}).port : Str", mk_import("data.json")).as_bytes()),
+        "should_fail",
+    )
+    .unwrap();
+    assert_matches!(prog.eval(), Err(Error::TypecheckError(..)));
+
+    let mut prog = Program::new_from_source(
+        BufReader::new(format!("({}).nonexistent : Str", mk_import("data.toml")).as_bytes()),
+        "should_fail",
+    )
+    .unwrap();
+    assert_matches!(prog.eval(), Err(Error::TypecheckError(..)));
+
+    // A field missing from some elements of an array doesn't make it optional: the elements are
+    // typed as `Dyn` instead.
+    let mut prog = Program::new_from_source(
+        BufReader::new(
+            format!("(array.head ({}).mixed).host : Str", mk_import("data.json")).as_bytes(),
+        ),
+        "should_fail",
+    )
+    .unwrap();
+    assert_matches!(prog.eval(), Err(Error::TypecheckError(..)));
+}
+
+#[test]
+fn typed_module_imports() {
+    let mut prog = Program::new_from_source(
+        BufReader::new(
+            format!(
+                "let lib = {} in (lib.inc (string.length lib.name) : Num)",
+                mk_import("typed_module.ncl")
+            )
+            .as_bytes(),
+        ),
+        "should_succeed",
+    )
+    .unwrap();
+    assert_eq!(prog.eval().map(Term::from), Ok(Term::Num(Number::from(6))));
+}
+
+#[test]
+fn typed_module_imports_fail() {
+    let mut prog = Program::new_from_source(
+        BufReader::new(
+            format!(
+                "let lib = {} in (lib.inc lib.name : Num)",
+                mk_import("typed_module.ncl")
+            )
+            .as_bytes(),
+        ),
+        "should_fail",
+    )
+    .unwrap();
+    assert_matches!(
+        prog.eval(),
+        Err(Error::TypecheckError(TypecheckError::TypeMismatch(..)))
+    );
+}
+
 #[test]
 fn serialize() {
     use nickel_lang::term::Term;
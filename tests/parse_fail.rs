@@ -1,5 +1,5 @@
 use assert_matches::assert_matches;
-use nickel_lang::error::Error;
+use nickel_lang::error::{Error, ParseError, ParseErrors};
 
 use nickel_lang_utilities::eval;
 
@@ -20,3 +20,35 @@ fn unexpected_token_buried() {
         Err(Error::ParseErrors(..))
     );
 }
+
+#[test]
+fn illformed_rec_types() {
+    assert_matches!(eval("1 | rec T. T"), Err(Error::ParseErrors(..)));
+    assert_matches!(eval("1 | rec T. Num \\/ T"), Err(Error::ParseErrors(..)));
+    assert_matches!(
+        eval("{} | rec T. {a: Num ; T}"),
+        Err(Error::ParseErrors(..))
+    );
+}
+
+/// Assert that parsing an expression fails with exactly one overlapping union error.
+macro_rules! assert_overlapping_union {
+    ($s:expr) => {
+        assert_matches!(
+            eval($s),
+            Err(Error::ParseErrors(ParseErrors { errors }))
+                if matches!(errors.as_slice(), [ParseError::OverlappingUnion(..)])
+        )
+    };
+}
+
+#[test]
+fn overlapping_unions() {
+    assert_overlapping_union!("{a = \"x\"} | {a: Num} \\/ {a: Str}");
+    assert_overlapping_union!("(fun x => x) | (Num -> Num) \\/ (Str -> Str)");
+    assert_overlapping_union!("[\"a\"] | Array Num \\/ Array Str");
+    assert_overlapping_union!("1 | Num \\/ Dyn");
+    assert_overlapping_union!("{a = 1} | {a: Num ; Dyn} \\/ {b: Str ; Dyn}");
+    assert_overlapping_union!("`foo | [| `foo, `bar |] \\/ [| `foo |]");
+    assert_overlapping_union!("1 | Str \\/ (Num \\/ Num)");
+}
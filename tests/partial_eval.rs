@@ -0,0 +1,162 @@
+use assert_matches::assert_matches;
+use nickel_lang::{
+    error::{Error, EvalError},
+    number::Number,
+    term::{BinaryOp, Term, UnaryOp},
+    transform::partial_eval,
+};
+
+use nickel_lang_utilities::{eval, parse};
+
+/// Partially evaluate an expression and return the result, skipping the `let` bindings which are
+/// kept by the transformation.
+fn fold(s: &str) -> Term {
+    let mut rt = partial_eval::transform(parse(s).unwrap());
+
+    while let Term::Let(_, _, body, _) = rt.as_ref() {
+        rt = body.clone();
+    }
+
+    rt.as_ref().clone()
+}
+
+#[test]
+fn folds_primops() {
+    assert_eq!(fold("1 + 2 * 3 - 1"), Term::Num(Number::from(6)));
+    assert_eq!(
+        fold("\"a\" ++ \"b\" ++ \"c\""),
+        Term::Str(String::from("abc"))
+    );
+    assert_eq!(fold("1 < 2 && !(2 == 3)"), Term::Bool(true));
+    assert_eq!(fold("`foo == `bar"), Term::Bool(false));
+    assert_eq!(fold("%typeof% {a = 1}"), Term::Enum("Record".into()));
+}
+
+#[test]
+fn leaves_failing_primops() {
+    assert_matches!(fold("1 / 0"), Term::Op2(BinaryOp::Div(), ..));
+    assert_matches!(fold("1 + \"a\""), Term::Op2(BinaryOp::Plus(), ..));
+    assert_matches!(fold("1 == \"a\""), Term::Op2(BinaryOp::Eq(), ..));
+    assert_matches!(fold("{a = 1}.b"), Term::Op1(UnaryOp::StaticAccess(_), _));
+}
+
+#[test]
+fn inlines_constants() {
+    assert_eq!(
+        fold("let x = 1 in let y = x + 1 in x + y"),
+        Term::Num(Number::from(3))
+    );
+    assert_eq!(
+        fold("let r = {a = 1, b = {c = \"c\"}} in r.b.c"),
+        Term::Str(String::from("c"))
+    );
+    assert_eq!(fold("let x = 1 in {a = x}.a"), Term::Num(Number::from(1)));
+    // Records with non constant fields are not inlined
+    assert_matches!(
+        fold("let r = {a = 1, b = fun x => x} in r.a"),
+        Term::Op1(UnaryOp::StaticAccess(_), _)
+    );
+}
+
+#[test]
+fn respects_shadowing() {
+    assert_matches!(
+        fold("let x = 1 in fun x => x"),
+        Term::FunPattern(_, _, body) if matches!(body.as_ref(), Term::Var(_))
+    );
+    assert_matches!(fold("let x = 1 in {x = 2, y = x}.y"), Term::Op1(..));
+    assert_matches!(
+        fold("let x = 1 in let {x, ..} = {x = 2} in x"),
+        Term::LetPattern(..)
+    );
+}
+
+#[test]
+fn resolves_branches() {
+    assert_eq!(
+        fold("if 1 < 2 then \"yes\" else \"no\""),
+        Term::Str(String::from("yes"))
+    );
+    assert_eq!(fold("false || true && false"), Term::Bool(false));
+    assert_eq!(
+        fold("switch {`foo => 1, `bar => 2, _ => 3} `bar"),
+        Term::Num(Number::from(2))
+    );
+    assert_eq!(
+        fold("switch {`foo => 1, _ => 3} `bar"),
+        Term::Num(Number::from(3))
+    );
+}
+
+#[test]
+fn compiles_regexes() {
+    let is_compiled = |t: Term| match t {
+        Term::Fun(_, body) => {
+            matches!(
+                body.as_ref(),
+                Term::Op1(
+                    UnaryOp::StrIsMatchCompiled(_) | UnaryOp::StrMatchCompiled(_),
+                    _
+                )
+            )
+        }
+        _ => false,
+    };
+
+    assert!(is_compiled(fold("%str_is_match% \"[0-9]+\"")));
+    assert!(is_compiled(fold("%str_match% \"[0-9]+\"")));
+    assert!(is_compiled(fold("string.is_match \"[0-9]+\"")));
+    // Unless `string` doesn't refer to the standard library anymore
+    assert!(!is_compiled(fold(
+        "let string = {is_match = fun x y => true} in string.is_match \"[0-9]+\""
+    )));
+    assert!(!is_compiled(fold(
+        "fun string => string.is_match \"[0-9]+\""
+    )));
+    // Invalid regexes are reported at runtime
+    assert!(!is_compiled(fold("%str_is_match% \"(\"")));
+}
+
+#[test]
+fn semantics_unchanged() {
+    assert_eq!(
+        eval("let x = 2 in let r = {a = x, b = x * 2} in r.a + r.b"),
+        Ok(Term::Num(Number::from(6)))
+    );
+    assert_eq!(
+        eval("let x = 1 in let f = fun x => x + 1 in f 5 + x"),
+        Ok(Term::Num(Number::from(7)))
+    );
+    assert_eq!(
+        eval("let x = 1 in {x = 2, y = x + 1}.y"),
+        Ok(Term::Num(Number::from(3)))
+    );
+    assert_eq!(
+        eval("array.all (string.is_match \"^[0-9]+$\") [\"1\", \"42\"]"),
+        Ok(Term::Bool(true))
+    );
+    assert_eq!(
+        eval("(string.match \"([a-z]+)\" \"123abc\").index"),
+        Ok(Term::Num(Number::from(3)))
+    );
+    assert_matches!(
+        eval("let x = 0 in 1 / x"),
+        Err(Error::EvalError(EvalError::Other(..)))
+    );
+    assert_matches!(
+        eval("let r = {a = 1} in r.b"),
+        Err(Error::EvalError(EvalError::FieldMissing(..)))
+    );
+    assert_matches!(
+        eval("string.is_match \"(\" \"a\""),
+        Err(Error::EvalError(EvalError::Other(..)))
+    );
+    assert_matches!(
+        eval("string.is_match \"a\" 1"),
+        Err(Error::EvalError(EvalError::BlameError(..)))
+    );
+    assert_matches!(
+        eval("%str_is_match% \"a\" 1"),
+        Err(Error::EvalError(EvalError::TypeError(..)))
+    );
+}
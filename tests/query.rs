@@ -1,3 +1,4 @@
+use nickel_lang::number::Number;
 use nickel_lang::program::Program;
 use nickel_lang::term::{MetaValue, SharedTerm, Term};
 
@@ -9,7 +10,10 @@ pub fn test_query_metadata_basic() {
 
     if let Term::MetaValue(meta) = result {
         assert_eq!(meta.doc, Some(String::from("Test basic")));
-        assert_eq!(meta.value.unwrap().term, SharedTerm::new(Term::Num(2.0)));
+        assert_eq!(
+            meta.value.unwrap().term,
+            SharedTerm::new(Term::Num(Number::from(2)))
+        );
     } else {
         panic!();
     }
@@ -26,7 +30,10 @@ pub fn test_query_metadata_from_func() {
 
     if let Term::MetaValue(meta) = result {
         assert_eq!(meta.doc, Some(String::from("Test from func")));
-        assert_eq!(meta.value.unwrap().term, SharedTerm::new(Term::Num(4.0)));
+        assert_eq!(
+            meta.value.unwrap().term,
+            SharedTerm::new(Term::Num(Number::from(4)))
+        );
     } else {
         panic!();
     }
@@ -1,7 +1,8 @@
 use assert_matches::assert_matches;
 use codespan::Files;
-use nickel_lang::cache::resolvers::DummyResolver;
+use nickel_lang::cache::{resolvers::DummyResolver, Cache, ErrorTolerance};
 use nickel_lang::error::TypecheckError;
+use nickel_lang::identifier::Ident;
 use nickel_lang::parser::{grammar, lexer};
 use nickel_lang::term::RichTerm;
 use nickel_lang::types::{AbsType, Types};
@@ -21,6 +22,20 @@ fn type_check_expr(s: impl std::string::ToString) -> Result<(), TypecheckError>
     )
 }
 
+/// Typecheck an expression in the initial environment of the standard library.
+fn type_check_expr_with_stdlib(s: impl std::string::ToString) -> Result<(), TypecheckError> {
+    let s = s.to_string();
+    let mut cache = Cache::new(ErrorTolerance::Strict);
+    cache.load_stdlib().unwrap();
+    let initial_env = cache.mk_type_env().unwrap();
+    let id = Files::new().add("<test>", s.clone());
+    let rt = grammar::TermParser::new()
+        .parse_term(id, lexer::Lexer::new(&s))
+        .unwrap();
+
+    typecheck::type_check(&rt, initial_env, &cache).map(|_| ())
+}
+
 macro_rules! assert_typecheck_fails {
     ($term:expr) => {{
         assert_matches!(type_check_expr($term), Err(..))
@@ -106,6 +121,116 @@ fn enum_complex() {
     );
 }
 
+#[test]
+fn enum_variants() {
+    assert_typecheck_fails!("`foo \"a\" : [| `foo Num |]");
+    assert_typecheck_fails!("`foo : [| `foo Num |]");
+    assert_typecheck_fails!("`foo 1 : [| `foo |]");
+    assert_typecheck_fails!("(switch { `foo x => x + 1 } (`foo true)) : Num");
+    assert_typecheck_fails!("(switch { `foo x => x, `bar => 0 } `foo) : Num");
+
+    assert_matches!(
+        type_check_expr(
+            "(fun x => switch { `foo y => y, `bar => 0 } x) : [| `foo Num, `bar, `baz |] -> Num"
+        ),
+        Err(TypecheckError::NonExhaustiveSwitch(missing, ..)) if missing == vec![Ident::from("baz")]
+    );
+}
+
+#[test]
+fn opaque_unions_and_intersections() {
+    assert_typecheck_fails!("1 : Num \\/ Str");
+    assert_typecheck_fails!("(fun x => x) : Num \\/ Str -> Num");
+    assert_typecheck_fails!("(fun x => x) : Num /\\ Str -> Num");
+    assert_typecheck_fails!("(fun x => x) : Num \\/ Str -> Str \\/ Num");
+}
+
+#[test]
+fn occurrence_typing() {
+    assert_matches!(
+        type_check_expr_with_stdlib(
+            "(fun x => if builtin.is_num x then 0 else x + 1) : Dyn -> Num"
+        ),
+        Err(..)
+    );
+    assert_matches!(
+        type_check_expr_with_stdlib(
+            "(fun x => if builtin.is_str x then x + 1 else 0) : Dyn -> Num"
+        ),
+        Err(..)
+    );
+    assert_matches!(
+        type_check_expr_with_stdlib(
+            "(fun x => if builtin.is_num x then 0 else x + 1) : Num \\/ Str -> Num"
+        ),
+        Err(..)
+    );
+    assert_matches!(
+        type_check_expr_with_stdlib(
+            "(fun x => (if builtin.is_num x then 0 else 1) + x) : Dyn -> Num"
+        ),
+        Err(..)
+    );
+
+    // A shadowed `builtin` isn't the module of the stdlib, and doesn't refine types.
+    assert_matches!(
+        type_check_expr_with_stdlib(
+            "(fun builtin x => if builtin.is_num x then x + 1 else 0)
+             : {is_num: Dyn -> Bool} -> Dyn -> Num"
+        ),
+        Err(..)
+    );
+    assert_matches!(
+        type_check_expr_with_stdlib(
+            "let builtin = {typeof = fun x => `Num} in
+             (fun x => if builtin.typeof x == `Num then x + 1 else 0) : Dyn -> Num"
+        ),
+        Err(..)
+    );
+}
+
+#[test]
+fn typed_record_operations() {
+    assert_matches!(
+        type_check_expr("({a = 1} & {a = 2}) : {a: Num}"),
+        Err(TypecheckError::OverlappingField(id, ..)) if id == Ident::from("a")
+    );
+    assert_matches!(
+        type_check_expr("(%record_insert% \"a\" {a = 1} 2) : {a: Num}"),
+        Err(TypecheckError::OverlappingField(id, ..)) if id == Ident::from("a")
+    );
+    assert_matches!(
+        type_check_expr("(%record_remove% \"b\" {a = 1}) : {a: Num}"),
+        Err(TypecheckError::MissingRow(id, ..)) if id == Ident::from("b")
+    );
+    assert_typecheck_fails!("({a = 1} & {b = 2}) : {a: Num}");
+    assert_typecheck_fails!("(%record_insert% \"b\" {a = 1} 2).b : Str");
+
+    // A shadowed `record` isn't the module of the stdlib, and its functions are typed as any
+    // other function.
+    assert_matches!(
+        type_check_expr_with_stdlib(
+            "(fun record => (record.insert \"b\" 1 {a = 1}).b)
+             : {insert: Str -> Num -> {a: Num} -> {a: Num}} -> Num"
+        ),
+        Err(..)
+    );
+    assert_matches!(
+        type_check_expr_with_stdlib(
+            "(fun record => record.remove \"a\" {a = 1, b = 2})
+             : {remove: Str -> {a: Num, b: Num} -> {a: Num, b: Num}} -> {b: Num}"
+        ),
+        Err(..)
+    );
+}
+
+#[test]
+fn recursive_types() {
+    assert_typecheck_fails!("{head = 1, tail = 2} : rec T. {head: Num, tail: T}");
+    assert_typecheck_fails!("(fun s => s.tail.head) : (rec T. {head: Num, tail: T}) -> Str");
+    assert_typecheck_fails!("(fun s => s) : (rec T. {next: T}) -> (rec T. {next: {next: Num}})");
+}
+
 #[test]
 fn static_record_simple() {
     assert_typecheck_fails!("{bla = true} : {bla : Num}");
@@ -125,6 +250,41 @@ fn static_record_simple() {
     );
 }
 
+#[test]
+fn optional_fields() {
+    assert_typecheck_fails!("{port = \"80\"} : {port: Num | optional}");
+    assert_typecheck_fails!("{host = \"a\"} : {port: Num | optional}");
+    assert_typecheck_fails!("{} : {host: Str, port: Num | optional}");
+    assert_typecheck_fails!("(fun r => r.port) : {port: Num | optional} -> Str");
+    assert_typecheck_fails!(
+        "let f : {port: Num | optional} -> Num = fun r => r.port in f {port = 1, host = \"a\"} : Num"
+    );
+
+    // A value which may lack a field can't be used where the field is required, nor where a
+    // record without it is expected.
+    assert_typecheck_fails!(
+        "(let f : {a: Num} -> Num = fun x => x.a in let r : {a: Num | optional} = {} in f r) : Num"
+    );
+    assert_typecheck_fails!("(let r : {a: Num | optional} = {} in r) : {}");
+    assert_typecheck_fails!(
+        "let f : {a: Num | optional} -> Num = fun r => 0 in (f : {a: Num} -> Num)"
+    );
+
+    // An optional field can't be accessed before testing that it is defined.
+    assert_matches!(
+        type_check_expr("(fun r => r.port) : {port: Num | optional} -> Num"),
+        Err(TypecheckError::OptionalFieldAccess(id, ..)) if id == Ident::from("port")
+    );
+    assert_typecheck_fails!("(let get = fun r => r.port in get {port = 1} + get {}) : Num");
+    assert_matches!(
+        type_check_expr(
+            "(fun r => if %has_field% \"host\" r then r.port else 0)
+             : {host: Str | optional, port: Num | optional} -> Num"
+        ),
+        Err(TypecheckError::OptionalFieldAccess(id, ..)) if id == Ident::from("port")
+    );
+}
+
 #[test]
 fn dynamic_record_simple() {
     assert_typecheck_fails!(
@@ -294,3 +454,34 @@ fn locally_different_flat_types() {
         ))
     );
 }
+
+#[test]
+fn multiple_errors() {
+    assert_matches!(
+        type_check_expr("{a : Num = \"a\", b : Str = 1, c = x}"),
+        Err(TypecheckError::Multiple(errors)) if errors.len() == 3
+    );
+    assert_matches!(
+        type_check_expr(
+            "let f : Num -> Num = fun x => x ++ \"a\" in
+             let g : Str -> Str = fun x => x + 1 in
+             (let h = fun x => x + 1 in h true) : Num"
+        ),
+        Err(TypecheckError::Multiple(errors)) if errors.len() == 3
+    );
+
+    // The errors entailed by a previous type error are not reported.
+    assert_matches!(
+        type_check_expr("(let x = y in x + %str_length% (x ++ \"a\")) : Num"),
+        Err(TypecheckError::UnboundIdentifier(..))
+    );
+
+    // Typechecking stops once the maximum number of errors is reached.
+    let fields: Vec<String> = (0..2 * typecheck::MAX_ERRORS)
+        .map(|i| format!("f{} : Num = \"a\"", i))
+        .collect();
+    assert_matches!(
+        type_check_expr(format!("{{{}}}", fields.join(", "))),
+        Err(TypecheckError::Multiple(errors)) if errors.len() == typecheck::MAX_ERRORS
+    );
+}